@@ -0,0 +1,84 @@
+// Squiller -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2023 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Benchmarks for code generation.
+//!
+//! Run with `cargo bench --bench codegen`. The inputs are synthetic queries
+//! with 200 parameters, to catch code generation that is quadratic in the
+//! number of parameters or statements. To compare against an older revision,
+//! run with `-- --save-baseline before` on that revision, and then with
+//! `-- --baseline before` on the new one.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use squiller::target::{Options, Target};
+use squiller::NamedDocument;
+use std::path::Path;
+
+/// Generate a single `insert` statement with `n` parameters.
+fn make_insert_input(n: usize) -> String {
+    let columns: Vec<String> = (0..n).map(|i| format!("c{}", i)).collect();
+    let args: Vec<String> = (0..n).map(|i| format!("c{}: i64", i)).collect();
+    let params: Vec<String> = (0..n).map(|i| format!(":c{}", i)).collect();
+    format!(
+        "-- @query insert_row({})\ninsert into t ({}) values ({});\n",
+        args.join(", "),
+        columns.join(", "),
+        params.join(", "),
+    )
+}
+
+/// Generate a `@begin` query with `n` parameters, spread over statements of 20.
+fn make_multi_statement_input(n: usize) -> String {
+    let args: Vec<String> = (0..n).map(|i| format!("c{}: i64", i)).collect();
+    let mut result = format!("-- @begin insert_rows({})\n", args.join(", "));
+    for chunk in (0..n).collect::<Vec<_>>().chunks(20) {
+        let columns: Vec<String> = chunk.iter().map(|i| format!("c{}", i)).collect();
+        let params: Vec<String> = chunk.iter().map(|i| format!(":c{}", i)).collect();
+        result.push_str(&format!(
+            "insert into t ({}) values ({});\n",
+            columns.join(", "),
+            params.join(", "),
+        ));
+    }
+    result.push_str("-- @end insert_rows\n");
+    result
+}
+
+fn bench_rust_sqlite(c: &mut Criterion) {
+    let inputs = [
+        ("insert_200_params", make_insert_input(200)),
+        (
+            "multi_statement_200_params",
+            make_multi_statement_input(200),
+        ),
+    ];
+    let target = Target::from_name("rust-sqlite").unwrap();
+    let options = Options::default();
+    let mut group = c.benchmark_group("rust-sqlite");
+    for (name, input) in inputs.iter() {
+        let document = NamedDocument::process_input(Path::new("bench.sql"), input.as_bytes())
+            .expect("Benchmark input should be valid.");
+        let documents = [document];
+        group.bench_with_input(
+            BenchmarkId::from_parameter(name),
+            &documents,
+            |b, documents| {
+                b.iter(|| {
+                    let mut out = Vec::new();
+                    target
+                        .process_files(&mut out, &options, black_box(documents))
+                        .unwrap();
+                    out
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_rust_sqlite);
+criterion_main!(benches);
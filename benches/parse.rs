@@ -0,0 +1,74 @@
+// Squiller -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2023 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Benchmarks for the document lexer and parser.
+//!
+//! Run with `cargo bench`. The inputs are `examples/users.sql` repeated a number
+//! of times, and a synthetic file with many queries and long comments.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use squiller::lexer::document::Lexer;
+use squiller::parser::document::Parser;
+
+const USERS_SQL: &str = include_str!("../examples/users.sql");
+
+/// Generate an input with `n` queries, with comments and type annotations.
+fn make_synthetic_input(n: usize) -> String {
+    let mut result = String::new();
+    for i in 0..n {
+        result.push_str("-- Select a user by id, and then some more documentation\n");
+        result.push_str("-- that spans multiple lines, to exercise the comment lexer.\n");
+        result.push_str(&format!(
+            "-- @query get_user_{}(id: i64) ->? User{}\n",
+            i, i
+        ));
+        result.push_str("select\n  id /* :i64 */,\n  name /* :str */,\n");
+        result.push_str("  email /* :str? */\n");
+        result.push_str("/* A block comment, that is a bit longer than the others,\n");
+        result.push_str("   to make sure that the lexer sees some long comments. */\n");
+        result.push_str("from users where id = :id and name <> 'It''s -- not a comment';\n\n");
+    }
+    result
+}
+
+fn bench_inputs() -> Vec<(&'static str, String)> {
+    vec![
+        ("users_x1", USERS_SQL.to_string()),
+        ("users_x100", USERS_SQL.repeat(100)),
+        ("synthetic_x1000", make_synthetic_input(1000)),
+    ]
+}
+
+fn bench_lexer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lexer");
+    for (name, input) in bench_inputs() {
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &input, |b, input| {
+            b.iter(|| Lexer::new(black_box(input)).run().unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_parser(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parser");
+    for (name, input) in bench_inputs() {
+        // We only measure the parser here, the lexer is measured separately.
+        let tokens = Lexer::new(&input).run().unwrap();
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &input, |b, input| {
+            b.iter(|| {
+                let mut parser = Parser::new(black_box(input), &tokens);
+                parser.parse_document().unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_lexer, bench_parser);
+criterion_main!(benches);
@@ -0,0 +1,11 @@
+-- @query setup_schema()
+create table if not exists numbers (value integer not null);
+
+-- @query insert_number(value: i64)
+insert into numbers (value) values (:value);
+
+-- @query select_single_number() ->1 i64
+select value from numbers;
+
+-- @query select_optional_number() ->? i64
+select value from numbers;
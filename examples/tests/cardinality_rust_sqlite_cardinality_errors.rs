@@ -0,0 +1,214 @@
+// This file was generated by Squiller 0.5.0-dev (unspecified checkout).
+// Input files:
+// - examples/tests/cardinality.sql
+
+#![allow(unknown_lints)]
+#![allow(dead_code)]
+#![allow(clippy::collapsible_if)]
+#![allow(clippy::needless_question_mark)]
+#![allow(clippy::let_unit_value)]
+#![allow(clippy::needless_lifetimes)]
+
+use std::collections::hash_map::Entry::{Occupied, Vacant};
+use std::collections::hash_map::HashMap;
+
+use sqlite::{
+    State::{Done, Row},
+    Statement,
+};
+
+pub type Result<T> = sqlite::Result<T>;
+
+pub struct Connection<'a> {
+    connection: &'a sqlite::Connection,
+    statements: HashMap<*const u8, Statement<'a>>,
+}
+
+pub struct Transaction<'tx, 'a> {
+    connection: &'a sqlite::Connection,
+    statements: &'tx mut HashMap<*const u8, Statement<'a>>,
+}
+
+pub struct Iter<'i, 'a, T> {
+    statement: &'i mut Statement<'a>,
+    decode_row: fn(&Statement<'a>) -> Result<T>,
+}
+
+impl<'a> Connection<'a> {
+    pub fn new(connection: &'a sqlite::Connection) -> Self {
+        Self {
+            connection,
+            statements: HashMap::with_capacity(4),
+        }
+    }
+
+    /// Begin a new transaction by executing the `BEGIN` statement.
+    pub fn begin<'tx>(&'tx mut self) -> Result<Transaction<'tx, 'a>> {
+        self.connection.execute("BEGIN;")?;
+        let result = Transaction {
+            connection: self.connection,
+            statements: &mut self.statements,
+        };
+        Ok(result)
+    }
+
+    /// Run `f` in a new transaction.
+    ///
+    /// Commits the transaction when `f` returns `Ok`, and rolls it back when
+    /// `f` returns `Err`.
+    pub fn transaction<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Transaction<'_, 'a>) -> Result<T>,
+    {
+        let mut tx = self.begin()?;
+        match f(&mut tx) {
+            Ok(result) => {
+                tx.commit()?;
+                Ok(result)
+            }
+            Err(err) => {
+                // If the rollback fails as well, the original error is the
+                // more useful one to report, so we ignore the rollback error.
+                let _ = tx.rollback();
+                Err(err)
+            }
+        }
+    }
+}
+
+impl<'tx, 'a> Transaction<'tx, 'a> {
+    /// Execute `COMMIT` statement.
+    pub fn commit(self) -> Result<()> {
+        self.connection.execute("COMMIT;")
+    }
+
+    /// Execute `ROLLBACK` statement.
+    pub fn rollback(self) -> Result<()> {
+        self.connection.execute("ROLLBACK;")
+    }
+}
+
+impl<'i, 'a, T> Iterator for Iter<'i, 'a, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        match self.statement.next() {
+            Ok(Row) => Some((self.decode_row)(self.statement)),
+            Ok(Done) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+/// Build the error for a query that returned an unexpected number of rows.
+fn cardinality_error(message: &str) -> sqlite::Error {
+    sqlite::Error {
+        code: None,
+        message: Some(message.to_string()),
+    }
+}
+
+pub fn setup_schema(tx: &mut Transaction) -> Result<()> {
+    let sql = r#"
+        create table if not exists numbers (value integer not null);
+        "#;
+    let statement = match tx.statements.entry(sql.as_ptr()) {
+        Occupied(entry) => entry.into_mut(),
+        Vacant(vacancy) => vacancy.insert(tx.connection.prepare(sql)?),
+    };
+    statement.reset()?;
+    let result = match statement.next()? {
+        Row => {
+            return Err(cardinality_error(
+                "Query 'setup_schema' unexpectedly returned a row.",
+            ))
+        }
+        Done => (),
+    };
+    Ok(result)
+}
+
+pub fn insert_number(tx: &mut Transaction, value: i64) -> Result<()> {
+    let sql = r#"
+        insert into numbers (value) values (:value);
+        "#;
+    let statement = match tx.statements.entry(sql.as_ptr()) {
+        Occupied(entry) => entry.into_mut(),
+        Vacant(vacancy) => vacancy.insert(tx.connection.prepare(sql)?),
+    };
+    statement.reset()?;
+    statement.bind(1, value)?;
+    let result = match statement.next()? {
+        Row => {
+            return Err(cardinality_error(
+                "Query 'insert_number' unexpectedly returned a row.",
+            ))
+        }
+        Done => (),
+    };
+    Ok(result)
+}
+
+pub fn select_single_number(tx: &mut Transaction) -> Result<i64> {
+    let sql = r#"
+        select value from numbers;
+        "#;
+    let statement = match tx.statements.entry(sql.as_ptr()) {
+        Occupied(entry) => entry.into_mut(),
+        Vacant(vacancy) => vacancy.insert(tx.connection.prepare(sql)?),
+    };
+    statement.reset()?;
+    let decode_row = |statement: &Statement| Ok(statement.read(0)?);
+    let result = match statement.next()? {
+        Row => decode_row(statement)?,
+        Done => {
+            return Err(cardinality_error(
+                "Query 'select_single_number' should return exactly one row.",
+            ))
+        }
+    };
+    if statement.next()? != Done {
+        return Err(cardinality_error(
+            "Query 'select_single_number' should return exactly one row.",
+        ));
+    }
+    Ok(result)
+}
+
+pub fn select_optional_number(tx: &mut Transaction) -> Result<Option<i64>> {
+    let sql = r#"
+        select value from numbers;
+        "#;
+    let statement = match tx.statements.entry(sql.as_ptr()) {
+        Occupied(entry) => entry.into_mut(),
+        Vacant(vacancy) => vacancy.insert(tx.connection.prepare(sql)?),
+    };
+    statement.reset()?;
+    let decode_row = |statement: &Statement| Ok(statement.read(0)?);
+    let result = match statement.next()? {
+        Row => Some(decode_row(statement)?),
+        Done => None,
+    };
+    if result.is_some() {
+        if statement.next()? != Done {
+            return Err(cardinality_error(
+                "Query 'select_optional_number' should return at most one row.",
+            ));
+        }
+    }
+    Ok(result)
+}
+
+// A useless main function, included only to make the example compile with
+// Cargo’s default settings for examples.
+#[allow(dead_code)]
+fn main() {
+    let raw_connection = sqlite::open(":memory:").unwrap();
+    let mut connection = Connection::new(&raw_connection);
+
+    let tx = connection.begin().unwrap();
+    tx.rollback().unwrap();
+
+    let tx = connection.begin().unwrap();
+    tx.commit().unwrap();
+}
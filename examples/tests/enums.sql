@@ -0,0 +1,21 @@
+-- @query setup_schema()
+create table if not exists users
+  ( id     integer primary key
+  , name   string not null
+  , status integer not null
+  );
+
+-- @query insert_user(name: str, status: Status(Active = 0, Inactive = 1, Banned = -1)) ->1 i64
+insert into users (name, status) values (:name, :status) returning id;
+
+-- @query select_user_status(id: i64) ->1 UserStatus
+select
+  name   /* :str */,
+  status /* :Status(Active = 0, Inactive = 1, Banned = -1) */
+from
+  users
+where
+  id = :id;
+
+-- @query set_raw_status(id: i64, code: i64)
+update users set status = :code where id = :id;
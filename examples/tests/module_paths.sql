@@ -0,0 +1,17 @@
+-- @query setup_schema()
+create table if not exists users
+  ( name     string not null
+  , is_admin integer not null
+  );
+
+-- @query users::insert(name: str, is_admin: i64)
+insert into users (name, is_admin) values (:name, :is_admin);
+
+-- @query users::select_by_name(name: str) ->? User
+select name /* :str */, is_admin /* :i64 */ from users where name = :name;
+
+-- @query users::count() ->1 i64
+select count(*) from users;
+
+-- @query users::admin::count() ->1 i64
+select count(*) from users where is_admin;
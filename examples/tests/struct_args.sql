@@ -0,0 +1,38 @@
+-- @query setup_schema()
+create table if not exists users
+  ( id    integer primary key
+  , name  string not null
+  , email string
+  );
+
+-- @query insert_user(user: NewUser) ->1 i64
+insert into users (name, email)
+values (:name /* :str */, :email /* :str? */)
+returning id;
+
+-- The struct argument borrows, but the rows do not borrow from it, so the
+-- argument does not need to outlive the iterator.
+-- @query iter_users_by_name(filter: UserFilter) ->* (i64, str?)
+select id, email from users where name = :name /* :str */ order by id;
+
+-- Insert many users in one call, the statement is executed once per user.
+-- @query insert_users(users: [BatchUser])
+insert into users (name, email)
+values (:name /* :str */, :email /* :str? */);
+
+-- An optional argument binds NULL when it is `None`, and `is` matches NULL.
+-- @query count_users_by_email(email: str?) ->1 i64
+select count(*) from users where email is :email;
+
+-- @query setup_blobs()
+create table if not exists blobs
+  ( id   integer primary key
+  , data blob not null
+  );
+
+-- A struct with only a `bytes` field borrows, so it needs a lifetime too.
+-- @query insert_blob(blob: Blob) ->1 i64
+insert into blobs (data) values (:data /* :bytes */) returning id;
+
+-- @query select_blob_by_id(id: i64) ->1 bytes
+select data from blobs where id = :id;
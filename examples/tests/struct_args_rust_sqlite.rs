@@ -0,0 +1,310 @@
+// This file was generated by Squiller 0.5.0-dev (unspecified checkout).
+// Input files:
+// - examples/tests/struct_args.sql
+
+#![allow(unknown_lints)]
+#![allow(dead_code)]
+#![allow(clippy::collapsible_if)]
+#![allow(clippy::needless_question_mark)]
+#![allow(clippy::let_unit_value)]
+#![allow(clippy::needless_lifetimes)]
+
+use std::collections::hash_map::Entry::{Occupied, Vacant};
+use std::collections::hash_map::HashMap;
+
+use sqlite::{
+    State::{Done, Row},
+    Statement,
+};
+
+pub type Result<T> = sqlite::Result<T>;
+
+pub struct Connection<'a> {
+    connection: &'a sqlite::Connection,
+    statements: HashMap<*const u8, Statement<'a>>,
+}
+
+pub struct Transaction<'tx, 'a> {
+    connection: &'a sqlite::Connection,
+    statements: &'tx mut HashMap<*const u8, Statement<'a>>,
+}
+
+pub struct Iter<'i, 'a, T> {
+    statement: &'i mut Statement<'a>,
+    decode_row: fn(&Statement<'a>) -> Result<T>,
+}
+
+impl<'a> Connection<'a> {
+    pub fn new(connection: &'a sqlite::Connection) -> Self {
+        Self {
+            connection,
+            statements: HashMap::with_capacity(8),
+        }
+    }
+
+    /// Begin a new transaction by executing the `BEGIN` statement.
+    pub fn begin<'tx>(&'tx mut self) -> Result<Transaction<'tx, 'a>> {
+        self.connection.execute("BEGIN;")?;
+        let result = Transaction {
+            connection: self.connection,
+            statements: &mut self.statements,
+        };
+        Ok(result)
+    }
+
+    /// Run `f` in a new transaction.
+    ///
+    /// Commits the transaction when `f` returns `Ok`, and rolls it back when
+    /// `f` returns `Err`.
+    pub fn transaction<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Transaction<'_, 'a>) -> Result<T>,
+    {
+        let mut tx = self.begin()?;
+        match f(&mut tx) {
+            Ok(result) => {
+                tx.commit()?;
+                Ok(result)
+            }
+            Err(err) => {
+                // If the rollback fails as well, the original error is the
+                // more useful one to report, so we ignore the rollback error.
+                let _ = tx.rollback();
+                Err(err)
+            }
+        }
+    }
+}
+
+impl<'tx, 'a> Transaction<'tx, 'a> {
+    /// Execute `COMMIT` statement.
+    pub fn commit(self) -> Result<()> {
+        self.connection.execute("COMMIT;")
+    }
+
+    /// Execute `ROLLBACK` statement.
+    pub fn rollback(self) -> Result<()> {
+        self.connection.execute("ROLLBACK;")
+    }
+}
+
+impl<'i, 'a, T> Iterator for Iter<'i, 'a, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        match self.statement.next() {
+            Ok(Row) => Some((self.decode_row)(self.statement)),
+            Ok(Done) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+pub fn setup_schema(tx: &mut Transaction) -> Result<()> {
+    let sql = r#"
+        create table if not exists users
+          ( id    integer primary key
+          , name  string not null
+          , email string
+          );
+        "#;
+    let statement = match tx.statements.entry(sql.as_ptr()) {
+        Occupied(entry) => entry.into_mut(),
+        Vacant(vacancy) => vacancy.insert(tx.connection.prepare(sql)?),
+    };
+    statement.reset()?;
+    let result = match statement.next()? {
+        Row => panic!("Query 'setup_schema' unexpectedly returned a row."),
+        Done => (),
+    };
+    Ok(result)
+}
+
+#[derive(Debug)]
+pub struct NewUser<'a> {
+    pub name: &'a str,
+    pub email: Option<&'a str>,
+}
+
+pub fn insert_user(tx: &mut Transaction, user: NewUser<'_>) -> Result<i64> {
+    let sql = r#"
+        insert into users (name, email)
+        values (:name, :email)
+        returning id;
+        "#;
+    let statement = match tx.statements.entry(sql.as_ptr()) {
+        Occupied(entry) => entry.into_mut(),
+        Vacant(vacancy) => vacancy.insert(tx.connection.prepare(sql)?),
+    };
+    statement.reset()?;
+    statement.bind(1, user.name)?;
+    statement.bind(2, user.email)?;
+    let decode_row = |statement: &Statement| Ok(statement.read(0)?);
+    let result = match statement.next()? {
+        Row => decode_row(statement)?,
+        Done => panic!("Query 'insert_user' should return exactly one row."),
+    };
+    if statement.next()? != Done {
+        panic!("Query 'insert_user' should return exactly one row.");
+    }
+    Ok(result)
+}
+
+#[derive(Debug)]
+pub struct UserFilter<'a> {
+    pub name: &'a str,
+}
+
+/// The struct argument borrows, but the rows do not borrow from it, so the
+/// argument does not need to outlive the iterator.
+pub fn iter_users_by_name<'i, 't, 'a>(
+    tx: &'i mut Transaction<'t, 'a>,
+    filter: UserFilter<'_>,
+) -> Result<Iter<'i, 'a, (i64, Option<String>)>> {
+    let sql = r#"
+        select id, email from users where name = :name order by id;
+        "#;
+    let statement = match tx.statements.entry(sql.as_ptr()) {
+        Occupied(entry) => entry.into_mut(),
+        Vacant(vacancy) => vacancy.insert(tx.connection.prepare(sql)?),
+    };
+    statement.reset()?;
+    statement.bind(1, filter.name)?;
+    let decode_row = |statement: &Statement| Ok((statement.read(0)?, statement.read(1)?));
+    let result = Iter {
+        statement,
+        decode_row,
+    };
+    Ok(result)
+}
+
+#[derive(Debug)]
+pub struct BatchUser<'a> {
+    pub name: &'a str,
+    pub email: Option<&'a str>,
+}
+
+/// Insert many users in one call, the statement is executed once per user.
+pub fn insert_users(tx: &mut Transaction, users: &[BatchUser<'_>]) -> Result<()> {
+    let sql = r#"
+        insert into users (name, email)
+        values (:name, :email);
+        "#;
+    let statement = match tx.statements.entry(sql.as_ptr()) {
+        Occupied(entry) => entry.into_mut(),
+        Vacant(vacancy) => vacancy.insert(tx.connection.prepare(sql)?),
+    };
+    for item in users {
+        statement.reset()?;
+        statement.bind(1, item.name)?;
+        statement.bind(2, item.email)?;
+        match statement.next()? {
+            Row => panic!("Query 'insert_users' unexpectedly returned a row."),
+            Done => {}
+        }
+    }
+    Ok(())
+}
+
+/// An optional argument binds NULL when it is `None`, and `is` matches NULL.
+pub fn count_users_by_email(tx: &mut Transaction, email: Option<&str>) -> Result<i64> {
+    let sql = r#"
+        select count(*) from users where email is :email;
+        "#;
+    let statement = match tx.statements.entry(sql.as_ptr()) {
+        Occupied(entry) => entry.into_mut(),
+        Vacant(vacancy) => vacancy.insert(tx.connection.prepare(sql)?),
+    };
+    statement.reset()?;
+    statement.bind(1, email)?;
+    let decode_row = |statement: &Statement| Ok(statement.read(0)?);
+    let result = match statement.next()? {
+        Row => decode_row(statement)?,
+        Done => panic!("Query 'count_users_by_email' should return exactly one row."),
+    };
+    if statement.next()? != Done {
+        panic!("Query 'count_users_by_email' should return exactly one row.");
+    }
+    Ok(result)
+}
+
+pub fn setup_blobs(tx: &mut Transaction) -> Result<()> {
+    let sql = r#"
+        create table if not exists blobs
+          ( id   integer primary key
+          , data blob not null
+          );
+        "#;
+    let statement = match tx.statements.entry(sql.as_ptr()) {
+        Occupied(entry) => entry.into_mut(),
+        Vacant(vacancy) => vacancy.insert(tx.connection.prepare(sql)?),
+    };
+    statement.reset()?;
+    let result = match statement.next()? {
+        Row => panic!("Query 'setup_blobs' unexpectedly returned a row."),
+        Done => (),
+    };
+    Ok(result)
+}
+
+#[derive(Debug)]
+pub struct Blob<'a> {
+    pub data: &'a [u8],
+}
+
+/// A struct with only a `bytes` field borrows, so it needs a lifetime too.
+pub fn insert_blob(tx: &mut Transaction, blob: Blob<'_>) -> Result<i64> {
+    let sql = r#"
+        insert into blobs (data) values (:data) returning id;
+        "#;
+    let statement = match tx.statements.entry(sql.as_ptr()) {
+        Occupied(entry) => entry.into_mut(),
+        Vacant(vacancy) => vacancy.insert(tx.connection.prepare(sql)?),
+    };
+    statement.reset()?;
+    statement.bind(1, blob.data)?;
+    let decode_row = |statement: &Statement| Ok(statement.read(0)?);
+    let result = match statement.next()? {
+        Row => decode_row(statement)?,
+        Done => panic!("Query 'insert_blob' should return exactly one row."),
+    };
+    if statement.next()? != Done {
+        panic!("Query 'insert_blob' should return exactly one row.");
+    }
+    Ok(result)
+}
+
+pub fn select_blob_by_id(tx: &mut Transaction, id: i64) -> Result<Vec<u8>> {
+    let sql = r#"
+        select data from blobs where id = :id;
+        "#;
+    let statement = match tx.statements.entry(sql.as_ptr()) {
+        Occupied(entry) => entry.into_mut(),
+        Vacant(vacancy) => vacancy.insert(tx.connection.prepare(sql)?),
+    };
+    statement.reset()?;
+    statement.bind(1, id)?;
+    let decode_row = |statement: &Statement| Ok(statement.read(0)?);
+    let result = match statement.next()? {
+        Row => decode_row(statement)?,
+        Done => panic!("Query 'select_blob_by_id' should return exactly one row."),
+    };
+    if statement.next()? != Done {
+        panic!("Query 'select_blob_by_id' should return exactly one row.");
+    }
+    Ok(result)
+}
+
+// A useless main function, included only to make the example compile with
+// Cargo’s default settings for examples.
+#[allow(dead_code)]
+fn main() {
+    let raw_connection = sqlite::open(":memory:").unwrap();
+    let mut connection = Connection::new(&raw_connection);
+
+    let tx = connection.begin().unwrap();
+    tx.rollback().unwrap();
+
+    let tx = connection.begin().unwrap();
+    tx.commit().unwrap();
+}
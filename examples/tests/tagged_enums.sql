@@ -0,0 +1,18 @@
+-- @query setup_schema()
+create table if not exists events
+  ( id      integer primary key
+  , kind    integer not null
+  , user_id integer null
+  , name    string null
+  );
+
+-- @query insert_event(kind: i64, user_id: i64?, name: str?)
+insert into events (kind, user_id, name) values (:kind, :user_id, :name);
+
+-- @query iter_events()
+-- ->* Event(
+--   Created = 0 (user_id: i64, name: str),
+--   Deleted = 1 (user_id: i64),
+--   Cleared = 2,
+-- )
+select kind, user_id, name from events order by id;
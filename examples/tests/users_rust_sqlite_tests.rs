@@ -0,0 +1,105 @@
+// This file was generated by Squiller 0.5.0-dev (unspecified checkout).
+// Input files:
+// - examples/users.sql
+
+#[cfg(test)]
+mod generated_tests {
+    use super::*;
+
+    const SCHEMA: &str = r#"
+-- The schema for examples/users.sql, used by the generated smoke tests in
+-- users_rust_sqlite_tests.rs.
+create table users
+  ( id    integer primary key
+  , name  string not null
+  , email string not null
+  );
+
+-- The generated tests pass 0 for integer arguments, so `select_user_by_id`
+-- needs a user with id 0 to return exactly one row.
+insert into users (id, name, email) values (0, '', '');
+"#;
+
+    /// Run `f` in a transaction, on a new in-memory database.
+    fn with_transaction<F>(f: F)
+    where
+        F: FnOnce(&mut Transaction) -> Result<()>,
+    {
+        let raw_connection = sqlite::open(":memory:").unwrap();
+        raw_connection.execute(SCHEMA).unwrap();
+        let mut connection = Connection::new(&raw_connection);
+        connection.transaction(f).unwrap();
+    }
+
+    #[test]
+    fn setup_schema() {
+        with_transaction(|tx| {
+            super::setup_schema(tx)?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn insert_user() {
+        with_transaction(|tx| {
+            super::insert_user(tx, "", "")?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn insert_user_alt_return() {
+        with_transaction(|tx| {
+            super::insert_user_alt_return(tx, "", "")?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn insert_user_alt_arg() {
+        with_transaction(|tx| {
+            super::insert_user_alt_arg(
+                tx,
+                InsertUser {
+                    name: "",
+                    email: "",
+                },
+            )?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn select_user_by_id() {
+        with_transaction(|tx| {
+            super::select_user_by_id(tx, 0)?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn select_all_users() {
+        with_transaction(|tx| {
+            for row in super::select_all_users(tx)? {
+                row?;
+            }
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn select_longest_email_length() {
+        with_transaction(|tx| {
+            super::select_longest_email_length(tx)?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn select_longest_email_length_alt() {
+        with_transaction(|tx| {
+            super::select_longest_email_length_alt(tx)?;
+            Ok(())
+        });
+    }
+}
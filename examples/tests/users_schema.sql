@@ -0,0 +1,11 @@
+-- The schema for examples/users.sql, used by the generated smoke tests in
+-- users_rust_sqlite_tests.rs.
+create table users
+  ( id    integer primary key
+  , name  string not null
+  , email string not null
+  );
+
+-- The generated tests pass 0 for integer arguments, so `select_user_by_id`
+-- needs a user with id 0 to return exactly one row.
+insert into users (id, name, email) values (0, '', '');
@@ -0,0 +1,122 @@
+.mode box
+
+[0m-- [32m@query[0m setup_schema
+create table if not exists users
+  ( id    integer primary key
+  , name  string not null
+  , email string not null
+  );
+
+insert into
+  users (name, email)
+values 
+  ("henk", "henk@example.com"),
+  ("piet", "piet@example.com");
+
+[31m-- Insert a new user and return its id.
+[0m-- [32m@query[0m insert_user
+-- name: [33mstr[0m
+-- email: [33mstr[0m
+-- ->1 [33mi64[0m
+insert into
+  users (name, email)
+values
+  ([37;1m:name[0m, [37;1m:email[0m)
+returning
+  id;
+
+[31m-- TODO: Add global type detection, use a single "User" type everywhere.
+[31m-- Insert a new user and return it.
+[0m-- [32m@query[0m insert_user_alt_return
+-- name: [33mstr[0m
+-- email: [33mstr[0m
+-- ->1 [33mUser1[0m {
+--   id: [33mi64[0m,
+--   name: [33mstr[0m,
+--   email: [33mstr[0m,
+-- }
+insert into
+  users (name, email)
+values
+  ([37;1m:name[0m, [37;1m:email[0m)
+returning
+  [34;1mid[0m    /* :[33mi64[0m */,
+  [34;1mname[0m  /* :[33mstr[0m */,
+  [34;1memail[0m /* :[33mstr[0m */;
+
+[31m-- Insert a new user and return its id.
+[0m-- [32m@query[0m insert_user_alt_arg
+-- user: InsertUser {
+--   name: [33mstr[0m
+--   email: [33mstr[0m
+-- }
+-- ->1 [33mi64[0m
+insert into
+  users (name, email)
+values
+  ([37;1m:name[0m /* :[33mstr[0m */, [37;1m:email[0m /* :[33mstr[0m */)
+returning
+  id;
+
+[31m-- Select a particular user by id.
+[31m--
+[31m-- We make a choice here to always expect one row, with "->1". If a user with
+[31m-- the given id does not exist, the function will panic. Alternatively, we could
+[31m-- write "->?", and then the return type would be wrapped in option in the
+[31m-- generated code, allowing us to handle the error.
+[0m-- [32m@query[0m select_user_by_id
+-- id: [33mi64[0m
+-- ->1 [33mUser2[0m {
+--   id: [33mi64[0m,
+--   name: [33mstr[0m,
+--   email: [33mstr[0m,
+-- }
+select
+  [34;1mid[0m    /* :[33mi64[0m */,
+  [34;1mname[0m  /* :[33mstr[0m */,
+  [34;1memail[0m /* :[33mstr[0m */
+from
+  users
+where
+  id = [37;1m:id[0m;
+
+[31m-- Iterate over all users ordered by id.
+[0m-- [32m@query[0m select_all_users
+-- ->* [33mUser3[0m {
+--   id: [33mi64[0m,
+--   name: [33mstr[0m,
+--   email: [33mstr[0m,
+-- }
+select
+  [34;1mid[0m    /* :[33mi64[0m */,
+  [34;1mname[0m  /* :[33mstr[0m */,
+  [34;1memail[0m /* :[33mstr[0m */
+from
+  users
+order by
+  id asc;
+
+[31m-- Select the length of the longest email address.
+[31m-- Note, `max` returns null when the table is empty, hence the `?` on the `i64`.
+[0m-- [32m@query[0m select_longest_email_length
+-- ->1 [33moption[0m<[33mi64[0m>
+select
+  max(length(email))
+from
+  users;
+
+[31m-- Select the length of the longest email address.
+[31m-- This query returns the same result as [`select_longest_email_length`], and
+[31m-- will have the same type in the generated code, but it works differently under
+[31m-- the hood: it returns zero or one rows with a non-null column, as opposed to
+[31m-- returning exactly one row with a nullable column.
+[0m-- [32m@query[0m select_longest_email_length_alt
+-- ->? [33mi64[0m
+select
+  length(email)
+from
+  users
+order by
+  length(email) desc
+limit
+  1;
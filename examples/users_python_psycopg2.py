@@ -64,7 +64,8 @@ def setup_schema(tx: Transaction) -> None:
           );
         """
     params = ()
-    return None
+    with tx.cursor() as cursor:
+        cursor.execute(sql, params)
 
 
 def insert_user(tx: Transaction, name, email) -> Any:
@@ -83,7 +84,18 @@ def insert_user(tx: Transaction, name, email) -> Any:
         name,
         email,
     )
-    return None
+    with tx.cursor() as cursor:
+        cursor.execute(sql, params)
+        row = cursor.fetchone()
+        if row is None:
+            raise LookupError("Query 'insert_user' returned no rows.")
+        return row[0]
+
+
+class User1(NamedTuple):
+    id: int
+    name: str
+    email: str
 
 
 def insert_user_alt_return(tx: Transaction, name, email) -> Any:
@@ -105,7 +117,12 @@ def insert_user_alt_return(tx: Transaction, name, email) -> Any:
         name,
         email,
     )
-    return None
+    with tx.cursor() as cursor:
+        cursor.execute(sql, params)
+        row = cursor.fetchone()
+        if row is None:
+            raise LookupError("Query 'insert_user_alt_return' returned no rows.")
+        return User1(*row)
 
 
 def insert_user_alt_arg(tx: Transaction, user: InsertUser) -> Any:
@@ -124,7 +141,18 @@ def insert_user_alt_arg(tx: Transaction, user: InsertUser) -> Any:
         name,
         email,
     )
-    return None
+    with tx.cursor() as cursor:
+        cursor.execute(sql, params)
+        row = cursor.fetchone()
+        if row is None:
+            raise LookupError("Query 'insert_user_alt_arg' returned no rows.")
+        return row[0]
+
+
+class User2(NamedTuple):
+    id: int
+    name: str
+    email: str
 
 
 def select_user_by_id(tx: Transaction, id) -> Any:
@@ -147,10 +175,21 @@ def select_user_by_id(tx: Transaction, id) -> Any:
           id = %s;
         """
     params = (id,)
-    return None
+    with tx.cursor() as cursor:
+        cursor.execute(sql, params)
+        row = cursor.fetchone()
+        if row is None:
+            raise LookupError("Query 'select_user_by_id' returned no rows.")
+        return User2(*row)
+
+
+class User3(NamedTuple):
+    id: int
+    name: str
+    email: str
 
 
-def select_all_users(tx: Transaction) -> Iterator[Any]:
+def select_all_users(tx: Transaction) -> list[User3]:
     """
     Iterate over all users ordered by id.
     """
@@ -165,7 +204,9 @@ def select_all_users(tx: Transaction) -> Iterator[Any]:
           id asc;
         """
     params = ()
-    return None
+    with tx.cursor() as cursor:
+        cursor.execute(sql, params)
+        return [User3(*row) for row in cursor.fetchall()]
 
 
 def select_longest_email_length(tx: Transaction) -> Any:
@@ -180,7 +221,12 @@ def select_longest_email_length(tx: Transaction) -> Any:
           users;
         """
     params = ()
-    return None
+    with tx.cursor() as cursor:
+        cursor.execute(sql, params)
+        row = cursor.fetchone()
+        if row is None:
+            raise LookupError("Query 'select_longest_email_length' returned no rows.")
+        return row[0]
 
 
 def select_longest_email_length_alt(tx: Transaction) -> Optional[Any]:
@@ -202,4 +248,9 @@ def select_longest_email_length_alt(tx: Transaction) -> Optional[Any]:
           1;
         """
     params = ()
-    return None
+    with tx.cursor() as cursor:
+        cursor.execute(sql, params)
+        row = cursor.fetchone()
+        if row is None:
+            return None
+        return row[0]
@@ -3,6 +3,7 @@
 // - examples/users.sql
 
 #![allow(unknown_lints)]
+#![allow(dead_code)]
 #![allow(clippy::collapsible_if)]
 #![allow(clippy::needless_question_mark)]
 #![allow(clippy::let_unit_value)]
@@ -37,9 +38,7 @@ impl<'a> Connection<'a> {
     pub fn new(connection: &'a sqlite::Connection) -> Self {
         Self {
             connection,
-            // TODO: We could do with_capacity here, because we know the number
-            // of queries.
-            statements: HashMap::new(),
+            statements: HashMap::with_capacity(8),
         }
     }
 
@@ -52,6 +51,29 @@ impl<'a> Connection<'a> {
         };
         Ok(result)
     }
+
+    /// Run `f` in a new transaction.
+    ///
+    /// Commits the transaction when `f` returns `Ok`, and rolls it back when
+    /// `f` returns `Err`.
+    pub fn transaction<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Transaction<'_, 'a>) -> Result<T>,
+    {
+        let mut tx = self.begin()?;
+        match f(&mut tx) {
+            Ok(result) => {
+                tx.commit()?;
+                Ok(result)
+            }
+            Err(err) => {
+                // If the rollback fails as well, the original error is the
+                // more useful one to report, so we ignore the rollback error.
+                let _ = tx.rollback();
+                Err(err)
+            }
+        }
+    }
 }
 
 impl<'tx, 'a> Transaction<'tx, 'a> {
@@ -177,7 +199,7 @@ pub struct InsertUser<'a> {
 }
 
 /// Insert a new user and return its id.
-pub fn insert_user_alt_arg(tx: &mut Transaction, user: InsertUser) -> Result<i64> {
+pub fn insert_user_alt_arg(tx: &mut Transaction, user: InsertUser<'_>) -> Result<i64> {
     let sql = r#"
         insert into
           users (name, email)
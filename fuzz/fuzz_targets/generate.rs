@@ -9,7 +9,7 @@
 
 use libfuzzer_sys::fuzz_target;
 use std::path::PathBuf;
-use squiller::target::TARGETS;
+use squiller::target::{Options, TARGETS};
 
 fuzz_target!(|input_bytes: &[u8]| {
     // The last byte of the input indicates the target we want to fuzz. This
@@ -34,5 +34,5 @@ fuzz_target!(|input_bytes: &[u8]| {
     };
 
     let mut out = Vec::new();
-    let _ = (target.handler)(&mut out, &[doc]);
+    let _ = target.process_files(&mut out, &Options::default(), &[doc]);
 });
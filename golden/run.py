@@ -12,7 +12,9 @@ A test runner for running the golden tests.
 
 The runner takes golden input files, splits them into inputs and expectations,
 and then prints whether they match. Inputs and expectations are separated by a
-double blank line.
+double blank line. When the first line of the input is a comment of the form
+"-- args: <args>", the arguments are passed to Squiller in addition to the
+target.
 
 SYNOPSIS
 
@@ -36,6 +38,7 @@ OPTIONS
 import difflib
 import os
 import re
+import shlex
 import subprocess
 import sys
 
@@ -81,6 +84,10 @@ def test_one(fname: str, *, rewrite_output: bool) -> bool:
         if input_lines[-1].strip() == "":
             input_lines.pop()
 
+    extra_args: List[str] = []
+    if input_lines[0].startswith("-- args:"):
+        extra_args = shlex.split(input_lines[0][len("-- args:") :])
+
     # Run with RUST_BACKTRACE=1 so we get a backtrace if the process panics.
     os.putenv("RUST_BACKTRACE", "1")
 
@@ -88,7 +95,7 @@ def test_one(fname: str, *, rewrite_output: bool) -> bool:
     squiller_bin = os.getenv("SQUILLER_BIN", default="target/debug/squiller")
 
     result = subprocess.run(
-        [squiller_bin, f"--target={target_name}", "-"],
+        [squiller_bin, f"--target={target_name}", *extra_args, "-"],
         input="".join(input_lines),
         capture_output=True,
         encoding="utf-8",
@@ -7,6 +7,63 @@
 
 use crate::Span;
 
+/// The SQL dialect of the input.
+///
+/// Squiller does not fully parse SQL, but some tokens have a different meaning
+/// in different dialects, which affects what we accept.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Dialect {
+    /// Treat all of `()`, `[]`, and `{}` as brackets that must be balanced.
+    Generic,
+    Postgres,
+    Sqlite,
+}
+
+impl Dialect {
+    /// Parse the dialect from its lowercase name.
+    pub fn from_keyword(keyword: &str) -> Option<Dialect> {
+        match keyword {
+            "generic" => Some(Dialect::Generic),
+            "postgres" => Some(Dialect::Postgres),
+            "sqlite" => Some(Dialect::Sqlite),
+            _ => None,
+        }
+    }
+
+    /// The lowercase name of the dialect, the inverse of `from_keyword`.
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            Dialect::Generic => "generic",
+            Dialect::Postgres => "postgres",
+            Dialect::Sqlite => "sqlite",
+        }
+    }
+
+    /// Whether `[]` group tokens in this dialect, so they must be balanced.
+    ///
+    /// In SQLite, brackets quote identifiers, so the name inside can contain
+    /// any character, including unbalanced brackets.
+    pub fn balances_brackets(&self) -> bool {
+        match self {
+            Dialect::Generic => true,
+            Dialect::Postgres => true,
+            Dialect::Sqlite => false,
+        }
+    }
+
+    /// Whether `{}` group tokens in this dialect, so they must be balanced.
+    ///
+    /// Neither PostgreSQL nor SQLite use braces for grouping, they can only
+    /// occur in operators and extensions, where they need not be balanced.
+    pub fn balances_braces(&self) -> bool {
+        match self {
+            Dialect::Generic => true,
+            Dialect::Postgres => false,
+            Dialect::Sqlite => false,
+        }
+    }
+}
+
 /// The primitive types that we support.
 ///
 /// These types map to SQL types on the one hand, and types in the target
@@ -14,11 +71,14 @@ use crate::Span;
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum PrimitiveType {
     Str,
+    I16,
     I32,
     I64,
+    U32,
     F32,
     F64,
     Bytes,
+    Json,
 }
 
 /// A simple type is a type that is not composite. It's primitive or a nullable primitive.
@@ -36,6 +96,21 @@ pub enum SimpleType<TSpan> {
         inner: TSpan,
         type_: PrimitiveType,
     },
+    /// An enum with integer codes, e.g. `Status(Active = 0, Inactive = 1)`.
+    ///
+    /// In the database, the value is stored as its `i64` code.
+    Enum {
+        outer: TSpan,
+        name: TSpan,
+        variants: Vec<EnumVariant<TSpan>>,
+    },
+}
+
+/// A variant of an enum type, with its integer code, e.g. `Active = 0`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EnumVariant<TSpan> {
+    pub name: TSpan,
+    pub code: i64,
 }
 
 impl<TSpan> SimpleType<TSpan> {
@@ -46,18 +121,27 @@ impl<TSpan> SimpleType<TSpan> {
         match &self {
             SimpleType::Primitive { inner, .. } => *inner,
             SimpleType::Option { outer, .. } => *outer,
+            SimpleType::Enum { outer, .. } => *outer,
         }
     }
 
+    /// Return the primitive type that the value is stored as.
     pub fn inner_type(&self) -> PrimitiveType {
         match self {
             SimpleType::Primitive { type_, .. } => *type_,
             SimpleType::Option { type_, .. } => *type_,
+            SimpleType::Enum { .. } => PrimitiveType::I64,
         }
     }
 
     /// Test equivalence of the types, regardless of the spans or formatting.
-    pub fn is_equal_to(&self, other: &SimpleType<TSpan>) -> bool {
+    ///
+    /// Enums are equal when their names and variants are, so for enums, this
+    /// is only meaningful for resolved types.
+    pub fn is_equal_to(&self, other: &SimpleType<TSpan>) -> bool
+    where
+        TSpan: PartialEq,
+    {
         match (self, other) {
             (
                 SimpleType::Primitive { type_: lhs, .. },
@@ -66,6 +150,18 @@ impl<TSpan> SimpleType<TSpan> {
             (SimpleType::Option { type_: lhs, .. }, SimpleType::Option { type_: rhs, .. }) => {
                 lhs == rhs
             }
+            (
+                SimpleType::Enum {
+                    name: lhs_name,
+                    variants: lhs_variants,
+                    ..
+                },
+                SimpleType::Enum {
+                    name: rhs_name,
+                    variants: rhs_variants,
+                    ..
+                },
+            ) => lhs_name == rhs_name && lhs_variants == rhs_variants,
             _ => false,
         }
     }
@@ -87,6 +183,21 @@ impl SimpleType<Span> {
                 inner: inner.resolve(input),
                 type_: *type_,
             },
+            SimpleType::Enum {
+                outer,
+                name,
+                variants,
+            } => SimpleType::Enum {
+                outer: outer.resolve(input),
+                name: name.resolve(input),
+                variants: variants
+                    .iter()
+                    .map(|v| EnumVariant {
+                        name: v.name.resolve(input),
+                        code: v.code,
+                    })
+                    .collect(),
+            },
         }
     }
 }
@@ -96,6 +207,12 @@ impl SimpleType<Span> {
 pub struct TypedIdent<TSpan> {
     pub ident: TSpan,
     pub type_: SimpleType<TSpan>,
+    /// Documentation from an `@param` line, for query arguments.
+    pub doc: Option<TSpan>,
+    /// Name from an output annotation of the form `/* :alias: type */`, for
+    /// query outputs. When present, the output is named after the alias
+    /// instead of after `ident`.
+    pub alias: Option<TSpan>,
 }
 
 impl TypedIdent<Span> {
@@ -103,6 +220,45 @@ impl TypedIdent<Span> {
         TypedIdent {
             ident: self.ident.resolve(input),
             type_: self.type_.resolve(input),
+            doc: self.doc.map(|doc| doc.resolve(input)),
+            alias: self.alias.map(|alias| alias.resolve(input)),
+        }
+    }
+}
+
+/// An element of a tuple, with an optional name, e.g. `count: i64` or `i64`.
+///
+/// The name does not affect the type of the tuple, it only serves as
+/// documentation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TupleElement<TSpan> {
+    pub name: Option<TSpan>,
+    pub type_: SimpleType<TSpan>,
+}
+
+impl TupleElement<Span> {
+    pub fn resolve<'a>(&self, input: &'a str) -> TupleElement<&'a str> {
+        TupleElement {
+            name: self.name.map(|name| name.resolve(input)),
+            type_: self.type_.resolve(input),
+        }
+    }
+}
+
+/// A variant of a tagged enum, with its code and fields, e.g. `Deleted = 1 (id: i64)`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TaggedVariant<TSpan> {
+    pub name: TSpan,
+    pub code: i64,
+    pub fields: Vec<TypedIdent<TSpan>>,
+}
+
+impl TaggedVariant<Span> {
+    pub fn resolve<'a>(&self, input: &'a str) -> TaggedVariant<&'a str> {
+        TaggedVariant {
+            name: self.name.resolve(input),
+            code: self.code,
+            fields: self.fields.iter().map(|f| f.resolve(input)).collect(),
         }
     }
 }
@@ -115,12 +271,21 @@ pub enum ComplexType<TSpan> {
     /// A tuple of zero or more types.
     ///
     /// Field 0 contains the span of the full tuple.
-    Tuple(TSpan, Vec<SimpleType<TSpan>>),
+    Tuple(TSpan, Vec<TupleElement<TSpan>>),
 
     /// A struct with zero or more fields.
     ///
-    /// Field 0 contains the span of the name of the struct.
-    Struct(TSpan, Vec<TypedIdent<TSpan>>),
+    /// Field 0 contains the span of the name of the struct. Field 2 is true
+    /// when the struct is marked `@extern`, it is then defined outside of the
+    /// generated code, and targets should not generate a definition for it.
+    Struct(TSpan, Vec<TypedIdent<TSpan>>, bool),
+
+    /// An enum where variants have fields, e.g. `Event(Created = 0 (id: i64))`.
+    ///
+    /// Field 0 contains the span of the name of the enum. Rows store the code
+    /// of the variant in the first column, and the fields in the columns after
+    /// it. Fields with the same name in different variants share a column.
+    TaggedEnum(TSpan, Vec<TaggedVariant<TSpan>>),
 }
 
 impl ComplexType<Span> {
@@ -131,10 +296,26 @@ impl ComplexType<Span> {
                 let fields = fields.iter().map(|t| t.resolve(input)).collect();
                 ComplexType::Tuple(outer.resolve(input), fields)
             }
-            ComplexType::Struct(name, fields) => {
+            ComplexType::Struct(name, fields, is_extern) => {
                 let fields = fields.iter().map(|t| t.resolve(input)).collect();
-                ComplexType::Struct(name.resolve(input), fields)
+                ComplexType::Struct(name.resolve(input), fields, *is_extern)
             }
+            ComplexType::TaggedEnum(name, variants) => {
+                let variants = variants.iter().map(|v| v.resolve(input)).collect();
+                ComplexType::TaggedEnum(name.resolve(input), variants)
+            }
+        }
+    }
+
+    /// Return the span of the type in the annotation.
+    ///
+    /// For structs and tagged enums, this is the span of the name only.
+    pub fn span(&self) -> Span {
+        match self {
+            ComplexType::Simple(inner) => inner.span(),
+            ComplexType::Tuple(outer, _) => *outer,
+            ComplexType::Struct(name, _, _) => *name,
+            ComplexType::TaggedEnum(name, _) => *name,
         }
     }
 }
@@ -203,6 +384,16 @@ pub enum ArgType<TSpan> {
         type_name: TSpan,
         fields: Vec<TypedIdent<TSpan>>,
     },
+
+    /// A slice of structs, e.g. `(users: [User])`, for batch inserts.
+    ///
+    /// The fields are inferred from the query body, as for `Struct`. The
+    /// statements are executed once for every element of the slice.
+    StructSlice {
+        var_name: TSpan,
+        type_name: TSpan,
+        fields: Vec<TypedIdent<TSpan>>,
+    },
 }
 
 impl ArgType<Span> {
@@ -218,6 +409,15 @@ impl ArgType<Span> {
                 type_name: type_name.resolve(input),
                 fields: fields.iter().map(|ti| ti.resolve(input)).collect(),
             },
+            ArgType::StructSlice {
+                var_name,
+                type_name,
+                fields,
+            } => ArgType::StructSlice {
+                var_name: var_name.resolve(input),
+                type_name: type_name.resolve(input),
+                fields: fields.iter().map(|ti| ti.resolve(input)).collect(),
+            },
         }
     }
 }
@@ -232,11 +432,22 @@ pub enum StatementType {
     ///
     /// Statements are part of the query, until the matching `@end` marker.
     Multi,
+
+    /// A query marked with `@call`, a single statement that calls a stored
+    /// procedure.
+    ///
+    /// The procedure returns its OUT parameters as a single row, so the result
+    /// type can only be unit or `->1`.
+    Call,
 }
 
 /// An annotation comment that describes the query that follows it.
 #[derive(Debug, Eq, PartialEq)]
 pub struct Annotation<TSpan> {
+    /// The module path before the name, as in `users::select_by_id`.
+    ///
+    /// Empty for queries that are not in a module.
+    pub path: Vec<TSpan>,
     pub name: TSpan,
     pub arguments: ArgType<TSpan>,
     pub result_type: ResultType<TSpan>,
@@ -245,6 +456,7 @@ pub struct Annotation<TSpan> {
 impl Annotation<Span> {
     pub fn resolve<'a>(&self, input: &'a str) -> Annotation<&'a str> {
         Annotation {
+            path: self.path.iter().map(|s| s.resolve(input)).collect(),
             name: self.name.resolve(input),
             arguments: self.arguments.resolve(input),
             result_type: self.result_type.resolve(input),
@@ -254,9 +466,11 @@ impl Annotation<Span> {
 
 /// A part of a query.
 ///
-/// We break down queries in consecutive spans of four kinds:
+/// We break down queries in consecutive spans of five kinds:
 ///
 /// * Verbatim content where we don't really care about its inner structure.
+/// * Comments that are not type annotations, including the `--` or `/* */`
+///   delimiters. Targets treat these as verbatim content.
 /// * Typed identifiers, the quoted part in a `select ... as "ident: type"`
 ///   select. These are kept separately, such that we can replace this with
 ///   just `ident` in the final query.
@@ -265,6 +479,7 @@ impl Annotation<Span> {
 #[derive(Debug, Eq, PartialEq)]
 pub enum Fragment<TSpan> {
     Verbatim(TSpan),
+    Comment(TSpan),
     TypedIdent(TSpan, TypedIdent<TSpan>),
     Param(TSpan),
     TypedParam(TSpan, TypedIdent<TSpan>),
@@ -274,6 +489,7 @@ impl Fragment<Span> {
     pub fn resolve<'a>(&self, input: &'a str) -> Fragment<&'a str> {
         match self {
             Fragment::Verbatim(s) => Fragment::Verbatim(s.resolve(input)),
+            Fragment::Comment(s) => Fragment::Comment(s.resolve(input)),
             Fragment::TypedIdent(s, ti) => {
                 Fragment::TypedIdent(s.resolve(input), ti.resolve(input))
             }
@@ -288,6 +504,7 @@ impl Fragment<Span> {
     pub fn span(&self) -> Span {
         match self {
             Fragment::Verbatim(s) => *s,
+            Fragment::Comment(s) => *s,
             Fragment::TypedIdent(s, _) => *s,
             Fragment::Param(s) => *s,
             Fragment::TypedParam(s, _) => *s,
@@ -312,6 +529,13 @@ impl Statement<Span> {
             fragments: self.fragments.iter().map(|f| f.resolve(input)).collect(),
         }
     }
+
+    /// The span of the full statement, from its first to its last fragment.
+    pub fn span(&self) -> Span {
+        let first = self.fragments.first().expect("Statements are not empty.");
+        let last = self.fragments.last().expect("Statements are not empty.");
+        first.span().union(last.span())
+    }
 }
 
 impl<TSpan> Statement<TSpan> {
@@ -322,6 +546,7 @@ impl<TSpan> Statement<TSpan> {
     {
         self.fragments.iter().filter_map(|fragment| match fragment {
             Fragment::Verbatim(..) => None,
+            Fragment::Comment(..) => None,
             Fragment::TypedIdent(..) => None,
             Fragment::Param(span) => Some(*span),
             Fragment::TypedParam(_full_span, ti) => Some(ti.ident),
@@ -338,6 +563,15 @@ pub struct Query<TSpan> {
     /// The annotation, which includes the name, parameters, and result type.
     pub annotation: Annotation<TSpan>,
 
+    /// Which marker started the query.
+    pub statement_type: StatementType,
+
+    /// The dialect set with an `@dialect` line, overrides `--dialect`.
+    pub dialect: Option<Dialect>,
+
+    /// The tags set with an `@tags` line, used to select queries with `--tags`.
+    pub tags: Vec<TSpan>,
+
     /// The SQL statements that make up the query, at least one.
     pub statements: Vec<Statement<TSpan>>,
 }
@@ -347,9 +581,33 @@ impl Query<Span> {
         Query {
             docs: self.docs.iter().map(|d| d.resolve(input)).collect(),
             annotation: self.annotation.resolve(input),
+            statement_type: self.statement_type,
+            dialect: self.dialect,
+            tags: self.tags.iter().map(|t| t.resolve(input)).collect(),
             statements: self.statements.iter().map(|f| f.resolve(input)).collect(),
         }
     }
+
+    /// The span of the query, from its first doc line or its name, to the end
+    /// of its last statement.
+    pub fn span(&self) -> Span {
+        let start = self
+            .docs
+            .first()
+            .or_else(|| self.annotation.path.first())
+            .unwrap_or(&self.annotation.name);
+        let last = self.statements.last().expect("Queries have a statement.");
+        start.union(last.span())
+    }
+
+    /// Return the fragment of the query body that contains the byte offset.
+    pub fn fragment_at(&self, offset: usize) -> Option<&Fragment<Span>> {
+        self.statements
+            .iter()
+            .filter(|statement| statement.span().contains(offset))
+            .flat_map(|statement| statement.fragments.iter())
+            .find(|fragment| fragment.span().contains(offset))
+    }
 }
 
 impl<TSpan> Query<TSpan> {
@@ -395,6 +653,12 @@ impl Document<Span> {
             sections: self.sections.iter().map(|s| s.resolve(input)).collect(),
         }
     }
+
+    /// Return the query that contains the byte offset, see also `Query::span`.
+    pub fn query_at(&self, offset: usize) -> Option<&Query<Span>> {
+        self.iter_queries()
+            .find(|query| query.span().contains(offset))
+    }
 }
 
 impl<TSpan> Document<TSpan> {
@@ -405,4 +669,53 @@ impl<TSpan> Document<TSpan> {
             Section::Query(q) => Some(q),
         })
     }
+
+    /// Return the number of queries in the document.
+    pub fn query_count(&self) -> usize {
+        self.iter_queries().count()
+    }
+
+    /// Whether the document contains at least one query.
+    pub fn has_queries(&self) -> bool {
+        self.iter_queries().next().is_some()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Fragment;
+
+    #[test]
+    fn query_at_and_fragment_at_locate_param() {
+        let input = "\
+-- Look up a user.
+-- @query get_user(id: i64) ->1 str
+select name from users where id = :id;
+
+-- @query count_users() ->1 i64
+select count(*) from users;
+";
+        let doc = crate::parse_str(input).unwrap();
+
+        let offset = input.find(":id").unwrap() + 1;
+        let query = doc.query_at(offset).unwrap();
+        assert_eq!(query.annotation.name.resolve(input), "get_user");
+        match query.fragment_at(offset) {
+            Some(Fragment::Param(span)) => assert_eq!(span.resolve(input), ":id"),
+            other => panic!("Expected the param fragment, got {:?}.", other),
+        }
+
+        // The doc comment belongs to the query, but is not a fragment.
+        let offset = input.find("Look up").unwrap();
+        assert!(doc.query_at(offset).is_some());
+        assert_eq!(doc.query_at(offset).unwrap().fragment_at(offset), None);
+
+        let offset = input.find("count(*)").unwrap();
+        let query = doc.query_at(offset).unwrap();
+        assert_eq!(query.annotation.name.resolve(input), "count_users");
+
+        // The blank line between the queries belongs to neither.
+        let offset = input.find("\n\n").unwrap() + 1;
+        assert_eq!(doc.query_at(offset), None);
+    }
 }
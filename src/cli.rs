@@ -12,15 +12,23 @@
 use std::fmt;
 use std::vec;
 
+use crate::ast::Dialect;
+use crate::target::{BeginMode, NullPolicy, Options, Visibility};
+
 const USAGE: &str = r#"
 Squiller -- Generate boilerplate from annotated SQL queries.
 
 Usage:
-  squiller --target <target> <file>...
+  squiller [generate] --target <target> <file>...
+  squiller targets
   squiller --target help
   squiller -h | --help
   squiller --version
 
+Commands:
+  generate              Generate code, this is the default when omitted.
+  targets               List supported targets.
+
 Arguments:
   <file>...             One or more input files to process, or '-' for stdin.
 
@@ -28,12 +36,118 @@ Options:
   -h --help             Show this screen.
   -t --target <target>  Target to generate code for, use '--target=help' to
                         list supported targets.
+  --schema <file>       Read column types from 'create table' statements in
+                        this file, to infer types of query parameters.
+  --annotations <file>  Read query names and signatures from this TOML file,
+                        instead of from annotations in the input.
+  --error-format <fmt>  How to print errors, 'human' (the default) or 'json'.
+  --dialect <dialect>   SQL dialect of the input, one of 'generic' (the
+                        default), 'postgres', or 'sqlite'.
+  --ignore-template-braces
+                        Treat '{{ ... }}' in the input as an opaque template
+                        expression, for templated SQL.
+  --visibility <vis>    Visibility of generated items for Rust targets, one of
+                        'pub' (the default), 'pub(crate)', or '' (private).
+  --no-cache            Prepare statements on every call, instead of caching
+                        them in the connection.
+  --borrow-rows         Pass rows of iterator queries to a callback as a view
+                        into the statement, instead of returning owned rows.
+  --begin-mode <mode>   Transaction mode for SQLite targets, one of 'deferred',
+                        'immediate', or 'exclusive'.
+  --methods             Also generate queries as methods on the transaction,
+                        for Rust targets.
+  --read-by-name        Read struct fields by column name instead of position,
+                        for Rust targets. Tagged enums are still read by
+                        position.
+  --minify-sql          Collapse whitespace in the generated SQL, instead of
+                        preserving the formatting of the input, for the
+                        'rust-sqlite' and 'sql' targets.
+  --strip-comments      Remove comments from the generated SQL, for the
+                        'rust-sqlite' and 'sql' targets.
+  --dedup-structs       Define structs with the same name only once, for Rust
+                        and Python targets.
+  --cardinality-errors  Return an error instead of panicking when a query
+                        returns an unexpected number of rows, for the
+                        'rust-sqlite' target.
+  --tracing             Log every query and its duration with the 'tracing'
+                        crate, for the 'rust-sqlite' target.
+  --expose-sql          Also generate a function that returns the SQL of every
+                        query, for the 'rust-sqlite' target.
+  --strict-null         Name the column when reading a result column fails,
+                        for the 'rust-sqlite' target.
+  --lenient-null        Like '--strict-null', and also check that columns that
+                        are not nullable are not null.
+  --cfg-feature <name>  Only compile the generated code when the given Cargo
+                        feature is enabled, for Rust targets.
+  --type-prefix <name>  Prefix for the generated 'Connection', 'Transaction',
+                        and 'Iter' types, for Rust targets.
+  --max-line-width <n>  Break the signatures of generated functions that are
+                        wider than this over multiple lines, for the
+                        'rust-sqlite' target. Defaults to 100.
+  --python-class <name> Generate the queries as methods on a class with this
+                        name, for Python targets.
+  --header <text>       Text to put at the top of the generated code, such as
+                        a license header.
+  --quiet --no-header   Omit the comment that lists the Squiller version and
+                        the input files from the generated code.
+  --tags <tags>         Only generate code for queries with one of these
+                        comma-separated tags, and for untagged queries.
+  --only-tagged         Leave out queries that have no '@tags' line.
+  --print-deps          Print the packages that the generated code depends on,
+                        one per line, instead of the code itself.
+  --cache-dir <dir>     Reuse the output of a previous run from this directory
+                        when the inputs and options are unchanged.
+  --show-spans          Print the byte range of every fragment, for the
+                        'debug' target.
   --version             Show version.
 "#;
 
+/// How to print errors.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ErrorFormat {
+    /// Human-readable, with the source line and colors.
+    Human,
+    /// One Json object per line, for editor integration.
+    Json,
+}
+
+/// Options for `generate` that the driver handles, rather than the target.
+///
+/// These do not change the code that a target generates for a query, unlike
+/// the target `Options`.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct DriverOptions {
+    /// Path of a TOML file that maps query names to signatures, for inputs
+    /// that have no annotations. See the `sidecar` module.
+    pub annotations: Option<String>,
+
+    /// Directory to cache generated output in, keyed on a hash of the inputs.
+    pub cache_dir: Option<String>,
+
+    /// When nonempty, only generate code for queries that have one of these
+    /// tags in their `@tags` line, and for untagged queries.
+    pub tags: Vec<String>,
+
+    /// Whether to leave out queries that have no `@tags` line.
+    pub only_tagged: bool,
+
+    /// Whether to print the packages that the generated code depends on,
+    /// instead of the code itself.
+    pub print_deps: bool,
+}
+
+// We parse only one command per run, so its size does not matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Eq, PartialEq)]
 pub enum Cmd {
-    Generate { target: String, fnames: Vec<String> },
+    Generate {
+        target: String,
+        fnames: Vec<String>,
+        schema: Option<String>,
+        error_format: ErrorFormat,
+        options: Options,
+        driver: DriverOptions,
+    },
     TargetHelp,
     Help,
     Version,
@@ -143,13 +257,35 @@ impl Iterator for ArgIter {
 }
 
 pub fn parse(argv: Vec<String>) -> Result<Cmd, String> {
+    // A subcommand is only recognized as the first argument, if it is absent,
+    // we default to `generate`, so the flag-based invocation keeps working.
+    let subcommand = match argv.get(1).map(|arg| &arg[..]) {
+        Some("generate") => Some("generate"),
+        Some("targets") => Some("targets"),
+        _ => None,
+    };
+
     let mut args = ArgIter::new(argv);
 
-    // Skip executable name.
+    // Skip executable name, and the subcommand if there is one.
     args.next();
+    if subcommand.is_some() {
+        args.next();
+    }
+
+    if subcommand == Some("targets") {
+        return match args.next() {
+            None => Ok(Cmd::TargetHelp),
+            Some(arg) => Err(format!("Unexpected argument '{}' after 'targets'.", arg)),
+        };
+    }
 
     let mut fnames = Vec::new();
     let mut target = None;
+    let mut schema = None;
+    let mut error_format = ErrorFormat::Human;
+    let mut options = Options::default();
+    let mut driver = DriverOptions::default();
     let mut is_help = false;
     let mut is_version = false;
 
@@ -160,6 +296,113 @@ pub fn parse(argv: Vec<String>) -> Result<Cmd, String> {
                 Some(Arg::Plain(t)) => target = Some(t),
                 _ => return Err(format!("Expected target name after '{}'.", arg)),
             },
+            Arg::Long("schema") => match args.next() {
+                Some(Arg::Plain(f)) => schema = Some(f),
+                _ => return Err(format!("Expected schema file after '{}'.", arg)),
+            },
+            Arg::Long("error-format") => match args.next() {
+                Some(Arg::Plain(f)) => match &f[..] {
+                    "human" => error_format = ErrorFormat::Human,
+                    "json" => error_format = ErrorFormat::Json,
+                    _ => {
+                        return Err(format!(
+                            "Invalid error format '{}', expected 'human' or 'json'.",
+                            f
+                        ))
+                    }
+                },
+                _ => return Err(format!("Expected error format after '{}'.", arg)),
+            },
+            Arg::Long("dialect") => match args.next() {
+                Some(Arg::Plain(d)) => match Dialect::from_keyword(&d) {
+                    Some(dialect) => options.dialect = dialect,
+                    None => {
+                        return Err(format!(
+                            "Invalid dialect '{}', expected 'generic', 'postgres', or 'sqlite'.",
+                            d
+                        ))
+                    }
+                },
+                _ => return Err(format!("Expected dialect after '{}'.", arg)),
+            },
+            Arg::Long("visibility") => match args.next() {
+                Some(Arg::Plain(v)) => match Visibility::from_keyword(&v) {
+                    Some(visibility) => options.visibility = visibility,
+                    None => {
+                        return Err(format!(
+                            "Invalid visibility '{}', expected 'pub', 'pub(crate)', or ''.",
+                            v
+                        ))
+                    }
+                },
+                _ => return Err(format!("Expected visibility after '{}'.", arg)),
+            },
+            Arg::Long("begin-mode") => match args.next() {
+                Some(Arg::Plain(m)) => match BeginMode::from_keyword(&m) {
+                    Some(mode) => options.begin_mode = Some(mode),
+                    None => {
+                        return Err(format!(
+                            "Invalid begin mode '{}', expected 'deferred', 'immediate', or 'exclusive'.",
+                            m
+                        ))
+                    }
+                },
+                _ => return Err(format!("Expected begin mode after '{}'.", arg)),
+            },
+            Arg::Long("cfg-feature") => match args.next() {
+                Some(Arg::Plain(f)) => options.cfg_feature = Some(f),
+                _ => return Err(format!("Expected feature name after '{}'.", arg)),
+            },
+            Arg::Long("annotations") => match args.next() {
+                Some(Arg::Plain(f)) => driver.annotations = Some(f),
+                _ => return Err(format!("Expected annotations file after '{}'.", arg)),
+            },
+            Arg::Long("header") => match args.next() {
+                Some(Arg::Plain(h)) => options.header = Some(h),
+                _ => return Err(format!("Expected header text after '{}'.", arg)),
+            },
+            Arg::Long("cache-dir") => match args.next() {
+                Some(Arg::Plain(d)) => driver.cache_dir = Some(d),
+                _ => return Err(format!("Expected directory after '{}'.", arg)),
+            },
+            Arg::Long("type-prefix") => match args.next() {
+                Some(Arg::Plain(p)) => options.type_prefix = p,
+                _ => return Err(format!("Expected prefix after '{}'.", arg)),
+            },
+            Arg::Long("max-line-width") => match args.next() {
+                Some(Arg::Plain(w)) => match w.parse() {
+                    Ok(width) => options.max_line_width = width,
+                    Err(..) => return Err(format!("Invalid width '{}', expected a number.", w)),
+                },
+                _ => return Err(format!("Expected width after '{}'.", arg)),
+            },
+            Arg::Long("python-class") => match args.next() {
+                Some(Arg::Plain(c)) => options.python_class = Some(c),
+                _ => return Err(format!("Expected class name after '{}'.", arg)),
+            },
+            Arg::Long("tags") => match args.next() {
+                Some(Arg::Plain(t)) => driver
+                    .tags
+                    .extend(t.split(',').map(|tag| tag.trim().to_string())),
+                _ => return Err(format!("Expected tags after '{}'.", arg)),
+            },
+            Arg::Long("no-cache") => options.cache_statements = false,
+            Arg::Long("quiet") | Arg::Long("no-header") => options.version_header = false,
+            Arg::Long("borrow-rows") => options.borrow_rows = true,
+            Arg::Long("methods") => options.methods = true,
+            Arg::Long("read-by-name") => options.read_by_name = true,
+            Arg::Long("minify-sql") => options.minify_sql = true,
+            Arg::Long("strip-comments") => options.strip_comments = true,
+            Arg::Long("dedup-structs") => options.dedup_structs = true,
+            Arg::Long("cardinality-errors") => options.cardinality_errors = true,
+            Arg::Long("tracing") => options.tracing = true,
+            Arg::Long("expose-sql") => options.expose_sql = true,
+            Arg::Long("strict-null") => options.null_policy = Some(NullPolicy::Strict),
+            Arg::Long("lenient-null") => options.null_policy = Some(NullPolicy::Lenient),
+            Arg::Long("ignore-template-braces") => options.template_braces = true,
+            Arg::Long("only-tagged") => driver.only_tagged = true,
+            Arg::Long("show-spans") => options.show_spans = true,
+            Arg::Long("print-deps") => driver.print_deps = true,
             Arg::Long("version") => {
                 is_help = false;
                 is_version = true;
@@ -193,7 +436,14 @@ pub fn parse(argv: Vec<String>) -> Result<Cmd, String> {
         return Err("No input files specified.".into());
     }
 
-    Ok(Cmd::Generate { target, fnames })
+    Ok(Cmd::Generate {
+        target,
+        fnames,
+        schema,
+        error_format,
+        options,
+        driver,
+    })
 }
 
 /// Print usage/help info, for `--help`.
@@ -213,7 +463,9 @@ pub fn print_version() {
 
 #[cfg(test)]
 mod test {
-    use super::{parse, Cmd};
+    use super::{parse, Cmd, DriverOptions, ErrorFormat};
+    use crate::ast::Dialect;
+    use crate::target::{BeginMode, NullPolicy, Options, Visibility};
 
     fn parse_slice(args: &[&'static str]) -> Result<Cmd, String> {
         let argv = args.iter().map(|&s| s.into()).collect();
@@ -249,6 +501,10 @@ mod test {
         let expected = Ok(Cmd::Generate {
             target: "foo".into(),
             fnames: vec!["bar".into(), "baz".into()],
+            schema: None,
+            error_format: ErrorFormat::Human,
+            options: Options::default(),
+            driver: DriverOptions::default(),
         });
         assert_eq!(parse_slice(&["squiller", "-tfoo", "bar", "baz"]), expected);
         assert_eq!(
@@ -265,11 +521,51 @@ mod test {
         );
     }
 
+    #[test]
+    fn parse_parses_subcommands() {
+        assert_eq!(parse_slice(&["squiller", "targets"]), Ok(Cmd::TargetHelp));
+        assert_eq!(
+            parse_slice(&["squiller", "generate", "-t", "debug", "x.sql"]),
+            Ok(Cmd::Generate {
+                target: "debug".into(),
+                fnames: vec!["x.sql".into()],
+                schema: None,
+                error_format: ErrorFormat::Human,
+                options: Options::default(),
+                driver: DriverOptions::default(),
+            }),
+        );
+        assert_eq!(
+            parse_slice(&["squiller", "generate", "--help"]),
+            Ok(Cmd::Help),
+        );
+        // Only the first argument can be a subcommand, elsewhere it is a file.
+        assert_eq!(
+            parse_slice(&["squiller", "-t", "debug", "generate"]),
+            Ok(Cmd::Generate {
+                target: "debug".into(),
+                fnames: vec!["generate".into()],
+                schema: None,
+                error_format: ErrorFormat::Human,
+                options: Options::default(),
+                driver: DriverOptions::default(),
+            }),
+        );
+        assert_eq!(
+            parse_slice(&["squiller", "targets", "x.sql"]),
+            Err("Unexpected argument 'x.sql' after 'targets'.".into()),
+        );
+    }
+
     #[test]
     fn parse_handles_raw_args() {
         let expected = Ok(Cmd::Generate {
             target: "foo".into(),
             fnames: vec!["--bar".into(), "--".into(), "-t".into()],
+            schema: None,
+            error_format: ErrorFormat::Human,
+            options: Options::default(),
+            driver: DriverOptions::default(),
         });
         assert_eq!(
             parse_slice(&["squiller", "-tfoo", "--", "--bar", "--", "-t"]),
@@ -282,10 +578,532 @@ mod test {
         let expected = Ok(Cmd::Generate {
             target: "foo".into(),
             fnames: vec!["-".into()],
+            schema: None,
+            error_format: ErrorFormat::Human,
+            options: Options::default(),
+            driver: DriverOptions::default(),
         });
         assert_eq!(parse_slice(&["squiller", "-tfoo", "-"]), expected,);
     }
 
+    #[test]
+    fn parse_parses_no_cache() {
+        let expected = Ok(Cmd::Generate {
+            target: "foo".into(),
+            fnames: vec!["bar".into()],
+            schema: None,
+            error_format: ErrorFormat::Human,
+            options: Options {
+                cache_statements: false,
+                ..Options::default()
+            },
+            driver: DriverOptions::default(),
+        });
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--no-cache", "bar"]),
+            expected
+        );
+    }
+
+    #[test]
+    fn parse_parses_borrow_rows() {
+        let expected = Ok(Cmd::Generate {
+            target: "foo".into(),
+            fnames: vec!["bar".into()],
+            schema: None,
+            error_format: ErrorFormat::Human,
+            options: Options {
+                borrow_rows: true,
+                ..Options::default()
+            },
+            driver: DriverOptions::default(),
+        });
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--borrow-rows", "bar"]),
+            expected
+        );
+    }
+
+    #[test]
+    fn parse_parses_visibility() {
+        let expected = |visibility| {
+            Ok(Cmd::Generate {
+                target: "foo".into(),
+                fnames: vec!["bar".into()],
+                schema: None,
+                error_format: ErrorFormat::Human,
+                options: Options {
+                    visibility,
+                    ..Options::default()
+                },
+                driver: DriverOptions::default(),
+            })
+        };
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--visibility=pub(crate)", "bar"]),
+            expected(Visibility::Crate),
+        );
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--visibility", "pub", "bar"]),
+            expected(Visibility::Public),
+        );
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--visibility=", "bar"]),
+            expected(Visibility::Private),
+        );
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--visibility=pub(super)", "bar"]),
+            Err("Invalid visibility 'pub(super)', expected 'pub', 'pub(crate)', or ''.".into()),
+        );
+    }
+
+    #[test]
+    fn parse_parses_methods() {
+        let expected = Ok(Cmd::Generate {
+            target: "foo".into(),
+            fnames: vec!["bar".into()],
+            schema: None,
+            error_format: ErrorFormat::Human,
+            options: Options {
+                methods: true,
+                ..Options::default()
+            },
+            driver: DriverOptions::default(),
+        });
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--methods", "bar"]),
+            expected
+        );
+    }
+
+    #[test]
+    fn parse_parses_read_by_name() {
+        let expected = Ok(Cmd::Generate {
+            target: "foo".into(),
+            fnames: vec!["bar".into()],
+            schema: None,
+            error_format: ErrorFormat::Human,
+            options: Options {
+                read_by_name: true,
+                ..Options::default()
+            },
+            driver: DriverOptions::default(),
+        });
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--read-by-name", "bar"]),
+            expected
+        );
+    }
+
+    #[test]
+    fn parse_parses_cardinality_errors() {
+        let expected = Ok(Cmd::Generate {
+            target: "foo".into(),
+            fnames: vec!["bar".into()],
+            schema: None,
+            error_format: ErrorFormat::Human,
+            options: Options {
+                cardinality_errors: true,
+                ..Options::default()
+            },
+            driver: DriverOptions::default(),
+        });
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--cardinality-errors", "bar"]),
+            expected
+        );
+    }
+
+    #[test]
+    fn parse_parses_tracing() {
+        let expected = Ok(Cmd::Generate {
+            target: "foo".into(),
+            fnames: vec!["bar".into()],
+            schema: None,
+            error_format: ErrorFormat::Human,
+            options: Options {
+                tracing: true,
+                ..Options::default()
+            },
+            driver: DriverOptions::default(),
+        });
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--tracing", "bar"]),
+            expected
+        );
+    }
+
+    #[test]
+    fn parse_parses_expose_sql() {
+        let expected = Ok(Cmd::Generate {
+            target: "foo".into(),
+            fnames: vec!["bar".into()],
+            schema: None,
+            error_format: ErrorFormat::Human,
+            options: Options {
+                expose_sql: true,
+                ..Options::default()
+            },
+            driver: DriverOptions::default(),
+        });
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--expose-sql", "bar"]),
+            expected
+        );
+    }
+
+    #[test]
+    fn parse_parses_null_policy() {
+        let expected = |null_policy| {
+            Ok(Cmd::Generate {
+                target: "foo".into(),
+                fnames: vec!["bar".into()],
+                schema: None,
+                error_format: ErrorFormat::Human,
+                options: Options {
+                    null_policy: Some(null_policy),
+                    ..Options::default()
+                },
+                driver: DriverOptions::default(),
+            })
+        };
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--strict-null", "bar"]),
+            expected(NullPolicy::Strict)
+        );
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--lenient-null", "bar"]),
+            expected(NullPolicy::Lenient)
+        );
+    }
+
+    #[test]
+    fn parse_parses_quiet() {
+        let expected = Ok(Cmd::Generate {
+            target: "foo".into(),
+            fnames: vec!["bar".into()],
+            schema: None,
+            error_format: ErrorFormat::Human,
+            options: Options {
+                version_header: false,
+                ..Options::default()
+            },
+            driver: DriverOptions::default(),
+        });
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--quiet", "bar"]),
+            expected
+        );
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--no-header", "bar"]),
+            expected
+        );
+    }
+
+    #[test]
+    fn parse_parses_tags() {
+        let expected = Ok(Cmd::Generate {
+            target: "foo".into(),
+            fnames: vec!["bar".into()],
+            schema: None,
+            error_format: ErrorFormat::Human,
+            options: Options::default(),
+            driver: DriverOptions {
+                tags: vec!["admin".into(), "batch".into(), "web".into()],
+                only_tagged: true,
+                ..DriverOptions::default()
+            },
+        });
+        assert_eq!(
+            parse_slice(&[
+                "squiller",
+                "-tfoo",
+                "--tags=admin,batch",
+                "--tags",
+                "web",
+                "--only-tagged",
+                "bar"
+            ]),
+            expected
+        );
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--tags"]),
+            Err("Expected tags after '--tags'.".into()),
+        );
+    }
+
+    #[test]
+    fn parse_parses_print_deps() {
+        let expected = Ok(Cmd::Generate {
+            target: "foo".into(),
+            fnames: vec!["bar".into()],
+            schema: None,
+            error_format: ErrorFormat::Human,
+            options: Options::default(),
+            driver: DriverOptions {
+                print_deps: true,
+                ..DriverOptions::default()
+            },
+        });
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--print-deps", "bar"]),
+            expected
+        );
+    }
+
+    #[test]
+    fn parse_parses_cache_dir() {
+        let expected = Ok(Cmd::Generate {
+            target: "foo".into(),
+            fnames: vec!["bar".into()],
+            schema: None,
+            error_format: ErrorFormat::Human,
+            options: Options::default(),
+            driver: DriverOptions {
+                cache_dir: Some("target/squiller".into()),
+                ..DriverOptions::default()
+            },
+        });
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--cache-dir", "target/squiller", "bar"]),
+            expected
+        );
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--cache-dir"]),
+            Err("Expected directory after '--cache-dir'.".into()),
+        );
+    }
+
+    #[test]
+    fn parse_parses_dialect() {
+        let expected = Ok(Cmd::Generate {
+            target: "foo".into(),
+            fnames: vec!["bar".into()],
+            schema: None,
+            error_format: ErrorFormat::Human,
+            options: Options {
+                dialect: Dialect::Postgres,
+                ..Options::default()
+            },
+            driver: DriverOptions::default(),
+        });
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--dialect", "postgres", "bar"]),
+            expected
+        );
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--dialect", "mysql", "bar"]),
+            Err("Invalid dialect 'mysql', expected 'generic', 'postgres', or 'sqlite'.".into())
+        );
+    }
+
+    #[test]
+    fn parse_parses_schema() {
+        let expected = Ok(Cmd::Generate {
+            target: "foo".into(),
+            fnames: vec!["bar".into()],
+            schema: Some("schema.sql".into()),
+            error_format: ErrorFormat::Human,
+            options: Options::default(),
+            driver: DriverOptions::default(),
+        });
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--schema", "schema.sql", "bar"]),
+            expected
+        );
+    }
+
+    #[test]
+    fn parse_parses_error_format() {
+        let expected = Ok(Cmd::Generate {
+            target: "foo".into(),
+            fnames: vec!["bar".into()],
+            schema: None,
+            error_format: ErrorFormat::Json,
+            options: Options::default(),
+            driver: DriverOptions::default(),
+        });
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--error-format=json", "bar"]),
+            expected
+        );
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--error-format=xml", "bar"]),
+            Err("Invalid error format 'xml', expected 'human' or 'json'.".into()),
+        );
+    }
+
+    #[test]
+    fn parse_parses_cfg_feature() {
+        let expected = Ok(Cmd::Generate {
+            target: "foo".into(),
+            fnames: vec!["bar".into()],
+            schema: None,
+            error_format: ErrorFormat::Human,
+            options: Options {
+                cfg_feature: Some("sqlite".into()),
+                ..Options::default()
+            },
+            driver: DriverOptions::default(),
+        });
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--cfg-feature=sqlite", "bar"]),
+            expected
+        );
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--cfg-feature"]),
+            Err("Expected feature name after '--cfg-feature'.".into()),
+        );
+    }
+
+    #[test]
+    fn parse_parses_type_prefix() {
+        let expected = Ok(Cmd::Generate {
+            target: "foo".into(),
+            fnames: vec!["bar".into()],
+            schema: None,
+            error_format: ErrorFormat::Human,
+            options: Options {
+                type_prefix: "Db".into(),
+                ..Options::default()
+            },
+            driver: DriverOptions::default(),
+        });
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--type-prefix", "Db", "bar"]),
+            expected
+        );
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--type-prefix"]),
+            Err("Expected prefix after '--type-prefix'.".into()),
+        );
+    }
+
+    #[test]
+    fn parse_parses_max_line_width() {
+        let expected = Ok(Cmd::Generate {
+            target: "foo".into(),
+            fnames: vec!["bar".into()],
+            schema: None,
+            error_format: ErrorFormat::Human,
+            options: Options {
+                max_line_width: 80,
+                ..Options::default()
+            },
+            driver: DriverOptions::default(),
+        });
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--max-line-width", "80", "bar"]),
+            expected
+        );
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--max-line-width", "wide"]),
+            Err("Invalid width 'wide', expected a number.".into()),
+        );
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--max-line-width"]),
+            Err("Expected width after '--max-line-width'.".into()),
+        );
+    }
+
+    #[test]
+    fn parse_parses_annotations() {
+        let expected = Ok(Cmd::Generate {
+            target: "foo".into(),
+            fnames: vec!["bar".into()],
+            schema: None,
+            error_format: ErrorFormat::Human,
+            options: Options::default(),
+            driver: DriverOptions {
+                annotations: Some("queries.toml".into()),
+                ..DriverOptions::default()
+            },
+        });
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--annotations", "queries.toml", "bar"]),
+            expected
+        );
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--annotations"]),
+            Err("Expected annotations file after '--annotations'.".into()),
+        );
+    }
+
+    #[test]
+    fn parse_parses_python_class() {
+        let expected = Ok(Cmd::Generate {
+            target: "foo".into(),
+            fnames: vec!["bar".into()],
+            schema: None,
+            error_format: ErrorFormat::Human,
+            options: Options {
+                python_class: Some("Queries".into()),
+                ..Options::default()
+            },
+            driver: DriverOptions::default(),
+        });
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--python-class", "Queries", "bar"]),
+            expected
+        );
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--python-class"]),
+            Err("Expected class name after '--python-class'.".into()),
+        );
+    }
+
+    #[test]
+    fn parse_parses_header() {
+        let expected = Ok(Cmd::Generate {
+            target: "foo".into(),
+            fnames: vec!["bar".into()],
+            schema: None,
+            error_format: ErrorFormat::Human,
+            options: Options {
+                header: Some("// Copyright".into()),
+                ..Options::default()
+            },
+            driver: DriverOptions::default(),
+        });
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--header", "// Copyright", "bar"]),
+            expected
+        );
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--header"]),
+            Err("Expected header text after '--header'.".into()),
+        );
+    }
+
+    #[test]
+    fn parse_parses_begin_mode() {
+        let expected = |begin_mode| {
+            Ok(Cmd::Generate {
+                target: "foo".into(),
+                fnames: vec!["bar".into()],
+                schema: None,
+                error_format: ErrorFormat::Human,
+                options: Options {
+                    begin_mode: Some(begin_mode),
+                    ..Options::default()
+                },
+                driver: DriverOptions::default(),
+            })
+        };
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--begin-mode=immediate", "bar"]),
+            expected(BeginMode::Immediate),
+        );
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--begin-mode", "exclusive", "bar"]),
+            expected(BeginMode::Exclusive),
+        );
+        assert_eq!(
+            parse_slice(&["squiller", "-tfoo", "--begin-mode=exclusively", "bar"]),
+            Err("Invalid begin mode 'exclusively', expected 'deferred', 'immediate', or 'exclusive'.".into()),
+        );
+    }
+
     #[test]
     fn parse_returns_error_on_misuse() {
         assert_eq!(
@@ -56,6 +56,8 @@ impl Block {
         let indent_str = &thirty_two_spaces[..indent as usize];
 
         match self {
+            // Do not indent empty lines, that would leave trailing whitespace.
+            Block::Line(line) if line.is_empty() => writeln!(out)?,
             Block::Line(line) => writeln!(out, "{}{}", indent_str, line)?,
             Block::Indent(block) => block.format_internal(out, indent + 4)?,
             Block::Stack(blocks) => {
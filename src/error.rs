@@ -29,6 +29,113 @@ pub trait Error: std::fmt::Debug {
 
     /// Optionally, a hint on how to fix the problem.
     fn hint(&self) -> Option<&str>;
+
+    /// Whether this is an error, or a warning that does not stop processing.
+    fn severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    /// Convert the error into a form that is suitable for editor integration.
+    fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic {
+            span: self.span(),
+            severity: self.severity(),
+            message: self.message().to_string(),
+            note: self.note().map(|(note, span)| (note.to_string(), span)),
+            hint: self.hint().map(|hint| hint.to_string()),
+        }
+    }
+}
+
+/// Whether a diagnostic stops processing.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A machine-readable diagnostic, for editor integration.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    /// Byte range in the input that the diagnostic is about.
+    pub span: Span,
+    pub severity: Severity,
+    pub message: String,
+    pub note: Option<(String, Span)>,
+    pub hint: Option<String>,
+}
+
+/// Format the string as a Json string literal, including quotes.
+fn json_string(s: &str) -> String {
+    use std::fmt::Write;
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            // Note, the unwrap is safe because writing to a string does not fail.
+            ch if (ch as u32) < 0x20 => write!(&mut result, "\\u{:04x}", ch as u32).unwrap(),
+            ch => result.push(ch),
+        }
+    }
+    result.push('"');
+    result
+}
+
+impl Diagnostic {
+    /// Format the diagnostic as a single line of Json.
+    pub fn to_json(&self, fname: &Path) -> String {
+        let note = match &self.note {
+            None => "null".to_string(),
+            Some((note, span)) => format!(
+                r#"{{"message":{},"start":{},"end":{}}}"#,
+                json_string(note),
+                span.start,
+                span.end,
+            ),
+        };
+        let hint = match &self.hint {
+            None => "null".to_string(),
+            Some(hint) => json_string(hint),
+        };
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        format!(
+            r#"{{"file":{},"start":{},"end":{},"severity":{},"message":{},"note":{},"hint":{}}}"#,
+            json_string(&fname.to_string_lossy()),
+            self.span.start,
+            self.span.end,
+            json_string(severity),
+            json_string(&self.message),
+            note,
+            hint,
+        )
+    }
+}
+
+/// A diagnostic can be printed like any other error, after adjusting its spans.
+impl Error for Diagnostic {
+    fn span(&self) -> Span {
+        self.span
+    }
+    fn message(&self) -> &str {
+        &self.message
+    }
+    fn note(&self) -> Option<(&str, Span)> {
+        self.note.as_ref().map(|(note, span)| (&note[..], *span))
+    }
+    fn hint(&self) -> Option<&str> {
+        self.hint.as_deref()
+    }
+    fn severity(&self) -> Severity {
+        self.severity
+    }
 }
 
 impl dyn Error {
@@ -37,9 +144,13 @@ impl dyn Error {
         let bold_yellow = "\x1b[33;1m";
         let reset = "\x1b[0m";
 
-        let highlight = highlight_span_in_line(fname, input, self.span(), bold_red);
+        let (color, label) = match self.severity() {
+            Severity::Warning => (bold_yellow, "Warning"),
+            Severity::Error => (bold_red, "Error"),
+        };
+        let highlight = highlight_span_in_line(fname, input, self.span(), color);
         eprint!("{}", highlight);
-        eprintln!("{}Error:{} {}", bold_red, reset, self.message());
+        eprintln!("{}{}:{} {}", color, label, reset, self.message());
 
         if let Some((note, note_span)) = self.note() {
             let highlight = highlight_span_in_line(fname, input, note_span, bold_yellow);
@@ -58,10 +169,25 @@ fn highlight_span_in_line(fname: &Path, input: &[u8], span: Span, highlight_ansi
     use std::fmt::Write;
     use unicode_width::UnicodeWidthStr;
 
+    // An error at the end of the input has an empty span there. When the input
+    // ends in a newline, that would point at a line past the last line, so we
+    // point at the end of the last line instead. Spans should not extend past
+    // the end of the input, but if they do, clamp them, rather than panic.
+    let span = match input.last() {
+        Some(b'\n') if span.is_empty() && span.start >= input.len() => Span {
+            start: input.len() - 1,
+            end: input.len() - 1,
+        },
+        _ => Span {
+            start: cmp::min(span.start, input.len()),
+            end: cmp::min(span.end, input.len()),
+        },
+    };
+
     // Locate the line that contains the error.
     let mut line = 1;
     let mut line_start = 0;
-    let mut line_end = 0;
+    let mut line_end = input.len();
     for (&c, i) in input.iter().zip(0..) {
         if i == span.start {
             break;
@@ -77,20 +203,30 @@ fn highlight_span_in_line(fname: &Path, input: &[u8], span: Span, highlight_ansi
             break;
         }
     }
-    if line_end <= line_start {
-        line_end = input.len();
-    }
 
     // Try as best as we can to report the error. However, if the parse failed
-    // because the input was invalid UTF-8, there is little we can do.
-    let line_content = String::from_utf8_lossy(&input[line_start..line_end]);
+    // because the input was invalid UTF-8, there is little we can do. We slice
+    // the input bytes rather than the lossy string, because the replacement
+    // characters in it shift the byte offsets.
+    let line_span = Span {
+        start: line_start,
+        end: line_end,
+    };
+    let line_content = String::from_utf8_lossy(line_span.resolve_bytes(input));
 
     // The length of the mark can be longer than the line, for example when
     // token to mark was a multiline string literal. In that case, highlight
     // only up to the newline, don't extend the tildes too far.
-    let indent_content = &line_content[..span.start - line_start];
-    let as_of_error = &line_content[span.start - line_start..];
-    let error_content = &as_of_error[..cmp::min(span.len(), as_of_error.len())];
+    let indent_span = Span {
+        start: line_start,
+        end: span.start,
+    };
+    let error_span = Span {
+        start: span.start,
+        end: cmp::min(span.end, line_end),
+    };
+    let indent_content = String::from_utf8_lossy(indent_span.resolve_bytes(input));
+    let error_content = String::from_utf8_lossy(error_span.resolve_bytes(input));
 
     // The width of the error is not necessarily the number of bytes,
     // measure the Unicode width of the span to underline.
@@ -137,6 +273,7 @@ pub struct ParseError {
     pub span: Span,
     pub message: &'static str,
     pub note: Option<(&'static str, Span)>,
+    pub hint: Option<&'static str>,
 }
 
 impl From<ParseError> for Box<dyn Error> {
@@ -156,7 +293,7 @@ impl Error for ParseError {
         self.note
     }
     fn hint(&self) -> Option<&str> {
-        None
+        self.hint
     }
 }
 
@@ -232,11 +369,131 @@ impl Error for TypeError {
 /// A typechecking result, either the typed value, or an error.
 pub type TResult<T> = std::result::Result<T, TypeError>;
 
+/// A problem in the input that does not prevent generating code.
+#[derive(Debug)]
+pub struct Warning {
+    pub span: Span,
+    pub message: &'static str,
+    pub hint: Option<&'static str>,
+}
+
+impl Error for Warning {
+    fn span(&self) -> Span {
+        self.span
+    }
+    fn message(&self) -> &str {
+        self.message
+    }
+    fn note(&self) -> Option<(&str, Span)> {
+        None
+    }
+    fn hint(&self) -> Option<&str> {
+        self.hint
+    }
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use std::path::PathBuf;
 
+    #[test]
+    fn error_at_end_of_input_highlights_end_of_last_line() {
+        let fname: PathBuf = "x.sql".into();
+        let input = b"-- @begin f()\nSELECT 1;\n";
+        let span = Span {
+            start: input.len(),
+            end: input.len(),
+        };
+        assert_eq!(
+            highlight_span_in_line(&fname, input, span, ""),
+            " --> x.sql:2:9\n  |\n2 | SELECT 1;\n  |          ^\x1b[0m\n",
+        );
+
+        // Without trailing newline, the end is on the last line already.
+        let input = b"-- @begin f()\nSELECT 1;";
+        let span = Span {
+            start: input.len(),
+            end: input.len(),
+        };
+        assert_eq!(
+            highlight_span_in_line(&fname, input, span, ""),
+            " --> x.sql:2:9\n  |\n2 | SELECT 1;\n  |          ^\x1b[0m\n",
+        );
+    }
+
+    #[test]
+    fn error_on_empty_line_highlights_only_that_line() {
+        let fname: PathBuf = "x.sql".into();
+        let input = b"a\n\nb\n";
+        let span = Span { start: 2, end: 2 };
+        assert_eq!(
+            highlight_span_in_line(&fname, input, span, ""),
+            " --> x.sql:2:0\n  |\n2 | \n  | ^\x1b[0m\n",
+        );
+    }
+
+    #[test]
+    fn type_error_converts_to_diagnostic() {
+        let err = TypeError {
+            span: Span { start: 4, end: 8 },
+            message: "Redefinition of argument.",
+            note: Some(("First defined here.".to_string(), Span { start: 0, end: 2 })),
+            hint: Some("Rename one of the \"arguments\".".to_string()),
+        };
+        let diagnostic = err.to_diagnostic();
+        let expected = Diagnostic {
+            span: Span { start: 4, end: 8 },
+            severity: Severity::Error,
+            message: "Redefinition of argument.".to_string(),
+            note: Some(("First defined here.".to_string(), Span { start: 0, end: 2 })),
+            hint: Some("Rename one of the \"arguments\".".to_string()),
+        };
+        assert_eq!(diagnostic, expected);
+
+        let fname: PathBuf = "x.sql".into();
+        assert_eq!(
+            diagnostic.to_json(&fname),
+            r#"{"file":"x.sql","start":4,"end":8,"severity":"error","message":"Redefinition of argument.","note":{"message":"First defined here.","start":0,"end":2},"hint":"Rename one of the \"arguments\"."}"#,
+        );
+
+        let err = TypeError::new(Span { start: 1, end: 2 }, "Undefined query parameter.");
+        assert_eq!(
+            err.to_diagnostic().to_json(&fname),
+            r#"{"file":"x.sql","start":1,"end":2,"severity":"error","message":"Undefined query parameter.","note":null,"hint":null}"#,
+        );
+    }
+
+    #[test]
+    fn warning_converts_to_diagnostic_with_warning_severity() {
+        let warning = Warning {
+            span: Span { start: 1, end: 2 },
+            message: "Unknown argument.",
+            hint: None,
+        };
+        let fname: PathBuf = "x.sql".into();
+        assert_eq!(
+            warning.to_diagnostic().to_json(&fname),
+            r#"{"file":"x.sql","start":1,"end":2,"severity":"warning","message":"Unknown argument.","note":null,"hint":null}"#,
+        );
+    }
+
+    #[test]
+    fn highlight_span_handles_invalid_utf8() {
+        let fname: PathBuf = "x.sql".into();
+        // The invalid byte becomes a three-byte replacement character in the
+        // lossy string, which should not shift the mark.
+        let input = b"a\xff bc";
+        let span = Span { start: 3, end: 5 };
+        let result = highlight_span_in_line(&fname, input, span, "");
+        let lines: Vec<_> = result.lines().collect();
+        assert_eq!(lines[2], "1 | a\u{fffd} bc");
+        assert_eq!(lines[3], "  |    ^~\x1b[0m");
+    }
+
     #[test]
     fn highlight_span_handles_eof_span() {
         let fname: PathBuf = "x.sql".into();
@@ -23,9 +23,14 @@ pub enum Token {
     Ident,
     LParen,
     RParen,
+    /// `[`, opens a slice type, as in `[User]`.
+    LBracket,
+    RBracket,
     Colon,
     Semicolon,
     Comma,
+    /// `=`, between an enum variant and its code.
+    Equals,
     Minus,
     /// `?`
     Question,
@@ -128,6 +133,14 @@ impl<'a> Lexer<'a> {
             self.push(Token::RParen, 1);
             return (self.start + 1, State::Base);
         }
+        if input[0] == b'[' {
+            self.push(Token::LBracket, 1);
+            return (self.start + 1, State::Base);
+        }
+        if input[0] == b']' {
+            self.push(Token::RBracket, 1);
+            return (self.start + 1, State::Base);
+        }
         if input[0] == b':' {
             self.push(Token::Colon, 1);
             return (self.start + 1, State::Base);
@@ -144,6 +157,10 @@ impl<'a> Lexer<'a> {
             self.push(Token::Comma, 1);
             return (self.start + 1, State::Base);
         }
+        if input[0] == b'=' {
+            self.push(Token::Equals, 1);
+            return (self.start + 1, State::Base);
+        }
         if input.starts_with(b"->?") {
             self.push(Token::ArrowOpt, 3);
             return (self.start + 3, State::Base);
@@ -203,7 +220,7 @@ impl<'a> Lexer<'a> {
     fn lex_in_ident(&mut self) -> (usize, State) {
         // The following characters are or may start punctuation of their own.
         // Anything else aside from whitespace can be part of an "identifier".
-        let end_chars = b",;:?-()";
+        let end_chars = b",;:?-()[]=";
         self.lex_skip_then_while(
             0,
             |ch| !ch.is_ascii_whitespace() && !end_chars.contains(&ch),
@@ -338,6 +355,24 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_lex_slice_argument() {
+        test_tokens(
+            "@query insert_users(users:[User])",
+            &[
+                (Token::Marker, "@query"),
+                (Token::Ident, "insert_users"),
+                (Token::LParen, "("),
+                (Token::Ident, "users"),
+                (Token::Colon, ":"),
+                (Token::LBracket, "["),
+                (Token::Ident, "User"),
+                (Token::RBracket, "]"),
+                (Token::RParen, ")"),
+            ],
+        );
+    }
+
     #[test]
     fn lex_bogus_input_with_at() {
         // The fuzzer found this input to cause OOM, this is a regression test.
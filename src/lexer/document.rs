@@ -57,6 +57,8 @@ pub enum Token {
     Semicolon,
     /// Punctuation that is not any of the previous punctuation tokens.
     Punct,
+    /// A `{{ ... }}` template expression, when template braces are enabled.
+    Template,
 }
 
 pub struct Lexer<'a> {
@@ -64,6 +66,7 @@ pub struct Lexer<'a> {
     start: usize,
     state: State,
     tokens: Vec<(Token, Span)>,
+    template_braces: bool,
 }
 
 impl<'a> Lexer<'a> {
@@ -73,6 +76,18 @@ impl<'a> Lexer<'a> {
             start: 0,
             state: State::Base,
             tokens: Vec::new(),
+            template_braces: false,
+        }
+    }
+
+    /// Lex `{{ ... }}` as a single opaque token, for templated SQL.
+    ///
+    /// The contents of a template expression are not SQL, so they should not
+    /// contribute parameters, brackets, or semicolons.
+    pub fn with_template_braces(self, template_braces: bool) -> Lexer<'a> {
+        Lexer {
+            template_braces,
+            ..self
         }
     }
 
@@ -85,6 +100,9 @@ impl<'a> Lexer<'a> {
     }
 
     /// Build a parse error at the current cursor location.
+    ///
+    /// The error spans the bytes from the cursor onward for which `include`
+    /// returns true.
     fn error_while<F: FnMut(u8) -> bool, T>(
         &self,
         mut include: F,
@@ -93,10 +111,10 @@ impl<'a> Lexer<'a> {
         let input = &self.input.as_bytes()[self.start..];
         let mut err_end = self.start;
         for ch in input {
-            if include(*ch) {
-                err_end += 1;
-                continue;
+            if !include(*ch) {
+                break;
             }
+            err_end += 1;
         }
         let error = ParseError {
             span: Span {
@@ -105,6 +123,7 @@ impl<'a> Lexer<'a> {
             },
             message: message,
             note: None,
+            hint: None,
         };
         Err(error)
     }
@@ -166,6 +185,9 @@ impl<'a> Lexer<'a> {
         if input[0].is_ascii_whitespace() {
             return Ok((self.start, State::InSpace));
         }
+        if self.template_braces && input.starts_with(b"{{") {
+            return self.lex_template();
+        }
         if input.len() > 1 && input[0] == b':' && input[1].is_ascii_alphabetic() {
             return Ok((self.start, State::InParam));
         }
@@ -220,6 +242,7 @@ impl<'a> Lexer<'a> {
             },
             message: "Unexpected end of input, string literal is not closed.",
             note: None,
+            hint: None,
         };
         Err(error)
     }
@@ -260,7 +283,20 @@ impl<'a> Lexer<'a> {
         // The `--` is its own token.
         self.push(Token::CommentStart, 2);
         self.start += 2;
-        self.lex_while(|ch| ch != b'\n', Token::CommentInner)
+
+        // Comments can be long, so rather than inspecting every byte in the
+        // state machine, we search for the end in one pass.
+        match self.input[self.start..].find('\n') {
+            Some(len) => {
+                self.push(Token::CommentInner, len);
+                (self.start + len, State::Base)
+            }
+            None => {
+                let len = self.input.len() - self.start;
+                self.push(Token::CommentInner, len);
+                (self.input.len(), State::Done)
+            }
+        }
     }
 
     fn lex_in_inline_comment(&mut self) -> PResult<(usize, State)> {
@@ -268,15 +304,11 @@ impl<'a> Lexer<'a> {
         self.push(Token::CommentStart, 2);
         self.start += 2;
 
-        let input = &self.input.as_bytes()[self.start..];
-
-        for len in 0..input.len() {
-            if input[len..].starts_with(b"*/") {
-                self.push(Token::CommentInner, len);
-                self.start += len;
-                self.push(Token::CommentEnd, 2);
-                return Ok((self.start + 2, State::Base));
-            }
+        if let Some(len) = self.input[self.start..].find("*/") {
+            self.push(Token::CommentInner, len);
+            self.start += len;
+            self.push(Token::CommentEnd, 2);
+            return Ok((self.start + 2, State::Base));
         }
 
         // If we did not return by now, then the comment is unclosed. Reset the
@@ -285,6 +317,17 @@ impl<'a> Lexer<'a> {
         self.error_while(|_ch| true, "Unclosed /* */ comment.")
     }
 
+    fn lex_template(&mut self) -> PResult<(usize, State)> {
+        match self.input[self.start + 2..].find("}}") {
+            Some(inner_len) => {
+                let len = inner_len + 4;
+                self.push(Token::Template, len);
+                Ok((self.start + len, State::Base))
+            }
+            None => self.error_while(|_ch| true, "Unclosed {{ }} template expression."),
+        }
+    }
+
     fn lex_in_param(&mut self) -> (usize, State) {
         self.lex_skip_then_while(1, is_ascii_identifier, Token::Param)
     }
@@ -315,10 +358,28 @@ impl<'a> Lexer<'a> {
             // punctuation ends, or we do hit one of those.
             _ => {
                 let end_punct_chars = b"'\"(){}[];";
-                return self.lex_while(
-                    |ch| ch.is_ascii_punctuation() && !end_punct_chars.contains(&ch),
-                    Token::Punct,
-                );
+                let input = self.input.as_bytes();
+                let mut end = self.start + 1;
+                while end < input.len() {
+                    let ch = input[end];
+                    if !ch.is_ascii_punctuation() || end_punct_chars.contains(&ch) {
+                        break;
+                    }
+                    // A colon followed by a letter starts a parameter, as in
+                    // `id=:id`, unless it is the second colon of a Postgres
+                    // cast, as in `x::int4`.
+                    let is_param = ch == b':'
+                        && input[end - 1] != b':'
+                        && input
+                            .get(end + 1)
+                            .map_or(false, |c| c.is_ascii_alphabetic());
+                    if is_param {
+                        break;
+                    }
+                    end += 1;
+                }
+                self.push(Token::Punct, end - self.start);
+                return (end, State::Base);
             }
         };
         self.push(token, 1);
@@ -390,6 +451,68 @@ mod test {
         );
     }
 
+    #[test]
+    fn it_lexes_casts_as_punctuation() {
+        test_tokens(
+            "x::time, '12:30'::int4",
+            &[
+                (Token::Ident, "x"),
+                (Token::Punct, "::"),
+                (Token::Ident, "time"),
+                (Token::Punct, ","),
+                (Token::Space, " "),
+                (Token::SingleQuoted, "'12:30'"),
+                (Token::Punct, "::"),
+                (Token::Ident, "int4"),
+            ],
+        );
+        test_tokens(
+            ":id::int4",
+            &[
+                (Token::Param, ":id"),
+                (Token::Punct, "::"),
+                (Token::Ident, "int4"),
+            ],
+        );
+    }
+
+    #[test]
+    fn it_lexes_param_after_punctuation() {
+        test_tokens(
+            "id=:id<>:x",
+            &[
+                (Token::Ident, "id"),
+                (Token::Punct, "="),
+                (Token::Param, ":id"),
+                (Token::Punct, "<>"),
+                (Token::Param, ":x"),
+            ],
+        );
+    }
+
+    #[test]
+    fn it_lexes_template_braces_when_enabled() {
+        let input = "{{ a:b; }}{x}";
+        let tokens = Lexer::new(input).with_template_braces(true).run().unwrap();
+        let tokens: Vec<_> = tokens.iter().map(|(t, s)| (*t, s.resolve(input))).collect();
+        assert_eq!(
+            tokens,
+            [
+                (Token::Template, "{{ a:b; }}"),
+                (Token::LBrace, "{"),
+                (Token::Ident, "x"),
+                (Token::RBrace, "}"),
+            ]
+        );
+
+        let error = Lexer::new("{{ x }")
+            .with_template_braces(true)
+            .run()
+            .err()
+            .unwrap();
+        assert_eq!(error.span, Span { start: 0, end: 6 });
+    }
+
     #[test]
     fn it_lexes_inline_comments() {
         let input = r#"
@@ -427,6 +550,43 @@ mod test {
         );
     }
 
+    #[test]
+    fn it_lexes_line_comment_at_end_of_input() {
+        let input = "SELECT -- comment";
+        test_tokens(
+            input,
+            &[
+                (Token::Ident, "SELECT"),
+                (Token::Space, " "),
+                (Token::CommentStart, "--"),
+                (Token::CommentInner, " comment"),
+            ],
+        );
+    }
+
+    #[test]
+    fn it_lexes_large_comments() {
+        // A comment full of almost-terminators, which is the worst case for a
+        // naive scan for `*/`.
+        let inner = "*".repeat(1 << 20);
+        let input = format!("/*{}*/ -- {}\nSELECT", inner, inner);
+        let tokens = Lexer::new(&input).run().unwrap();
+        let tokens: Vec<Token> = tokens.iter().map(|t| t.0).collect();
+        assert_eq!(
+            tokens,
+            [
+                Token::CommentStart,
+                Token::CommentInner,
+                Token::CommentEnd,
+                Token::Space,
+                Token::CommentStart,
+                Token::CommentInner,
+                Token::Space,
+                Token::Ident,
+            ]
+        );
+    }
+
     #[test]
     fn ascii_control_bytes_result_in_error() {
         let input = "\x01";
@@ -442,6 +602,11 @@ mod test {
         assert_eq!(error.span.resolve(input), "Ä");
         assert_eq!(error.span, Span { start: 0, end: 2 });
         assert!(error.message.contains("Non-ascii"));
+
+        // The error covers only the first sequence, not later ones.
+        let input = "Ä x Ö";
+        let error = Lexer::new(input).run().err().unwrap();
+        assert_eq!(error.span.resolve(input), "Ä");
     }
 
     #[test]
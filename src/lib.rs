@@ -22,14 +22,17 @@ pub mod parser {
     pub mod annotation;
     pub mod document;
 }
+pub mod schema;
+pub mod sidecar;
 pub mod target;
 pub mod typecheck;
 
-mod version;
+pub mod version;
 
-use ast::Document;
+use ast::{Dialect, Document};
 use lexer::document::Lexer;
 use parser::document::Parser;
+use schema::Schema;
 use std::path::Path;
 
 /// Check if a byte is part of an identifier.
@@ -50,6 +53,7 @@ fn str_from_utf8(input: &[u8]) -> error::PResult<&str> {
         },
         message: "This input is not valid UTF-8.",
         note: None,
+        hint: None,
     })
 }
 
@@ -69,16 +73,108 @@ impl Span {
         &input[self.start..self.end]
     }
 
+    /// Return the bytes from the input that this span spans.
+    ///
+    /// Unlike `resolve`, this works on input that is not valid UTF-8, and for
+    /// spans that do not start or end at a character boundary.
+    pub fn resolve_bytes<'a>(&self, input: &'a [u8]) -> &'a [u8] {
+        &input[self.start..self.end]
+    }
+
     pub fn len(&self) -> usize {
         self.end - self.start
     }
 
+    /// Whether the span is empty, such as the span of an error at the end of the input.
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Whether the byte offset lies inside the span.
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset < self.end
+    }
+
     pub fn trim_start(&self, n: usize) -> Span {
         Span {
             start: self.start + n,
             end: self.end,
         }
     }
+
+    /// Return the smallest span that covers both spans.
+    ///
+    /// The spans do not need to overlap, or be in order.
+    pub fn union(&self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+
+    /// Return the span from the end of this span to the start of `other`.
+    ///
+    /// The other span should not start before this one ends.
+    pub fn between(&self, other: Span) -> Span {
+        debug_assert!(self.end <= other.start, "Spans should be in order.");
+        Span {
+            start: self.end,
+            end: other.start,
+        }
+    }
+}
+
+/// Options that control how we lex and parse the input.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ParseOptions {
+    /// The SQL dialect of the input.
+    pub dialect: Dialect,
+
+    /// Whether to treat `{{ ... }}` as opaque template expressions.
+    pub template_braces: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> ParseOptions {
+        ParseOptions {
+            dialect: Dialect::Generic,
+            template_braces: false,
+        }
+    }
+}
+
+/// Run the lexer, parser, and typechecker on the input.
+fn parse_and_check(
+    input: &str,
+    schema: &Schema,
+    options: ParseOptions,
+) -> error::Result<(Document<Span>, Vec<error::Warning>)> {
+    let tokens = Lexer::new(input)
+        .with_template_braces(options.template_braces)
+        .run()?;
+    let mut parser = Parser::new(input, &tokens).with_dialect(options.dialect);
+    let doc = parser.parse_document()?;
+    let mut warnings = parser.into_warnings();
+    let doc = typecheck::check_document(input, schema, doc, &mut warnings)?;
+    Ok((doc, warnings))
+}
+
+/// Parse and typecheck a document from a string, without schema.
+///
+/// This is a convenience for using Squiller as a library, when there is no
+/// input file. Warnings are discarded. The spans in the document, and in the
+/// error if there is one, are byte offsets into `input`. There is no file name,
+/// so to print an error, pass a placeholder such as `<input>`.
+///
+/// ```
+/// let input = "-- @query get_name(id: i64) ->1 str\nselect name from users where id = :id;";
+/// let doc = squiller::parse_str(input).unwrap();
+/// let query = doc.iter_queries().next().unwrap();
+/// assert_eq!(query.annotation.name.resolve(input), "get_name");
+/// ```
+pub fn parse_str(input: &str) -> error::Result<Document<Span>> {
+    let (doc, _warnings) = parse_and_check(input, &Schema::default(), ParseOptions::default())?;
+    Ok(doc)
 }
 
 /// A parsed document, along with its source code and source file name.
@@ -86,6 +182,8 @@ pub struct NamedDocument<'a> {
     pub fname: &'a Path,
     pub input: &'a str,
     pub document: Document<Span>,
+    /// Problems in the input that did not prevent processing it.
+    pub warnings: Vec<error::Warning>,
 }
 
 impl<'a> NamedDocument<'a> {
@@ -97,17 +195,86 @@ impl<'a> NamedDocument<'a> {
     pub fn process_input(
         fname: &'a Path,
         input_bytes: &'a [u8],
+    ) -> error::Result<NamedDocument<'a>> {
+        NamedDocument::process_input_with_schema(fname, input_bytes, &Schema::default())
+    }
+
+    /// As `process_input`, but use the schema to infer types of parameters.
+    pub fn process_input_with_schema(
+        fname: &'a Path,
+        input_bytes: &'a [u8],
+        schema: &Schema,
+    ) -> error::Result<NamedDocument<'a>> {
+        NamedDocument::process_input_with_options(
+            fname,
+            input_bytes,
+            schema,
+            ParseOptions::default(),
+        )
+    }
+
+    /// As `process_input_with_schema`, but lex and parse with the given options.
+    pub fn process_input_with_options(
+        fname: &'a Path,
+        input_bytes: &'a [u8],
+        schema: &Schema,
+        options: ParseOptions,
     ) -> error::Result<NamedDocument<'a>> {
         let input_str = str_from_utf8(input_bytes)?;
-        let tokens = Lexer::new(input_str).run()?;
-        let mut parser = Parser::new(input_str, &tokens);
-        let doc = parser.parse_document()?;
-        let doc = typecheck::check_document(input_str, doc)?;
+        let (doc, warnings) = parse_and_check(input_str, schema, options)?;
         let result = NamedDocument {
             fname,
             input: input_str,
             document: doc,
+            warnings,
         };
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::Span;
+
+    fn span(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    #[test]
+    fn span_union_covers_overlapping_spans() {
+        assert_eq!(span(2, 5).union(span(4, 8)), span(2, 8));
+        assert_eq!(span(2, 8).union(span(4, 5)), span(2, 8));
+        assert_eq!(span(2, 5).union(span(2, 5)), span(2, 5));
+    }
+
+    #[test]
+    fn span_union_covers_disjoint_spans() {
+        assert_eq!(span(2, 3).union(span(7, 9)), span(2, 9));
+        assert_eq!(span(2, 3).union(span(3, 3)), span(2, 3));
+    }
+
+    #[test]
+    fn span_union_covers_reversed_spans() {
+        assert_eq!(span(7, 9).union(span(2, 3)), span(2, 9));
+        assert_eq!(span(4, 8).union(span(2, 5)), span(2, 8));
+    }
+
+    #[test]
+    fn span_resolve_bytes_slices_bytes() {
+        let input = "caf\u{e9} au lait";
+        assert_eq!(
+            span(0, 5).resolve_bytes(input.as_bytes()),
+            "caf\u{e9}".as_bytes()
+        );
+        // A span can end halfway a character when we resolve it as bytes.
+        assert_eq!(span(3, 4).resolve_bytes(input.as_bytes()), &[0xc3]);
+        assert_eq!(span(2, 2).resolve_bytes(b"abc"), b"");
+        assert_eq!(span(1, 3).resolve_bytes(b"a\xffc"), b"\xffc");
+    }
+
+    #[test]
+    fn span_between_spans_the_gap() {
+        assert_eq!(span(2, 3).between(span(7, 9)), span(3, 7));
+        assert_eq!(span(2, 3).between(span(3, 9)), span(3, 3));
+    }
+}
@@ -5,14 +5,22 @@
 // you may not use this file except in compliance with the License.
 // A copy of the License has been included in the root of the repository.
 
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::io;
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 
 use squiller::cli;
-use squiller::cli::Cmd;
-use squiller::target::{Target, TARGETS};
-use squiller::NamedDocument;
+use squiller::cli::{Cmd, DriverOptions, ErrorFormat};
+use squiller::error::Error;
+use squiller::schema::Schema;
+use squiller::sidecar::{Origin, Sidecar, SourceMap};
+use squiller::target::{
+    check_shared_structs, check_target_flags, check_target_support, select_tagged_queries, Options,
+    Target, TARGETS,
+};
+use squiller::{NamedDocument, ParseOptions};
 
 fn print_available_targets() -> io::Result<()> {
     let mut stdout = std::io::stdout();
@@ -33,23 +41,290 @@ fn print_available_targets() -> io::Result<()> {
     Ok(())
 }
 
-fn process_inputs(out: &mut dyn Write, target: &Target, inputs: &[(&Path, Vec<u8>)]) {
+/// Print the error or warning to stderr in the requested format.
+fn print_diagnostic(
+    err: &(dyn Error + 'static),
+    error_format: ErrorFormat,
+    fname: &Path,
+    input: &[u8],
+) {
+    match error_format {
+        ErrorFormat::Human => err.print(fname, input),
+        ErrorFormat::Json => eprintln!("{}", err.to_diagnostic().to_json(fname)),
+    }
+}
+
+/// Print the error to stderr in the requested format, and exit.
+fn report_error(err: Box<dyn Error>, error_format: ErrorFormat, fname: &Path, input: &[u8]) -> ! {
+    print_diagnostic(err.as_ref(), error_format, fname, input);
+    std::process::exit(1);
+}
+
+/// The annotations file, and where we inserted its annotations into the inputs.
+struct Annotations {
+    fname: PathBuf,
+    bytes: Vec<u8>,
+    /// For every input, its original contents, and the map to report errors.
+    inputs: Vec<(Vec<u8>, SourceMap)>,
+}
+
+/// Prints errors and warnings in the inputs.
+///
+/// With `--annotations`, we process the annotated inputs, but errors should
+/// point at what the user wrote: the original input, or the TOML file.
+struct Reporter {
+    error_format: ErrorFormat,
+    annotations: Option<Annotations>,
+}
+
+impl Reporter {
+    /// Print the error or warning in input `i` to stderr.
+    fn print(&self, err: &(dyn Error + 'static), inputs: &[(&Path, Vec<u8>)], i: usize) {
+        let (fname, input) = &inputs[i];
+        let annotations = match &self.annotations {
+            None => return print_diagnostic(err, self.error_format, fname, input),
+            Some(annotations) => annotations,
+        };
+        let (original, source_map) = &annotations.inputs[i];
+        let mut diagnostic = err.to_diagnostic();
+        let in_sidecar = match source_map.origin(diagnostic.span) {
+            Origin::Input(span) => {
+                diagnostic.span = span;
+                false
+            }
+            Origin::Sidecar(span) => {
+                diagnostic.span = span;
+                true
+            }
+        };
+        // We can only highlight a note in the same file as the error itself,
+        // if the note points into the other file, we leave it out.
+        diagnostic.note = match diagnostic.note.take() {
+            Some((note, span)) => match (source_map.origin(span), in_sidecar) {
+                (Origin::Input(span), false) | (Origin::Sidecar(span), true) => Some((note, span)),
+                _ => None,
+            },
+            None => None,
+        };
+        match in_sidecar {
+            true => print_diagnostic(
+                &diagnostic,
+                self.error_format,
+                &annotations.fname,
+                &annotations.bytes,
+            ),
+            false => print_diagnostic(&diagnostic, self.error_format, fname, original),
+        }
+    }
+
+    /// Print the error in input `i` to stderr, and exit.
+    fn report(&self, err: Box<dyn Error>, inputs: &[(&Path, Vec<u8>)], i: usize) -> ! {
+        self.print(err.as_ref(), inputs, i);
+        std::process::exit(1);
+    }
+}
+
+/// Generate code for the inputs, and return whether any warnings were printed.
+fn process_inputs(
+    out: &mut dyn Write,
+    target: &Target,
+    options: &Options,
+    driver: &DriverOptions,
+    schema: &Schema,
+    reporter: &Reporter,
+    inputs: &[(&Path, Vec<u8>)],
+) -> bool {
     let mut documents = Vec::with_capacity(inputs.len());
+    let mut has_warnings = false;
 
-    for (fname, input_bytes) in inputs {
-        let named_document = match NamedDocument::process_input(fname, input_bytes) {
+    for (i, (fname, input_bytes)) in inputs.iter().enumerate() {
+        let parse_options = ParseOptions {
+            dialect: options.dialect,
+            template_braces: options.template_braces,
+        };
+        let named_document = match NamedDocument::process_input_with_options(
+            fname,
+            input_bytes,
+            schema,
+            parse_options,
+        ) {
             Ok(doc) => doc,
-            Err(err) => {
-                err.print(fname, input_bytes);
-                std::process::exit(1);
-            }
+            Err(err) => reporter.report(err, inputs, i),
         };
+        for warning in &named_document.warnings {
+            reporter.print(warning, inputs, i);
+            has_warnings = true;
+        }
         documents.push(named_document);
     }
 
+    select_tagged_queries(&driver.tags, driver.only_tagged, &mut documents);
+
+    if options.dedup_structs {
+        if let Err((i, err)) = check_shared_structs(options, &documents) {
+            reporter.report(err.into(), inputs, i);
+        }
+    }
+
+    if let Err((i, err)) = check_target_support(target, &documents) {
+        reporter.report(err.into(), inputs, i);
+    }
+
+    if driver.print_deps {
+        for dep in target.dependencies(options, &documents) {
+            writeln!(out, "{}", dep).expect("Failed to write output.");
+        }
+        return has_warnings;
+    }
+
     target
-        .process_files(out, &documents[..])
+        .process_files(out, options, &documents[..])
         .expect("Failed to write output.");
+
+    has_warnings
+}
+
+/// Return the path of the cached output for these inputs in the cache directory.
+///
+/// The key covers everything that affects the output: the Squiller version,
+/// the target, the options (which include the schema), the selected tags,
+/// whether we print dependencies, and the name and contents of every input.
+fn cache_path(
+    target: &Target,
+    options: &Options,
+    driver: &DriverOptions,
+    cache_dir: &str,
+    inputs: &[(&Path, Vec<u8>)],
+) -> PathBuf {
+    use squiller::version::{REV, VERSION};
+    let mut hasher = DefaultHasher::new();
+    VERSION.hash(&mut hasher);
+    REV.hash(&mut hasher);
+    target.name.hash(&mut hasher);
+    format!("{:?}", options).hash(&mut hasher);
+    driver.tags.hash(&mut hasher);
+    driver.only_tagged.hash(&mut hasher);
+    driver.print_deps.hash(&mut hasher);
+    for (fname, input_bytes) in inputs {
+        fname.hash(&mut hasher);
+        input_bytes.hash(&mut hasher);
+    }
+    Path::new(cache_dir).join(format!("{:016x}.out", hasher.finish()))
+}
+
+/// Like `process_inputs`, but with `--cache-dir`, reuse the output of a previous
+/// run if possible.
+///
+/// A cache hit would not print the warnings of the inputs, so we only cache
+/// output that was generated without warnings. Errors are never cached either,
+/// so a run that fails fails again the next time.
+fn process_inputs_cached(
+    out: &mut dyn Write,
+    target: &Target,
+    options: &Options,
+    driver: &DriverOptions,
+    schema: &Schema,
+    reporter: &Reporter,
+    inputs: &[(&Path, Vec<u8>)],
+) {
+    let cache_dir = match &driver.cache_dir {
+        Some(dir) => dir,
+        None => {
+            process_inputs(out, target, options, driver, schema, reporter, inputs);
+            return;
+        }
+    };
+    let path = cache_path(target, options, driver, cache_dir, inputs);
+    if let Ok(cached) = std::fs::read(&path) {
+        out.write_all(&cached).expect("Failed to write output.");
+        return;
+    }
+
+    let mut output = Vec::new();
+    let has_warnings = process_inputs(
+        &mut output,
+        target,
+        options,
+        driver,
+        schema,
+        reporter,
+        inputs,
+    );
+    out.write_all(&output).expect("Failed to write output.");
+    if !has_warnings {
+        write_cache_file(cache_dir, &path, &output);
+    }
+}
+
+/// Store generated output in the cache, ignoring any failure to do so.
+///
+/// The output itself was generated fine, so a cache directory that we cannot
+/// write to should not fail the run. We write to a temporary file first, so an
+/// interrupted run does not leave behind a truncated file that a later run
+/// would use. The name includes the process id, so concurrent runs with the
+/// same inputs do not write to the same temporary file.
+fn write_cache_file(cache_dir: &str, path: &Path, output: &[u8]) {
+    let tmp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+    let result = std::fs::create_dir_all(cache_dir)
+        .and_then(|()| std::fs::write(&tmp_path, output))
+        .and_then(|()| std::fs::rename(&tmp_path, path));
+    if result.is_err() {
+        // If the write failed, there may be nothing to remove, that is fine.
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+}
+
+/// Insert the annotations from the sidecar TOML file into the inputs.
+///
+/// This replaces the contents of the inputs, so everything after this point,
+/// including the cache key, sees the annotated source. The original contents
+/// are in the returned `Annotations`, to report errors against.
+fn annotate_inputs(
+    fname: &str,
+    error_format: ErrorFormat,
+    inputs: &mut [(&Path, Vec<u8>)],
+) -> Annotations {
+    let bytes = std::fs::read(fname).expect("Failed to read annotations file.");
+    let toml_str = match std::str::from_utf8(&bytes) {
+        Ok(s) => s,
+        Err(..) => {
+            eprintln!("Annotations file '{}' is not valid UTF-8.", fname);
+            std::process::exit(1);
+        }
+    };
+    let mut sidecar = match Sidecar::parse(toml_str) {
+        Ok(sidecar) => sidecar,
+        Err(err) => report_error(err.into(), error_format, fname.as_ref(), &bytes),
+    };
+
+    let mut originals = Vec::with_capacity(inputs.len());
+    for (input_fname, input_bytes) in inputs.iter_mut() {
+        let input_str = match std::str::from_utf8(input_bytes) {
+            Ok(s) => s,
+            // Leave the input as-is, processing it reports the error.
+            Err(..) => {
+                originals.push((input_bytes.clone(), SourceMap::default()));
+                continue;
+            }
+        };
+        match sidecar.annotate(input_str) {
+            Ok((annotated, source_map)) => {
+                let original = std::mem::replace(input_bytes, annotated.into_bytes());
+                originals.push((original, source_map));
+            }
+            Err(err) => report_error(err.into(), error_format, input_fname, input_bytes),
+        }
+    }
+
+    if let Err(err) = sidecar.check_all_used() {
+        report_error(err.into(), error_format, fname.as_ref(), &bytes);
+    }
+
+    Annotations {
+        fname: fname.into(),
+        bytes,
+        inputs: originals,
+    }
 }
 
 fn main() {
@@ -61,7 +336,7 @@ fn main() {
         }
     };
 
-    let (target, input_files) = match cmd {
+    let (target, mut options, driver, schema_file, error_format, input_files) = match cmd {
         Cmd::Help => {
             cli::print_usage();
             std::process::exit(0);
@@ -74,19 +349,60 @@ fn main() {
             cli::print_version();
             std::process::exit(0);
         }
-        Cmd::Generate { target, fnames } => {
+        Cmd::Generate {
+            target,
+            fnames,
+            schema,
+            error_format,
+            options,
+            driver,
+        } => {
             let target = match Target::from_name(&target) {
                 Some(t) => t,
                 None => {
-                    eprintln!(
-                        "Unknown target '{}'. See 'squiller --target=help' \
-                        for supported targets.",
-                        target,
-                    );
+                    match Target::suggest(&target) {
+                        Some(suggestion) => eprintln!(
+                            "Unknown target '{}', did you mean '{}'? See \
+                            'squiller --target=help' for supported targets.",
+                            target, suggestion.name,
+                        ),
+                        None => eprintln!(
+                            "Unknown target '{}'. See 'squiller --target=help' \
+                            for supported targets.",
+                            target,
+                        ),
+                    }
                     std::process::exit(1);
                 }
             };
-            (target, fnames)
+            if let Err(flag) = check_target_flags(target, &options) {
+                eprintln!(
+                    "The '{}' target does not support '{}'. See 'squiller --help'.",
+                    target.name, flag,
+                );
+                std::process::exit(1);
+            }
+            (target, options, driver, schema, error_format, fnames)
+        }
+    };
+
+    let schema = match schema_file {
+        None => Schema::default(),
+        Some(fname) => {
+            let bytes = std::fs::read(&fname).expect("Failed to read schema file.");
+            let schema_str = match std::str::from_utf8(&bytes) {
+                Ok(s) => s,
+                Err(..) => {
+                    eprintln!("Schema file '{}' is not valid UTF-8.", fname);
+                    std::process::exit(1);
+                }
+            };
+            // Targets that set up a database need the schema itself.
+            options.schema_sql = Some(schema_str.to_string());
+            match Schema::parse(schema_str) {
+                Ok(schema) => schema,
+                Err(err) => report_error(err.into(), error_format, fname.as_ref(), &bytes),
+            }
         }
     };
 
@@ -94,7 +410,7 @@ fn main() {
     let mut stdout = stdout.lock();
     let fname_stdin: PathBuf = "stdin".into();
 
-    let inputs: Vec<_> = input_files
+    let mut inputs: Vec<_> = input_files
         .iter()
         .map(|fname| match fname.as_str() {
             "-" => {
@@ -111,5 +427,21 @@ fn main() {
         })
         .collect();
 
-    process_inputs(&mut stdout, target, &inputs);
+    let reporter = Reporter {
+        error_format,
+        annotations: driver
+            .annotations
+            .as_ref()
+            .map(|fname| annotate_inputs(fname, error_format, &mut inputs)),
+    };
+
+    process_inputs_cached(
+        &mut stdout,
+        target,
+        &options,
+        &driver,
+        &schema,
+        &reporter,
+        &inputs,
+    );
 }
@@ -14,9 +14,33 @@ type Annotation = crate::ast::Annotation<Span>;
 type ArgType = crate::ast::ArgType<Span>;
 type ResultType = crate::ast::ResultType<Span>;
 type TypedIdent = crate::ast::TypedIdent<Span>;
+type EnumVariant = crate::ast::EnumVariant<Span>;
+type TupleElement = crate::ast::TupleElement<Span>;
+type TaggedVariant = crate::ast::TaggedVariant<Span>;
 type SimpleType = crate::ast::SimpleType<Span>;
 type ComplexType = crate::ast::ComplexType<Span>;
 
+/// The sentence that lists all primitive types, for use in hints.
+///
+/// This is a macro rather than a constant, so the hints can include it with
+/// `concat!` and still be `&'static str`.
+macro_rules! primitive_types_hint {
+    () => {
+        "The primitive types are 'str', 'bytes', 'i16', 'i32', 'i64', 'u32', 'f32', 'f64', \
+        and 'json'."
+    };
+}
+
+/// Hint for errors where a type is not one of the primitive types.
+const PRIMITIVE_TYPE_HINT: &str = primitive_types_hint!();
+
+/// Hint for errors where a result type is missing after an arrow.
+const RESULT_TYPE_HINT: &str = concat!(
+    "The result type is a primitive type such as 'i64' or 'str?', \
+    a struct such as 'User', or a tuple such as '(i64, str)'. ",
+    primitive_types_hint!(),
+);
+
 /// Annotation parser.
 ///
 /// The annotation parser can parse annotation comments and its constituents,
@@ -57,6 +81,7 @@ impl<'a> Parser<'a> {
             span,
             message,
             note: None,
+            hint: None,
         };
 
         Err(err)
@@ -75,6 +100,14 @@ impl<'a> Parser<'a> {
         })
     }
 
+    /// Build a parse error at the current cursor location, with a hint.
+    fn error_with_hint<T>(&self, message: &'static str, hint: &'static str) -> PResult<T> {
+        self.error(message).map_err(|err| ParseError {
+            hint: Some(hint),
+            ..err
+        })
+    }
+
     /// Return the token under the cursor, if there is one.
     fn peek(&self) -> Option<Token> {
         self.tokens.get(self.cursor).map(|t| t.0)
@@ -85,6 +118,22 @@ impl<'a> Parser<'a> {
         self.tokens.get(self.cursor).map(|t| (t.0, t.1))
     }
 
+    /// Return whether the cursor is at a `::` path separator.
+    ///
+    /// The lexer produces two colon tokens, they only separate a path when
+    /// there is no space between them.
+    fn peek_path_separator(&self) -> bool {
+        match (
+            self.tokens.get(self.cursor),
+            self.tokens.get(self.cursor + 1),
+        ) {
+            (Some((Token::Colon, first)), Some((Token::Colon, second))) => {
+                first.end == second.start
+            }
+            _ => false,
+        }
+    }
+
     /// Return the token before the cursor, assuming it exists.
     fn previous_span(&self) -> Span {
         self.tokens[self.cursor - 1].1
@@ -113,9 +162,61 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Report an error if the identifier contains non-ascii characters.
+    ///
+    /// The annotation lexer accepts anything that is not punctuation as part
+    /// of an identifier, but targets need names that are valid identifiers in
+    /// the target language, so we only allow ascii.
+    fn check_ascii_ident(&self, ident: Span) -> PResult<()> {
+        let name = ident.resolve(self.input);
+        match name.char_indices().find(|(_, ch)| !ch.is_ascii()) {
+            None => Ok(()),
+            Some((i, ch)) => Err(ParseError {
+                span: Span {
+                    start: ident.start + i,
+                    end: ident.start + i + ch.len_utf8(),
+                },
+                message: "Non-ascii characters are not supported in identifiers.",
+                note: None,
+                hint: Some("Identifiers can contain ascii letters, digits, and underscores."),
+            }),
+        }
+    }
+
+    /// Report an error if the identifier is not a valid module name.
+    ///
+    /// Module names become modules in the target language, so unlike other
+    /// identifiers, we only allow letters, digits, and underscores.
+    fn check_module_ident(&self, ident: Span) -> PResult<()> {
+        let name = ident.resolve(self.input);
+        let is_valid = !name.starts_with(|ch: char| ch.is_ascii_digit())
+            && name
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || ch == '_');
+        match is_valid {
+            true => Ok(()),
+            false => Err(ParseError {
+                span: ident,
+                message: "Invalid module name.",
+                note: None,
+                hint: Some(
+                    "Module names can contain ascii letters, digits, and underscores, \
+                    and cannot start with a digit.",
+                ),
+            }),
+        }
+    }
+
+    /// Consume an identifier, or return the error message if there is none.
+    fn expect_ident(&mut self, message: &'static str) -> PResult<Span> {
+        let ident = self.expect_consume(Token::Ident, message)?;
+        self.check_ascii_ident(ident)?;
+        Ok(ident)
+    }
+
     /// Parse a typed identifier, such as `id: i64`.
     pub fn parse_typed_ident(&mut self) -> PResult<TypedIdent> {
-        let ident = self.expect_consume(Token::Ident, "Expected an identifier here.")?;
+        let ident = self.expect_ident("Expected an identifier here.")?;
         self.expect_consume(
             Token::Colon,
             "Expected a ':' here before the start of the type.",
@@ -123,7 +224,12 @@ impl<'a> Parser<'a> {
 
         let type_ = self.parse_simple_type()?;
 
-        let result = TypedIdent { ident, type_ };
+        let result = TypedIdent {
+            ident,
+            type_,
+            doc: None,
+            alias: None,
+        };
         Ok(result)
     }
 
@@ -136,13 +242,11 @@ impl<'a> Parser<'a> {
         let alt_int = [
             "int",
             "i8",
-            "i16",
             "i32",
             "i64",
             "uint",
             "u8",
             "u16",
-            "u32",
             "u64",
             "int4",
             "int8",
@@ -155,34 +259,258 @@ impl<'a> Parser<'a> {
             Some((Token::Ident, span)) => {
                 let result = match span.resolve(self.input) {
                     "str" => PrimitiveType::Str,
+                    "i16" => PrimitiveType::I16,
                     "i32" => PrimitiveType::I32,
                     "i64" => PrimitiveType::I64,
+                    "u32" => PrimitiveType::U32,
                     "f32" => PrimitiveType::F32,
                     "f64" => PrimitiveType::F64,
                     "bytes" => PrimitiveType::Bytes,
+                    "json" => PrimitiveType::Json,
                     unknown if alt_str.contains(&&unknown.to_ascii_lowercase()[..]) => {
-                        return self.error("Unknown type, did you mean 'str'?");
+                        return self.error_with_hint(
+                            "Unknown type, expected a primitive type here.",
+                            "Did you mean 'str'?",
+                        );
                     }
                     unknown if alt_int.contains(&&unknown.to_ascii_lowercase()[..]) => {
-                        return self.error("Unknown type, did you mean 'i32' or 'i64'?");
+                        return self.error_with_hint(
+                            "Unknown type, expected a primitive type here.",
+                            "Did you mean 'i32' or 'i64'?",
+                        );
                     }
                     unknown if alt_float.contains(&&unknown.to_ascii_lowercase()[..]) => {
-                        return self.error("Unknown type, did you mean 'f32' or 'f64'?");
+                        return self.error_with_hint(
+                            "Unknown type, expected a primitive type here.",
+                            "Did you mean 'f32' or 'f64'?",
+                        );
                     }
                     _ => {
-                        return self.error("Unknown type, expected a primitive type here.");
+                        return self.error_with_hint(
+                            "Unknown type, expected a primitive type here.",
+                            PRIMITIVE_TYPE_HINT,
+                        );
                     }
                 };
                 self.consume();
                 Ok((span, result))
             }
+            Some((Token::Minus, _)) => self.error_minus_before_type(),
             Some(_not_ident) => self.error("Expected a primitive type here."),
             None => self.error("Unexpected end of input, expected a primitive type here."),
         }
     }
 
-    /// Parse a simple type (primitive or option).
+    /// Parse an enum type with integer codes, e.g. `Status(Active = 0, Inactive = 1)`.
+    fn parse_enum_type(&mut self) -> PResult<SimpleType> {
+        let (name, close, variants) = self.parse_enum_variants(false)?;
+        let result = SimpleType::Enum {
+            outer: name.union(close),
+            name,
+            variants: variants
+                .into_iter()
+                .map(|v| EnumVariant {
+                    name: v.name,
+                    code: v.code,
+                })
+                .collect(),
+        };
+        Ok(result)
+    }
+
+    /// Parse an enum or tagged enum result type.
+    ///
+    /// When any of the variants has fields, as in `Event(Created = 0 (id: i64))`,
+    /// this is a tagged enum, otherwise it is a regular enum.
+    fn parse_result_enum_type(&mut self) -> PResult<ComplexType> {
+        let (name, close, variants) = self.parse_enum_variants(true)?;
+        if variants.iter().any(|v| !v.fields.is_empty()) {
+            return Ok(ComplexType::TaggedEnum(name, variants));
+        }
+        let result = SimpleType::Enum {
+            outer: name.union(close),
+            name,
+            variants: variants
+                .into_iter()
+                .map(|v| EnumVariant {
+                    name: v.name,
+                    code: v.code,
+                })
+                .collect(),
+        };
+        Ok(ComplexType::Simple(result))
+    }
+
+    /// Parse the fields of a tagged enum variant, the cursor should be on the '('.
+    ///
+    /// Fields with the same name in different variants share a column, so
+    /// they must have the same type. `previous` holds the earlier variants.
+    fn parse_variant_fields(&mut self, previous: &[TaggedVariant]) -> PResult<Vec<TypedIdent>> {
+        self.expect_consume(Token::LParen, "Expected '(' here.")?;
+        let mut fields: Vec<TypedIdent> = Vec::new();
+        loop {
+            if let Some(Token::RParen) = self.peek() {
+                if fields.is_empty() {
+                    return self.error("Expected a field here.");
+                }
+                self.consume();
+                return Ok(fields);
+            }
+
+            let field_start = self.cursor;
+            let field = self.parse_typed_ident()?;
+            let name = field.ident.resolve(self.input);
+            if let Some(prev) = fields.iter().find(|f| f.ident.resolve(self.input) == name) {
+                self.cursor = field_start;
+                return self.error_with_note(
+                    "Redefinition of field.",
+                    prev.ident,
+                    "First defined here.",
+                );
+            }
+            let shared = previous
+                .iter()
+                .flat_map(|v| v.fields.iter())
+                .find(|f| f.ident.resolve(self.input) == name);
+            if let Some(prev) = shared {
+                let type_ = field.type_.resolve(self.input);
+                if !prev.type_.resolve(self.input).is_equal_to(&type_) {
+                    return Err(ParseError {
+                        span: field.type_.span(),
+                        message: "Fields with the same name share a column, \
+                        so they must have the same type in every variant.",
+                        note: Some(("The field is first defined here.", prev.ident)),
+                        hint: None,
+                    });
+                }
+            }
+            fields.push(field);
+
+            match self.peek() {
+                Some(Token::Comma) => {
+                    self.consume();
+                }
+                Some(Token::RParen) => continue,
+                _ => return self.error("Expected ',' or ')' here."),
+            }
+        }
+    }
+
+    /// Parse the name and variants of an enum.
+    ///
+    /// Returns the span of the name, the span of the closing paren, and the
+    /// variants. Variants can only have fields when `allow_fields` is true.
+    fn parse_enum_variants(
+        &mut self,
+        allow_fields: bool,
+    ) -> PResult<(Span, Span, Vec<TaggedVariant>)> {
+        let name = self.expect_ident("Expected the name of the enum here.")?;
+        self.expect_consume(Token::LParen, "Expected '(' here.")?;
+
+        let mut variants: Vec<TaggedVariant> = Vec::new();
+
+        loop {
+            if let Some(Token::RParen) = self.peek() {
+                if variants.is_empty() {
+                    return self.error("Expected an enum variant here.");
+                }
+                break;
+            }
+
+            let variant_name = self.expect_ident("Expected an enum variant here.")?;
+            if let Some(prev) = variants
+                .iter()
+                .find(|v| v.name.resolve(self.input) == variant_name.resolve(self.input))
+            {
+                self.cursor -= 1;
+                return self.error_with_note(
+                    "Redefinition of enum variant.",
+                    prev.name,
+                    "First defined here.",
+                );
+            }
+
+            if self.peek() != Some(Token::Equals) {
+                return self.error_with_hint(
+                    "Expected '=' here.",
+                    "Every variant needs an integer code, as in 'Active = 1'.",
+                );
+            }
+            self.consume();
+
+            let code_start = self.cursor;
+            let is_negative = self.peek() == Some(Token::Minus);
+            if is_negative {
+                self.consume();
+            }
+            let code = match self.peek_with_span() {
+                Some((Token::Ident, span)) => match span.resolve(self.input).parse::<i64>() {
+                    Ok(code) if is_negative => -code,
+                    Ok(code) => code,
+                    Err(..) => return self.error("Expected an integer code here."),
+                },
+                _ => return self.error("Expected an integer code here."),
+            };
+            self.consume();
+            if let Some(prev) = variants.iter().find(|v| v.code == code) {
+                self.cursor = code_start;
+                return self.error_with_note(
+                    "Duplicate enum code, every variant needs a different code.",
+                    prev.name,
+                    "This variant has the same code.",
+                );
+            }
+
+            let fields = match self.peek() {
+                Some(Token::LParen) if allow_fields => self.parse_variant_fields(&variants)?,
+                Some(Token::LParen) => {
+                    return self.error_with_hint(
+                        "Enum variants can only have fields in result types.",
+                        "An enum with fields is a tagged enum, \
+                        which can only be used as the result type of a query.",
+                    )
+                }
+                _ => Vec::new(),
+            };
+
+            variants.push(TaggedVariant {
+                name: variant_name,
+                code,
+                fields,
+            });
+
+            match self.peek() {
+                Some(Token::Comma) => {
+                    self.consume();
+                }
+                Some(Token::RParen) => continue,
+                _ => return self.error("Expected ',' or ')' here."),
+            }
+        }
+
+        let close = self.consume();
+
+        if let Some(Token::Question) = self.peek() {
+            return self.error_with_hint(
+                "Enum types cannot be nullable.",
+                "Store a separate code for the absence of a value, \
+                and add a variant for it.",
+            );
+        }
+
+        Ok((name, close, variants))
+    }
+
+    /// Parse a simple type (primitive, option, or enum).
     pub fn parse_simple_type(&mut self) -> PResult<SimpleType> {
+        // A name followed by parentheses is an enum, primitive types are a
+        // single identifier.
+        if let (Some(Token::Ident), Some((Token::LParen, _))) =
+            (self.peek(), self.tokens.get(self.cursor + 1))
+        {
+            return self.parse_enum_type();
+        }
+
         let (inner, primitive) = self.parse_primitive_type()?;
 
         // If a primitive type is followed by a question mark, that
@@ -190,10 +518,7 @@ impl<'a> Parser<'a> {
         // it remains primitive.
         let result = match self.peek() {
             Some(Token::Question) => SimpleType::Option {
-                outer: Span {
-                    start: inner.start,
-                    end: self.consume().end,
-                },
+                outer: inner.union(self.consume()),
                 inner: inner,
                 type_: primitive,
             },
@@ -213,46 +538,103 @@ impl<'a> Parser<'a> {
             Some((Token::LParen, span)) => {
                 let inner = self.parse_tuple()?;
                 let final_span = self.previous_span();
-                let full_span = Span {
-                    start: span.start,
-                    end: final_span.end,
-                };
+                let full_span = span.union(final_span);
                 Ok(ComplexType::Tuple(full_span, inner))
             }
             Some((Token::Ident, span)) => {
                 // If it's an identifier, then it can be a user-defined type (a
                 // struct), or a builtin type. Struct names start with an uppercase
                 // letter, and no builtin types do, so that's how we distinguish.
-                let is_struct = span
-                    .resolve(self.input)
-                    .chars()
-                    .next()
-                    .expect("Parser does not produce empty spans.")
-                    .is_ascii_uppercase();
+                // A name followed by parentheses is an enum, which is simple.
+                let is_enum = matches!(self.tokens.get(self.cursor + 1), Some((Token::LParen, _)));
+                let is_struct = !is_enum
+                    && span
+                        .resolve(self.input)
+                        .chars()
+                        .next()
+                        .expect("Parser does not produce empty spans.")
+                        .is_ascii_uppercase();
                 if is_struct {
+                    self.check_ascii_ident(span)?;
                     self.consume();
-                    Ok(ComplexType::Struct(span, Vec::new()))
+                    Ok(ComplexType::Struct(span, Vec::new(), false))
                 } else {
                     let simple = self.parse_simple_type()?;
                     Ok(ComplexType::Simple(simple))
                 }
             }
+            Some((Token::Minus, _)) => self.error_minus_before_type(),
             Some(_) => self.error("Expected a type here."),
             None => self.error("Unexpected end of input, expected a type here."),
         }
     }
 
+    /// Parse the complex type after a result arrow.
+    ///
+    /// This is a regular complex type, except that a struct can be marked
+    /// `@extern`, to indicate that it is defined outside of the generated code.
+    fn parse_result_complex_type(&mut self) -> PResult<ComplexType> {
+        match self.peek_with_span() {
+            Some((Token::Marker, mark)) if mark.resolve(self.input) == "@extern" => {
+                self.consume();
+                match self.parse_complex_type()? {
+                    ComplexType::Struct(name, fields, _) => {
+                        Ok(ComplexType::Struct(name, fields, true))
+                    }
+                    _ => Err(ParseError {
+                        span: mark,
+                        message: "Only struct types can be marked '@extern'.",
+                        note: None,
+                        hint: Some("Struct names start with an uppercase letter."),
+                    }),
+                }
+            }
+            Some((Token::Ident, _))
+                if matches!(self.tokens.get(self.cursor + 1), Some((Token::LParen, _))) =>
+            {
+                self.parse_result_enum_type()
+            }
+            Some((Token::Ident, _)) | Some((Token::LParen, _)) | Some((Token::Minus, _)) => {
+                self.parse_complex_type()
+            }
+            // The error goes right after the arrow, with a hint, because a
+            // bare "expected a type" does not say what kind of types fit here.
+            Some(_) => self.error_with_hint("Expected a result type here.", RESULT_TYPE_HINT),
+            None => self.error_with_hint(
+                "Unexpected end of input, expected a result type after the arrow.",
+                RESULT_TYPE_HINT,
+            ),
+        }
+    }
+
     /// Parse a tuple, the cursor should be on the opening paren.
-    fn parse_tuple(&mut self) -> PResult<Vec<SimpleType>> {
+    fn parse_tuple(&mut self) -> PResult<Vec<TupleElement>> {
         self.expect_consume(Token::LParen, "Expected a '(' here to start a tuple.")?;
         let mut elements = Vec::new();
         loop {
-            if let Some(Token::RParen) = self.peek() {
-                self.consume();
-                return Ok(elements);
+            match self.peek() {
+                Some(Token::RParen) => {
+                    self.consume();
+                    return Ok(elements);
+                }
+                Some(Token::Arrow)
+                | Some(Token::ArrowOpt)
+                | Some(Token::ArrowOne)
+                | Some(Token::ArrowStar) => return self.error_arrow_in_tuple(),
+                _ => {}
             }
 
-            elements.push(self.parse_simple_type()?);
+            // Elements can optionally be named, as in `(count: i64, name: str)`.
+            let name = match (self.peek(), self.tokens.get(self.cursor + 1)) {
+                (Some(Token::Ident), Some((Token::Colon, _))) => {
+                    let name = self.expect_ident("Expected an identifier here.")?;
+                    self.consume();
+                    Some(name)
+                }
+                _ => None,
+            };
+            let type_ = self.parse_simple_type()?;
+            elements.push(TupleElement { name, type_ });
 
             match self.peek() {
                 // Don't consume, the next iterator of the loop will do that.
@@ -265,6 +647,11 @@ impl<'a> Parser<'a> {
                     self.consume();
                 }
 
+                Some(Token::Arrow)
+                | Some(Token::ArrowOpt)
+                | Some(Token::ArrowOne)
+                | Some(Token::ArrowStar) => return self.error_arrow_in_tuple(),
+
                 Some(_unexpected) => {
                     return self.error("Unexpected token inside a tuple, expected ',' or ')' here.")
                 }
@@ -274,6 +661,26 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Build the error for a `-` where a type was expected.
+    ///
+    /// The lexer only produces `Minus` on its own when it is not part of an
+    /// arrow, so this is likely a typo, or an attempt at a negative number.
+    fn error_minus_before_type<T>(&self) -> PResult<T> {
+        self.error_with_hint(
+            "Unexpected '-', expected a type here.",
+            "Types cannot have a sign. A '-' is only valid in an arrow such as '->1', \
+            or before the code of an enum variant, as in 'Unknown = -1'.",
+        )
+    }
+
+    /// Report an arrow inside a tuple, the cursor should be on the arrow.
+    fn error_arrow_in_tuple<T>(&self) -> PResult<T> {
+        self.error_with_hint(
+            "Unexpected '->' inside a tuple.",
+            "The arrow goes before the result type, as in '->1 (i64, str)'.",
+        )
+    }
+
     /// Parse an argument list, the cursor should be on the opening paren.
     fn parse_arguments(&mut self) -> PResult<ArgType> {
         self.expect_consume(
@@ -284,22 +691,38 @@ impl<'a> Parser<'a> {
         let start_span = self.tokens[self.cursor - 1].1;
 
         // We first do a pass to collect all arguments as complex types, and
-        // then later we validate.
-        let mut arguments: Vec<(Span, ComplexType)> = Vec::new();
+        // whether they are a slice, and then later we validate.
+        let mut arguments: Vec<(Span, ComplexType, bool)> = Vec::new();
         loop {
             if let Some(Token::RParen) = self.peek() {
                 self.consume();
                 break;
             }
 
-            let ident = self.expect_consume(Token::Ident, "Expected an identifier here.")?;
+            let ident = self.expect_ident("Expected an identifier here.")?;
             self.expect_consume(
                 Token::Colon,
                 "Expected a ':' here before the start of the type.",
             )?;
+
+            let is_slice = self.peek() == Some(Token::LBracket);
+            if is_slice {
+                self.consume();
+            }
             let type_ = self.parse_complex_type()?;
+            if is_slice {
+                if !matches!(type_, ComplexType::Struct(..)) {
+                    return Err(ParseError {
+                        span: type_.span(),
+                        message: "Slice arguments can only be slices of structs.",
+                        note: None,
+                        hint: Some("Struct names start with an uppercase letter."),
+                    });
+                }
+                self.expect_consume(Token::RBracket, "Expected a ']' here to close the slice.")?;
+            }
 
-            arguments.push((ident, type_));
+            arguments.push((ident, type_, is_slice));
 
             match self.peek() {
                 Some(Token::RParen) => {
@@ -329,13 +752,22 @@ impl<'a> Parser<'a> {
                 span,
                 message: "Tuples can only be used in result types, not in arguments.",
                 note: None,
+                hint: None,
             })
         };
 
         match arguments.len() {
             0 => return Ok(ArgType::Args(Vec::new())),
             1 => match arguments.pop().unwrap() {
-                (var_name, ComplexType::Struct(type_name, fields)) => {
+                (var_name, ComplexType::Struct(type_name, fields, _), true) => {
+                    let result = ArgType::StructSlice {
+                        var_name,
+                        type_name,
+                        fields,
+                    };
+                    return Ok(result);
+                }
+                (var_name, ComplexType::Struct(type_name, fields, _), false) => {
                     let result = ArgType::Struct {
                         var_name,
                         type_name,
@@ -343,11 +775,16 @@ impl<'a> Parser<'a> {
                     };
                     return Ok(result);
                 }
-                (_, ComplexType::Tuple(span, _fields)) => return err_tuple(span),
-                (var_name, ComplexType::Simple(t)) => {
+                (_, ComplexType::Tuple(span, _fields), _) => return err_tuple(span),
+                (_, ComplexType::TaggedEnum(..), _) => {
+                    unreachable!("Tagged enums are only parsed in result types.")
+                }
+                (var_name, ComplexType::Simple(t), _) => {
                     let ti = TypedIdent {
                         ident: var_name,
                         type_: t,
+                        doc: None,
+                        alias: None,
                     };
                     return Ok(ArgType::Args(vec![ti]));
                 }
@@ -356,20 +793,26 @@ impl<'a> Parser<'a> {
         }
 
         let mut simple_args = Vec::with_capacity(arguments.len());
-        for (var_name, arg) in arguments.drain(..) {
+        for (var_name, arg, _is_slice) in arguments.drain(..) {
             match arg {
-                ComplexType::Struct(type_name, _fields) => {
+                ComplexType::Struct(type_name, ..) => {
                     return Err(ParseError {
                         span: type_name,
                         message: "Struct arguments can only be used in queries that take a single argument.",
                         note: None,
+                        hint: None,
                     });
                 }
                 ComplexType::Tuple(span, _fields) => return err_tuple(span),
+                ComplexType::TaggedEnum(..) => {
+                    unreachable!("Tagged enums are only parsed in result types.")
+                }
                 ComplexType::Simple(t) => {
                     let ti = TypedIdent {
                         ident: var_name,
                         type_: t,
+                        doc: None,
+                        alias: None,
                     };
                     simple_args.push(ti);
                 }
@@ -385,19 +828,35 @@ impl<'a> Parser<'a> {
             Some((Token::Marker, mark)) => match mark.resolve(self.input) {
                 "@query" => StatementType::Single,
                 "@begin" => StatementType::Multi,
-                _ => return self.error("Invalid annotation, expected '@query' or '@begin' here."),
+                "@call" => StatementType::Call,
+                _ => {
+                    return self
+                        .error("Invalid annotation, expected '@query', '@begin', or '@call' here.")
+                }
             },
             Some(_) => {
-                return self.error("Invalid annotation, expected '@query' or '@begin' here.")
+                return self
+                    .error("Invalid annotation, expected '@query', '@begin', or '@call' here.")
             }
             None => {
-                return self.error("Unexpected end of input, expected '@query' or '@begin' here.")
+                return self.error(
+                    "Unexpected end of input, expected '@query', '@begin', or '@call' here.",
+                )
             }
         };
         self.consume();
 
-        // 2. The name of the query..
-        let name = self.expect_consume(Token::Ident, "Expected an identifier here.")?;
+        // 2. The name of the query, optionally preceded by a module path, as
+        // in `users::select_by_id`.
+        let mut path = Vec::new();
+        let mut name = self.expect_ident("Expected an identifier here.")?;
+        while self.peek_path_separator() {
+            self.check_module_ident(name)?;
+            path.push(name);
+            self.consume();
+            self.consume();
+            name = self.expect_ident("Expected an identifier after '::'.")?;
+        }
 
         // 3. The query arguments, including parens.
         let arguments = self.parse_arguments()?;
@@ -405,26 +864,46 @@ impl<'a> Parser<'a> {
         // 4. Optionally an arrow followed by the result type.
         let result_type = match self.peek() {
             None => ResultType::Unit,
+            Some(Token::Arrow)
+            | Some(Token::ArrowOpt)
+            | Some(Token::ArrowOne)
+            | Some(Token::ArrowStar)
+                if matches!(arguments, ArgType::StructSlice { .. }) =>
+            {
+                return self.error_with_hint(
+                    "A query that takes a slice executes once per element, \
+                    so it cannot return a result.",
+                    "Remove the result type, or take a single struct argument.",
+                )
+            }
+            Some(Token::ArrowOpt) | Some(Token::ArrowStar) if stmt_type == StatementType::Call => {
+                return self.error_with_hint(
+                    "A procedure returns its OUT parameters as a single row, \
+                    so a '@call' query cannot return zero or multiple rows.",
+                    "Use '->1' to return the OUT parameters.",
+                )
+            }
             Some(Token::ArrowOpt) => {
                 self.consume();
-                let type_ = self.parse_complex_type()?;
+                let type_ = self.parse_result_complex_type()?;
                 ResultType::Option(type_)
             }
             Some(Token::ArrowOne) => {
                 self.consume();
-                let type_ = self.parse_complex_type()?;
+                let type_ = self.parse_result_complex_type()?;
                 ResultType::Single(type_)
             }
             Some(Token::ArrowStar) => {
                 self.consume();
-                let type_ = self.parse_complex_type()?;
+                let type_ = self.parse_result_complex_type()?;
                 ResultType::Iterator(type_)
             }
             Some(Token::Arrow) => {
-                return self.error(
+                return self.error_with_hint(
                     "A return type arrow must include the number of rows \
-                    that the query will return: '->?' for zero or one, \
-                    '->1' for exactly one, and '->*' for zero or more.",
+                    that the query will return.",
+                    "Use '->?' for zero or one row, '->1' for exactly one, \
+                    and '->*' for zero or more.",
                 )
             }
             Some(_unexpected) => {
@@ -436,6 +915,7 @@ impl<'a> Parser<'a> {
         };
 
         let result = Annotation {
+            path,
             name,
             arguments,
             result_type,
@@ -446,10 +926,10 @@ impl<'a> Parser<'a> {
 
 #[cfg(test)]
 mod test {
-    use super::Parser;
+    use super::{Parser, PRIMITIVE_TYPE_HINT, RESULT_TYPE_HINT};
     use crate::ast::{
-        Annotation, ArgType, ComplexType, PrimitiveType, ResultType, SimpleType, StatementType,
-        TypedIdent,
+        Annotation, ArgType, ComplexType, EnumVariant, PrimitiveType, ResultType, SimpleType,
+        StatementType, TaggedVariant, TupleElement, TypedIdent,
     };
     use crate::lexer::annotation::Lexer;
     use crate::Span;
@@ -497,6 +977,26 @@ mod test {
             };
             assert_eq!(result, expected);
         });
+
+        let input = "json";
+        with_parser(input, |p| {
+            let result = p.parse_simple_type().unwrap().resolve(input);
+            let expected = SimpleType::Primitive {
+                inner: "json",
+                type_: PrimitiveType::Json,
+            };
+            assert_eq!(result, expected);
+        });
+
+        let input = "i16";
+        with_parser(input, |p| {
+            let result = p.parse_simple_type().unwrap().resolve(input);
+            let expected = SimpleType::Primitive {
+                inner: "i16",
+                type_: PrimitiveType::I16,
+            };
+            assert_eq!(result, expected);
+        });
     }
 
     #[test]
@@ -528,6 +1028,63 @@ mod test {
         with_parser("(i64?)", |p| assert!(p.parse_simple_type().is_err()));
     }
 
+    #[test]
+    fn test_parse_simple_type_enum() {
+        let input = "Status(Active = 0, Banned=-1,)";
+        with_parser(input, |p| {
+            let result = p.parse_simple_type().unwrap().resolve(input);
+            let expected = SimpleType::Enum {
+                outer: input,
+                name: "Status",
+                variants: vec![
+                    EnumVariant {
+                        name: "Active",
+                        code: 0,
+                    },
+                    EnumVariant {
+                        name: "Banned",
+                        code: -1,
+                    },
+                ],
+            };
+            assert_eq!(result, expected);
+        });
+
+        // In argument position, the enum is not mistaken for a struct.
+        let input = "Status(Active = 0)";
+        with_parser(input, |p| {
+            let result = p.parse_complex_type().unwrap();
+            assert!(matches!(
+                result,
+                ComplexType::Simple(SimpleType::Enum { .. })
+            ));
+        });
+
+        let errors = [
+            ("Status()", ")", "Expected an enum variant here."),
+            ("Status(Active)", ")", "Expected '=' here."),
+            (
+                "Status(Active = one)",
+                "one",
+                "Expected an integer code here.",
+            ),
+            ("Status(A = 0, A = 1)", "A", "Redefinition of enum variant."),
+            (
+                "Status(A = 0, B = 0)",
+                "0",
+                "Duplicate enum code, every variant needs a different code.",
+            ),
+            ("Status(A = 0)?", "?", "Enum types cannot be nullable."),
+        ];
+        for (input, span, message) in &errors {
+            with_parser(input, |p| {
+                let err = p.parse_simple_type().err().unwrap();
+                assert_eq!(err.message, *message, "Input: {}", input);
+                assert_eq!(err.span.resolve(input), *span, "Input: {}", input);
+            });
+        }
+    }
+
     #[test]
     fn test_parse_complex_type_tuple() {
         let input = "()";
@@ -542,9 +1099,12 @@ mod test {
             let result = p.parse_complex_type().unwrap().resolve(input);
             let expected = ComplexType::Tuple(
                 "(i64)",
-                vec![SimpleType::Primitive {
-                    inner: "i64",
-                    type_: PrimitiveType::I64,
+                vec![TupleElement {
+                    name: None,
+                    type_: SimpleType::Primitive {
+                        inner: "i64",
+                        type_: PrimitiveType::I64,
+                    },
                 }],
             );
             assert_eq!(result, expected);
@@ -556,9 +1116,12 @@ mod test {
             let result = p.parse_complex_type().unwrap().resolve(input);
             let expected = ComplexType::Tuple(
                 "(i64,)",
-                vec![SimpleType::Primitive {
-                    inner: "i64",
-                    type_: PrimitiveType::I64,
+                vec![TupleElement {
+                    name: None,
+                    type_: SimpleType::Primitive {
+                        inner: "i64",
+                        type_: PrimitiveType::I64,
+                    },
                 }],
             );
             assert_eq!(result, expected);
@@ -570,13 +1133,45 @@ mod test {
             let expected = ComplexType::Tuple(
                 "(i64, str)",
                 vec![
-                    SimpleType::Primitive {
-                        inner: "i64",
-                        type_: PrimitiveType::I64,
+                    TupleElement {
+                        name: None,
+                        type_: SimpleType::Primitive {
+                            inner: "i64",
+                            type_: PrimitiveType::I64,
+                        },
                     },
-                    SimpleType::Primitive {
-                        inner: "str",
-                        type_: PrimitiveType::Str,
+                    TupleElement {
+                        name: None,
+                        type_: SimpleType::Primitive {
+                            inner: "str",
+                            type_: PrimitiveType::Str,
+                        },
+                    },
+                ],
+            );
+            assert_eq!(result, expected);
+        });
+
+        // Elements can optionally be named.
+        let input = "(count: i64, name: str)";
+        with_parser(input, |p| {
+            let result = p.parse_complex_type().unwrap().resolve(input);
+            let expected = ComplexType::Tuple(
+                "(count: i64, name: str)",
+                vec![
+                    TupleElement {
+                        name: Some("count"),
+                        type_: SimpleType::Primitive {
+                            inner: "i64",
+                            type_: PrimitiveType::I64,
+                        },
+                    },
+                    TupleElement {
+                        name: Some("name"),
+                        type_: SimpleType::Primitive {
+                            inner: "str",
+                            type_: PrimitiveType::Str,
+                        },
                     },
                 ],
             );
@@ -584,7 +1179,16 @@ mod test {
         });
 
         // Also confirm that the following are parse errors.
-        let invalid_inputs: &[&'static str] = &["(,)", "(f32, <)", "(", "(f32", "(f32,"];
+        let invalid_inputs: &[&'static str] = &[
+            "(,)",
+            "(f32, <)",
+            "(",
+            "(f32",
+            "(f32,",
+            "(n:)",
+            "(n: f32",
+            "(n: m: f32)",
+        ];
         for input in invalid_inputs {
             with_parser(input, |p| assert!(p.parse_complex_type().is_err()));
         }
@@ -601,6 +1205,8 @@ mod test {
                     inner: "i64",
                     type_: PrimitiveType::I64,
                 },
+                doc: None,
+                alias: None,
             };
             assert_eq!(result, expected);
         });
@@ -612,6 +1218,7 @@ mod test {
         with_parser(input, |p| {
             let result = p.parse_annotation().unwrap();
             let expected = Annotation {
+                path: Vec::new(),
                 name: "drop_table_users",
                 arguments: ArgType::Args(vec![]),
                 result_type: ResultType::Unit,
@@ -627,6 +1234,7 @@ mod test {
         with_parser(input, |p| {
             let result = p.parse_annotation().unwrap();
             let expected = Annotation {
+                path: Vec::new(),
                 name: "init_schema",
                 arguments: ArgType::Args(vec![]),
                 result_type: ResultType::Unit,
@@ -636,6 +1244,65 @@ mod test {
         });
     }
 
+    #[test]
+    fn test_parse_annotation_call_procedure() {
+        let input = "@call proc(a: i64)";
+        with_parser(input, |p| {
+            let result = p.parse_annotation().unwrap();
+            let expected = Annotation {
+                path: Vec::new(),
+                name: "proc",
+                arguments: ArgType::Args(vec![TypedIdent {
+                    ident: "a",
+                    type_: SimpleType::Primitive {
+                        inner: "i64",
+                        type_: PrimitiveType::I64,
+                    },
+                    doc: None,
+                    alias: None,
+                }]),
+                result_type: ResultType::Unit,
+            };
+            assert_eq!(result.0.resolve(input), expected);
+            assert_eq!(result.1, StatementType::Call);
+        });
+
+        let input = "@call proc(a: i64) ->* i64";
+        with_parser(input, |p| {
+            let err = p.parse_annotation().err().unwrap();
+            assert_eq!(err.span.resolve(input), "->*");
+            assert_eq!(err.hint, Some("Use '->1' to return the OUT parameters."));
+        });
+    }
+
+    #[test]
+    fn test_parse_annotation_module_path() {
+        let input = "@query admin::users::select_by_id(id: i64) ->1 str";
+        with_parser(input, |p| {
+            let result = p.parse_annotation().unwrap().0.resolve(input);
+            assert_eq!(result.path, ["admin", "users"]);
+            assert_eq!(result.name, "select_by_id");
+        });
+
+        let input = "@query 2fa::get_secret()";
+        with_parser(input, |p| {
+            let err = p.parse_annotation().err().unwrap();
+            assert_eq!(err.span.resolve(input), "2fa");
+            assert_eq!(err.message, "Invalid module name.");
+        });
+
+        let input = "@query users::()";
+        with_parser(input, |p| {
+            let err = p.parse_annotation().err().unwrap();
+            assert_eq!(err.span.resolve(input), "(");
+            assert_eq!(err.message, "Expected an identifier after '::'.");
+        });
+
+        // With a space between the colons, they do not form a path separator.
+        let input = "@query users: :select_all()";
+        with_parser(input, |p| assert!(p.parse_annotation().is_err()));
+    }
+
     #[test]
     fn test_parse_annotation_query_argument() {
         // Test with wonky whitespace, and a trailing comma.
@@ -648,6 +1315,7 @@ mod test {
             with_parser(input, |p| {
                 let result = p.parse_annotation().unwrap();
                 let expected = Annotation {
+                    path: Vec::new(),
                     name: "delete_user_by_id",
                     arguments: ArgType::Args(vec![TypedIdent {
                         ident: "id",
@@ -655,6 +1323,8 @@ mod test {
                             inner: "i64",
                             type_: PrimitiveType::I64,
                         },
+                        doc: None,
+                        alias: None,
                     }]),
                     result_type: ResultType::Unit,
                 };
@@ -676,6 +1346,7 @@ mod test {
             with_parser(input, |p| {
                 let result = p.parse_annotation().unwrap();
                 let expected = Annotation {
+                    path: Vec::new(),
                     name: "get_widgets_in_range",
                     arguments: ArgType::Args(vec![
                         TypedIdent {
@@ -684,6 +1355,8 @@ mod test {
                                 inner: "i64",
                                 type_: PrimitiveType::I64,
                             },
+                            doc: None,
+                            alias: None,
                         },
                         TypedIdent {
                             ident: "high",
@@ -691,6 +1364,8 @@ mod test {
                                 inner: "i64",
                                 type_: PrimitiveType::I64,
                             },
+                            doc: None,
+                            alias: None,
                         },
                     ]),
                     result_type: ResultType::Unit,
@@ -707,6 +1382,7 @@ mod test {
         with_parser(input, |p| {
             let result = p.parse_annotation().unwrap();
             let expected = Annotation {
+                path: Vec::new(),
                 name: "get_next_id",
                 arguments: ArgType::Args(vec![]),
                 result_type: ResultType::Single(ComplexType::Simple(SimpleType::Primitive {
@@ -719,6 +1395,148 @@ mod test {
         });
     }
 
+    #[test]
+    fn test_parse_annotation_extern_struct() {
+        let input = "@query get_user() ->? @extern User";
+        with_parser(input, |p| {
+            let result = p.parse_annotation().unwrap();
+            let expected = ResultType::Option(ComplexType::Struct("User", vec![], true));
+            assert_eq!(result.0.resolve(input).result_type, expected);
+        });
+
+        let input = "@query get_id() ->1 @extern i64";
+        with_parser(input, |p| {
+            let err = p.parse_annotation().err().unwrap();
+            assert_eq!(err.span.resolve(input), "@extern");
+            assert_eq!(err.message, "Only struct types can be marked '@extern'.");
+        });
+    }
+
+    #[test]
+    fn test_parse_annotation_struct_slice() {
+        let input = "@query insert_users(users: [User])";
+        with_parser(input, |p| {
+            let result = p.parse_annotation().unwrap();
+            let expected = ArgType::StructSlice {
+                var_name: "users",
+                type_name: "User",
+                fields: vec![],
+            };
+            assert_eq!(result.0.resolve(input).arguments, expected);
+        });
+
+        let input = "@query insert_ids(ids: [i64])";
+        with_parser(input, |p| {
+            let err = p.parse_annotation().err().unwrap();
+            assert_eq!(err.span.resolve(input), "i64");
+            assert_eq!(
+                err.message,
+                "Slice arguments can only be slices of structs."
+            );
+        });
+
+        let input = "@query insert_users(users: [User]) ->1 i64";
+        with_parser(input, |p| {
+            let err = p.parse_annotation().err().unwrap();
+            assert_eq!(err.span.resolve(input), "->1");
+            assert!(err.hint.is_some());
+        });
+    }
+
+    #[test]
+    fn test_parse_annotation_tagged_enum() {
+        let input = "@query iter_events() ->* Event(Created = 0 (id: i64, name: str), Cleared = 1)";
+        with_parser(input, |p| {
+            let result = p.parse_annotation().unwrap().0.resolve(input);
+            let i64_type = SimpleType::Primitive {
+                inner: "i64",
+                type_: PrimitiveType::I64,
+            };
+            let str_type = SimpleType::Primitive {
+                inner: "str",
+                type_: PrimitiveType::Str,
+            };
+            let expected = ResultType::Iterator(ComplexType::TaggedEnum(
+                "Event",
+                vec![
+                    TaggedVariant {
+                        name: "Created",
+                        code: 0,
+                        fields: vec![
+                            TypedIdent {
+                                ident: "id",
+                                type_: i64_type,
+                                doc: None,
+                                alias: None,
+                            },
+                            TypedIdent {
+                                ident: "name",
+                                type_: str_type,
+                                doc: None,
+                                alias: None,
+                            },
+                        ],
+                    },
+                    TaggedVariant {
+                        name: "Cleared",
+                        code: 1,
+                        fields: vec![],
+                    },
+                ],
+            ));
+            assert_eq!(result.result_type, expected);
+        });
+
+        // Without any fields, the result is a regular enum.
+        let input = "@query get_status() ->1 Status(Active = 0, Banned = 1)";
+        with_parser(input, |p| {
+            let result = p.parse_annotation().unwrap().0;
+            assert!(matches!(
+                result.result_type,
+                ResultType::Single(ComplexType::Simple(SimpleType::Enum { .. }))
+            ));
+        });
+
+        let errors = [
+            ("@query f() ->* E(A = 0 ())", ")", "Expected a field here."),
+            (
+                "@query f() ->* E(A = 0 (x: i64, x: str))",
+                "x",
+                "Redefinition of field.",
+            ),
+            (
+                "@query f() ->* E(A = 0 (x: i64), B = 1 (x: str))",
+                "str",
+                "Fields with the same name share a column, \
+                so they must have the same type in every variant.",
+            ),
+            (
+                "@query f(e: E(A = 0 (x: i64)))",
+                "(",
+                "Enum variants can only have fields in result types.",
+            ),
+        ];
+        for (input, span, message) in &errors {
+            with_parser(input, |p| {
+                let err = p.parse_annotation().err().unwrap();
+                assert_eq!(err.message, *message, "Input: {}", input);
+                assert_eq!(err.span.resolve(input), *span, "Input: {}", input);
+            });
+        }
+    }
+
+    #[test]
+    fn test_error_on_arrow_inside_tuple() {
+        for input in &["(-> i64)", "(i64, ->1 str)", "(i64 ->* str)"] {
+            with_parser(input, |p| {
+                let err = p.parse_complex_type().err().unwrap();
+                assert_eq!(err.message, "Unexpected '->' inside a tuple.");
+                assert!(err.span.resolve(input).starts_with("->"));
+                assert!(err.hint.is_some());
+            });
+        }
+    }
+
     #[test]
     fn test_error_on_unexpected_end_is_past_end() {
         let input = "id";
@@ -733,4 +1551,93 @@ mod test {
             assert_eq!(err.span, Span { start: 3, end: 3 });
         });
     }
+
+    #[test]
+    fn test_error_on_trailing_arrow_is_after_arrow() {
+        let input = "@query f() ->1";
+        with_parser(input, |p| {
+            let err = p.parse_annotation().err().unwrap();
+            assert_eq!(
+                err.message,
+                "Unexpected end of input, expected a result type after the arrow."
+            );
+            assert_eq!(
+                err.span,
+                Span {
+                    start: input.len(),
+                    end: input.len()
+                }
+            );
+            assert_eq!(err.hint, Some(RESULT_TYPE_HINT));
+        });
+
+        let input = "@query f() ->* ,";
+        with_parser(input, |p| {
+            let err = p.parse_annotation().err().unwrap();
+            assert_eq!(err.message, "Expected a result type here.");
+            assert_eq!(err.span.resolve(input), ",");
+            assert_eq!(err.hint, Some(RESULT_TYPE_HINT));
+        });
+    }
+
+    #[test]
+    fn test_error_on_minus_before_type() {
+        for input in &[
+            "@query f() ->1 -i64",
+            "@query f(x: -i64)",
+            "@query f() ->* (i64, -str)",
+        ] {
+            with_parser(input, |p| {
+                let err = p.parse_annotation().err().unwrap();
+                assert_eq!(err.message, "Unexpected '-', expected a type here.");
+                assert_eq!(err.span.resolve(input), "-");
+                assert!(err.hint.is_some());
+            });
+        }
+    }
+
+    #[test]
+    fn test_error_on_unknown_type_has_hint() {
+        with_parser("integer", |p| {
+            let err = p.parse_simple_type().err().unwrap();
+            assert_eq!(err.message, "Unknown type, expected a primitive type here.");
+            assert_eq!(err.hint, Some("Did you mean 'i32' or 'i64'?"));
+        });
+        with_parser("uuid", |p| {
+            let err = p.parse_simple_type().err().unwrap();
+            assert_eq!(err.hint, Some(PRIMITIVE_TYPE_HINT));
+            assert!(RESULT_TYPE_HINT.ends_with(PRIMITIVE_TYPE_HINT));
+        });
+    }
+
+    #[test]
+    fn test_error_on_non_ascii_identifier() {
+        let input = "@query café()";
+        with_parser(input, |p| {
+            let err = p.parse_annotation().err().unwrap();
+            assert_eq!(err.span.resolve(input), "é");
+            assert_eq!(
+                err.message,
+                "Non-ascii characters are not supported in identifiers."
+            );
+        });
+
+        let input = "@query get_drink(café: str) ->1 Drink";
+        with_parser(input, |p| {
+            let err = p.parse_annotation().err().unwrap();
+            assert_eq!(err.span, Span { start: 20, end: 22 });
+        });
+
+        let input = "@query get_drink() ->1 Café";
+        with_parser(input, |p| {
+            let err = p.parse_annotation().err().unwrap();
+            assert_eq!(err.span.resolve(input), "é");
+        });
+
+        let input = "@query get_drink() ->1 (café: str, price: i64)";
+        with_parser(input, |p| {
+            let err = p.parse_annotation().err().unwrap();
+            assert_eq!(err.span.resolve(input), "é");
+        });
+    }
 }
@@ -5,8 +5,10 @@
 // you may not use this file except in compliance with the License.
 // A copy of the License has been included in the root of the repository.
 
-use crate::ast::StatementType;
-use crate::error::{PResult, ParseError};
+use std::cmp;
+
+use crate::ast::{ArgType, Dialect, StatementType};
+use crate::error::{PResult, ParseError, Warning};
 use crate::lexer::annotation as ann;
 use crate::lexer::document as doc;
 use crate::parser::annotation as parse_ann;
@@ -30,6 +32,12 @@ pub struct Parser<'a> {
 
     /// The unclosed opening brackets (all of `()`, `[]`, `{}`) encountered.
     bracket_stack: Vec<(doc::Token, Span)>,
+
+    /// The dialect determines which brackets need to be balanced.
+    dialect: Dialect,
+
+    /// Problems found while parsing that do not stop the parse.
+    warnings: Vec<Warning>,
 }
 
 impl<'a> Parser<'a> {
@@ -39,9 +47,21 @@ impl<'a> Parser<'a> {
             tokens: tokens,
             cursor: 0,
             bracket_stack: Vec::new(),
+            dialect: Dialect::Generic,
+            warnings: Vec::new(),
         }
     }
 
+    /// Parse the input as the given dialect, rather than the generic one.
+    pub fn with_dialect(self, dialect: Dialect) -> Parser<'a> {
+        Parser { dialect, ..self }
+    }
+
+    /// Return the warnings produced while parsing.
+    pub fn into_warnings(self) -> Vec<Warning> {
+        self.warnings
+    }
+
     /// Build a parse error at the current cursor location.
     fn error<T>(&self, message: &'static str) -> PResult<T> {
         let span = self
@@ -62,6 +82,7 @@ impl<'a> Parser<'a> {
             span,
             message,
             note: None,
+            hint: None,
         };
 
         Err(err)
@@ -198,6 +219,14 @@ impl<'a> Parser<'a> {
             end: section_start_span.end,
         };
 
+        // Annotations must start a line, only whitespace can precede them. The
+        // section may start halfway a line, after the end of a previous query.
+        let line_prefix = self.input[..section_start_span.start]
+            .rsplit('\n')
+            .next()
+            .expect("Split yields at least one element.");
+        let mut at_line_start = line_prefix.trim().is_empty();
+
         while self.peek().is_some() {
             let (token, span) = self.tokens[self.cursor];
             section_span.end = span.end;
@@ -207,6 +236,9 @@ impl<'a> Parser<'a> {
                 doc::Token::Space => {
                     let span_bytes = &self.input.as_bytes()[span.start..span.end];
                     let num_newlines = span_bytes.iter().filter(|ch| **ch == b'\n').count();
+                    if num_newlines > 0 {
+                        at_line_start = true;
+                    }
                     if num_newlines >= 2 {
                         // If there was a blank line, that marks the end of the
                         // section, and given that we did not yet switch to
@@ -223,12 +255,31 @@ impl<'a> Parser<'a> {
                     if span_bytes.contains(&b'@') {
                         let mut comment_lexer = ann::Lexer::new(self.input);
                         comment_lexer.run(span);
-                        if let Some((ann::Token::Marker, _)) = comment_lexer.tokens().first() {
-                            // If the comment starts with a marker, then this
-                            // means we are inside a query section, and we
-                            // continue parsing in query mode.
-                            let query = self.parse_query(comments, comment_lexer)?;
-                            return Ok(Section::Query(query));
+                        if let Some((ann::Token::Marker, marker)) = comment_lexer.tokens().first() {
+                            // If the comment starts with a marker that starts
+                            // a query, then this means we are inside a query
+                            // section, and we continue parsing in query mode.
+                            // Other uses of '@', such as '@todo', or a stray
+                            // '@end', are just part of the comment.
+                            match marker.resolve(self.input) {
+                                "@query" | "@begin" | "@call" if at_line_start => {
+                                    let query = self.parse_query(comments, comment_lexer)?;
+                                    return Ok(Section::Query(query));
+                                }
+                                // After code on the same line, the annotation
+                                // is likely a mistake, but the code before it
+                                // is not part of the query, so the best we can
+                                // do is to treat it as a plain comment.
+                                "@query" | "@begin" | "@call" => {
+                                    self.warnings.push(Warning {
+                                        span: *marker,
+                                        message: "Annotations must be at the start of a line, \
+                                        this one is treated as a regular comment.",
+                                        hint: Some("Move the annotation to a new line."),
+                                    });
+                                }
+                                _ => {}
+                            }
                         }
                     }
 
@@ -237,7 +288,8 @@ impl<'a> Parser<'a> {
                     // preceding comments serve as the doc comment for the query.
                     comments.push(span);
                 }
-                _ => {}
+                doc::Token::CommentStart => {}
+                _ => at_line_start = false,
             }
         }
 
@@ -251,9 +303,16 @@ impl<'a> Parser<'a> {
     /// When we enter this state, we already have one comment line that contains
     /// an annotation, but the annotation may be spread over multiple lines, so
     /// consume those up to the start of the query itself.
+    ///
+    /// Lines that start with `@param` are not part of the signature, those are
+    /// added to `param_docs` instead. Similarly, an `@dialect` line sets
+    /// `query_dialect`, and an `@tags` line adds to `tags`.
     fn parse_annotation(
         &mut self,
         mut comment_lexer: ann::Lexer<'a>,
+        param_docs: &mut Vec<(Span, Span)>,
+        query_dialect: &mut Option<(Span, Dialect)>,
+        tags: &mut Vec<Span>,
     ) -> PResult<(Annotation, StatementType)> {
         loop {
             match self.peek() {
@@ -265,7 +324,15 @@ impl<'a> Parser<'a> {
                 }
                 Some(doc::Token::CommentInner) => {
                     let span = self.tokens[self.cursor].1;
-                    comment_lexer.run(span);
+                    if let Some((marker, dialect)) = self.parse_dialect_marker(span)? {
+                        self.set_query_dialect(query_dialect, marker, dialect)?;
+                    } else if let Some(line_tags) = self.parse_tags_marker(span)? {
+                        tags.extend(line_tags);
+                    } else if let Some(param_doc) = self.parse_param_doc(span)? {
+                        param_docs.push(param_doc);
+                    } else {
+                        comment_lexer.run(span);
+                    }
                     self.consume();
                 }
                 None => {
@@ -282,12 +349,174 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// If the comment is an `@param` line, return the argument name and its doc.
+    ///
+    /// The doc is the remainder of the line after the name, without leading or
+    /// trailing whitespace.
+    fn parse_param_doc(&self, comment: Span) -> PResult<Option<(Span, Span)>> {
+        // Lexing is not free, and most comments are not `@param` lines.
+        if !comment.resolve(self.input).contains("@param") {
+            return Ok(None);
+        }
+        let mut lexer = ann::Lexer::new(self.input);
+        lexer.run(comment);
+        let tokens = lexer.tokens();
+        let marker = match tokens.first() {
+            Some((ann::Token::Marker, marker)) if marker.resolve(self.input) == "@param" => *marker,
+            _ => return Ok(None),
+        };
+        let name = match tokens.get(1) {
+            Some((ann::Token::Ident, name)) => *name,
+            _ => {
+                return Err(ParseError {
+                    span: marker,
+                    message: "Expected an argument name after '@param'.",
+                    note: None,
+                    hint: Some("The syntax is '@param <name> <description>'."),
+                })
+            }
+        };
+        let rest = &self.input[name.end..comment.end];
+        let start = name.end + (rest.len() - rest.trim_start().len());
+        let end = cmp::max(start, name.end + rest.trim_end().len());
+        Ok(Some((name, Span { start, end })))
+    }
+
+    /// If the comment is an `@dialect` line, return the dialect that it sets.
+    fn parse_dialect_marker(&self, comment: Span) -> PResult<Option<(Span, Dialect)>> {
+        if !comment.resolve(self.input).contains("@dialect") {
+            return Ok(None);
+        }
+        let mut lexer = ann::Lexer::new(self.input);
+        lexer.run(comment);
+        let tokens = lexer.tokens();
+        let marker = match tokens.first() {
+            Some((ann::Token::Marker, marker)) if marker.resolve(self.input) == "@dialect" => {
+                *marker
+            }
+            _ => return Ok(None),
+        };
+        let hint = Some("The dialect is one of 'generic', 'postgres', or 'sqlite'.");
+        let name = match tokens.get(1) {
+            Some((ann::Token::Ident, name)) => *name,
+            _ => {
+                return Err(ParseError {
+                    span: marker,
+                    message: "Expected a dialect after '@dialect'.",
+                    note: None,
+                    hint,
+                })
+            }
+        };
+        match Dialect::from_keyword(name.resolve(self.input)) {
+            Some(dialect) => Ok(Some((marker, dialect))),
+            None => Err(ParseError {
+                span: name,
+                message: "Unknown dialect.",
+                note: None,
+                hint,
+            }),
+        }
+    }
+
+    /// If the comment is an `@tags` line, return the tags that it lists.
+    ///
+    /// The tags are identifiers separated by commas, as in `@tags admin, batch`.
+    fn parse_tags_marker(&self, comment: Span) -> PResult<Option<Vec<Span>>> {
+        if !comment.resolve(self.input).contains("@tags") {
+            return Ok(None);
+        }
+        let mut lexer = ann::Lexer::new(self.input);
+        lexer.run(comment);
+        let tokens = lexer.tokens();
+        let marker = match tokens.first() {
+            Some((ann::Token::Marker, marker)) if marker.resolve(self.input) == "@tags" => *marker,
+            _ => return Ok(None),
+        };
+        let hint = Some("The syntax is '@tags <tag>, <tag>, ...'.");
+        let mut tags = Vec::new();
+        let mut prev = marker;
+        let mut rest = tokens[1..].iter();
+        loop {
+            match rest.next() {
+                Some((ann::Token::Ident, tag)) => tags.push(*tag),
+                _ => {
+                    return Err(ParseError {
+                        span: prev,
+                        message: "Expected a tag name.",
+                        note: None,
+                        hint,
+                    })
+                }
+            }
+            match rest.next() {
+                None => return Ok(Some(tags)),
+                Some((ann::Token::Comma, comma)) => prev = *comma,
+                Some((_, unexpected)) => {
+                    return Err(ParseError {
+                        span: *unexpected,
+                        message: "Expected ',' or the end of the line after a tag.",
+                        note: None,
+                        hint,
+                    })
+                }
+            }
+        }
+    }
+
+    /// Record the dialect of an `@dialect` line, a query can have only one.
+    fn set_query_dialect(
+        &self,
+        query_dialect: &mut Option<(Span, Dialect)>,
+        marker: Span,
+        dialect: Dialect,
+    ) -> PResult<()> {
+        if let Some((prev, _)) = query_dialect {
+            return Err(ParseError {
+                span: marker,
+                message: "Redefinition of the dialect, a query can have only one '@dialect'.",
+                note: Some(("First defined here.", *prev)),
+                hint: None,
+            });
+        }
+        *query_dialect = Some((marker, dialect));
+        Ok(())
+    }
+
+    /// Attach `@param` docs to the arguments, and warn about unknown names.
+    fn attach_param_docs(&mut self, annotation: &mut Annotation, param_docs: Vec<(Span, Span)>) {
+        for (name, doc) in param_docs {
+            let name_str = name.resolve(self.input);
+            let input = self.input;
+            match &mut annotation.arguments {
+                ArgType::Args(args) => {
+                    match args.iter_mut().find(|a| a.ident.resolve(input) == name_str) {
+                        Some(arg) => arg.doc = Some(doc),
+                        None => self.warnings.push(Warning {
+                            span: name,
+                            message: "Unknown argument, the query has no argument with this name.",
+                            hint: None,
+                        }),
+                    }
+                }
+                ArgType::Struct { .. } | ArgType::StructSlice { .. } => {
+                    self.warnings.push(Warning {
+                        span: name,
+                        message: "A query with a struct argument cannot have '@param' docs.",
+                        hint: Some("Document the struct in the query's doc comment instead."),
+                    })
+                }
+            }
+        }
+    }
+
     /// If next non-whitespace token is a comment with an `@end` marker, consume it.
     ///
     /// If something other than an `@end` marker is found, this leaves the
     /// cursor at the current token, and returns false. Any whitespace is
-    /// consumed unconditionally.
-    fn try_parse_end_marker(&mut self) -> bool {
+    /// consumed unconditionally. If the marker is followed by a name, it must
+    /// be `query_name`, the name of the query that the marker ends.
+    fn try_parse_end_marker(&mut self, query_name: Span) -> PResult<bool> {
         let mut backtrack_to = self.cursor;
 
         loop {
@@ -306,11 +535,22 @@ impl<'a> Parser<'a> {
                     let span = self.tokens[self.cursor].1;
                     comment_lexer.run(span);
 
-                    let first_token = comment_lexer.tokens().iter().next();
-                    if let Some((ann::Token::Marker, span)) = first_token {
+                    let tokens = comment_lexer.tokens();
+                    if let Some((ann::Token::Marker, span)) = tokens.first() {
                         if span.resolve(self.input) == "@end" {
+                            if let Some((ann::Token::Ident, end_name)) = tokens.get(1) {
+                                if end_name.resolve(self.input) != query_name.resolve(self.input) {
+                                    return Err(ParseError {
+                                        span: *end_name,
+                                        message: "The name after '@end' does not match \
+                                        the name of the query.",
+                                        note: Some(("The query is named here.", query_name)),
+                                        hint: None,
+                                    });
+                                }
+                            }
                             self.consume();
-                            return true;
+                            return Ok(true);
                         }
                     }
 
@@ -322,7 +562,7 @@ impl<'a> Parser<'a> {
 
         // We found something other than an end marker, backtrack.
         self.cursor = backtrack_to;
-        false
+        Ok(false)
     }
 
     /// Skip whitespace, then parse a double quoted string as typed identifier.
@@ -338,12 +578,21 @@ impl<'a> Parser<'a> {
                 span: type_span,
                 message: "Empty type annotation, expected a type after the ':'.",
                 note: None,
+                hint: None,
             };
             return Err(err);
         }
 
+        // An annotation of the form `/* :alias: type */` names the output
+        // after the alias. Without it, the annotation is just the type.
         let mut parser = parse_ann::Parser::new(self.input, lexer.tokens());
-        let mut type_ = parser.parse_simple_type()?;
+        let (alias, mut type_) = match lexer.tokens() {
+            [(ann::Token::Ident, _), (ann::Token::Colon, _), ..] => {
+                let ti = parser.parse_typed_ident()?;
+                (Some(ti.ident), ti.type_)
+            }
+            _ => (None, parser.parse_simple_type()?),
+        };
 
         // Consume the CommentInner token that we are parsing the annotation from.
         let annotation_token_index = self.cursor;
@@ -361,16 +610,18 @@ impl<'a> Parser<'a> {
         // Now that we have the annotation itself, we need to walk back to find
         // the token which is being annotated. We must be inside a comment, so
         // preceding this token must be a CommentStart token that we skip over.
-        for i in (0..annotation_token_index - 1).rev() {
+        // If there is nothing before that, then the loop is empty, and we
+        // report an error below.
+        let comment_start_index = annotation_token_index.saturating_sub(1);
+        for i in (0..comment_start_index).rev() {
             let (prev_token, prev_span) = self.tokens[i];
             let ident = TypedIdent {
                 ident: prev_span,
                 type_: type_,
+                doc: None,
+                alias: alias,
             };
-            let full_span = Span {
-                start: prev_span.start,
-                end: end_span.end,
-            };
+            let full_span = prev_span.union(end_span);
             match prev_token {
                 doc::Token::Space => {
                     // We put the type in the typed ident and then if we are not
@@ -384,6 +635,18 @@ impl<'a> Parser<'a> {
                     break;
                 }
                 doc::Token::Param => {
+                    if let Some(alias) = alias {
+                        let err = ParseError {
+                            span: alias,
+                            message: "Invalid type annotation, a parameter cannot have an alias.",
+                            note: None,
+                            hint: Some(
+                                "An alias renames a query output, \
+                                parameters are named after the parameter itself.",
+                            ),
+                        };
+                        return Err(err);
+                    }
                     result = Some(Fragment::TypedParam(full_span, ident));
                     break;
                 }
@@ -403,8 +666,40 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Push a regular comment, that is not a type comment, as a fragment.
+    ///
+    /// The cursor should be on the inner token of the comment. The fragment
+    /// includes the delimiters, and this consumes the closing `*/`, if any.
+    fn push_comment(&mut self, fragments: &mut Vec<Fragment>, fragment: &mut Span) {
+        let inner = self.tokens[self.cursor].1;
+        let start = match self.cursor.checked_sub(1).map(|i| &self.tokens[i]) {
+            Some((doc::Token::CommentStart, span)) => span.start,
+            _ => inner.start,
+        };
+        self.consume();
+        let end = match self.tokens.get(self.cursor) {
+            Some((doc::Token::CommentEnd, span)) => {
+                let end = span.end;
+                self.consume();
+                end
+            }
+            _ => inner.end,
+        };
+
+        fragment.end = start;
+        if !fragment.is_empty() {
+            fragments.push(Fragment::Verbatim(*fragment));
+        }
+        fragments.push(Fragment::Comment(Span { start, end }));
+        fragment.start = end;
+        fragment.end = end;
+    }
+
     /// Parse a single statement, until the closing semicolon.
-    fn parse_statement(&mut self) -> PResult<Statement> {
+    ///
+    /// If `allow_eof` is true, the statement may also end at the end of the
+    /// input, without semicolon.
+    fn parse_statement(&mut self, allow_eof: bool) -> PResult<Statement> {
         let fragment_start = match self.tokens.get(self.cursor) {
             None => return self.error("Expected a SQL statement here."),
             Some((_, span)) => span.start,
@@ -418,6 +713,14 @@ impl<'a> Parser<'a> {
 
         while let Some((token, span)) = self.tokens.get(self.cursor) {
             match token {
+                doc::Token::LBracket | doc::Token::RBracket
+                    if !self.dialect.balances_brackets() =>
+                {
+                    self.consume();
+                }
+                doc::Token::LBrace | doc::Token::RBrace if !self.dialect.balances_braces() => {
+                    self.consume();
+                }
                 doc::Token::LParen | doc::Token::LBrace | doc::Token::LBracket => {
                     self.push_bracket();
                 }
@@ -432,7 +735,7 @@ impl<'a> Parser<'a> {
                     let content = span.resolve(self.input);
                     let colon_pos = match content.find(':') {
                         None => {
-                            self.consume();
+                            self.push_comment(&mut fragments, &mut fragment);
                             continue;
                         }
                         Some(i) => i,
@@ -441,7 +744,7 @@ impl<'a> Parser<'a> {
                         .bytes()
                         .all(|ch| ch.is_ascii_whitespace())
                     {
-                        self.consume();
+                        self.push_comment(&mut fragments, &mut fragment);
                         continue;
                     }
 
@@ -460,7 +763,7 @@ impl<'a> Parser<'a> {
                         frag @ Fragment::TypedIdent(..) => {
                             fragment.end = hole_span.start;
                             debug_assert!(fragment.start <= fragment.end);
-                            if fragment.len() > 0 {
+                            if !fragment.is_empty() {
                                 fragments.push(Fragment::Verbatim(fragment));
                             }
                             fragments.push(frag);
@@ -469,15 +772,34 @@ impl<'a> Parser<'a> {
                             // If this type annotation turned out to annotate a
                             // parameter, then we replace the parameter fragment
                             // that we pushed previously with the new typed
-                            // parameter fragment.
-                            fragments.pop();
-                            fragment = fragments
-                                .pop()
-                                .expect("Must have a fragment before parameter fragment.")
-                                .span();
+                            // parameter fragment. If there is a verbatim
+                            // fragment right before the parameter, then we
+                            // extend that one up to the typed parameter.
+                            match fragments.pop() {
+                                Some(Fragment::Param(..)) => {
+                                    fragment.start = match fragments.last() {
+                                        Some(Fragment::Verbatim(before)) => {
+                                            let start = before.start;
+                                            fragments.pop();
+                                            start
+                                        }
+                                        _ => hole_span.start,
+                                    };
+                                }
+                                _ => {
+                                    let err = ParseError {
+                                        span: hole_span,
+                                        message: "Invalid type annotation, expected \
+                                            a parameter in this statement before the annotation.",
+                                        note: None,
+                                        hint: None,
+                                    };
+                                    return Err(err);
+                                }
+                            }
                             fragment.end = hole_span.start;
                             debug_assert!(fragment.start <= fragment.end);
-                            if fragment.len() > 0 {
+                            if !fragment.is_empty() {
                                 fragments.push(Fragment::Verbatim(fragment));
                             }
                             fragments.push(frag);
@@ -489,7 +811,9 @@ impl<'a> Parser<'a> {
                 }
                 doc::Token::Param => {
                     fragment.end = span.start;
-                    fragments.push(Fragment::Verbatim(fragment));
+                    if !fragment.is_empty() {
+                        fragments.push(Fragment::Verbatim(fragment));
+                    }
                     fragments.push(Fragment::Param(*span));
                     fragment.start = span.end;
                     fragment.end = span.end;
@@ -512,7 +836,50 @@ impl<'a> Parser<'a> {
             }
         }
 
-        self.error("Unexpected end of input, annotated query does not end with ';'.")
+        if !allow_eof {
+            return self.error("Unexpected end of input, annotated query does not end with ';'.");
+        }
+
+        // SQLite does not require a semicolon after the final statement, so
+        // we accept the end of the input as the end of the statement. The
+        // statement then ends at the last token that is not whitespace.
+        self.ensure_bracket_stack_empty()?;
+        let content_end = self.tokens[..self.cursor]
+            .iter()
+            .rev()
+            .find(|(token, _span)| *token != doc::Token::Space)
+            .map(|(_token, span)| span.end)
+            .unwrap_or(fragment.start);
+        fragment.end = cmp::max(fragment.start, content_end);
+        if !fragment.is_empty() {
+            fragments.push(Fragment::Verbatim(fragment));
+        }
+        if fragments.is_empty() {
+            return self.error("Expected a SQL statement here.");
+        }
+
+        let result = Statement { fragments };
+        Ok(result)
+    }
+
+    /// Parse a statement inside a `@begin` block.
+    ///
+    /// When the statement is not terminated because we hit the end of the
+    /// input, the block was not closed either, so add a note that points at
+    /// the start of the block.
+    fn parse_statement_in_block(
+        &mut self,
+        marker_span: Span,
+        unclosed_note: &'static str,
+    ) -> PResult<Statement> {
+        self.parse_statement(false)
+            .map_err(|err| match (self.peek(), err.note) {
+                (None, None) => ParseError {
+                    note: Some((unclosed_note, marker_span)),
+                    ..err
+                },
+                _ => err,
+            })
     }
 
     /// Parse a single section from the document.
@@ -521,26 +888,91 @@ impl<'a> Parser<'a> {
         comments: Vec<Span>,
         comment_lexer: ann::Lexer<'a>,
     ) -> PResult<Query> {
-        let (annotation, stmt_type) = self.parse_annotation(comment_lexer)?;
+        // The caller only enters query mode if the comment starts with a
+        // marker, so the first token is the `@query`, `@begin`, or `@call` marker.
+        let marker_span = comment_lexer.tokens()[0].1;
+
+        // `@param`, `@dialect`, and `@tags` lines can go before or after the
+        // signature, the other comments before the signature are the doc
+        // comment of the query.
+        let mut docs = Vec::with_capacity(comments.len());
+        let mut param_docs = Vec::new();
+        let mut query_dialect = None;
+        let mut tags = Vec::new();
+        for comment in comments {
+            if let Some((marker, dialect)) = self.parse_dialect_marker(comment)? {
+                self.set_query_dialect(&mut query_dialect, marker, dialect)?;
+                continue;
+            }
+            if let Some(line_tags) = self.parse_tags_marker(comment)? {
+                tags.extend(line_tags);
+                continue;
+            }
+            match self.parse_param_doc(comment)? {
+                Some(param_doc) => param_docs.push(param_doc),
+                None => docs.push(comment),
+            }
+        }
+        let (mut annotation, stmt_type) = self.parse_annotation(
+            comment_lexer,
+            &mut param_docs,
+            &mut query_dialect,
+            &mut tags,
+        )?;
+        self.attach_param_docs(&mut annotation, param_docs);
+
+        // The dialect of the query determines which brackets must balance in
+        // its statements, so it overrides the document dialect while we parse
+        // them.
+        let dialect = query_dialect.map(|(_marker, dialect)| dialect);
+        let document_dialect = self.dialect;
+        self.dialect = dialect.unwrap_or(document_dialect);
+        let statements = self.parse_query_statements(stmt_type, marker_span, annotation.name)?;
+        self.dialect = document_dialect;
+
+        let result = Query {
+            docs,
+            annotation,
+            statement_type: stmt_type,
+            dialect,
+            tags,
+            statements,
+        };
+        Ok(result)
+    }
 
-        let mut statements = vec![self.parse_statement()?];
+    /// Parse the statements of a query, and the `@end` marker for a block.
+    fn parse_query_statements(
+        &mut self,
+        stmt_type: StatementType,
+        marker_span: Span,
+        query_name: Span,
+    ) -> PResult<Vec<Statement>> {
+        let unclosed_note = "Unclosed '@begin' block starts here, it should end with '@end'.";
+
+        let mut statements = vec![match stmt_type {
+            StatementType::Single | StatementType::Call => self.parse_statement(true)?,
+            StatementType::Multi => self.parse_statement_in_block(marker_span, unclosed_note)?,
+        }];
 
         match stmt_type {
-            StatementType::Single => {}
+            StatementType::Single | StatementType::Call => {}
             StatementType::Multi => loop {
-                if self.try_parse_end_marker() {
+                if self.try_parse_end_marker(query_name)? {
                     break;
                 }
-                statements.push(self.parse_statement()?);
+                if self.peek().is_none() {
+                    return self.error_with_note(
+                        "Unexpected end of input, expected an '@end' marker.",
+                        marker_span,
+                        unclosed_note,
+                    );
+                }
+                statements.push(self.parse_statement_in_block(marker_span, unclosed_note)?);
             },
         }
 
-        let result = Query {
-            docs: comments,
-            annotation,
-            statements,
-        };
-        Ok(result)
+        Ok(statements)
     }
 }
 
@@ -548,8 +980,8 @@ impl<'a> Parser<'a> {
 mod test {
     use super::Parser;
     use crate::ast::{
-        Annotation, ArgType, ComplexType, Fragment, PrimitiveType, Query, ResultType, Section,
-        SimpleType, Statement, TypedIdent,
+        Annotation, ArgType, ComplexType, Dialect, Fragment, PrimitiveType, Query, ResultType,
+        Section, SimpleType, Statement, StatementType, TypedIdent,
     };
     use crate::error::Error;
     use crate::lexer::document::Lexer;
@@ -561,6 +993,42 @@ mod test {
         f(&mut parser)
     }
 
+    #[test]
+    fn parse_document_counts_queries() {
+        let input = "\
+        -- Verbatim content.
+        create table users (id integer);
+
+        -- @query get_user_ids() ->* i64
+        select id from users;
+
+        -- @query count_users() ->1 i64
+        select count(*) from users;
+        ";
+        with_parser(input, |p| {
+            let doc = p.parse_document().unwrap();
+            assert!(matches!(doc.sections[0], Section::Verbatim(..)));
+            assert_eq!(doc.query_count(), 2);
+        });
+    }
+
+    #[test]
+    fn parse_statement_skips_template_expressions() {
+        let input = "select {{ col }} from t where x = {{ a:b; }};";
+        let tokens = Lexer::new(input).with_template_braces(true).run().unwrap();
+        let mut parser = Parser::new(input, &tokens);
+        let statement = parser.parse_statement(false).unwrap().resolve(input);
+        assert_eq!(statement.fragments, [Fragment::Verbatim(input)]);
+        assert!(parser.peek().is_none());
+
+        // Without template braces, the semicolon inside the template
+        // expression ends the statement while a brace is still open.
+        with_parser(input, |p| {
+            let err = p.parse_statement(false).err().unwrap();
+            assert_eq!(err.message, "Expected '}'.");
+        });
+    }
+
     #[test]
     fn parse_section_handles_newline_in_annotation() {
         let input = "
@@ -575,6 +1043,7 @@ mod test {
             let expected = Section::Query(Query {
                 docs: vec![],
                 annotation: Annotation {
+                    path: Vec::new(),
                     name: "multiline_signature",
                     arguments: ArgType::Args(vec![
                         TypedIdent {
@@ -583,6 +1052,8 @@ mod test {
                                 inner: "str",
                                 type_: PrimitiveType::Str,
                             },
+                            doc: None,
+                            alias: None,
                         },
                         TypedIdent {
                             ident: "value",
@@ -590,6 +1061,8 @@ mod test {
                                 inner: "str",
                                 type_: PrimitiveType::Str,
                             },
+                            doc: None,
+                            alias: None,
                         },
                     ]),
                     result_type: ResultType::Iterator(ComplexType::Simple(SimpleType::Primitive {
@@ -597,6 +1070,9 @@ mod test {
                         type_: PrimitiveType::I64,
                     })),
                 },
+                statement_type: StatementType::Single,
+                dialect: None,
+                tags: Vec::new(),
                 statements: vec![Statement {
                     fragments: vec![Fragment::Verbatim("SELECT * FROM kv;")],
                 }],
@@ -605,6 +1081,92 @@ mod test {
         });
     }
 
+    #[test]
+    fn parse_section_handles_result_type_on_next_line() {
+        let input = "
+        -- @query get_user_name(id: i64)
+        -- ->? str
+        SELECT name FROM users WHERE id = :id;
+        ";
+        with_parser(input, |p| {
+            let result = p.parse_section().unwrap().resolve(input);
+            let query = match result {
+                Section::Query(query) => query,
+                _ => panic!("Expected a query."),
+            };
+            assert_eq!(query.annotation.name, "get_user_name");
+            assert_eq!(
+                query.annotation.result_type,
+                ResultType::Option(ComplexType::Simple(SimpleType::Primitive {
+                    inner: "str",
+                    type_: PrimitiveType::Str,
+                }))
+            );
+            assert_eq!(query.docs, Vec::<&str>::new());
+        });
+
+        // Errors in the continuation line point into that line.
+        let input = "-- @query get_user_name(id: i64)\n-- -> str\nSELECT 1;";
+        with_parser(input, |p| {
+            let err = p.parse_section().err().unwrap();
+            assert_eq!(err.span.resolve(input), "->");
+            assert_eq!(err.span.start, input.find("->").unwrap());
+        });
+    }
+
+    #[test]
+    fn parse_section_attaches_param_docs() {
+        let input = "
+        -- Look up a user.
+        -- @param id The user's primary key.
+        -- @query get_user(id: i64, name: str) ->? str
+        -- @param name   The user's name.  
+        -- @param email The user's email.
+        SELECT email FROM users WHERE id = :id AND name = :name;
+        ";
+        with_parser(input, |p| {
+            let result = p.parse_section().unwrap().resolve(input);
+            let query = match result {
+                Section::Query(query) => query,
+                _ => panic!("Expected a query."),
+            };
+            assert_eq!(query.docs, vec![" Look up a user."]);
+            let args = match query.annotation.arguments {
+                ArgType::Args(args) => args,
+                _ => panic!("Expected individual arguments."),
+            };
+            assert_eq!(args[0].doc, Some("The user's primary key."));
+            assert_eq!(args[1].doc, Some("The user's name."));
+
+            // The `email` argument does not exist, that is a warning.
+            assert_eq!(p.warnings.len(), 1);
+            assert_eq!(p.warnings[0].span.resolve(input), "email");
+        });
+    }
+
+    #[test]
+    fn parse_section_ignores_annotation_after_code() {
+        let input = "SELECT 1; -- @query f()\nSELECT 2;";
+        with_parser(input, |p| {
+            let result = p.parse_section().unwrap();
+            assert_eq!(result, Section::Verbatim(Span { start: 0, end: 33 }));
+            assert!(p.peek().is_none());
+            assert_eq!(p.warnings.len(), 1);
+            assert_eq!(p.warnings[0].span.resolve(input), "@query");
+        });
+
+        // When the code before the annotation belongs to a previous query,
+        // the annotation is still not at the start of the line.
+        let input = "-- @query f()\nSELECT 1; -- @query g()\nSELECT 2;";
+        with_parser(input, |p| {
+            let first = p.parse_section().unwrap().resolve(input);
+            assert!(matches!(first, Section::Query(q) if q.annotation.name == "f"));
+            let second = p.parse_section().unwrap();
+            assert!(matches!(second, Section::Verbatim(..)));
+            assert_eq!(p.warnings.len(), 1);
+        });
+    }
+
     #[test]
     fn parse_section_handles_multi_statement_query() {
         let input = "
@@ -618,10 +1180,14 @@ mod test {
             let expected = Section::Query(Query {
                 docs: vec![],
                 annotation: Annotation {
+                    path: Vec::new(),
                     name: "drop_schema",
                     arguments: ArgType::Args(vec![]),
                     result_type: ResultType::Unit,
                 },
+                statement_type: StatementType::Multi,
+                dialect: None,
+                tags: Vec::new(),
                 statements: vec![
                     Statement {
                         fragments: vec![Fragment::Verbatim("DROP TABLE albums;")],
@@ -635,6 +1201,173 @@ mod test {
         });
     }
 
+    #[test]
+    fn parse_section_rejects_mismatched_end_name() {
+        let input = "
+        -- @begin drop_schema()
+        DROP TABLE albums;
+        DROP TABLE artists;
+        -- @end create_schema
+        ";
+        with_parser(input, |p| {
+            let err = p.parse_section().err().unwrap();
+            assert_eq!(err.span.resolve(input), "create_schema");
+            let (_note, note_span) = err.note.unwrap();
+            assert_eq!(note_span.resolve(input), "drop_schema");
+        });
+
+        // Without a name, the marker ends any query.
+        let input = "-- @begin drop_schema()\nDROP TABLE albums;\n-- @end\n";
+        with_parser(input, |p| {
+            assert!(p.parse_section().is_ok());
+        });
+    }
+
+    #[test]
+    fn parse_section_ignores_semicolons_in_strings_and_comments() {
+        let input = "
+        -- @begin q()
+        select ';' as x;
+        select 1 /* ; */;
+        select \"a;b\" from t;
+        -- @end q
+        ";
+        with_parser(input, |p| {
+            let result = p.parse_section().unwrap().resolve(input);
+            let statements = match result {
+                Section::Query(q) => q.statements,
+                Section::Verbatim(..) => panic!("Expected a query."),
+            };
+            let expected = vec![
+                Statement {
+                    fragments: vec![Fragment::Verbatim("select ';' as x;")],
+                },
+                Statement {
+                    fragments: vec![
+                        Fragment::Verbatim("select 1 "),
+                        Fragment::Comment("/* ; */"),
+                        Fragment::Verbatim(";"),
+                    ],
+                },
+                Statement {
+                    fragments: vec![Fragment::Verbatim("select \"a;b\" from t;")],
+                },
+            ];
+            assert_eq!(statements, expected);
+        });
+    }
+
+    #[test]
+    fn parse_statement_keeps_comments_as_separate_fragments() {
+        let input = "select a, -- The first column.\n  b /*: str */ /* b */ from t;";
+        with_parser(input, |p| {
+            let result = p.parse_statement(false).unwrap().resolve(input);
+            let expected = Statement {
+                fragments: vec![
+                    Fragment::Verbatim("select a, "),
+                    Fragment::Comment("-- The first column."),
+                    Fragment::Verbatim("\n  "),
+                    Fragment::TypedIdent(
+                        "b /*: str */",
+                        TypedIdent {
+                            ident: "b",
+                            type_: SimpleType::Primitive {
+                                inner: "str",
+                                type_: PrimitiveType::Str,
+                            },
+                            doc: None,
+                            alias: None,
+                        },
+                    ),
+                    Fragment::Verbatim(" "),
+                    Fragment::Comment("/* b */"),
+                    Fragment::Verbatim(" from t;"),
+                ],
+            };
+            assert_eq!(result, expected);
+        });
+
+        // A comment at the end of the input is not part of a verbatim fragment.
+        let input = "select 1 -- One.";
+        with_parser(input, |p| {
+            let result = p.parse_statement(true).unwrap().resolve(input);
+            let expected = Statement {
+                fragments: vec![
+                    Fragment::Verbatim("select 1 "),
+                    Fragment::Comment("-- One."),
+                ],
+            };
+            assert_eq!(result, expected);
+        });
+    }
+
+    #[test]
+    fn parse_section_accepts_missing_semicolon_at_end_of_input() {
+        let input = "-- @query f()\nSELECT 1";
+        with_parser(input, |p| {
+            let result = p.parse_section().unwrap().resolve(input);
+            let expected = Section::Query(Query {
+                docs: vec![],
+                annotation: Annotation {
+                    path: Vec::new(),
+                    name: "f",
+                    arguments: ArgType::Args(vec![]),
+                    result_type: ResultType::Unit,
+                },
+                statement_type: StatementType::Single,
+                dialect: None,
+                tags: Vec::new(),
+                statements: vec![Statement {
+                    fragments: vec![Fragment::Verbatim("SELECT 1")],
+                }],
+            });
+            assert_eq!(result, expected);
+        });
+
+        // Trailing whitespace is not part of the statement.
+        let input = "-- @query f(x: i64)\nSELECT :x \n";
+        with_parser(input, |p| {
+            let result = p.parse_section().unwrap().resolve(input);
+            let statements = match result {
+                Section::Query(q) => q.statements,
+                Section::Verbatim(..) => panic!("Expected a query."),
+            };
+            let expected = vec![Statement {
+                fragments: vec![Fragment::Verbatim("SELECT "), Fragment::Param(":x")],
+            }];
+            assert_eq!(statements, expected);
+        });
+
+        // A statement can consist of only a parameter, then there is no empty
+        // verbatim fragment before it.
+        let input = "-- @query f(x: i64)\n:x;\n-- @query g()\n:y /* :i64 */;";
+        with_parser(input, |p| {
+            let statements = match p.parse_section().unwrap().resolve(input) {
+                Section::Query(q) => q.statements,
+                Section::Verbatim(..) => panic!("Expected a query."),
+            };
+            let expected = vec![Statement {
+                fragments: vec![Fragment::Param(":x"), Fragment::Verbatim(";")],
+            }];
+            assert_eq!(statements, expected);
+
+            let statements = match p.parse_section().unwrap().resolve(input) {
+                Section::Query(q) => q.statements,
+                Section::Verbatim(..) => panic!("Expected a query."),
+            };
+            assert_eq!(statements[0].fragments.len(), 2);
+            assert!(matches!(
+                statements[0].fragments[0],
+                Fragment::TypedParam(..)
+            ));
+            assert_eq!(statements[0].fragments[1], Fragment::Verbatim(";"));
+        });
+
+        // In a multi-statement query, the semicolon is still required.
+        let input = "-- @begin f()\nSELECT 1;\nSELECT 2";
+        with_parser(input, |p| assert!(p.parse_section().is_err()));
+    }
+
     #[test]
     fn unmatched_paren_at_statement_end_causes_error() {
         let input = "
@@ -649,6 +1382,139 @@ mod test {
         });
     }
 
+    #[test]
+    fn postgres_dialect_does_not_balance_braces() {
+        // Braces in the JSON paths are inside strings, so they are fine in any
+        // dialect. The stray '}' and ']' are not.
+        let input = "
+        -- @query get_paths() ->* str
+        SELECT data #>> '{a,b}' FROM docs WHERE data @? '$.tags[*]' AND x = '}' || } ];
+        ";
+        let tokens = Lexer::new(input).run().unwrap();
+        let mut parser = Parser::new(input, &tokens);
+        let err: Box<dyn Error> = parser.parse_section().err().unwrap().into();
+        assert_eq!(err.message(), "Found unmatched '}'.");
+
+        let mut parser = Parser::new(input, &tokens).with_dialect(Dialect::Postgres);
+        let err: Box<dyn Error> = parser.parse_section().err().unwrap().into();
+        assert_eq!(err.message(), "Found unmatched ']'.");
+
+        let mut parser = Parser::new(input, &tokens).with_dialect(Dialect::Sqlite);
+        assert!(parser.parse_section().is_ok());
+
+        // Parens remain strict in every dialect.
+        let input = "-- @query q()\nSELECT data #>> '{a}' FROM (docs;";
+        let tokens = Lexer::new(input).run().unwrap();
+        let mut parser = Parser::new(input, &tokens).with_dialect(Dialect::Postgres);
+        let err: Box<dyn Error> = parser.parse_section().err().unwrap().into();
+        assert_eq!(err.message(), "Expected ')'.");
+    }
+
+    #[test]
+    fn dialect_marker_overrides_dialect_for_query() {
+        // The marker can go before or after the signature, and it is not part
+        // of the doc comment.
+        let inputs = [
+            "-- Get the paths.\n-- @dialect sqlite\n-- @query get_paths() ->* str\nSELECT x ];",
+            "-- Get the paths.\n-- @query get_paths()\n-- @dialect sqlite\n-- ->* str\nSELECT x ];",
+        ];
+        for input in &inputs {
+            let tokens = Lexer::new(input).run().unwrap();
+            let mut parser = Parser::new(input, &tokens).with_dialect(Dialect::Postgres);
+            let query = match parser.parse_section().unwrap() {
+                Section::Query(q) => q.resolve(input),
+                Section::Verbatim(..) => panic!("Expected a query."),
+            };
+            assert_eq!(query.dialect, Some(Dialect::Sqlite), "{}", input);
+            assert_eq!(query.docs, [" Get the paths."], "{}", input);
+            assert_eq!(
+                query.annotation.result_type,
+                ResultType::Iterator(ComplexType::Simple(SimpleType::Primitive {
+                    inner: "str",
+                    type_: PrimitiveType::Str,
+                })),
+            );
+        }
+
+        // The override applies only to the query that has the marker.
+        let input = "-- @dialect sqlite\n-- @query a()\nSELECT ];\n-- @query b()\nSELECT ];";
+        let tokens = Lexer::new(input).run().unwrap();
+        let mut parser = Parser::new(input, &tokens).with_dialect(Dialect::Postgres);
+        let err: Box<dyn Error> = parser.parse_document().err().unwrap().into();
+        assert_eq!(err.message(), "Found unmatched ']'.");
+        assert_eq!(err.span().start, input.rfind(']').unwrap());
+
+        let errors = [
+            (
+                "-- @dialect\n-- @query q()\nSELECT 1;",
+                "@dialect",
+                "Expected a dialect after '@dialect'.",
+            ),
+            (
+                "-- @dialect mysql\n-- @query q()\nSELECT 1;",
+                "mysql",
+                "Unknown dialect.",
+            ),
+            (
+                "-- @dialect sqlite\n-- @query q()\n-- @dialect postgres\nSELECT 1;",
+                "@dialect",
+                "Redefinition of the dialect, a query can have only one '@dialect'.",
+            ),
+        ];
+        for (input, span, message) in &errors {
+            with_parser(input, |p| {
+                let err = p.parse_section().err().unwrap();
+                assert_eq!(err.message, *message, "Input: {}", input);
+                assert_eq!(err.span.resolve(input), *span, "Input: {}", input);
+            });
+        }
+    }
+
+    #[test]
+    fn tags_marker_sets_query_tags() {
+        // Like `@dialect`, the marker can go before or after the signature,
+        // and it is not part of the doc comment. Multiple lines add up.
+        let inputs = [
+            "-- Get the paths.\n-- @tags admin, batch\n-- @query get_paths() ->* str\nSELECT 1;",
+            "-- Get the paths.\n-- @query get_paths()\n-- @tags admin\n-- @tags batch\n-- ->* str\nSELECT 1;",
+        ];
+        for input in &inputs {
+            with_parser(input, |p| {
+                let query = match p.parse_section().unwrap() {
+                    Section::Query(q) => q.resolve(input),
+                    Section::Verbatim(..) => panic!("Expected a query."),
+                };
+                assert_eq!(query.tags, ["admin", "batch"], "{}", input);
+                assert_eq!(query.docs, [" Get the paths."], "{}", input);
+            });
+        }
+
+        let errors = [
+            (
+                "-- @tags\n-- @query q()\nSELECT 1;",
+                "@tags",
+                "Expected a tag name.",
+            ),
+            (
+                "-- @tags a,\n-- @query q()\nSELECT 1;",
+                ",",
+                "Expected a tag name.",
+            ),
+            (
+                "-- @tags a b\n-- @query q()\nSELECT 1;",
+                "b",
+                "Expected ',' or the end of the line after a tag.",
+            ),
+        ];
+        for (input, span, message) in &errors {
+            with_parser(input, |p| {
+                let err = p.parse_section().err().unwrap();
+                assert_eq!(err.message, *message, "Input: {}", input);
+                assert_eq!(err.span.resolve(input), *span, "Input: {}", input);
+            });
+        }
+    }
+
     #[test]
     fn empty_type_annotation_is_error() {
         let input = r#"
@@ -689,6 +1555,8 @@ mod test {
                             inner: "str",
                             type_: PrimitiveType::Str,
                         },
+                        doc: None,
+                        alias: None,
                     },
                 ),
                 Fragment::Verbatim(" FROM t;"),
@@ -697,6 +1565,22 @@ mod test {
         });
     }
 
+    #[test]
+    fn it_keeps_comments_with_other_markers_verbatim() {
+        let inputs = [
+            "-- @todo Add more queries here.\n-- Some more comments.",
+            "-- @\n",
+            "/* @ */",
+            "-- @end\n-- @queryx f()\n",
+        ];
+        for input in inputs {
+            with_parser(input, |p| {
+                let result = p.parse_section().unwrap();
+                assert_eq!(result.resolve(input), Section::Verbatim(input));
+            });
+        }
+    }
+
     #[test]
     fn it_parses_a_sinple_comment_without_newline() {
         // The fuzzer found this input to cause OOM. The problem was not in the
@@ -716,10 +1600,14 @@ mod test {
             let expected = Section::Query(Query {
                 docs: vec![],
                 annotation: Annotation {
+                    path: Vec::new(),
                     name: "q",
                     arguments: ArgType::Args(vec![]),
                     result_type: ResultType::Unit,
                 },
+                statement_type: StatementType::Single,
+                dialect: None,
+                tags: Vec::new(),
                 statements: vec![Statement {
                     fragments: vec![
                         Fragment::Verbatim("SELECT a from b where c = "),
@@ -731,6 +1619,8 @@ mod test {
                                     inner: "str",
                                     type_: PrimitiveType::Str,
                                 },
+                                doc: None,
+                                alias: None,
                             },
                         ),
                         Fragment::Verbatim(";"),
@@ -751,6 +1641,52 @@ mod test {
         });
     }
 
+    #[test]
+    fn parse_section_reports_unclosed_begin_block() {
+        let input = "-- @begin drop_all()\nDROP TABLE a;\nDROP TABLE b;\n";
+        with_parser(input, |p| {
+            let err = p.parse_section().err().unwrap();
+            assert!(err.message.contains("expected an '@end' marker"));
+            let (note, note_span) = err.note.unwrap();
+            assert!(note.contains("Unclosed '@begin' block"));
+            assert_eq!(note_span.resolve(input), "@begin");
+        });
+
+        // If the last statement is not terminated either, we still point out
+        // the unclosed block.
+        let input = "-- @begin drop_all()\nDROP TABLE a;\nDROP TABLE b\n";
+        with_parser(input, |p| {
+            let err = p.parse_section().err().unwrap();
+            assert!(err.message.contains("does not end with ';'"));
+            let (_note, note_span) = err.note.unwrap();
+            assert_eq!(note_span.resolve(input), "@begin");
+        });
+    }
+
+    #[test]
+    fn it_does_not_crash_on_type_annotation_at_start_of_query() {
+        let input = "--@query q()\n/*:i64*/;";
+        with_parser(input, |p| {
+            let result = p.parse_section();
+            assert!(result.is_err());
+        });
+    }
+
+    #[test]
+    fn it_does_not_crash_on_type_annotation_at_start_of_input() {
+        // Normally we would not get here without an annotation before, but the
+        // type annotation parser itself should not assume that there are
+        // tokens before the comment.
+        let input = "/*:i64*/";
+        with_parser(input, |p| {
+            // Tokens are: CommentStart, CommentInner, CommentEnd.
+            p.cursor = 1;
+            let type_span = Span { start: 3, end: 6 };
+            let err = p.parse_type_annotation(type_span).err().unwrap();
+            assert_eq!(err.span.resolve(input), ":i64");
+        });
+    }
+
     #[test]
     fn it_does_not_crash_on_invalid_type_annotation_after_param() {
         // The fuzzer found this input to trigger an assertion failure.
@@ -0,0 +1,248 @@
+// Squiller -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2023 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Column types read from `create table` statements in a schema file.
+//!
+//! This is not a full SQL parser. We only look for `create table` statements,
+//! and for every column definition in them, we look at the column name and the
+//! name of its type. Everything else in the schema is ignored.
+
+use std::collections::HashMap;
+
+use crate::ast::PrimitiveType;
+use crate::error::{PResult, ParseError};
+use crate::lexer::document::{Lexer, Token};
+use crate::Span;
+
+/// Column types for the tables defined in a schema.
+#[derive(Debug, Default)]
+pub struct Schema {
+    /// Column types, keyed on lowercase table and column name.
+    columns: HashMap<(String, String), PrimitiveType>,
+}
+
+/// Map an SQL type name to a primitive type, if we know a suitable one.
+///
+/// This follows the rules that SQLite uses to determine column affinity, with
+/// the addition of `string`, which SQLite considers numeric.
+fn primitive_type_from_sql(type_name: &str) -> Option<PrimitiveType> {
+    let t = type_name.to_ascii_lowercase();
+    if t == "smallint" || t == "int2" {
+        Some(PrimitiveType::I16)
+    } else if t.contains("int") {
+        Some(PrimitiveType::I64)
+    } else if t.contains("char") || t.contains("clob") || t.contains("text") || t == "string" {
+        Some(PrimitiveType::Str)
+    } else if t.contains("blob") {
+        Some(PrimitiveType::Bytes)
+    } else if t.contains("real") || t.contains("floa") || t.contains("doub") {
+        Some(PrimitiveType::F64)
+    } else {
+        None
+    }
+}
+
+struct SchemaParser<'a> {
+    input: &'a str,
+    /// The tokens, without whitespace and comments.
+    tokens: Vec<(Token, Span)>,
+    cursor: usize,
+}
+
+impl<'a> SchemaParser<'a> {
+    fn new(input: &'a str, tokens: Vec<(Token, Span)>) -> Self {
+        let tokens = tokens
+            .into_iter()
+            .filter(|(token, _span)| match token {
+                Token::Space | Token::CommentStart | Token::CommentInner | Token::CommentEnd => {
+                    false
+                }
+                _ => true,
+            })
+            .collect();
+        SchemaParser {
+            input,
+            tokens,
+            cursor: 0,
+        }
+    }
+
+    fn error<T>(&self, message: &'static str) -> PResult<T> {
+        let span = match self.tokens.get(self.cursor) {
+            Some((_token, span)) => *span,
+            None => Span {
+                start: self.input.len(),
+                end: self.input.len(),
+            },
+        };
+        Err(ParseError {
+            span,
+            message,
+            note: None,
+            hint: None,
+        })
+    }
+
+    /// Return the token under the cursor, with its content.
+    fn peek(&self) -> Option<(Token, &'a str)> {
+        self.tokens
+            .get(self.cursor)
+            .map(|(token, span)| (*token, span.resolve(self.input)))
+    }
+
+    /// Consume the identifier under the cursor if it is the given keyword.
+    fn consume_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some((Token::Ident, ident)) if ident.eq_ignore_ascii_case(keyword) => {
+                self.cursor += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Consume a table or column name, which may be quoted.
+    fn consume_name(&mut self) -> Option<String> {
+        let name = match self.peek()? {
+            (Token::Ident, ident) => ident,
+            (Token::DoubleQuoted, quoted) => &quoted[1..quoted.len() - 1],
+            _ => return None,
+        };
+        self.cursor += 1;
+        Some(name.to_ascii_lowercase())
+    }
+
+    /// Skip to the next `,` or `)` at the current nesting level.
+    fn skip_to_column_end(&mut self) -> PResult<()> {
+        let mut depth = 0_u32;
+        loop {
+            match self.peek() {
+                None => return self.error("Unexpected end of input, expected ')'."),
+                Some((Token::LParen, _)) => depth += 1,
+                Some((Token::RParen, _)) if depth == 0 => return Ok(()),
+                Some((Token::RParen, _)) => depth -= 1,
+                Some((Token::Punct, ",")) if depth == 0 => return Ok(()),
+                Some(_) => {}
+            }
+            self.cursor += 1;
+        }
+    }
+
+    /// Parse a `create table` statement, the cursor should be past `create`.
+    fn parse_create_table(&mut self, schema: &mut Schema) -> PResult<()> {
+        self.consume_keyword("temp");
+        self.consume_keyword("temporary");
+        if !self.consume_keyword("table") {
+            // Could be `create index` or something else, that's not for us.
+            return Ok(());
+        }
+        let is_if_not_exists = self.consume_keyword("if");
+        if is_if_not_exists && !(self.consume_keyword("not") && self.consume_keyword("exists")) {
+            return self.error("Expected 'if not exists' here.");
+        }
+        let table = match self.consume_name() {
+            Some(name) => name,
+            None => return self.error("Expected a table name here."),
+        };
+        match self.peek() {
+            Some((Token::LParen, _)) => self.cursor += 1,
+            // A `create table ... as select`, we can't learn the types from it.
+            _ => return Ok(()),
+        }
+
+        loop {
+            // Table constraints can be mixed with column definitions.
+            let constraints = ["constraint", "primary", "unique", "check", "foreign"];
+            let is_constraint = match self.peek() {
+                Some((Token::Ident, t)) => constraints.iter().any(|kw| t.eq_ignore_ascii_case(kw)),
+                _ => false,
+            };
+
+            if !is_constraint {
+                let column = match self.consume_name() {
+                    Some(name) => name,
+                    None => return self.error("Expected a column name here."),
+                };
+                if let Some((Token::Ident, type_name)) = self.peek() {
+                    if let Some(type_) = primitive_type_from_sql(type_name) {
+                        schema.columns.insert((table.clone(), column), type_);
+                    }
+                }
+            }
+
+            self.skip_to_column_end()?;
+            match self.peek() {
+                Some((Token::RParen, _)) => {
+                    self.cursor += 1;
+                    return Ok(());
+                }
+                // Must be a comma, otherwise `skip_to_column_end` would not
+                // have returned.
+                _ => self.cursor += 1,
+            }
+        }
+    }
+
+    fn parse(mut self) -> PResult<Schema> {
+        let mut schema = Schema::default();
+        while let Some((token, content)) = self.peek() {
+            self.cursor += 1;
+            if token == Token::Ident && content.eq_ignore_ascii_case("create") {
+                self.parse_create_table(&mut schema)?;
+            }
+        }
+        Ok(schema)
+    }
+}
+
+impl Schema {
+    /// Read the column types from the `create table` statements in the input.
+    pub fn parse(input: &str) -> PResult<Schema> {
+        let tokens = Lexer::new(input).run()?;
+        SchemaParser::new(input, tokens).parse()
+    }
+
+    /// Return the type of the column, if the schema defines it.
+    pub fn get(&self, table: &str, column: &str) -> Option<PrimitiveType> {
+        let key = (table.to_ascii_lowercase(), column.to_ascii_lowercase());
+        self.columns.get(&key).copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Schema;
+    use crate::ast::PrimitiveType;
+
+    #[test]
+    fn parse_reads_column_types() {
+        let input = "
+            -- Comments are ignored.
+            create table if not exists users
+              ( id    integer primary key
+              , name  varchar(64) not null
+              , email string not null
+              , score real check (score >= 0.0)
+              , photo blob
+              , age   smallint
+              , other numeric
+              , unique (name, email)
+              );
+            create index ix_users_email on users (email);
+            ";
+        let schema = Schema::parse(input).unwrap();
+        assert_eq!(schema.get("users", "id"), Some(PrimitiveType::I64));
+        assert_eq!(schema.get("users", "name"), Some(PrimitiveType::Str));
+        assert_eq!(schema.get("Users", "Email"), Some(PrimitiveType::Str));
+        assert_eq!(schema.get("users", "score"), Some(PrimitiveType::F64));
+        assert_eq!(schema.get("users", "photo"), Some(PrimitiveType::Bytes));
+        assert_eq!(schema.get("users", "age"), Some(PrimitiveType::I16));
+        assert_eq!(schema.get("users", "other"), None);
+        assert_eq!(schema.get("users", "unique"), None);
+        assert_eq!(schema.get("ix_users_email", "email"), None);
+    }
+}
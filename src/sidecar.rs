@@ -0,0 +1,469 @@
+// Squiller -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2023 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Query annotations read from a sidecar TOML file, set with `--annotations`.
+//!
+//! For inputs that should not contain annotation comments, a TOML file maps
+//! query names to signatures, in the order of the statements:
+//!
+//! ```toml
+//! select_user_by_id = "(id: i64) ->1 User"
+//! count_users = "() ->1 i64"
+//! ```
+//!
+//! We match the entries to the statements positionally, and insert an
+//! `@query` annotation before every statement. The result is parsed like any
+//! other input, and a `SourceMap` maps the spans of errors in it back to the
+//! input or the TOML file. This is not a full TOML parser, it only understands
+//! top-level keys with string values, and comments.
+
+use crate::error::{PResult, ParseError};
+use crate::lexer::document::{Lexer, Token};
+use crate::Span;
+
+/// A query name and its signature, from one line of the TOML file.
+#[derive(Debug, Eq, PartialEq)]
+struct Entry {
+    /// Span of the key in the TOML file, for error reporting.
+    span: Span,
+    /// Span of the string with the signature, including quotes.
+    value_span: Span,
+    /// Where the signature starts in the TOML file, if it contains no escape
+    /// sequences, so offsets in the signature map directly to the file.
+    signature_start: Option<usize>,
+    name: String,
+    signature: String,
+}
+
+/// An annotation that `annotate` inserted, and the entry it came from.
+#[derive(Debug, Eq, PartialEq)]
+struct Insertion {
+    /// Byte offset of the inserted text in the annotated source.
+    start: usize,
+    /// Byte offset of the signature in the annotated source.
+    signature_start: usize,
+    /// Byte offset of the end of the inserted text in the annotated source.
+    end: usize,
+    /// Total length of this and all preceding insertions.
+    inserted_len: usize,
+    /// Span of the key in the TOML file.
+    key_span: Span,
+    /// Span of the signature string in the TOML file, including quotes.
+    value_span: Span,
+    /// Where the signature starts in the TOML file, if it maps directly.
+    value_signature_start: Option<usize>,
+}
+
+/// Where a span in the annotated source came from.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Origin {
+    /// The span is part of the input, this is its span there.
+    Input(Span),
+    /// The span is part of an inserted annotation, this is the span of its
+    /// entry in the TOML file.
+    Sidecar(Span),
+}
+
+/// The annotations that `annotate` inserted into one input.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    insertions: Vec<Insertion>,
+}
+
+impl SourceMap {
+    /// Map an offset in the annotated source to the original input.
+    ///
+    /// An offset inside an insertion maps to where the annotation was inserted.
+    fn input_offset(&self, offset: usize) -> usize {
+        let mut removed = 0;
+        for insertion in &self.insertions {
+            if offset < insertion.start {
+                break;
+            }
+            removed = insertion.inserted_len - insertion.end.saturating_sub(offset);
+        }
+        offset - removed
+    }
+
+    /// Return where a span in the annotated source came from.
+    ///
+    /// A span that starts in an inserted annotation maps to its entry in the
+    /// TOML file: to the exact location in the signature when possible, or
+    /// else to the signature string or the key as a whole.
+    pub fn origin(&self, span: Span) -> Origin {
+        let insertion = self
+            .insertions
+            .iter()
+            .find(|ins| ins.start <= span.start && span.start < ins.end);
+
+        let ins = match insertion {
+            None => {
+                return Origin::Input(Span {
+                    start: self.input_offset(span.start),
+                    end: self.input_offset(span.end),
+                })
+            }
+            Some(ins) => ins,
+        };
+
+        if span.start < ins.signature_start {
+            return Origin::Sidecar(ins.key_span);
+        }
+        match ins.value_signature_start {
+            // The span of an error at the end of the signature can include the
+            // newline that ends the annotation, stop at the closing quote.
+            Some(start) if span.end <= ins.end => Origin::Sidecar(Span {
+                start: start + span.start - ins.signature_start,
+                end: start + span.end.min(ins.end - 1) - ins.signature_start,
+            }),
+            _ => Origin::Sidecar(ins.value_span),
+        }
+    }
+}
+
+/// The entries of a sidecar file, that have not been matched to a statement yet.
+#[derive(Debug)]
+pub struct Sidecar {
+    entries: Vec<Entry>,
+    cursor: usize,
+}
+
+fn error<T>(start: usize, end: usize, message: &'static str) -> PResult<T> {
+    Err(ParseError {
+        span: Span { start, end },
+        message,
+        note: None,
+        hint: None,
+    })
+}
+
+/// Parse a TOML key or string at the start of `line`.
+///
+/// Returns the unescaped content, and the length of the token in `line`.
+/// The `offset` is the position of `line` in the input, for error reporting.
+fn parse_string(line: &str, offset: usize) -> PResult<(String, usize)> {
+    let bytes = line.as_bytes();
+    match bytes.first() {
+        // A literal string, there are no escapes.
+        Some(b'\'') => match line[1..].find('\'') {
+            Some(end) => Ok((line[1..end + 1].to_string(), end + 2)),
+            None => error(offset, offset + line.len(), "Unterminated string."),
+        },
+        Some(b'"') => {
+            let mut result = String::new();
+            let mut chars = line.char_indices().skip(1);
+            while let Some((i, ch)) = chars.next() {
+                match ch {
+                    '"' => return Ok((result, i + 1)),
+                    '\\' => match chars.next() {
+                        Some((_, '"')) => result.push('"'),
+                        Some((_, '\\')) => result.push('\\'),
+                        Some((_, 'n')) => result.push('\n'),
+                        Some((_, 't')) => result.push('\t'),
+                        _ => {
+                            return error(
+                                offset + i,
+                                offset + i + 2.min(line.len() - i),
+                                "Unsupported escape sequence.",
+                            )
+                        }
+                    },
+                    _ => result.push(ch),
+                }
+            }
+            error(offset, offset + line.len(), "Unterminated string.")
+        }
+        _ => {
+            let len = line
+                .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_' || ch == '-'))
+                .unwrap_or(line.len());
+            match len {
+                0 => error(offset, offset + 1, "Expected a query name here."),
+                _ => Ok((line[..len].to_string(), len)),
+            }
+        }
+    }
+}
+
+impl Sidecar {
+    /// Parse the entries from the TOML file.
+    pub fn parse(input: &str) -> PResult<Sidecar> {
+        let mut entries = Vec::new();
+        let mut offset = 0;
+
+        for line in input.split_inclusive('\n') {
+            let line_start = offset;
+            offset += line.len();
+
+            let trimmed = line.trim_start();
+            let mut pos = line_start + line.len() - trimmed.len();
+            let trimmed = trimmed.trim_end();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            if trimmed.starts_with('[') {
+                return error(
+                    pos,
+                    pos + trimmed.len(),
+                    "Tables are not supported, the entries must be at the top level.",
+                );
+            }
+
+            let (name, len) = parse_string(trimmed, pos)?;
+            let span = Span {
+                start: pos,
+                end: pos + len,
+            };
+            let rest = trimmed[len..].trim_start();
+            pos += trimmed.len() - rest.len();
+            if !rest.starts_with('=') {
+                return error(pos, pos + 1, "Expected '=' after the query name.");
+            }
+            let rest_value = rest[1..].trim_start();
+            pos += rest.len() - rest_value.len();
+            if !(rest_value.starts_with('"') || rest_value.starts_with('\'')) {
+                return error(pos, pos + 1, "Expected a string with the signature here.");
+            }
+            let (signature, len) = parse_string(rest_value, pos)?;
+            let value_span = Span {
+                start: pos,
+                end: pos + len,
+            };
+            // Without escapes, the signature is the literal between the quotes.
+            let signature_start = match len == signature.len() + 2 {
+                true => Some(pos + 1),
+                false => None,
+            };
+            let trailing = rest_value[len..].trim_start();
+            if !(trailing.is_empty() || trailing.starts_with('#')) {
+                let start = pos + rest_value.len() - trailing.len();
+                return error(start, start + 1, "Expected the end of the line here.");
+            }
+
+            entries.push(Entry {
+                span,
+                value_span,
+                signature_start,
+                name,
+                signature,
+            });
+        }
+
+        Ok(Sidecar { entries, cursor: 0 })
+    }
+
+    /// Insert an `@query` annotation before every statement in the input.
+    ///
+    /// Every statement takes the next entry of the sidecar file, so when there
+    /// are multiple inputs, the entries continue where the previous input left
+    /// off. Comments directly above a statement become its documentation.
+    /// Returns the annotated source, and the map to report errors in it.
+    pub fn annotate(&mut self, input: &str) -> PResult<(String, SourceMap)> {
+        let tokens = Lexer::new(input).run()?;
+        let mut result = String::with_capacity(input.len());
+        let mut source_map = SourceMap::default();
+        let mut inserted_len = 0;
+        let mut copied = 0;
+        let mut in_statement = false;
+
+        for (token, span) in tokens {
+            match token {
+                Token::Space | Token::CommentStart | Token::CommentInner | Token::CommentEnd => {
+                    continue
+                }
+                Token::Semicolon => {
+                    in_statement = false;
+                    continue;
+                }
+                _ if in_statement => continue,
+                _ => {}
+            }
+            in_statement = true;
+
+            let entry = match self.entries.get(self.cursor) {
+                Some(entry) => entry,
+                None => {
+                    return error(
+                        span.start,
+                        span.end,
+                        "This statement has no entry in the annotations file.",
+                    )
+                }
+            };
+            self.cursor += 1;
+
+            result.push_str(&input[copied..span.start]);
+            let start = result.len();
+            if !(result.is_empty() || result.ends_with('\n')) {
+                result.push('\n');
+            }
+            result.push_str("-- @query ");
+            result.push_str(&entry.name);
+            let signature_start = result.len();
+            result.push_str(&entry.signature.replace('\n', "\n-- "));
+            result.push('\n');
+            copied = span.start;
+
+            inserted_len += result.len() - start;
+            source_map.insertions.push(Insertion {
+                start,
+                signature_start,
+                end: result.len(),
+                inserted_len,
+                key_span: entry.span,
+                value_span: entry.value_span,
+                value_signature_start: entry.signature_start,
+            });
+        }
+
+        result.push_str(&input[copied..]);
+        Ok((result, source_map))
+    }
+
+    /// Return an error for the first entry that no statement used, if any.
+    pub fn check_all_used(&self) -> PResult<()> {
+        match self.entries.get(self.cursor) {
+            Some(entry) => error(
+                entry.span.start,
+                entry.span.end,
+                "This entry has no statement in the input.",
+            ),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Entry, Origin, Sidecar};
+    use crate::target::{Options, Target};
+    use crate::{NamedDocument, Span};
+    use std::path::Path;
+
+    #[test]
+    fn parse_reads_keys_and_strings() {
+        let input = r#"
+# Comments and blank lines are ignored.
+get_name = "(id: i64) ->1 str"
+"count" = '() ->1 i64'  # Trailing comment.
+"#;
+        let sidecar = Sidecar::parse(input).unwrap();
+        assert_eq!(
+            sidecar.entries,
+            [
+                Entry {
+                    span: Span { start: 41, end: 49 },
+                    value_span: Span { start: 52, end: 71 },
+                    signature_start: Some(53),
+                    name: "get_name".into(),
+                    signature: "(id: i64) ->1 str".into(),
+                },
+                Entry {
+                    span: Span { start: 72, end: 79 },
+                    value_span: Span { start: 82, end: 94 },
+                    signature_start: Some(83),
+                    name: "count".into(),
+                    signature: "() ->1 i64".into(),
+                },
+            ]
+        );
+        assert!(Sidecar::parse("[queries]\n").is_err());
+        assert!(Sidecar::parse("f = \"()\" x\n").is_err());
+        assert!(Sidecar::parse("f = \"()\n").is_err());
+        assert!(Sidecar::parse("f \"()\"\n").is_err());
+    }
+
+    #[test]
+    fn annotate_generates_rust_sqlite_for_two_queries() {
+        let toml = r#"
+get_user_by_id = "(id: i64) ->1 User"
+count_users = "() ->1 i64"
+"#;
+        let sql = "\
+-- Look up a user by id.
+select name /* :str */, email /* :str? */ from users where id = :id;
+
+select count(*) from users;
+";
+        let mut sidecar = Sidecar::parse(toml).unwrap();
+        let (annotated, _) = sidecar.annotate(sql).unwrap();
+        assert!(sidecar.check_all_used().is_ok());
+        assert_eq!(
+            annotated,
+            "\
+-- Look up a user by id.
+-- @query get_user_by_id(id: i64) ->1 User
+select name /* :str */, email /* :str? */ from users where id = :id;
+
+-- @query count_users() ->1 i64
+select count(*) from users;
+"
+        );
+
+        let doc = NamedDocument::process_input(Path::new("x.sql"), annotated.as_bytes()).unwrap();
+        let target = Target::from_name("rust-sqlite").unwrap();
+        let mut out = Vec::new();
+        target
+            .process_files(&mut out, &Options::default(), &[doc])
+            .unwrap();
+        let output = String::from_utf8(out).unwrap();
+        assert!(output.contains("pub struct User {\n    pub name: String,\n"));
+        assert!(output.contains("/// Look up a user by id.\npub fn get_user_by_id("));
+        assert!(output.contains("pub fn count_users(tx: &mut Transaction) -> Result<i64> {"));
+    }
+
+    #[test]
+    fn annotate_reports_unmatched_statements_and_entries() {
+        let mut sidecar = Sidecar::parse("f = \"()\"\n").unwrap();
+        let sql = "delete from a; delete from b;";
+        let err = sidecar.annotate(sql).err().unwrap();
+        assert_eq!(err.span.resolve(sql), "delete");
+        assert_eq!(err.span.start, 15);
+
+        let toml = "f = \"()\"\ng = \"()\"\n";
+        let mut sidecar = Sidecar::parse(toml).unwrap();
+        assert_eq!(
+            sidecar.annotate("delete from a;").unwrap().0,
+            "-- @query f()\ndelete from a;"
+        );
+        let err = sidecar.check_all_used().err().unwrap();
+        assert_eq!(err.span.resolve(toml), "g");
+    }
+
+    #[test]
+    fn source_map_maps_spans_back_to_input_and_entries() {
+        let toml = "f = \"(id: i64) ->1 i64\"\ng = \"() ->1 \\\"Name\\\"\"\n";
+        let sql = "select 1; select 2;\n";
+        let mut sidecar = Sidecar::parse(toml).unwrap();
+        let (annotated, source_map) = sidecar.annotate(sql).unwrap();
+        assert_eq!(
+            annotated,
+            "-- @query f(id: i64) ->1 i64\nselect 1; \n-- @query g() ->1 \"Name\"\nselect 2;\n"
+        );
+        let origin = |pattern: &str| {
+            let start = annotated.find(pattern).unwrap();
+            source_map.origin(Span {
+                start,
+                end: start + pattern.len(),
+            })
+        };
+        let resolve = |origin: Origin| match origin {
+            Origin::Input(span) => format!("input: {}", span.resolve(sql)),
+            Origin::Sidecar(span) => format!("toml: {}", span.resolve(toml)),
+        };
+
+        // Outside of the annotations, spans map to the input.
+        assert_eq!(resolve(origin("select 1")), "input: select 1");
+        assert_eq!(resolve(origin("select 2;\n")), "input: select 2;\n");
+        // In a signature without escapes, the span maps exactly.
+        assert_eq!(resolve(origin("i64)")), "toml: i64)");
+        assert_eq!(resolve(origin("i64\n")), "toml: i64");
+        // With escapes, it maps to the string, and names map to the key.
+        assert_eq!(resolve(origin("Name")), "toml: \"() ->1 \\\"Name\\\"\"");
+        assert_eq!(resolve(origin("g()")), "toml: g");
+    }
+}
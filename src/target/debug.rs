@@ -0,0 +1,378 @@
+// Squiller -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+use std::io;
+
+use crate::ast::{ArgType, ComplexType, Fragment, ResultType, Section, SimpleType, Statement};
+use crate::target::Options;
+use crate::{NamedDocument, Span};
+
+fn print_simple_type(
+    out: &mut dyn io::Write,
+    input: &str,
+    type_: &SimpleType<Span>,
+) -> io::Result<()> {
+    let yellow = "\x1b[33m";
+    let reset = "\x1b[0m";
+    match type_ {
+        SimpleType::Primitive { inner, .. } => {
+            write!(out, "{}{}{}", yellow, inner.resolve(input), reset)
+        }
+        SimpleType::Option { inner, .. } => {
+            write!(
+                out,
+                "{}option{}<{}{}{}>",
+                yellow,
+                reset,
+                yellow,
+                inner.resolve(input),
+                reset
+            )
+        }
+        SimpleType::Enum { name, variants, .. } => {
+            write!(out, "{}{}{}(", yellow, name.resolve(input), reset)?;
+            for (i, variant) in variants.iter().enumerate() {
+                if i > 0 {
+                    write!(out, ", ")?;
+                }
+                write!(out, "{} = {}", variant.name.resolve(input), variant.code)?;
+            }
+            write!(out, ")")
+        }
+    }
+}
+
+fn print_complex_type(
+    out: &mut dyn io::Write,
+    input: &str,
+    type_: &ComplexType<Span>,
+) -> io::Result<()> {
+    let yellow = "\x1b[33m";
+    let reset = "\x1b[0m";
+    match type_ {
+        ComplexType::Simple(t) => print_simple_type(out, input, t)?,
+        ComplexType::Tuple(_span, fields) => {
+            write!(out, "(")?;
+            let mut is_first = true;
+            for field in fields {
+                if !is_first {
+                    write!(out, ", ")?;
+                }
+                if let Some(name) = field.name {
+                    write!(out, "{}: ", name.resolve(input))?;
+                }
+                print_simple_type(out, input, &field.type_)?;
+                is_first = false;
+            }
+            write!(out, ")")?;
+        }
+        ComplexType::Struct(name_span, fields, _) => {
+            writeln!(out, "{}{}{} {{", yellow, name_span.resolve(input), reset)?;
+            for field in fields {
+                write!(out, "--   {}: ", field.ident.resolve(input))?;
+                print_simple_type(out, input, &field.type_)?;
+                writeln!(out, ",")?;
+            }
+            write!(out, "-- }}")?;
+        }
+        ComplexType::TaggedEnum(name_span, variants) => {
+            writeln!(out, "{}{}{}(", yellow, name_span.resolve(input), reset)?;
+            for variant in variants {
+                write!(
+                    out,
+                    "--   {} = {}",
+                    variant.name.resolve(input),
+                    variant.code
+                )?;
+                if !variant.fields.is_empty() {
+                    write!(out, " (")?;
+                    for (i, field) in variant.fields.iter().enumerate() {
+                        if i > 0 {
+                            write!(out, ", ")?;
+                        }
+                        write!(out, "{}: ", field.ident.resolve(input))?;
+                        print_simple_type(out, input, &field.type_)?;
+                    }
+                    write!(out, ")")?;
+                }
+                writeln!(out, ",")?;
+            }
+            write!(out, "-- )")?;
+        }
+    }
+    Ok(())
+}
+
+/// Pretty-print the parsed file, for debugging purposes.
+pub fn print_statement(
+    out: &mut dyn io::Write,
+    input: &str,
+    statement: &Statement<Span>,
+) -> io::Result<()> {
+    let blue = "\x1b[34;1m";
+    let white = "\x1b[37;1m";
+    let gray = "\x1b[37m";
+    let reset = "\x1b[0m";
+
+    for fragment in &statement.fragments {
+        match fragment {
+            Fragment::Verbatim(s) => {
+                write!(out, "{}", s.resolve(input))?;
+            }
+            Fragment::Comment(s) => {
+                write!(out, "{}{}{}", gray, s.resolve(input), reset)?;
+            }
+            Fragment::TypedIdent(raw, parsed) => {
+                write!(out, "{}{}{}", blue, parsed.ident.resolve(input), reset)?;
+                let mid = parsed.ident.between(parsed.type_.span());
+                let end = Span {
+                    start: parsed.type_.span().end,
+                    end: raw.end,
+                };
+                write!(out, "{}", mid.resolve(input))?;
+                print_simple_type(out, input, &parsed.type_)?;
+                write!(out, "{}", end.resolve(input))?;
+            }
+            Fragment::Param(s) => {
+                write!(out, "{}{}{}", white, s.resolve(input), reset)?;
+            }
+            Fragment::TypedParam(raw, parsed) => {
+                write!(out, "{}{}{}", white, parsed.ident.resolve(input), reset)?;
+                let mid = parsed.ident.between(parsed.type_.span());
+                let end = Span {
+                    start: parsed.type_.span().end,
+                    end: raw.end,
+                };
+                write!(out, "{}", mid.resolve(input))?;
+                print_simple_type(out, input, &parsed.type_)?;
+                write!(out, "{}", end.resolve(input))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the kind and byte range of every fragment in the statement.
+fn print_fragment_spans(out: &mut dyn io::Write, statement: &Statement<Span>) -> io::Result<()> {
+    let gray = "\x1b[37m";
+    let reset = "\x1b[0m";
+
+    for fragment in &statement.fragments {
+        let kind = match fragment {
+            Fragment::Verbatim(..) => "Verbatim",
+            Fragment::Comment(..) => "Comment",
+            Fragment::TypedIdent(..) => "TypedIdent",
+            Fragment::Param(..) => "Param",
+            Fragment::TypedParam(..) => "TypedParam",
+        };
+        let span = fragment.span();
+        write!(
+            out,
+            "\n{}-- {} {}..{}{}",
+            gray, kind, span.start, span.end, reset
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Pretty-print the parsed file, for debugging purposes.
+pub fn process_documents(
+    out: &mut dyn io::Write,
+    options: &Options,
+    documents: &[NamedDocument],
+) -> io::Result<()> {
+    let red = "\x1b[31m";
+    let green = "\x1b[32m";
+    let reset = "\x1b[0m";
+
+    for named_document in documents {
+        let input = named_document.input;
+        let document = &named_document.document;
+        for section in &document.sections {
+            match section {
+                Section::Verbatim(s) => {
+                    write!(out, "{}", s.resolve(input))?;
+                }
+                Section::Query(query) => {
+                    let annotation = &query.annotation;
+
+                    for doc_line in &query.docs {
+                        writeln!(out, "{}--{}", red, doc_line.resolve(input))?;
+                    }
+
+                    let marker = match query.statements.len() {
+                        0 | 1 => "@query",
+                        _ => "@begin",
+                    };
+                    write!(out, "{}-- {}{}{} ", reset, green, marker, reset)?;
+                    for segment in &annotation.path {
+                        write!(out, "{}::", segment.resolve(input))?;
+                    }
+                    writeln!(out, "{}", annotation.name.resolve(input))?;
+
+                    if let Some(dialect) = query.dialect {
+                        writeln!(out, "-- {}@dialect{} {}", green, reset, dialect.keyword())?;
+                    }
+
+                    if !query.tags.is_empty() {
+                        write!(out, "-- {}@tags{} ", green, reset)?;
+                        for (i, tag) in query.tags.iter().enumerate() {
+                            if i > 0 {
+                                write!(out, ", ")?;
+                            }
+                            write!(out, "{}", tag.resolve(input))?;
+                        }
+                        writeln!(out)?;
+                    }
+
+                    match &annotation.arguments {
+                        ArgType::Args(args) => {
+                            for param in args {
+                                write!(out, "-- {}: ", param.ident.resolve(input))?;
+                                print_simple_type(out, input, &param.type_)?;
+                                writeln!(out)?;
+                            }
+                        }
+                        ArgType::Struct {
+                            var_name,
+                            type_name,
+                            fields,
+                        } => {
+                            writeln!(
+                                out,
+                                "-- {}: {} {{",
+                                var_name.resolve(input),
+                                type_name.resolve(input),
+                            )?;
+                            for field in fields {
+                                write!(out, "--   {}: ", field.ident.resolve(input))?;
+                                print_simple_type(out, input, &field.type_)?;
+                                writeln!(out)?;
+                            }
+                            writeln!(out, "-- }}")?;
+                        }
+                        ArgType::StructSlice {
+                            var_name,
+                            type_name,
+                            fields,
+                        } => {
+                            writeln!(
+                                out,
+                                "-- {}: [{} {{",
+                                var_name.resolve(input),
+                                type_name.resolve(input),
+                            )?;
+                            for field in fields {
+                                write!(out, "--   {}: ", field.ident.resolve(input))?;
+                                print_simple_type(out, input, &field.type_)?;
+                                writeln!(out)?;
+                            }
+                            writeln!(out, "-- }}]")?;
+                        }
+                    }
+
+                    match &annotation.result_type {
+                        ResultType::Unit => {}
+                        ResultType::Option(t) => {
+                            write!(out, "-- ->? ")?;
+                            print_complex_type(out, input, t)?;
+                            writeln!(out)?;
+                        }
+                        ResultType::Single(t) => {
+                            write!(out, "-- ->1 ")?;
+                            print_complex_type(out, input, t)?;
+                            writeln!(out)?;
+                        }
+                        ResultType::Iterator(t) => {
+                            write!(out, "-- ->* ")?;
+                            print_complex_type(out, input, t)?;
+                            writeln!(out)?;
+                        }
+                    }
+
+                    let mut is_first = true;
+                    for statement in &query.statements {
+                        // Insert a newline between statements, because we strip
+                        // whitespace in between statements.
+                        if is_first {
+                            is_first = false;
+                        } else {
+                            writeln!(out)?;
+                        }
+
+                        print_statement(out, input, statement)?;
+
+                        if options.show_spans {
+                            print_fragment_spans(out, statement)?;
+                        }
+                    }
+
+                    if query.statements.len() > 1 {
+                        writeln!(
+                            out,
+                            "\n-- {}@end{} {}",
+                            green,
+                            reset,
+                            annotation.name.resolve(input)
+                        )?;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::process_documents;
+    use crate::target::Options;
+    use crate::NamedDocument;
+    use std::path::Path;
+
+    #[test]
+    fn show_spans_prints_fragment_ranges() {
+        let input = "-- @query q(id: i64)\nselect 1 from t where id = :id;\n";
+        let document = NamedDocument::process_input(Path::new("test.sql"), input.as_bytes())
+            .expect("Test input should be valid.");
+        let options = Options {
+            show_spans: true,
+            ..Options::default()
+        };
+        let mut out = Vec::new();
+        process_documents(&mut out, &options, &[document]).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        // The statement starts at byte 21, the parameter is at bytes 48..51.
+        assert_eq!(&input[21..48], "select 1 from t where id = ");
+        assert!(out.contains("-- Verbatim 21..48"), "{}", out);
+        assert!(out.contains("-- Param 48..51"), "{}", out);
+        assert!(out.contains("-- Verbatim 51..52"), "{}", out);
+    }
+
+    #[test]
+    fn show_spans_prints_comment_ranges() {
+        let input = "-- @query q()\nselect 1 /* one */ from t;\n";
+        let document = NamedDocument::process_input(Path::new("test.sql"), input.as_bytes())
+            .expect("Test input should be valid.");
+        let options = Options {
+            show_spans: true,
+            ..Options::default()
+        };
+        let mut out = Vec::new();
+        process_documents(&mut out, &options, &[document]).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert_eq!(&input[23..32], "/* one */");
+        assert!(out.contains("-- Verbatim 14..23"), "{}", out);
+        assert!(out.contains("-- Comment 23..32"), "{}", out);
+        assert!(out.contains("-- Verbatim 32..40"), "{}", out);
+    }
+}
@@ -0,0 +1,502 @@
+// Squiller -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2023 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Target Haskell and the `postgresql-simple` package.
+//!
+//! Every query becomes a function that takes a `Connection`, and uses `query`,
+//! `query_`, `execute`, or `execute_` with `?` placeholders to run it. Result
+//! structs become records with a `FromRow` instance. Parameters are passed as
+//! a list of `toField` actions, so there is no limit on their number.
+
+use std::collections::HashSet;
+use std::io;
+
+use crate::ast::{
+    ArgType, ComplexType, Fragment, PrimitiveType, Query, ResultType, SimpleType, Statement,
+    TypedIdent,
+};
+use crate::target::Options;
+use crate::NamedDocument;
+
+const PREAMBLE: &str = r#"
+{-# LANGUAGE OverloadedStrings #-}
+
+module Queries where
+
+import Control.Monad (forM_)
+import Data.ByteString (ByteString)
+import Data.Int (Int16, Int32, Int64)
+import Data.Text (Text)
+import Database.PostgreSQL.Simple (Binary (..), Connection, Only (..), execute, execute_, query, query_)
+import Database.PostgreSQL.Simple.FromRow (FromRow (..), field)
+import Database.PostgreSQL.Simple.ToField (toField)
+"#;
+
+/// Convert a `snake_case` or `PascalCase` name to `camelCase`, or to `PascalCase`.
+fn camel_case(name: &str, upper_first: bool) -> String {
+    let mut result = String::with_capacity(name.len());
+    let mut upper = upper_first;
+    for ch in name.chars() {
+        match ch {
+            '_' if !result.is_empty() => upper = true,
+            _ if upper => {
+                result.extend(ch.to_uppercase());
+                upper = false;
+            }
+            _ if result.is_empty() => result.extend(ch.to_lowercase()),
+            _ => result.push(ch),
+        }
+    }
+    result
+}
+
+/// The name of the record field accessor, e.g. `userName` for `User.name`.
+fn field_accessor(type_name: &str, field_name: &str) -> String {
+    let mut result = camel_case(type_name, false);
+    result.push_str(&camel_case(field_name, true));
+    result
+}
+
+/// Wrap a type in parentheses, when it is an application like `Maybe Text`.
+fn parenthesize(type_: String) -> String {
+    match type_.contains(' ') && !type_.starts_with('(') && !type_.starts_with('[') {
+        true => format!("({})", type_),
+        false => type_,
+    }
+}
+
+fn primitive_type(type_: PrimitiveType) -> &'static str {
+    match type_ {
+        PrimitiveType::Str => "Text",
+        PrimitiveType::I16 => "Int16",
+        PrimitiveType::I32 => "Int32",
+        PrimitiveType::I64 => "Int64",
+        // PostgreSQL has no unsigned integer types, an `Int64` holds every `u32`.
+        PrimitiveType::U32 => "Int64",
+        PrimitiveType::F32 => "Float",
+        PrimitiveType::F64 => "Double",
+        PrimitiveType::Bytes => "Binary ByteString",
+        PrimitiveType::Json => unreachable!("Json is rejected by `check_target_support`."),
+    }
+}
+
+fn simple_type(type_: &SimpleType<&str>) -> String {
+    match type_ {
+        SimpleType::Primitive { type_: t, .. } => primitive_type(*t).to_string(),
+        SimpleType::Option { type_: t, .. } => {
+            format!("Maybe {}", parenthesize(primitive_type(*t).to_string()))
+        }
+        // This target does not generate enums, it passes the integer code.
+        SimpleType::Enum { .. } => primitive_type(PrimitiveType::I64).to_string(),
+    }
+}
+
+/// The type that a row decodes into.
+fn row_type(type_: &ComplexType<&str>) -> String {
+    match type_ {
+        ComplexType::Simple(t) => simple_type(t),
+        // There are no 1-tuples in Haskell, `postgresql-simple` uses `Only`.
+        ComplexType::Tuple(_, fields) if fields.len() == 1 => {
+            format!("Only {}", parenthesize(simple_type(&fields[0].type_)))
+        }
+        ComplexType::Tuple(_, fields) => {
+            let types: Vec<String> = fields.iter().map(|f| simple_type(&f.type_)).collect();
+            format!("({})", types.join(", "))
+        }
+        ComplexType::Struct(name, ..) => name.to_string(),
+        ComplexType::TaggedEnum(..) => {
+            unreachable!("Tagged enums are rejected by `check_target_support`.")
+        }
+    }
+}
+
+fn result_type(type_: &ResultType<&str>) -> String {
+    match type_ {
+        ResultType::Unit => "()".to_string(),
+        ResultType::Option(t) => format!("Maybe {}", parenthesize(row_type(t))),
+        ResultType::Single(t) => row_type(t),
+        ResultType::Iterator(t) => format!("[{}]", row_type(t)),
+    }
+}
+
+/// Define a record, with a `FromRow` instance if it is a result.
+fn write_record_definition(
+    out: &mut dyn io::Write,
+    name: &str,
+    fields: &[TypedIdent<&str>],
+    is_result: bool,
+) -> io::Result<()> {
+    writeln!(out)?;
+    write!(out, "data {} = {}", name, name)?;
+    for (i, field) in fields.iter().enumerate() {
+        let sep = if i == 0 { "\n  { " } else { "\n  , " };
+        write!(
+            out,
+            "{}{} :: {}",
+            sep,
+            field_accessor(name, field.ident),
+            simple_type(&field.type_)
+        )?;
+    }
+    if !fields.is_empty() {
+        write!(out, "\n  }}")?;
+    }
+    writeln!(out, "\n  deriving (Show)")?;
+
+    if is_result {
+        writeln!(out)?;
+        writeln!(out, "instance FromRow {} where", name)?;
+        write!(out, "  fromRow = ")?;
+        match fields.len() {
+            0 => writeln!(out, "pure {}", name)?,
+            n => writeln!(out, "{} <$> field{}", name, " <*> field".repeat(n - 1))?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Generate definitions for the records that the query uses, unless they are
+/// extern, or defined already.
+fn write_record_definitions<'a>(
+    out: &mut dyn io::Write,
+    defined: &mut HashSet<&'a str>,
+    query: &Query<&'a str>,
+) -> io::Result<()> {
+    if let ArgType::Struct {
+        type_name, fields, ..
+    }
+    | ArgType::StructSlice {
+        type_name, fields, ..
+    } = &query.annotation.arguments
+    {
+        if defined.insert(type_name) {
+            write_record_definition(out, type_name, fields, false)?;
+        }
+    }
+    match query.annotation.result_type.get() {
+        Some(ComplexType::Struct(name, fields, false)) if defined.insert(name) => {
+            write_record_definition(out, name, fields, true)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn write_function_signature(out: &mut dyn io::Write, query: &Query<&str>) -> io::Result<()> {
+    let ann = &query.annotation;
+    let name = camel_case(ann.name, false);
+
+    write!(out, "{} :: Connection", name)?;
+    match &ann.arguments {
+        ArgType::Args(args) => {
+            for arg in args {
+                write!(out, " -> {}", simple_type(&arg.type_))?;
+            }
+        }
+        ArgType::Struct { type_name, .. } => write!(out, " -> {}", type_name)?,
+        ArgType::StructSlice { type_name, .. } => write!(out, " -> [{}]", type_name)?,
+    }
+    writeln!(
+        out,
+        " -> IO {}",
+        parenthesize(result_type(&ann.result_type))
+    )?;
+
+    // The connection and the locals in the body end in a prime, which argument
+    // names cannot, so they never clash with the arguments.
+    write!(out, "{} conn'", name)?;
+    match &ann.arguments {
+        ArgType::Args(args) => {
+            for arg in args {
+                write!(out, " {}", camel_case(arg.ident, false))?;
+            }
+        }
+        ArgType::Struct { var_name, .. } | ArgType::StructSlice { var_name, .. } => {
+            write!(out, " {}", camel_case(var_name, false))?
+        }
+    }
+    writeln!(out, " = do")
+}
+
+/// Return the SQL of the statement with `?` placeholders, and the parameters.
+///
+/// A parameter that occurs multiple times, is bound multiple times.
+fn statement_sql<'a>(statement: &Statement<&'a str>) -> (String, Vec<&'a str>) {
+    let mut sql = String::new();
+    let mut params = Vec::new();
+    for fragment in &statement.fragments {
+        match fragment {
+            Fragment::Verbatim(s) | Fragment::Comment(s) => sql.push_str(s),
+            // Omit the type annotations, but keep the alias, it becomes the
+            // column name.
+            Fragment::TypedIdent(_full, ti) => {
+                sql.push_str(ti.ident);
+                if let Some(alias) = ti.alias {
+                    sql.push_str(" as ");
+                    sql.push_str(alias);
+                }
+            }
+            Fragment::Param(s) => {
+                params.push(&s[1..]);
+                sql.push('?');
+            }
+            Fragment::TypedParam(_full, ti) => {
+                params.push(&ti.ident[1..]);
+                sql.push('?');
+            }
+        }
+    }
+    (sql, params)
+}
+
+/// Bind `sql` to the string as a Haskell string literal.
+///
+/// SQL that spans multiple lines goes on lines of its own, with a string gap
+/// at every line break.
+fn write_sql_binding(out: &mut dyn io::Write, indent: &str, sql: &str) -> io::Result<()> {
+    let escaped = sql
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\t', "\\t");
+    let mut lines = escaped.lines();
+    let first = lines.next().unwrap_or("");
+    match lines.next() {
+        None => writeln!(out, "{}let sql' = \"{}\"", indent, first),
+        Some(second) => {
+            writeln!(out, "{}let sql' =", indent)?;
+            write!(out, "{}      \"{}", indent, first)?;
+            for line in std::iter::once(second).chain(lines) {
+                write!(out, "\\n\\\n{}      \\{}", indent, line)?;
+            }
+            writeln!(out, "\"")
+        }
+    }
+}
+
+/// Write the list of `toField` actions, or nothing if there are no parameters.
+fn write_params(out: &mut dyn io::Write, args: &ArgType<&str>, params: &[&str]) -> io::Result<()> {
+    if params.is_empty() {
+        return Ok(());
+    }
+    write!(out, " [")?;
+    for (i, param) in params.iter().enumerate() {
+        if i > 0 {
+            write!(out, ", ")?;
+        }
+        match args {
+            ArgType::Args(..) => write!(out, "toField {}", camel_case(param, false))?,
+            ArgType::Struct {
+                var_name,
+                type_name,
+                ..
+            } => write!(
+                out,
+                "toField ({} {})",
+                field_accessor(type_name, param),
+                camel_case(var_name, false)
+            )?,
+            ArgType::StructSlice { type_name, .. } => {
+                write!(out, "toField ({} item)", field_accessor(type_name, param))?
+            }
+        }
+    }
+    write!(out, "]")
+}
+
+/// Execute the statement, and decode the result, if it is the last statement.
+fn write_statement(
+    out: &mut dyn io::Write,
+    indent: &str,
+    query: &Query<&str>,
+    statement: &Statement<&str>,
+    result_type: &ResultType<&str>,
+) -> io::Result<()> {
+    let (sql, params) = statement_sql(statement);
+    let args = &query.annotation.arguments;
+    write_sql_binding(out, indent, &sql)?;
+
+    let query_fn = if params.is_empty() { "query_" } else { "query" };
+    match result_type {
+        ResultType::Unit => {
+            match params.is_empty() {
+                true => write!(out, "{}_ <- execute_ conn' sql'", indent)?,
+                false => write!(out, "{}_ <- execute conn' sql'", indent)?,
+            }
+            write_params(out, args, &params)?;
+            writeln!(out)
+        }
+        ResultType::Iterator(ComplexType::Simple(..)) => {
+            write!(out, "{}map fromOnly <$> {} conn' sql'", indent, query_fn)?;
+            write_params(out, args, &params)?;
+            writeln!(out)
+        }
+        ResultType::Iterator(..) => {
+            write!(out, "{}{} conn' sql'", indent, query_fn)?;
+            write_params(out, args, &params)?;
+            writeln!(out)
+        }
+        ResultType::Option(t) | ResultType::Single(t) => {
+            write!(out, "{}rows' <- {} conn' sql'", indent, query_fn)?;
+            write_params(out, args, &params)?;
+            writeln!(out)?;
+            // Simple types are decoded as `Only`, unwrap them.
+            let pattern = match t {
+                ComplexType::Simple(..) => "Only row",
+                _ => "row",
+            };
+            let name = query.annotation.name;
+            writeln!(out, "{}case rows' of", indent)?;
+            match result_type {
+                ResultType::Option(..) => {
+                    writeln!(out, "{}  [] -> pure Nothing", indent)?;
+                    writeln!(out, "{}  [{}] -> pure (Just row)", indent, pattern)?;
+                    writeln!(
+                        out,
+                        "{}  _ -> fail \"Query '{}' should return at most one row.\"",
+                        indent, name
+                    )
+                }
+                _ => {
+                    writeln!(out, "{}  [{}] -> pure row", indent, pattern)?;
+                    writeln!(
+                        out,
+                        "{}  _ -> fail \"Query '{}' should return exactly one row.\"",
+                        indent, name
+                    )
+                }
+            }
+        }
+    }
+}
+
+fn write_query<'a>(
+    out: &mut dyn io::Write,
+    defined: &mut HashSet<&'a str>,
+    query: &Query<&'a str>,
+) -> io::Result<()> {
+    write_record_definitions(out, defined, query)?;
+
+    writeln!(out)?;
+    for (i, doc_line) in query.docs.iter().enumerate() {
+        match i {
+            0 => writeln!(out, "-- |{}", doc_line)?,
+            _ => writeln!(out, "--{}", doc_line)?,
+        }
+    }
+    write_function_signature(out, query)?;
+
+    // For a slice argument, we execute every statement once per element.
+    let indent = match &query.annotation.arguments {
+        ArgType::StructSlice { var_name, .. } => {
+            writeln!(
+                out,
+                "  forM_ {} $ \\item -> do",
+                camel_case(var_name, false)
+            )?;
+            "    "
+        }
+        _ => "  ",
+    };
+
+    // All but the last statement return nothing, only the final statement
+    // has the result type of the query.
+    let unit = ResultType::Unit;
+    for (i, statement) in query.statements.iter().enumerate() {
+        let result_type = match i + 1 == query.statements.len() {
+            true => &query.annotation.result_type,
+            false => &unit,
+        };
+        write_statement(out, indent, query, statement, result_type)?;
+    }
+
+    match query.annotation.result_type {
+        ResultType::Unit => writeln!(out, "{}pure ()", indent),
+        _ => Ok(()),
+    }
+}
+
+pub fn process_documents(
+    out: &mut dyn io::Write,
+    options: &Options,
+    documents: &[NamedDocument],
+) -> io::Result<()> {
+    use crate::version::{REV, VERSION};
+    if options.version_header {
+        write!(out, "-- This file was generated by Squiller {}", VERSION)?;
+        match REV {
+            Some(rev) => writeln!(out, " (commit {}).", &rev[..10])?,
+            None => writeln!(out, " (unspecified checkout).")?,
+        }
+        writeln!(out, "-- Input files:")?;
+        for doc in documents.iter().filter(|doc| doc.document.has_queries()) {
+            writeln!(out, "-- - {}", doc.fname.to_string_lossy())?;
+        }
+    }
+    write!(out, "{}", PREAMBLE)?;
+
+    let mut defined = HashSet::new();
+    for named_document in documents {
+        let input = named_document.input;
+        for query in named_document.document.iter_queries() {
+            write_query(out, &mut defined, &query.resolve(input))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{camel_case, process_documents};
+    use crate::target::Options;
+    use crate::NamedDocument;
+    use std::path::Path;
+
+    fn generate(input: &str) -> String {
+        let doc = NamedDocument::process_input(Path::new("x.sql"), input.as_bytes()).unwrap();
+        let mut out = Vec::new();
+        process_documents(&mut out, &Options::default(), &[doc]).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn camel_case_converts_snake_case() {
+        assert_eq!(camel_case("select_user_by_id", false), "selectUserById");
+        assert_eq!(camel_case("user_id", true), "UserId");
+        assert_eq!(camel_case("name", false), "name");
+        assert_eq!(camel_case("_private", false), "_private");
+        assert_eq!(camel_case("NewUser", false), "newUser");
+    }
+
+    #[test]
+    fn struct_argument_passes_fields_by_accessor() {
+        let input = "\
+-- @query insert_user(user: NewUser)
+insert into users (name, email)
+values (:name /* :str */, :email /* :str? */);";
+        let output = generate(input);
+        assert!(output.contains(
+            "data NewUser = NewUser\n  \
+            { newUserName :: Text\n  \
+            , newUserEmail :: Maybe Text\n  \
+            }\n  \
+            deriving (Show)\n"
+        ));
+        // Argument structs are not decoded from rows, so they have no instance.
+        assert!(!output.contains("instance FromRow NewUser"));
+        assert!(output.contains(
+            "insertUser :: Connection -> NewUser -> IO ()\n\
+            insertUser conn' user = do\n"
+        ));
+        assert!(output.contains(
+            "  let sql' =\n        \
+            \"insert into users (name, email)\\n\\\n        \
+            \\values (?, ?);\"\n  \
+            _ <- execute conn' sql' [toField (newUserName user), toField (newUserEmail user)]\n"
+        ));
+    }
+}
@@ -0,0 +1,127 @@
+// Squiller -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2023 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! A target that lists the queries in a document, one per line.
+//!
+//! This is meant for quickly inspecting a file, it prints only the signature
+//! of every query, in the same syntax as the annotation.
+
+use std::io;
+
+use crate::ast::{Annotation, ArgType, ComplexType, ResultType, SimpleType};
+use crate::target::Options;
+use crate::NamedDocument;
+
+fn write_simple_type(out: &mut dyn io::Write, type_: &SimpleType<&str>) -> io::Result<()> {
+    match type_ {
+        SimpleType::Primitive { inner, .. } => write!(out, "{}", inner),
+        SimpleType::Option { inner, .. } => write!(out, "{}?", inner),
+        SimpleType::Enum { outer, .. } => write!(out, "{}", outer),
+    }
+}
+
+fn write_complex_type(out: &mut dyn io::Write, type_: &ComplexType<&str>) -> io::Result<()> {
+    match type_ {
+        ComplexType::Simple(t) => write_simple_type(out, t),
+        ComplexType::Struct(name, ..) => write!(out, "{}", name),
+        ComplexType::TaggedEnum(name, ..) => write!(out, "{}", name),
+        ComplexType::Tuple(_span, fields) => {
+            write!(out, "(")?;
+            for (i, field) in fields.iter().enumerate() {
+                if i > 0 {
+                    write!(out, ", ")?;
+                }
+                if let Some(name) = field.name {
+                    write!(out, "{}: ", name)?;
+                }
+                write_simple_type(out, &field.type_)?;
+            }
+            write!(out, ")")
+        }
+    }
+}
+
+fn write_annotation(out: &mut dyn io::Write, annotation: &Annotation<&str>) -> io::Result<()> {
+    for segment in &annotation.path {
+        write!(out, "{}::", segment)?;
+    }
+    write!(out, "{}(", annotation.name)?;
+    match &annotation.arguments {
+        ArgType::Args(args) => {
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    write!(out, ", ")?;
+                }
+                write!(out, "{}: ", arg.ident)?;
+                write_simple_type(out, &arg.type_)?;
+            }
+        }
+        ArgType::Struct {
+            var_name,
+            type_name,
+            ..
+        } => write!(out, "{}: {}", var_name, type_name)?,
+        ArgType::StructSlice {
+            var_name,
+            type_name,
+            ..
+        } => write!(out, "{}: [{}]", var_name, type_name)?,
+    }
+    write!(out, ")")?;
+
+    let (arrow, type_) = match &annotation.result_type {
+        ResultType::Unit => return writeln!(out),
+        ResultType::Option(t) => ("->?", t),
+        ResultType::Single(t) => ("->1", t),
+        ResultType::Iterator(t) => ("->*", t),
+    };
+    write!(out, " {} ", arrow)?;
+    write_complex_type(out, type_)?;
+    writeln!(out)
+}
+
+pub fn process_documents(
+    out: &mut dyn io::Write,
+    _options: &Options,
+    documents: &[NamedDocument],
+) -> io::Result<()> {
+    for named_document in documents {
+        let input = named_document.input;
+        for query in named_document.document.iter_queries() {
+            write_annotation(out, &query.annotation.resolve(input))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::process_documents;
+    use crate::target::Options;
+    use crate::NamedDocument;
+    use std::path::Path;
+
+    #[test]
+    fn example_file_users_is_listed() {
+        let fname = Path::new("examples/users.sql");
+        let input = std::fs::read(fname).unwrap();
+        let document = NamedDocument::process_input(fname, &input).unwrap();
+        let mut out = Vec::new();
+        process_documents(&mut out, &Options::default(), &[document]).unwrap();
+        let expected = "\
+setup_schema()
+insert_user(name: str, email: str) ->1 i64
+insert_user_alt_return(name: str, email: str) ->1 User1
+insert_user_alt_arg(user: InsertUser) ->1 i64
+select_user_by_id(id: i64) ->1 User2
+select_all_users() ->* User3
+select_longest_email_length() ->1 i64?
+select_longest_email_length_alt() ->? i64
+";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+}
@@ -0,0 +1,1182 @@
+// Squiller -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+mod debug;
+mod haskell;
+mod list;
+mod python;
+mod python_psycopg2;
+mod python_sqlite;
+mod rust_sqlite;
+mod rust_sqlite_tests;
+mod rust_sqlx;
+mod sql;
+
+use std::collections::hash_map::{Entry, HashMap};
+use std::io;
+
+use crate::ast::{
+    ArgType, ComplexType, Dialect, PrimitiveType, Query, ResultType, Section, SimpleType,
+    TypedIdent,
+};
+use crate::error::TypeError;
+use crate::{NamedDocument, Span};
+
+/// The visibility of items in generated code, for targets that have a notion of it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Visibility {
+    /// `pub`, the default.
+    Public,
+    /// `pub(crate)`.
+    Crate,
+    /// No visibility keyword, items are private to the module.
+    Private,
+}
+
+impl Visibility {
+    /// Parse the visibility from how it is written in Rust.
+    pub fn from_keyword(keyword: &str) -> Option<Visibility> {
+        match keyword {
+            "pub" => Some(Visibility::Public),
+            "pub(crate)" => Some(Visibility::Crate),
+            "" => Some(Visibility::Private),
+            _ => None,
+        }
+    }
+
+    /// The visibility keyword, including a trailing space when it is nonempty.
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Visibility::Public => "pub ",
+            Visibility::Crate => "pub(crate) ",
+            Visibility::Private => "",
+        }
+    }
+}
+
+/// The kind of transaction to start, for SQLite targets.
+///
+/// See also <https://www.sqlite.org/lang_transaction.html>.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BeginMode {
+    Deferred,
+    Immediate,
+    Exclusive,
+}
+
+impl BeginMode {
+    /// Parse the begin mode from its lowercase SQL keyword.
+    pub fn from_keyword(keyword: &str) -> Option<BeginMode> {
+        match keyword {
+            "deferred" => Some(BeginMode::Deferred),
+            "immediate" => Some(BeginMode::Immediate),
+            "exclusive" => Some(BeginMode::Exclusive),
+            _ => None,
+        }
+    }
+
+    /// The statement that begins a transaction in this mode, without semicolon.
+    pub fn begin_statement(&self) -> &'static str {
+        match self {
+            BeginMode::Deferred => "BEGIN DEFERRED",
+            BeginMode::Immediate => "BEGIN IMMEDIATE",
+            BeginMode::Exclusive => "BEGIN EXCLUSIVE",
+        }
+    }
+}
+
+/// How to read columns whose type is not nullable, for `rust-sqlite`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum NullPolicy {
+    /// Read the column as before, but name the column when the read fails.
+    Strict,
+    /// Read the column as nullable, and fail with the column name when it is null.
+    Lenient,
+}
+
+/// Options that control code generation.
+///
+/// Not every option applies to every target, targets ignore the options that
+/// do not apply to them.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Options {
+    /// Visibility of generated items, for the Rust targets.
+    pub visibility: Visibility,
+
+    /// Whether to cache prepared statements in the connection, for targets
+    /// that support it. When disabled, statements are prepared on every call.
+    pub cache_statements: bool,
+
+    /// Whether queries that return multiple rows should pass every row to a
+    /// callback as a view into the statement, rather than returning an
+    /// iterator of owned rows.
+    pub borrow_rows: bool,
+
+    /// The kind of transaction to begin, for SQLite targets. When not set,
+    /// targets use the default of the underlying library.
+    pub begin_mode: Option<BeginMode>,
+
+    /// Whether to also generate every query as a method on the transaction,
+    /// for targets that support it.
+    pub methods: bool,
+
+    /// Whether to read struct fields from the column with the same name,
+    /// rather than by position, for the Rust targets.
+    pub read_by_name: bool,
+
+    /// Whether to collapse whitespace in the generated SQL, instead of
+    /// preserving the formatting of the input, for `rust-sqlite` and `sql`.
+    pub minify_sql: bool,
+
+    /// Whether to remove comments from the generated SQL, for `rust-sqlite`
+    /// and `sql`.
+    pub strip_comments: bool,
+
+    /// Whether to define structs with the same name only once, for the Rust
+    /// and Python targets. See also `check_shared_structs`.
+    pub dedup_structs: bool,
+
+    /// Whether to return an error instead of panicking when a query returns
+    /// more or fewer rows than its result type allows, for `rust-sqlite`.
+    pub cardinality_errors: bool,
+
+    /// Whether to log every query in a `tracing` span, for `rust-sqlite`.
+    pub tracing: bool,
+
+    /// Whether to generate a `name_sql` function next to every query function,
+    /// that returns the SQL of the query, for `rust-sqlite`.
+    pub expose_sql: bool,
+
+    /// How to handle null values in columns that are not nullable, for
+    /// `rust-sqlite`. When not set, reads fail with the error of the `sqlite`
+    /// crate, which does not name the column.
+    pub null_policy: Option<NullPolicy>,
+
+    /// When set, make the generated code conditional on this Cargo feature,
+    /// for the Rust targets.
+    pub cfg_feature: Option<String>,
+
+    /// Prefix for the names of the generated `Connection`, `Transaction`, and
+    /// `Iter` types, for the Rust targets. Empty by default.
+    pub type_prefix: String,
+
+    /// When set, generate the queries as methods on a class with this name,
+    /// for the Python targets. The class takes the transaction in `__init__`.
+    pub python_class: Option<String>,
+
+    /// Width beyond which the signatures of generated functions get broken
+    /// over multiple lines, for the `rust-sqlite` target. 100 by default.
+    pub max_line_width: usize,
+
+    /// The contents of the schema file, for targets that generate code which
+    /// sets up a database.
+    pub schema_sql: Option<String>,
+
+    /// The SQL dialect of the input files.
+    pub dialect: Dialect,
+
+    /// Whether to treat `{{ ... }}` in the input as opaque template expressions.
+    pub template_braces: bool,
+
+    /// Whether to print the byte range of every fragment, for the `debug` target.
+    pub show_spans: bool,
+
+    /// Text to put at the top of the generated code, such as a license header
+    /// or attributes that suppress lints.
+    pub header: Option<String>,
+
+    /// Whether to start the generated code with a comment that lists the
+    /// Squiller version and the input files.
+    pub version_header: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            visibility: Visibility::Public,
+            cache_statements: true,
+            borrow_rows: false,
+            begin_mode: None,
+            methods: false,
+            read_by_name: false,
+            minify_sql: false,
+            strip_comments: false,
+            dedup_structs: false,
+            cardinality_errors: false,
+            tracing: false,
+            expose_sql: false,
+            null_policy: None,
+            cfg_feature: None,
+            type_prefix: String::new(),
+            python_class: None,
+            max_line_width: 100,
+            schema_sql: None,
+            dialect: Dialect::Generic,
+            template_braces: false,
+            show_spans: false,
+            header: None,
+            version_header: true,
+        }
+    }
+}
+
+/// Lowercase the name and replace underscores with dashes.
+fn normalize_name(name: &str) -> String {
+    name.to_ascii_lowercase().replace('_', "-")
+}
+
+/// Return the Levenshtein distance between the two strings, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // Row `i` holds the distances from the first `i` characters of `a` to
+    // every prefix of `b`. We only need to keep the previous row.
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = Vec::with_capacity(b.len() + 1);
+        row.push(i + 1);
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + if ca == *cb { 0 } else { 1 };
+            let delete = prev[j + 1] + 1;
+            let insert = row[j] + 1;
+            row.push(substitute.min(delete).min(insert));
+        }
+        prev = row;
+    }
+    prev[b.len()]
+}
+
+pub struct Target {
+    pub name: &'static str,
+    pub help: &'static str,
+    /// Generates the code, this is called through `process_files`, which first
+    /// checks that the target supports the documents.
+    handler: fn(&mut dyn io::Write, &Options, &[NamedDocument]) -> io::Result<()>,
+    /// Whether the target supports module paths in query names.
+    pub supports_module_paths: bool,
+    /// Whether the target supports tagged enum result types.
+    pub supports_tagged_enums: bool,
+    /// Whether the target supports slice-of-struct arguments.
+    pub supports_struct_slices: bool,
+    /// Whether the target supports the `json` type.
+    pub supports_json: bool,
+    /// The target-specific flags that the target accepts, see `check_target_flags`.
+    pub flags: &'static [&'static str],
+    /// The packages that the generated code depends on, see `dependencies`.
+    pub packages: &'static [&'static str],
+    /// Packages that the generated code additionally depends on with `--tracing`.
+    pub tracing_packages: &'static [&'static str],
+    /// Packages that the generated code additionally depends on when a query
+    /// uses the `json` type.
+    pub json_packages: &'static [&'static str],
+}
+
+/// The target-specific flags of `rust-sqlite`. The `rust-sqlite-tests` target
+/// accepts them too, so the code and its tests can be generated with the same
+/// arguments.
+const RUST_SQLITE_FLAGS: &[&str] = &[
+    "--minify-sql",
+    "--strip-comments",
+    "--cardinality-errors",
+    "--tracing",
+    "--expose-sql",
+];
+
+/// The different targets that we can generate code for.
+pub const TARGETS: &[Target] = &[
+    Target {
+        name: "help",
+        help: "List all supported targets.",
+        handler: |_output, _options, _documents| {
+            // We should not get here, the CLI parser handles this case.
+            panic!("This pseudo-target should not be used for processing.");
+        },
+        supports_module_paths: false,
+        supports_tagged_enums: false,
+        supports_struct_slices: false,
+        supports_json: false,
+        flags: &[],
+        packages: &[],
+        tracing_packages: &[],
+        json_packages: &[],
+    },
+    Target {
+        name: "debug",
+        help: "For debugging, run the parser and print a highlighted document.",
+        handler: debug::process_documents,
+        supports_module_paths: true,
+        supports_tagged_enums: true,
+        supports_struct_slices: true,
+        supports_json: true,
+        flags: &[],
+        packages: &[],
+        tracing_packages: &[],
+        json_packages: &[],
+    },
+    Target {
+        name: "haskell",
+        help: "Haskell with the 'postgresql-simple' package.",
+        handler: haskell::process_documents,
+        supports_module_paths: false,
+        supports_tagged_enums: false,
+        supports_struct_slices: true,
+        supports_json: false,
+        flags: &[],
+        packages: &["bytestring", "postgresql-simple", "text"],
+        tracing_packages: &[],
+        json_packages: &[],
+    },
+    Target {
+        name: "list",
+        help: "List the signature of every query, one per line.",
+        handler: list::process_documents,
+        supports_module_paths: true,
+        supports_tagged_enums: true,
+        supports_struct_slices: true,
+        supports_json: true,
+        flags: &[],
+        packages: &[],
+        tracing_packages: &[],
+        json_packages: &[],
+    },
+    Target {
+        name: "python-psycopg2",
+        help: "Python with the 'psycopg2' package.",
+        handler: python_psycopg2::process_documents,
+        supports_module_paths: false,
+        supports_tagged_enums: false,
+        supports_struct_slices: false,
+        supports_json: false,
+        flags: &[],
+        packages: &["psycopg2"],
+        tracing_packages: &[],
+        json_packages: &[],
+    },
+    Target {
+        name: "python-sqlite",
+        help: "Python with the 'sqlite3' module.",
+        handler: python_sqlite::process_documents,
+        supports_module_paths: false,
+        supports_tagged_enums: false,
+        supports_struct_slices: false,
+        supports_json: false,
+        flags: &[],
+        packages: &[],
+        tracing_packages: &[],
+        json_packages: &[],
+    },
+    Target {
+        name: "rust-sqlite",
+        help: "Rust with the 'sqlite' crate.",
+        handler: rust_sqlite::process_documents,
+        supports_module_paths: true,
+        supports_tagged_enums: true,
+        supports_struct_slices: true,
+        supports_json: true,
+        flags: RUST_SQLITE_FLAGS,
+        packages: &["sqlite"],
+        tracing_packages: &["tracing"],
+        json_packages: &["serde_json"],
+    },
+    Target {
+        name: "rust-sqlite-tests",
+        help: "Smoke tests for the 'rust-sqlite' target.",
+        handler: rust_sqlite_tests::process_documents,
+        supports_module_paths: true,
+        supports_tagged_enums: true,
+        supports_struct_slices: true,
+        supports_json: true,
+        flags: RUST_SQLITE_FLAGS,
+        packages: &["sqlite"],
+        tracing_packages: &[],
+        json_packages: &["serde_json"],
+    },
+    Target {
+        name: "rust-sqlx",
+        help: "Rust with async functions for the 'sqlx' crate.",
+        handler: rust_sqlx::process_documents,
+        supports_module_paths: false,
+        supports_tagged_enums: false,
+        supports_struct_slices: true,
+        supports_json: true,
+        flags: &[],
+        packages: &["sqlx"],
+        tracing_packages: &[],
+        json_packages: &["serde_json"],
+    },
+    Target {
+        name: "sql",
+        help: "The plain SQL of every query, with type annotations removed.",
+        handler: sql::process_documents,
+        supports_module_paths: true,
+        supports_tagged_enums: true,
+        supports_struct_slices: true,
+        supports_json: true,
+        flags: &["--minify-sql", "--strip-comments"],
+        packages: &[],
+        tracing_packages: &[],
+        json_packages: &[],
+    },
+];
+
+impl Target {
+    /// Get a target by name.
+    ///
+    /// If there is no exact match, this also accepts names that differ only
+    /// in case, or that use underscores instead of dashes.
+    pub fn from_name(name: &str) -> Option<&'static Target> {
+        for t in TARGETS.iter() {
+            if t.name == name {
+                return Some(t);
+            }
+        }
+        let normalized = normalize_name(name);
+        TARGETS.iter().find(|t| t.name == normalized)
+    }
+
+    /// Find the target with the name closest to `name`, to suggest on a typo.
+    ///
+    /// Returns `None` when no target is close enough to be a likely typo.
+    pub fn suggest(name: &str) -> Option<&'static Target> {
+        let normalized = normalize_name(name);
+        let max_distance = (normalized.len() / 3).max(1);
+        TARGETS
+            .iter()
+            .map(|t| (edit_distance(&normalized, t.name), t))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, t)| t)
+    }
+
+    /// The packages that the generated code for the documents depends on.
+    ///
+    /// These are crate names for the Rust targets, module names for the Python
+    /// targets, and package names for the Haskell target, excluding the
+    /// standard library and `base`. Besides the packages that the target always
+    /// needs, this includes the packages that options and types call for.
+    pub fn dependencies(
+        &self,
+        options: &Options,
+        documents: &[NamedDocument],
+    ) -> Vec<&'static str> {
+        let mut deps = self.packages.to_vec();
+        if options.tracing {
+            deps.extend_from_slice(self.tracing_packages);
+        }
+        if documents_use(documents, |t| t.inner_type() == PrimitiveType::Json) {
+            deps.extend_from_slice(self.json_packages);
+        }
+        deps
+    }
+
+    /// Generate code for the documents.
+    ///
+    /// Fails with `InvalidInput` when the documents use features that the
+    /// target does not support. The driver reports those with a diagnostic
+    /// through `check_target_support` before it gets here, but the handlers
+    /// rely on the check, so we repeat it for other callers.
+    pub fn process_files(
+        &self,
+        output: &mut dyn io::Write,
+        options: &Options,
+        documents: &[NamedDocument],
+    ) -> io::Result<()> {
+        if let Err((_, err)) = check_target_support(self, documents) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, err.message));
+        }
+        if let Some(header) = &options.header {
+            output.write_all(header.as_bytes())?;
+            if !header.ends_with('\n') {
+                writeln!(output)?;
+            }
+        }
+        (self.handler)(output, options, documents)
+    }
+}
+
+/// How a generated struct is used, this affects its definition.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum StructKind {
+    Argument,
+    Result,
+    /// A view into the current row, for iterator results with `--borrow-rows`.
+    RowView,
+}
+
+/// A struct that a query defines, and where it was first defined.
+struct StructDef<'a> {
+    kind: StructKind,
+    fields: &'a [TypedIdent<Span>],
+    query_name: &'a str,
+    input: &'a str,
+}
+
+impl<'a> StructDef<'a> {
+    fn is_equal_to(&self, other: &StructDef) -> bool {
+        self.kind == other.kind
+            && self.fields.len() == other.fields.len()
+            && self.fields.iter().zip(other.fields).all(|(lhs, rhs)| {
+                lhs.ident.resolve(self.input) == rhs.ident.resolve(other.input)
+                    && lhs
+                        .type_
+                        .resolve(self.input)
+                        .is_equal_to(&rhs.type_.resolve(other.input))
+            })
+    }
+}
+
+/// Check that structs with the same name have the same definition.
+///
+/// With `--dedup-structs`, targets define every struct only once, even when
+/// multiple queries, possibly in different documents, use it. That is only
+/// valid if all those queries agree on the fields. On error, this returns the
+/// index of the document that the error refers to, along with the error.
+pub fn check_shared_structs(
+    options: &Options,
+    documents: &[NamedDocument],
+) -> Result<(), (usize, TypeError)> {
+    let mut structs: HashMap<&str, StructDef> = HashMap::new();
+
+    for (i, doc) in documents.iter().enumerate() {
+        let input = doc.input;
+        for query in doc.document.iter_queries() {
+            let ann = &query.annotation;
+            let mut defs = Vec::new();
+            if let ArgType::Struct {
+                type_name, fields, ..
+            }
+            | ArgType::StructSlice {
+                type_name, fields, ..
+            } = &ann.arguments
+            {
+                defs.push((*type_name, StructKind::Argument, &fields[..]));
+            }
+            // Extern structs are not defined by us, so they cannot conflict.
+            match &ann.result_type {
+                ResultType::Iterator(ComplexType::Struct(name, fields, _))
+                    if options.borrow_rows =>
+                {
+                    defs.push((*name, StructKind::RowView, &fields[..]))
+                }
+                ResultType::Option(ComplexType::Struct(name, fields, false))
+                | ResultType::Single(ComplexType::Struct(name, fields, false))
+                | ResultType::Iterator(ComplexType::Struct(name, fields, false)) => {
+                    defs.push((*name, StructKind::Result, &fields[..]))
+                }
+                _ => {}
+            }
+
+            for (name_span, kind, fields) in defs {
+                let def = StructDef {
+                    kind,
+                    fields,
+                    query_name: ann.name.resolve(input),
+                    input,
+                };
+                match structs.entry(name_span.resolve(input)) {
+                    Entry::Vacant(vacancy) => {
+                        vacancy.insert(def);
+                    }
+                    Entry::Occupied(entry) if entry.get().is_equal_to(&def) => {}
+                    Entry::Occupied(entry) => {
+                        let error = TypeError {
+                            span: name_span,
+                            message: "Another query defines a struct with this name, \
+                            but with a different definition.",
+                            note: None,
+                            hint: Some(format!(
+                                "Query '{}' defines '{}' too. Structs with the same name \
+                                must have the same fields, and must both be arguments \
+                                or both be results. Rename one of the structs, \
+                                or make the definitions equal.",
+                                entry.get().query_name,
+                                entry.key(),
+                            )),
+                        };
+                        return Err((i, error));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove the queries that `--tags` and `--only-tagged` leave out.
+///
+/// A query with tags is selected when one of its tags is in `tags`, or when no
+/// tags were passed at all. A query without tags is selected unless
+/// `only_tagged` is set.
+pub fn select_tagged_queries(tags: &[String], only_tagged: bool, documents: &mut [NamedDocument]) {
+    for doc in documents.iter_mut() {
+        let input = doc.input;
+        doc.document.sections.retain(|section| {
+            let query = match section {
+                Section::Verbatim(..) => return true,
+                Section::Query(query) => query,
+            };
+            if query.tags.is_empty() {
+                return !only_tagged;
+            }
+            tags.is_empty()
+                || query
+                    .tags
+                    .iter()
+                    .any(|tag| tags.iter().any(|t| t == tag.resolve(input)))
+        });
+    }
+}
+
+/// Return the first argument or result type of the query that matches.
+fn find_type<F: Fn(&SimpleType<Span>) -> bool>(
+    query: &Query<Span>,
+    is_match: F,
+) -> Option<&SimpleType<Span>> {
+    let mut types: Vec<&SimpleType<Span>> = Vec::new();
+    match &query.annotation.arguments {
+        ArgType::Args(args) => types.extend(args.iter().map(|f| &f.type_)),
+        ArgType::Struct { fields, .. } | ArgType::StructSlice { fields, .. } => {
+            types.extend(fields.iter().map(|f| &f.type_))
+        }
+    }
+    match query.annotation.result_type.get() {
+        None => {}
+        Some(ComplexType::Simple(t)) => types.push(t),
+        Some(ComplexType::Tuple(_, elems)) => types.extend(elems.iter().map(|e| &e.type_)),
+        Some(ComplexType::Struct(_, fields, _)) => types.extend(fields.iter().map(|f| &f.type_)),
+        Some(ComplexType::TaggedEnum(_, variants)) => types.extend(
+            variants
+                .iter()
+                .flat_map(|v| v.fields.iter().map(|f| &f.type_)),
+        ),
+    }
+    types.into_iter().find(|t| is_match(t))
+}
+
+/// Return whether any argument or result in the documents has a matching type.
+fn documents_use<F: Fn(&SimpleType<Span>) -> bool>(
+    documents: &[NamedDocument],
+    is_match: F,
+) -> bool {
+    documents
+        .iter()
+        .flat_map(|doc| doc.document.iter_queries())
+        .any(|query| find_type(query, &is_match).is_some())
+}
+
+/// Check that the target accepts all target-specific flags that are set.
+///
+/// On error, this returns the flag that the target does not accept.
+pub fn check_target_flags(target: &Target, options: &Options) -> Result<(), &'static str> {
+    let flags = [
+        ("--minify-sql", options.minify_sql),
+        ("--strip-comments", options.strip_comments),
+        ("--cardinality-errors", options.cardinality_errors),
+        ("--tracing", options.tracing),
+        ("--expose-sql", options.expose_sql),
+    ];
+    for (flag, is_set) in flags.iter() {
+        if *is_set && !target.flags.contains(flag) {
+            return Err(flag);
+        }
+    }
+    Ok(())
+}
+
+/// Return the names of the targets that have a given feature, for use in hints.
+fn supporting_targets(has_feature: fn(&Target) -> bool) -> String {
+    let names: Vec<&str> = TARGETS
+        .iter()
+        .filter(|t| has_feature(t))
+        .map(|t| t.name)
+        .collect();
+    names.join(", ")
+}
+
+/// Check that the target can generate code for all queries.
+///
+/// Tagged enums, slice arguments, the json type, and module paths in query
+/// names are only supported by the targets that declare so in `TARGETS`. On
+/// error, this returns the index of the document that the error refers to,
+/// along with the error.
+pub fn check_target_support(
+    target: &Target,
+    documents: &[NamedDocument],
+) -> Result<(), (usize, TypeError)> {
+    for (i, doc) in documents.iter().enumerate() {
+        for query in doc.document.iter_queries() {
+            let path = &query.annotation.path;
+            if let (Some(first), Some(last)) = (path.first(), path.last()) {
+                if !target.supports_module_paths {
+                    let error = TypeError {
+                        span: first.union(*last),
+                        message: "This target does not support module paths in query names.",
+                        note: None,
+                        hint: Some(format!(
+                            "Module paths are not supported by {}, only by {}. \
+                            Include the module name in the query name instead.",
+                            target.name,
+                            supporting_targets(|t| t.supports_module_paths),
+                        )),
+                    };
+                    return Err((i, error));
+                }
+            }
+            if let ArgType::StructSlice { type_name, .. } = &query.annotation.arguments {
+                if !target.supports_struct_slices {
+                    let error = TypeError {
+                        span: *type_name,
+                        message: "This target does not support slice arguments.",
+                        note: None,
+                        hint: Some(format!(
+                            "Slice arguments are not supported by {}, only by {}. \
+                            Take a single struct argument and call the query \
+                            once per element instead.",
+                            target.name,
+                            supporting_targets(|t| t.supports_struct_slices),
+                        )),
+                    };
+                    return Err((i, error));
+                }
+            }
+            if let Some(json) = find_type(query, |t| t.inner_type() == PrimitiveType::Json) {
+                if !target.supports_json {
+                    let error = TypeError {
+                        span: json.span(),
+                        message: "This target does not support the json type.",
+                        note: None,
+                        hint: Some(format!(
+                            "The json type is not supported by {}, only by {}. \
+                            Use 'str' and encode the value instead.",
+                            target.name,
+                            supporting_targets(|t| t.supports_json),
+                        )),
+                    };
+                    return Err((i, error));
+                }
+            }
+            if let Some(ComplexType::TaggedEnum(name, _)) = query.annotation.result_type.get() {
+                if !target.supports_tagged_enums {
+                    let error = TypeError {
+                        span: *name,
+                        message: "This target does not support tagged enums.",
+                        note: None,
+                        hint: Some(format!(
+                            "Tagged enums are not supported by {}, only by {}. \
+                            Return a struct with the code as an integer field instead.",
+                            target.name,
+                            supporting_targets(|t| t.supports_tagged_enums),
+                        )),
+                    };
+                    return Err((i, error));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        check_shared_structs, check_target_flags, check_target_support, edit_distance,
+        select_tagged_queries, Options, Target, TARGETS,
+    };
+    use crate::error::Error;
+    use crate::NamedDocument;
+    use std::path::Path;
+
+    #[test]
+    fn edit_distance_counts_edits() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("abc", ""), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("rust-sqlit", "rust-sqlite"), 1);
+    }
+
+    #[test]
+    fn from_name_accepts_normalized_names() {
+        let target = Target::from_name("rust-sqlite").unwrap();
+        assert_eq!(target.name, "rust-sqlite");
+        assert_eq!(
+            Target::from_name("rust_sqlite").unwrap().name,
+            "rust-sqlite"
+        );
+        assert_eq!(
+            Target::from_name("Rust-SQLite").unwrap().name,
+            "rust-sqlite"
+        );
+        assert!(Target::from_name("rust-sqlit").is_none());
+    }
+
+    #[test]
+    fn suggest_finds_closest_target() {
+        assert_eq!(Target::suggest("rust_sqlite").unwrap().name, "rust-sqlite");
+        assert_eq!(Target::suggest("rust-sqlit").unwrap().name, "rust-sqlite");
+        assert_eq!(
+            Target::suggest("pyhton-sqlite").unwrap().name,
+            "python-sqlite"
+        );
+        assert!(Target::suggest("cobol-db2").is_none());
+    }
+
+    #[test]
+    fn process_files_prepends_header() {
+        let input = "-- @query get_answer() ->1 i64\nselect 42;";
+        let doc = NamedDocument::process_input(Path::new("x.sql"), input.as_bytes()).unwrap();
+        let options = Options {
+            header: Some("#![allow(clippy::all)]".to_string()),
+            ..Options::default()
+        };
+        let target = Target::from_name("rust-sqlite").unwrap();
+        let mut out = Vec::new();
+        target.process_files(&mut out, &options, &[doc]).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        let generated_by = format!(
+            "#![allow(clippy::all)]\n// This file was generated by Squiller {}",
+            crate::version::VERSION
+        );
+        assert!(out.starts_with(&generated_by), "{}", out);
+    }
+
+    #[test]
+    fn process_files_names_squiller_and_version() {
+        let input = "-- @query get_answer() ->1 i64\nselect 42;";
+        let generated_by = format!(
+            "This file was generated by Squiller {}",
+            crate::version::VERSION
+        );
+        // The targets that generate code include a header, the other ones
+        // print the input or a summary of it.
+        let code_targets = TARGETS
+            .iter()
+            .filter(|t| t.name.starts_with("rust-") || t.name.starts_with("python-"));
+        for target in code_targets {
+            let doc = NamedDocument::process_input(Path::new("x.sql"), input.as_bytes()).unwrap();
+            let mut out = Vec::new();
+            target
+                .process_files(&mut out, &Options::default(), &[doc])
+                .unwrap();
+            let out = String::from_utf8(out).unwrap();
+            assert!(out.contains(&generated_by), "{}", target.name);
+            assert!(!out.contains("Querybinder"), "{}", target.name);
+            assert!(!out.contains("TODO: version"), "{}", target.name);
+        }
+    }
+
+    #[test]
+    fn process_files_omits_version_header_when_quiet() {
+        let input = "-- @query get_answer() ->1 i64\nselect 42;";
+        let options = Options {
+            version_header: false,
+            ..Options::default()
+        };
+        for target in TARGETS.iter().filter(|t| t.name != "help") {
+            let doc = NamedDocument::process_input(Path::new("x.sql"), input.as_bytes()).unwrap();
+            let mut out = Vec::new();
+            target.process_files(&mut out, &options, &[doc]).unwrap();
+            let out = String::from_utf8(out).unwrap();
+            assert!(!out.contains("generated by Squiller"), "{}", target.name);
+            assert!(!out.contains("Input files:"), "{}", target.name);
+            assert!(!out.contains("x.sql"), "{}", target.name);
+        }
+    }
+
+    #[test]
+    fn process_files_output_is_deterministic() {
+        // The type checker and the targets use hash maps and sets for lookups.
+        // Every hash map gets its own random seed, so if iteration order leaked
+        // into the output, generating twice would eventually differ.
+        let input = "\
+-- @query insert_user(user: User) ->1 i64
+insert into users (zeta, alpha, mu, beta, omega, gamma)
+values (:zeta /* :str */, :alpha /* :i64 */, :mu /* :str? */,
+        :beta /* :f64 */, :omega /* :bytes */, :gamma /* :i32 */)
+returning id;
+
+-- @query select_user(zeta: str, alpha: i64, mu: str) ->* Row
+select zeta /* :str */, alpha /* :i64 */, mu /* :str? */,
+       beta /* :f64 */, omega /* :bytes */, gamma /* :i32 */
+from users where zeta = :zeta and alpha = :alpha and mu = :mu or zeta = :mu;
+";
+        for target in TARGETS.iter().filter(|t| t.name != "help") {
+            let generate = || {
+                let doc =
+                    NamedDocument::process_input(Path::new("x.sql"), input.as_bytes()).unwrap();
+                let mut out = Vec::new();
+                target
+                    .process_files(&mut out, &Options::default(), &[doc])
+                    .unwrap();
+                out
+            };
+            let first = generate();
+            for _ in 0..10 {
+                assert!(generate() == first, "{}", target.name);
+            }
+        }
+    }
+
+    #[test]
+    fn dependencies_include_base_packages_and_tracing() {
+        let input = "-- @query get_answer() ->1 i64\nselect 42;";
+        let docs = [NamedDocument::process_input(Path::new("x.sql"), input.as_bytes()).unwrap()];
+        let deps = |name, options: &Options| {
+            Target::from_name(name)
+                .unwrap()
+                .dependencies(options, &docs)
+        };
+        let default = Options::default();
+        let tracing = Options {
+            tracing: true,
+            ..Options::default()
+        };
+        assert_eq!(deps("rust-sqlite", &default), ["sqlite"]);
+        assert_eq!(deps("rust-sqlite", &tracing), ["sqlite", "tracing"]);
+        assert_eq!(deps("rust-sqlx", &default), ["sqlx"]);
+        assert_eq!(deps("python-psycopg2", &default), ["psycopg2"]);
+        assert!(deps("python-sqlite", &default).is_empty());
+        assert!(deps("debug", &tracing).is_empty());
+    }
+
+    #[test]
+    fn dependencies_include_packages_for_json() {
+        let input = "\
+-- @query get_settings(id: i64) ->1 json
+select settings from users where id = :id;";
+        let docs = [NamedDocument::process_input(Path::new("x.sql"), input.as_bytes()).unwrap()];
+        let deps = |name| {
+            Target::from_name(name)
+                .unwrap()
+                .dependencies(&Options::default(), &docs)
+        };
+        assert_eq!(deps("rust-sqlite"), ["sqlite", "serde_json"]);
+        assert_eq!(deps("rust-sqlx"), ["sqlx", "serde_json"]);
+        assert!(deps("sql").is_empty());
+    }
+
+    #[test]
+    fn check_target_support_rejects_json() {
+        let input = "\
+-- @query set_settings(id: i64, settings: json?)
+update users set settings = :settings where id = :id;";
+        let docs = [NamedDocument::process_input(Path::new("x.sql"), input.as_bytes()).unwrap()];
+
+        let rust_sqlite = Target::from_name("rust-sqlite").unwrap();
+        assert!(check_target_support(rust_sqlite, &docs).is_ok());
+
+        let python = Target::from_name("python-sqlite").unwrap();
+        let (i, err) = check_target_support(python, &docs).err().unwrap();
+        assert_eq!(i, 0);
+        assert_eq!(err.span().resolve(input), "json?");
+    }
+
+    #[test]
+    fn select_tagged_queries_filters_by_tag() {
+        let input = "\
+-- @query get_answer() ->1 i64
+select 42;
+
+-- @tags admin
+-- @query delete_users()
+delete from users;
+
+-- @query iter_jobs() ->* i64
+-- @tags batch, admin
+select id from jobs;
+
+-- @tags batch
+-- @query run_jobs()
+update jobs set done = 1;
+";
+        let generate = |tags: &[&str], only_tagged: bool| {
+            let mut docs =
+                [NamedDocument::process_input(Path::new("x.sql"), input.as_bytes()).unwrap()];
+            let tags: Vec<String> = tags.iter().map(|t| t.to_string()).collect();
+            select_tagged_queries(&tags, only_tagged, &mut docs);
+            let target = Target::from_name("list").unwrap();
+            let mut out = Vec::new();
+            target
+                .process_files(&mut out, &Options::default(), &docs)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let all = "get_answer() ->1 i64\ndelete_users()\niter_jobs() ->* i64\nrun_jobs()\n";
+        assert_eq!(generate(&[], false), all);
+        assert_eq!(
+            generate(&["admin"], false),
+            "get_answer() ->1 i64\ndelete_users()\niter_jobs() ->* i64\n",
+        );
+        assert_eq!(
+            generate(&["batch"], true),
+            "iter_jobs() ->* i64\nrun_jobs()\n"
+        );
+    }
+
+    #[test]
+    fn check_shared_structs_rejects_different_fields() {
+        let users = "\
+-- @query get_user(id: i64) ->1 User
+select name /* :str */, email /* :str */ from users where id = :id;";
+        let same = "\
+-- @query iter_users() ->* User
+select name /* :str */, email /* :str */ from users;";
+        let different = "\
+-- @query get_user_by_email(email: str) ->? User
+select name /* :str */, karma /* :i64 */ from users where email = :email;";
+        let argument = "\
+-- @query insert_user(user: User)
+insert into users (name, email) values (:name /* :str */, :email /* :str */);";
+
+        let process = |input: &'static str| {
+            NamedDocument::process_input(Path::new("x.sql"), input.as_bytes()).unwrap()
+        };
+        let options = Options::default();
+
+        let docs = [process(users), process(same)];
+        assert!(check_shared_structs(&options, &docs).is_ok());
+
+        let docs = [process(users), process(different)];
+        let (i, err) = check_shared_structs(&options, &docs).err().unwrap();
+        assert_eq!(i, 1);
+        assert_eq!(err.span().resolve(different), "User");
+
+        // An argument struct has a different definition than a result struct.
+        let docs = [process(users), process(argument)];
+        assert!(check_shared_structs(&options, &docs).is_err());
+
+        // With `--borrow-rows`, iterator queries define a row view instead.
+        let options = Options {
+            borrow_rows: true,
+            ..Options::default()
+        };
+        let docs = [process(users), process(same)];
+        assert!(check_shared_structs(&options, &docs).is_err());
+    }
+
+    #[test]
+    fn check_target_flags_rejects_flags_of_other_targets() {
+        let options = Options {
+            cardinality_errors: true,
+            ..Options::default()
+        };
+        let rust_sqlite = Target::from_name("rust-sqlite").unwrap();
+        assert_eq!(check_target_flags(rust_sqlite, &options), Ok(()));
+        let rust_sqlx = Target::from_name("rust-sqlx").unwrap();
+        assert_eq!(check_target_flags(rust_sqlx, &Options::default()), Ok(()));
+        assert_eq!(
+            check_target_flags(rust_sqlx, &options),
+            Err("--cardinality-errors")
+        );
+        let options = Options {
+            tracing: true,
+            ..Options::default()
+        };
+        assert_eq!(check_target_flags(rust_sqlite, &options), Ok(()));
+        assert_eq!(check_target_flags(rust_sqlx, &options), Err("--tracing"));
+        let options = Options {
+            expose_sql: true,
+            ..Options::default()
+        };
+        assert_eq!(check_target_flags(rust_sqlite, &options), Ok(()));
+        assert_eq!(check_target_flags(rust_sqlx, &options), Err("--expose-sql"));
+
+        let options = Options {
+            minify_sql: true,
+            ..Options::default()
+        };
+        let sql = Target::from_name("sql").unwrap();
+        assert_eq!(check_target_flags(sql, &options), Ok(()));
+        assert_eq!(check_target_flags(rust_sqlx, &options), Err("--minify-sql"));
+        let options = Options {
+            strip_comments: true,
+            ..Options::default()
+        };
+        assert_eq!(check_target_flags(sql, &options), Ok(()));
+        assert_eq!(
+            check_target_flags(rust_sqlx, &options),
+            Err("--strip-comments")
+        );
+    }
+
+    #[test]
+    fn check_target_support_rejects_tagged_enums() {
+        let plain = "\
+-- @query get_user(id: i64) ->1 User
+select name /* :str */, email /* :str */ from users where id = :id;";
+        let tagged = "\
+-- @query iter_events() ->* Event(Created = 0 (id: i64), Cleared = 1)
+select kind, id from events;";
+
+        let process = |input: &'static str| {
+            NamedDocument::process_input(Path::new("x.sql"), input.as_bytes()).unwrap()
+        };
+        let docs = [process(plain), process(tagged)];
+
+        let rust_sqlite = Target::from_name("rust-sqlite").unwrap();
+        assert!(check_target_support(rust_sqlite, &docs).is_ok());
+
+        let python = Target::from_name("python-sqlite").unwrap();
+        assert!(check_target_support(python, &docs[..1]).is_ok());
+        let (i, err) = check_target_support(python, &docs).err().unwrap();
+        assert_eq!(i, 1);
+        assert_eq!(err.span().resolve(tagged), "Event");
+        let hint = err.hint.unwrap();
+        assert!(hint.contains("rust-sqlite"), "{}", hint);
+        assert!(!hint.contains("rust-sqlx"), "{}", hint);
+
+        // Callers that skip the check get an error, not a panic in the target.
+        for target in TARGETS
+            .iter()
+            .filter(|t| !t.supports_tagged_enums && t.name != "help")
+        {
+            let err = target.process_files(&mut Vec::new(), &Options::default(), &docs);
+            assert_eq!(err.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn check_target_support_rejects_struct_slices() {
+        let input = "\
+-- @query insert_users(users: [User])
+insert into users (name) values (:name /* :str */);";
+        let docs = [NamedDocument::process_input(Path::new("x.sql"), input.as_bytes()).unwrap()];
+
+        let rust_sqlite = Target::from_name("rust-sqlite").unwrap();
+        assert!(check_target_support(rust_sqlite, &docs).is_ok());
+
+        let python = Target::from_name("python-sqlite").unwrap();
+        let (i, err) = check_target_support(python, &docs).err().unwrap();
+        assert_eq!(i, 0);
+        assert_eq!(err.span().resolve(input), "User");
+        let hint = err.hint.unwrap();
+        assert!(hint.contains("rust-sqlx"), "{}", hint);
+        assert!(!hint.contains("python-psycopg2,"), "{}", hint);
+    }
+
+    #[test]
+    fn check_target_support_rejects_module_paths() {
+        let input = "\
+-- @query users::admin::get_name(id: i64) ->1 str
+select name from users where id = :id;";
+        let docs = [NamedDocument::process_input(Path::new("x.sql"), input.as_bytes()).unwrap()];
+
+        let rust_sqlite = Target::from_name("rust-sqlite").unwrap();
+        assert!(check_target_support(rust_sqlite, &docs).is_ok());
+
+        let python = Target::from_name("python-psycopg2").unwrap();
+        let (i, err) = check_target_support(python, &docs).err().unwrap();
+        assert_eq!(i, 0);
+        assert_eq!(err.span().resolve(input), "users::admin");
+        let hint = err.hint.unwrap();
+        assert!(hint.contains("rust-sqlite"), "{}", hint);
+        assert!(!hint.contains("rust-sqlx"), "{}", hint);
+    }
+}
@@ -0,0 +1,460 @@
+// Squiller -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2023 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Shared code generation for all Python targets.
+
+use std::collections::HashSet;
+
+use crate::ast::{
+    Annotation, ArgType, ComplexType, Fragment, PrimitiveType, ResultType, SimpleType,
+};
+use crate::codegen::Block;
+use crate::target::Options;
+use crate::{NamedDocument, Span};
+
+/// How to reference parameters in the SQL string, see also PEP 249.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ParamStyle {
+    /// Counted `%s`-style references, used by `psycopg2`.
+    Format,
+    /// Named `%(name)s`-style references, used by `psycopg2`.
+    Pyformat,
+    /// Counted `?`-style references, used by `sqlite3`.
+    Qmark,
+    /// Named `:name`-style references, used by `sqlite3`.
+    Named,
+}
+
+impl ParamStyle {
+    fn is_named(&self) -> bool {
+        matches!(self, ParamStyle::Pyformat | ParamStyle::Named)
+    }
+}
+
+pub fn header_comment(options: &Options, documents: &[NamedDocument]) -> Block {
+    use crate::version::{REV, VERSION};
+
+    let mut block = Block::new();
+    if !options.version_header {
+        return block;
+    }
+
+    let mut header = "# This file was generated by Squiller ".to_string();
+    header.push_str(VERSION);
+    match REV {
+        Some(rev) => {
+            header.push_str(" (commit ");
+            header.push_str(&rev[..10]);
+            header.push_str(").");
+        }
+        None => header.push_str(" (unspecified checkout)."),
+    }
+    block.push_line(header);
+    block.push_line_str("# Input files:");
+    // Files without queries contribute nothing to the output, omit them.
+    for doc in documents.iter().filter(|doc| doc.document.has_queries()) {
+        block.push_line(format!("# - {}", doc.fname.to_string_lossy()));
+    }
+
+    block
+}
+
+/// Return the expression that refers to the transaction in a query function.
+///
+/// With `--python-class`, the queries are methods that take the transaction
+/// from `self`, otherwise they are functions that take it as first argument.
+pub fn transaction_expr(options: &Options) -> &'static str {
+    match options.python_class {
+        Some(..) => "self.tx",
+        None => "tx",
+    }
+}
+
+/// Format the `def` line of the query function.
+///
+/// When `is_method` is true, the function is a method of the class set with
+/// `--python-class`, and it takes `self` instead of the transaction.
+pub fn function_signature(ann: &Annotation<Span>, input: &str, is_method: bool) -> Block {
+    let mut block = Block::new();
+    block.push_line_str("");
+    if !is_method {
+        block.push_line_str("");
+    }
+
+    let mut line = "def ".to_string();
+    line.push_str(ann.name.resolve(input));
+    line.push_str(if is_method {
+        "(self"
+    } else {
+        "(tx: Transaction"
+    });
+
+    match &ann.arguments {
+        ArgType::Args(args) => {
+            for arg in args {
+                // TODO: Include types.
+                line.push_str(", ");
+                line.push_str(arg.ident.resolve(input));
+            }
+        }
+        ArgType::Struct {
+            var_name,
+            type_name,
+            ..
+        } => {
+            line.push_str(", ");
+            line.push_str(var_name.resolve(input));
+            line.push_str(": ");
+            line.push_str(type_name.resolve(input));
+        }
+        ArgType::StructSlice { .. } => {
+            unreachable!("Slice arguments are rejected by `check_target_support`.")
+        }
+    }
+
+    line.push_str(") -> ");
+
+    match &ann.result_type {
+        ResultType::Unit => line.push_str("None:"),
+        ResultType::Option(_t) => {
+            // TODO: Write the actual type.
+            // TODO: Ensure import.
+            line.push_str("Optional[Any]:");
+        }
+        ResultType::Single(_t) => {
+            // TODO: Write the actual type.
+            line.push_str("Any:");
+        }
+        ResultType::Iterator(t) => {
+            // The Python targets fetch all rows into a list, see `fetch_result`.
+            // TODO: Write the actual type for simple types and tuples.
+            match t {
+                ComplexType::Struct(name, ..) => {
+                    line.push_str("list[");
+                    line.push_str(name.resolve(input));
+                    line.push_str("]:");
+                }
+                _ => line.push_str("list[Any]:"),
+            }
+        }
+    }
+
+    block.push_line(line);
+
+    block
+}
+
+/// Define the class set with `--python-class`, with the given methods.
+pub fn class_definition(class_name: &str, methods: Block) -> Block {
+    let mut block = Block::new();
+    block.push_line_str("");
+    block.push_line_str("");
+    block.push_line(format!("class {}:", class_name));
+
+    let mut class_body = Block::new();
+    class_body.push_line_str("def __init__(self, tx: Transaction) -> None:");
+    let mut init_body = Block::new();
+    init_body.push_line_str("self.tx = tx");
+    class_body.push_block(init_body.indent());
+    class_body.push_block(methods);
+    block.push_block(class_body.indent());
+
+    block
+}
+
+/// Format the docstring, if there are doc comments or `@param` docs.
+pub fn docstring(docs: &[Span], arguments: &ArgType<Span>, input: &str) -> Block {
+    let mut block = Block::new();
+
+    let param_docs: Vec<(&str, &str)> = match arguments {
+        ArgType::Args(args) => args
+            .iter()
+            .filter_map(|arg| {
+                arg.doc
+                    .map(|doc| (arg.ident.resolve(input), doc.resolve(input)))
+            })
+            .collect(),
+        ArgType::Struct { .. } | ArgType::StructSlice { .. } => Vec::new(),
+    };
+
+    if !docs.is_empty() || !param_docs.is_empty() {
+        // Code examples may contain backslashes, which should end up in the
+        // docstring literally, so use a raw string when there are any.
+        let has_backslash = docs.iter().any(|doc| doc.resolve(input).contains('\\'))
+            || param_docs.iter().any(|(_, doc)| doc.contains('\\'));
+        block.push_line_str(if has_backslash { "r\"\"\"" } else { "\"\"\"" });
+
+        let mut in_code_block = false;
+        let mut fence_indent = 0;
+        for doc_line in docs {
+            // The comment lines usually start with a space that went after
+            // the "--" that starts the comment. In Python docstrings, we
+            // don't want to start the line with a space, so remove them.
+            // Inside a fenced code block the indentation is significant, so
+            // there we remove only as much as we removed from the fence.
+            let doc_line_str = doc_line.resolve(input);
+            let n_spaces = doc_line_str.len() - doc_line_str.trim_start_matches(' ').len();
+            let trim = match in_code_block {
+                true => n_spaces.min(fence_indent),
+                false => n_spaces.min(1),
+            };
+            if doc_line_str.trim_start().starts_with("```") {
+                if !in_code_block {
+                    fence_indent = trim;
+                }
+                in_code_block = !in_code_block;
+            }
+            block.push_line_str(&doc_line_str[trim..]);
+        }
+        if !docs.is_empty() && !param_docs.is_empty() {
+            block.push_line_str("");
+        }
+        for (name, doc) in param_docs {
+            block.push_line(format!(":param {}: {}", name, doc));
+        }
+        block.push_line_str("\"\"\"");
+    }
+
+    block
+}
+
+/// Return the Python type annotation for a simple type.
+fn python_type<TSpan>(type_: &SimpleType<TSpan>) -> String {
+    let primitive = |t: &PrimitiveType| match t {
+        PrimitiveType::Str => "str",
+        PrimitiveType::I16 | PrimitiveType::I32 | PrimitiveType::I64 | PrimitiveType::U32 => "int",
+        PrimitiveType::F32 | PrimitiveType::F64 => "float",
+        PrimitiveType::Bytes => "bytes",
+        PrimitiveType::Json => unreachable!("Json is rejected by `check_target_support`."),
+    };
+    match type_ {
+        SimpleType::Primitive { type_, .. } => primitive(type_).to_string(),
+        SimpleType::Option { type_, .. } => format!("Optional[{}]", primitive(type_)),
+        // Enums are passed as their integer code.
+        SimpleType::Enum { .. } => "int".to_string(),
+    }
+}
+
+/// Define the `NamedTuple` for the result struct, if the query returns one.
+///
+/// Structs that are marked `@extern` do not get a new definition. With
+/// `--dedup-structs`, neither do structs that are in `defined` already, the
+/// driver checks with `check_shared_structs` that their fields are the same.
+pub fn result_struct_definition<'a>(
+    options: &Options,
+    defined: &mut HashSet<&'a str>,
+    result_type: &ResultType<Span>,
+    input: &'a str,
+) -> Block {
+    let mut block = Block::new();
+
+    let (name, fields) = match result_type.get() {
+        Some(ComplexType::Struct(name, fields, false)) => (name.resolve(input), fields),
+        _ => return block,
+    };
+    if !defined.insert(name) && options.dedup_structs {
+        return block;
+    }
+
+    block.push_line_str("");
+    block.push_line_str("");
+    block.push_line(format!("class {}(NamedTuple):", name));
+    let mut fields_block = Block::new();
+    for field in fields {
+        fields_block.push_line(format!(
+            "{}: {}",
+            field.ident.resolve(input),
+            python_type(&field.type_)
+        ));
+    }
+    block.push_block(fields_block.indent());
+
+    block
+}
+
+/// Return the names of the parameters in the statement, without leading `:`.
+///
+/// Every parameter is included once, in order of first occurrence.
+pub fn unique_parameters<'a>(fragments: &[Fragment<Span>], input: &'a str) -> Vec<&'a str> {
+    let mut result = Vec::new();
+    for fragment in fragments {
+        let param = match fragment {
+            Fragment::Param(span) => span,
+            Fragment::TypedParam(_full_span, ti) => &ti.ident,
+            _ => continue,
+        };
+        // Cut off the leading ':' from the parameter name.
+        let name = param.trim_start(1).resolve(input);
+        if !result.contains(&name) {
+            result.push(name);
+        }
+    }
+    result
+}
+
+/// Format the SQL string, with parameters substituted with placeholders.
+pub fn sql_string(fragments: &[Fragment<Span>], input: &str, style: ParamStyle) -> Block {
+    let mut block = Block::new();
+    block.push_line_str("\"\"\"");
+
+    let mut sql = String::new();
+    for fragment in fragments {
+        let param = match fragment {
+            Fragment::Verbatim(span) | Fragment::Comment(span) => {
+                sql.push_str(span.resolve(input));
+                continue;
+            }
+            // When we put the SQL in the source code, omit the type
+            // annotations, it's only a distraction. An alias in the annotation
+            // does matter, it becomes the column name.
+            Fragment::TypedIdent(_full_span, ti) => {
+                sql.push_str(ti.ident.resolve(input));
+                if let Some(alias) = ti.alias {
+                    sql.push_str(" as ");
+                    sql.push_str(alias.resolve(input));
+                }
+                continue;
+            }
+            Fragment::Param(span) => span,
+            Fragment::TypedParam(_full_span, ti) => &ti.ident,
+        };
+        let name = param.trim_start(1).resolve(input);
+        match style {
+            ParamStyle::Format => sql.push_str("%s"),
+            ParamStyle::Pyformat => {
+                sql.push_str("%(");
+                sql.push_str(name);
+                sql.push_str(")s");
+            }
+            ParamStyle::Qmark => sql.push('?'),
+            ParamStyle::Named => {
+                sql.push(':');
+                sql.push_str(name);
+            }
+        }
+    }
+    for line in sql.lines() {
+        block.push_line_str(line);
+    }
+
+    block.push_line_str("\"\"\"");
+    block
+}
+
+/// Assign the SQL string and its parameters to the `sql` and `params` locals.
+///
+/// When a parameter occurs more than once in the statement, we use the named
+/// style, so we can pass every parameter once, the same way the Rust targets
+/// bind it once. Otherwise we use the counted style (to save a dict lookup),
+/// and write out the parameters in the same order.
+pub fn statement_locals(
+    fragments: &[Fragment<Span>],
+    input: &str,
+    counted: ParamStyle,
+    named: ParamStyle,
+) -> Block {
+    let mut block = Block::new();
+    let params = unique_parameters(fragments, input);
+    let num_occurrences = fragments
+        .iter()
+        .filter(|f| matches!(f, Fragment::Param(..) | Fragment::TypedParam(..)))
+        .count();
+    let style = if params.len() < num_occurrences {
+        named
+    } else {
+        counted
+    };
+
+    // TODO: Include the source file name and line number as a comment.
+    block.push_line_str("sql =\\");
+    block.push_block(sql_string(fragments, input, style).indent());
+
+    if params.is_empty() {
+        block.push_line_str("params = ()");
+    } else if style.is_named() {
+        block.push_line_str("params = {");
+        let mut param_block = Block::new();
+        for variable_name in params {
+            // TODO: Deal with prefix in case we are accessing a struct.
+            param_block.push_line(format!("\"{}\": {},", variable_name, variable_name));
+        }
+        block.push_block(param_block.indent());
+        block.push_line_str("}");
+    } else {
+        block.push_line_str("params = (");
+        let mut param_block = Block::new();
+        for variable_name in params {
+            // TODO: Deal with prefix in case we are accessing a struct.
+            param_block.push_line(format!("{},", variable_name));
+        }
+        block.push_block(param_block.indent());
+        block.push_line_str(")");
+    }
+
+    block
+}
+
+/// Return the Python expression that decodes the row in variable `row`.
+///
+/// This is the value itself for simple types, a tuple for tuples, and the
+/// `NamedTuple` for structs.
+fn decode_row(type_: &ComplexType<Span>, input: &str) -> String {
+    match type_ {
+        ComplexType::Simple(..) => "row[0]".to_string(),
+        ComplexType::Tuple(..) => "row".to_string(),
+        ComplexType::Struct(name, ..) => format!("{}(*row)", name.resolve(input)),
+        ComplexType::TaggedEnum(..) => {
+            unreachable!("Tagged enums are rejected by `check_target_support`.")
+        }
+    }
+}
+
+/// Fetch the result of a query from `cursor`, after executing its statement.
+///
+/// For `->?`, return `None` when there is no row. For `->1`, raise a
+/// `LookupError` when there is no row. For `->*`, fetch all rows into a list.
+/// We don't return a lazy iterator, because the cursor would have to outlive
+/// the function call, and executing another statement on the transaction
+/// would reset it. Rows are decoded as described in `decode_row`.
+pub fn fetch_result(
+    cursor: &str,
+    query_name: &str,
+    result_type: &ResultType<Span>,
+    input: &str,
+) -> Block {
+    let mut block = Block::new();
+
+    let (is_option, type_) = match result_type {
+        ResultType::Option(t) => (true, t),
+        ResultType::Single(t) => (false, t),
+        ResultType::Iterator(t) => {
+            block.push_line(format!(
+                "return [{} for row in {}.fetchall()]",
+                decode_row(t, input),
+                cursor
+            ));
+            return block;
+        }
+        ResultType::Unit => return block,
+    };
+
+    block.push_line(format!("row = {}.fetchone()", cursor));
+    block.push_line_str("if row is None:");
+    let mut if_block = Block::new();
+    if is_option {
+        if_block.push_line_str("return None");
+    } else {
+        if_block.push_line(format!(
+            "raise LookupError(\"Query '{}' returned no rows.\")",
+            query_name
+        ));
+    }
+    block.push_block(if_block.indent());
+    block.push_line(format!("return {}", decode_row(type_, input)));
+
+    block
+}
@@ -0,0 +1,143 @@
+// Squiller -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Target Python and `psycopg2` package.
+
+use crate::codegen::Block;
+use crate::target::python::{self, ParamStyle};
+use crate::target::Options;
+use crate::NamedDocument;
+
+use std::collections::HashSet;
+use std::io;
+
+const PREAMBLE: &str = r#"
+from __future__ import annotations
+
+import contextlib
+
+from typing import Any, Iterator, NamedTuple, Optional
+
+import psycopg2.extensions  # type: ignore
+import psycopg2.extras  # type: ignore
+import psycopg2.pool  # type: ignore
+
+
+class Transaction:
+    def __init__(self, conn: psycopg2.extensions.connection) -> None:
+        self.conn = conn
+
+    def commit(self) -> None:
+        self.conn.commit()
+        # Ensure we cannot reuse the connection.
+        self.conn = None
+
+    def rollback(self) -> None:
+        self.conn.rollback()
+        self.conn = None
+
+    def cursor(self) -> psycopg2.extensions.cursor:
+        return self.conn.cursor()
+
+
+class ConnectionPool(NamedTuple):
+    pool: psycopg2.pool.ThreadedConnectionPool
+
+    @contextlib.contextmanager
+    def begin(self) -> Iterator[Transaction]:
+        conn: Optional[psycopg2.extensions.connection] = None
+        try:
+            # Use psycopg2 in "no-autocommit" mode, where it implicitly starts a
+            # transaction at the first statement, and we need to explicitly
+            # commit() or rollback() afterwards.
+            conn = self.pool.getconn()
+            conn.isolation_level = "SERIALIZABLE"
+            conn.autocommit = False
+            yield Transaction(conn)
+
+        except:
+            if conn is not None:
+                self.pool.putconn(conn, close=True)
+            raise
+
+        else:
+            assert conn is not None
+            self.pool.putconn(conn, close=False)
+"#;
+
+/// Generate Python code that uses the `psycopg2` package.
+pub fn format_documents(options: &Options, documents: &[NamedDocument]) -> Block {
+    let mut root = Block::new();
+    root.push_block(python::header_comment(options, documents));
+    root.push_line(PREAMBLE.trim_end().to_string());
+
+    let mut defined_structs = HashSet::new();
+    let is_method = options.python_class.is_some();
+    let tx = python::transaction_expr(options);
+    let mut methods = Block::new();
+
+    for named_document in documents {
+        let input = named_document.input;
+
+        for query in named_document.document.iter_queries() {
+            let ann = &query.annotation;
+            let sig = python::function_signature(ann, input, is_method);
+            let query_name = ann.name.resolve(input);
+
+            let mut function_body = Block::new();
+            function_body.push_block(python::docstring(&query.docs, &ann.arguments, input));
+
+            for (i, statement) in query.statements.iter().enumerate() {
+                function_body.push_block(python::statement_locals(
+                    &statement.fragments,
+                    input,
+                    ParamStyle::Format,
+                    ParamStyle::Pyformat,
+                ));
+
+                function_body.push_line(format!("with {}.cursor() as cursor:", tx));
+                let mut with_block = Block::new();
+                with_block.push_line_str("cursor.execute(sql, params)");
+                // Only the last statement of a query can return a result.
+                if i + 1 == query.statements.len() {
+                    with_block.push_block(python::fetch_result(
+                        "cursor",
+                        query_name,
+                        &ann.result_type,
+                        input,
+                    ));
+                }
+                function_body.push_block(with_block.indent());
+            }
+
+            root.push_block(python::result_struct_definition(
+                options,
+                &mut defined_structs,
+                &ann.result_type,
+                input,
+            ));
+            let functions_out = if is_method { &mut methods } else { &mut root };
+            functions_out.push_block(sig);
+            functions_out.push_block(function_body.indent());
+        }
+    }
+
+    if let Some(class_name) = &options.python_class {
+        root.push_block(python::class_definition(class_name, methods));
+    }
+
+    root
+}
+
+/// Generate Python code that uses the `psycopg2` package.
+pub fn process_documents(
+    out: &mut dyn io::Write,
+    options: &Options,
+    documents: &[NamedDocument],
+) -> io::Result<()> {
+    format_documents(options, documents).format(out)
+}
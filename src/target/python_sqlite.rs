@@ -0,0 +1,119 @@
+// Squiller -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2023 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Target Python and `sqlite3` module.
+
+use std::collections::HashSet;
+use std::io;
+
+use crate::codegen::Block;
+use crate::target::python::{self, ParamStyle};
+use crate::target::Options;
+use crate::NamedDocument;
+
+const PREAMBLE: &str = r#"
+from __future__ import annotations
+
+import contextlib
+
+from typing import Any, Iterator, NamedTuple, Optional
+
+import sqlite3
+
+
+class Transaction:
+    def __init__(self, conn: sqlite3.Connection) -> None:
+        self.conn = conn
+        self.cursor = conn.cursor()
+        self.cursor.execute("BEGIN DEFERRED")
+
+    def commit(self) -> None:
+        self.conn.commit()
+        # Ensure we cannot reuse the connection.
+        self.conn = None
+        self.cursor = None
+
+    def rollback(self) -> None:
+        self.conn.rollback()
+        self.conn = None
+        self.cursor = None
+
+"#;
+
+/// Generate Python code that uses the `sqlite` module.
+fn format_documents(options: &Options, documents: &[NamedDocument]) -> Block {
+    let mut root = Block::new();
+    root.push_block(python::header_comment(options, documents));
+    let preamble = match options.begin_mode {
+        Some(mode) => PREAMBLE.replace(
+            "execute(\"BEGIN DEFERRED\")",
+            &format!("execute(\"{}\")", mode.begin_statement()),
+        ),
+        None => PREAMBLE.to_string(),
+    };
+    root.push_line(preamble.trim_end().to_string());
+
+    let mut defined_structs = HashSet::new();
+    let is_method = options.python_class.is_some();
+    let tx = python::transaction_expr(options);
+    let mut methods = Block::new();
+
+    for named_document in documents {
+        let input = named_document.input;
+
+        for query in named_document.document.iter_queries() {
+            let ann = &query.annotation;
+            let sig = python::function_signature(ann, input, is_method);
+            let query_name = ann.name.resolve(input);
+
+            let mut function_body = Block::new();
+            function_body.push_block(python::docstring(&query.docs, &ann.arguments, input));
+
+            for statement in query.statements.iter() {
+                function_body.push_block(python::statement_locals(
+                    &statement.fragments,
+                    input,
+                    ParamStyle::Qmark,
+                    ParamStyle::Named,
+                ));
+                function_body.push_line(format!("{}.cursor.execute(sql, params)", tx));
+            }
+
+            function_body.push_block(python::fetch_result(
+                &format!("{}.cursor", tx),
+                query_name,
+                &ann.result_type,
+                input,
+            ));
+
+            root.push_block(python::result_struct_definition(
+                options,
+                &mut defined_structs,
+                &ann.result_type,
+                input,
+            ));
+            let functions_out = if is_method { &mut methods } else { &mut root };
+            functions_out.push_block(sig);
+            functions_out.push_block(function_body.indent());
+        }
+    }
+
+    if let Some(class_name) = &options.python_class {
+        root.push_block(python::class_definition(class_name, methods));
+    }
+
+    root
+}
+
+/// Generate Python code that uses the `sqlite` module.
+pub fn process_documents(
+    out: &mut dyn io::Write,
+    options: &Options,
+    documents: &[NamedDocument],
+) -> std::io::Result<()> {
+    format_documents(options, documents).format(out)
+}
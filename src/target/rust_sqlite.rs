@@ -0,0 +1,2617 @@
+// Squiller -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+use crate::ast::{
+    Annotation, ArgType, ComplexType, EnumVariant, Fragment, PrimitiveType, Query, ResultType,
+    SimpleType, Statement, TaggedVariant, TypedIdent,
+};
+use crate::target::sql::{minify_sql, strip_comments};
+use crate::target::{documents_use, NullPolicy, Options, Visibility};
+use crate::{NamedDocument, Span};
+
+use std::collections::hash_map::HashMap;
+use std::collections::hash_set::HashSet;
+use std::io;
+use std::io::Write;
+
+// It would be nice if we could make a method for this instead of repeating the
+// boilerplate in each method, but I haven't discovered a way to make it work
+// lifetime-wise, because the Entry API needs to borrow self as mutable.
+const GET_STATEMENT: &str = r#"
+    let statement = match tx.statements.entry(sql.as_ptr()) {
+        Occupied(entry) => entry.into_mut(),
+        Vacant(vacancy) => vacancy.insert(tx.connection.prepare(sql)?),
+    };
+"#;
+
+/// Return the names of the parameters of the statement, without leading ':'.
+///
+/// SQLite numbers parameters by unique name, so if the same name occurs twice,
+/// it is only included once, at its first occurrence. We collect the names in
+/// a single pass over the fragments, so that statements with many parameters
+/// do not make code generation quadratic.
+fn statement_parameters<'a>(statement: &Statement<Span>, input: &'a str) -> Vec<&'a str> {
+    let mut seen = HashSet::new();
+    statement
+        .iter_parameters()
+        .map(|param| param.trim_start(1).resolve(input))
+        .filter(|name| seen.insert(*name))
+        .collect()
+}
+
+/// Generate a main function, to make the generated file compile as an example.
+fn write_main(out: &mut dyn io::Write, options: &Options) -> io::Result<()> {
+    write!(
+        out,
+        r#"
+// A useless main function, included only to make the example compile with
+// Cargo’s default settings for examples.
+#[allow(dead_code)]
+fn main() {{
+    let raw_connection = sqlite::open(":memory:").unwrap();
+    let mut connection = {p}Connection::new(&raw_connection);
+
+    let tx = connection.begin().unwrap();
+    tx.rollback().unwrap();
+
+    let tx = connection.begin().unwrap();
+    tx.commit().unwrap();
+}}
+"#,
+        p = options.type_prefix,
+    )
+}
+
+/// Generate the module-level attributes, imports, and the connection types.
+///
+/// The structure of the types depends on whether we cache prepared statements,
+/// and their names and visibility are configurable, so we compose them here.
+///
+/// The connection reserves room in its statement cache for `query_count`
+/// statements.
+fn write_preamble(
+    out: &mut dyn io::Write,
+    options: &Options,
+    query_count: usize,
+    uses_enums: bool,
+) -> io::Result<()> {
+    let vis = options.visibility.prefix();
+    let p = &options.type_prefix[..];
+
+    writeln!(out)?;
+    // The cfg attribute goes first, so it applies to the entire module.
+    if let Some(feature) = &options.cfg_feature {
+        writeln!(out, "#![cfg(feature = \"{}\")]", feature)?;
+    }
+    out.write_all(
+        br#"#![allow(unknown_lints)]
+#![allow(dead_code)]
+#![allow(clippy::collapsible_if)]
+#![allow(clippy::needless_question_mark)]
+#![allow(clippy::let_unit_value)]
+#![allow(clippy::needless_lifetimes)]
+"#,
+    )?;
+
+    match options.cache_statements {
+        // Only the query functions look up statements in the cache, so
+        // without queries, the entry variants would be unused imports.
+        true if query_count > 0 => out.write_all(
+            br#"
+use std::collections::hash_map::Entry::{Occupied, Vacant};
+use std::collections::hash_map::HashMap;
+"#,
+        )?,
+        true => out.write_all(
+            br#"
+use std::collections::hash_map::HashMap;
+"#,
+        )?,
+        false => out.write_all(
+            br#"
+use std::marker::PhantomData;
+"#,
+        )?,
+    }
+
+    // Enums are read from their integer code with `try_from`.
+    if uses_enums {
+        writeln!(out, "use std::convert::TryFrom;")?;
+    }
+
+    write!(
+        out,
+        r#"
+use sqlite::{{State::{{Row, Done}}, Statement}};
+
+{vis}type Result<T> = sqlite::Result<T>;
+
+{vis}struct {p}Connection<'a> {{
+    connection: &'a sqlite::Connection,
+"#,
+        vis = vis,
+        p = p,
+    )?;
+
+    // With `--read-by-name`, the iterator holds the column indices that the
+    // query looked up, so rows can be decoded without looking them up again.
+    let (decode_row_type, columns_field) = match options.read_by_name {
+        true => (
+            "fn(&Statement<'a>, &[usize]) -> Result<T>",
+            "\n    columns: Vec<usize>,",
+        ),
+        false => ("fn(&Statement<'a>) -> Result<T>", ""),
+    };
+
+    match options.cache_statements {
+        true => write!(
+            out,
+            r#"    statements: HashMap<*const u8, Statement<'a>>,
+}}
+
+{vis}struct {p}Transaction<'tx, 'a> {{
+    connection: &'a sqlite::Connection,
+    statements: &'tx mut HashMap<*const u8, Statement<'a>>,
+}}
+
+{vis}struct {p}Iter<'i, 'a, T> {{
+    statement: &'i mut Statement<'a>,
+    decode_row: {decode_row},{columns}
+}}
+
+impl<'a> {p}Connection<'a> {{
+    pub fn new(connection: &'a sqlite::Connection) -> Self {{
+        Self {{
+            connection,
+            statements: HashMap::with_capacity({n}),
+        }}
+    }}
+"#,
+            vis = vis,
+            p = p,
+            n = query_count,
+            decode_row = decode_row_type,
+            columns = columns_field,
+        )?,
+        false => write!(
+            out,
+            r#"}}
+
+{vis}struct {p}Transaction<'tx, 'a> {{
+    connection: &'a sqlite::Connection,
+    // The transaction borrows the connection mutably, even though it does not
+    // use it, to ensure that there is only one transaction at a time.
+    _connection: PhantomData<&'tx mut {p}Connection<'a>>,
+}}
+
+{vis}struct {p}Iter<'i, 'a, T> {{
+    statement: Statement<'a>,
+    decode_row: {decode_row},{columns}
+    // The iterator borrows the transaction, so the transaction cannot end
+    // while the iterator is still in use.
+    _tx: PhantomData<&'i mut ()>,
+}}
+
+impl<'a> {p}Connection<'a> {{
+    pub fn new(connection: &'a sqlite::Connection) -> Self {{
+        Self {{ connection }}
+    }}
+"#,
+            vis = vis,
+            p = p,
+            decode_row = decode_row_type,
+            columns = columns_field,
+        )?,
+    }
+
+    let begin_statement = match options.begin_mode {
+        Some(mode) => mode.begin_statement(),
+        None => "BEGIN",
+    };
+    let statements_field = match options.cache_statements {
+        true => "statements: &mut self.statements",
+        false => "_connection: PhantomData",
+    };
+    write!(
+        out,
+        r#"
+    /// Begin a new transaction by executing the `BEGIN` statement.
+    pub fn begin<'tx>(&'tx mut self) -> Result<{p}Transaction<'tx, 'a>> {{
+        self.connection.execute("{begin};")?;
+        let result = {p}Transaction {{
+            connection: self.connection,
+            {statements_field},
+        }};
+        Ok(result)
+    }}
+
+    /// Run `f` in a new transaction.
+    ///
+    /// Commits the transaction when `f` returns `Ok`, and rolls it back when
+    /// `f` returns `Err`.
+    pub fn transaction<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut {p}Transaction<'_, 'a>) -> Result<T>,
+    {{
+        let mut tx = self.begin()?;
+        match f(&mut tx) {{
+            Ok(result) => {{
+                tx.commit()?;
+                Ok(result)
+            }}
+            Err(err) => {{
+                // If the rollback fails as well, the original error is the
+                // more useful one to report, so we ignore the rollback error.
+                let _ = tx.rollback();
+                Err(err)
+            }}
+        }}
+    }}
+}}
+
+impl<'tx, 'a> {p}Transaction<'tx, 'a> {{
+    /// Execute `COMMIT` statement.
+    pub fn commit(self) -> Result<()> {{
+        self.connection.execute("COMMIT;")
+    }}
+
+    /// Execute `ROLLBACK` statement.
+    pub fn rollback(self) -> Result<()> {{
+        self.connection.execute("ROLLBACK;")
+    }}
+}}
+
+impl<'i, 'a, T> Iterator for {p}Iter<'i, 'a, T> {{
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {{
+        match self.statement.next() {{
+            Ok(Row) => Some((self.decode_row)({statement_ref}{columns_arg})),
+            Ok(Done) => None,
+            Err(err) => Some(Err(err)),
+        }}
+    }}
+}}
+"#,
+        p = p,
+        begin = begin_statement,
+        statements_field = statements_field,
+        statement_ref = match options.cache_statements {
+            true => "self.statement",
+            false => "&self.statement",
+        },
+        columns_arg = match options.read_by_name {
+            true => ", &self.columns",
+            false => "",
+        },
+    )?;
+
+    if options.cardinality_errors {
+        out.write_all(
+            br#"
+/// Build the error for a query that returned an unexpected number of rows.
+fn cardinality_error(message: &str) -> sqlite::Error {
+    sqlite::Error {
+        code: None,
+        message: Some(message.to_string()),
+    }
+}
+"#,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Return the code that handles a query returning an unexpected number of rows.
+///
+/// This panics by default, with `--cardinality-errors` it returns an error.
+fn cardinality_violation(options: &Options, message: &str) -> String {
+    match options.cardinality_errors {
+        true => format!("return Err(cardinality_error(\"{}\"))", message),
+        false => format!("panic!(\"{}\")", message),
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum Ownership {
+    Borrow,
+    BorrowNamed,
+    Owned,
+}
+
+fn write_primitive_type(
+    out: &mut dyn io::Write,
+    owned: Ownership,
+    type_: PrimitiveType,
+) -> io::Result<()> {
+    use Ownership::{Borrow, BorrowNamed, Owned};
+    let name = match (type_, owned) {
+        (PrimitiveType::Str, Borrow) => "&str",
+        (PrimitiveType::Str, BorrowNamed) => "&'a str",
+        (PrimitiveType::Str, Owned) => "String",
+        (PrimitiveType::Bytes, Borrow) => "&[u8]",
+        (PrimitiveType::Bytes, BorrowNamed) => "&'a [u8]",
+        (PrimitiveType::Bytes, Owned) => "Vec<u8>",
+        (PrimitiveType::Json, Borrow) => "&serde_json::Value",
+        (PrimitiveType::Json, BorrowNamed) => "&'a serde_json::Value",
+        (PrimitiveType::Json, Owned) => "serde_json::Value",
+        (PrimitiveType::I16, _) => "i16",
+        (PrimitiveType::I32, _) => "i32",
+        (PrimitiveType::I64, _) => "i64",
+        (PrimitiveType::U32, _) => "u32",
+        // TODO: Convert to f64 under the hood.
+        (PrimitiveType::F32, _) => "! /* TODO: f32 is not supported right now. */",
+        (PrimitiveType::F64, _) => "f64",
+    };
+    out.write_all(name.as_bytes())
+}
+
+fn write_simple_type(
+    out: &mut dyn io::Write,
+    owned: Ownership,
+    type_: &SimpleType<&str>,
+) -> io::Result<()> {
+    match type_ {
+        SimpleType::Primitive { type_: t, .. } => write_primitive_type(out, owned, *t)?,
+        SimpleType::Option { type_: t, .. } => {
+            write!(out, "Option<")?;
+            write_primitive_type(out, owned, *t)?;
+            write!(out, ">")?;
+        }
+        SimpleType::Enum { name, .. } => write!(out, "{}", name)?,
+    }
+    Ok(())
+}
+
+fn write_complex_type(
+    out: &mut dyn io::Write,
+    owned: Ownership,
+    type_: &ComplexType<&str>,
+) -> io::Result<()> {
+    match type_ {
+        ComplexType::Simple(t) => write_simple_type(out, owned, t),
+        ComplexType::Struct(name, ..) => write!(out, "{}", name),
+        ComplexType::TaggedEnum(name, ..) => write!(out, "{}", name),
+        ComplexType::Tuple(_full_span, fields) => {
+            write!(out, "(")?;
+            let mut is_first = true;
+            for field in fields {
+                if !is_first {
+                    write!(out, ", ")?;
+                }
+                write_simple_type(out, owned, &field.type_)?;
+                is_first = false;
+            }
+            write!(out, ")")
+        }
+    }
+}
+
+/// Whether the struct needs a lifetime parameter, when it borrows its fields.
+fn has_lifetime_types(fields: &[TypedIdent<&str>]) -> bool {
+    // TODO: This all feels a bit ad-hoc. I should probably parametrize the AST
+    // over the type type, then add a pass that translates the language-agnostic
+    // types into Rust types, and then have some helper methods on those for this
+    // kind of stuff.
+    fields.iter().any(|field| {
+        matches!(
+            field.type_.inner_type(),
+            PrimitiveType::Str | PrimitiveType::Bytes | PrimitiveType::Json
+        )
+    })
+}
+
+/// Generate Rust code for a struct type.
+fn write_struct_definition(
+    out: &mut dyn io::Write,
+    visibility: Visibility,
+    owned: Ownership,
+    name: &str,
+    fields: &[TypedIdent<&str>],
+) -> io::Result<()> {
+    // TODO: Would be nice to generate docs for cross-referencing.
+    writeln!(out, "\n#[derive(Debug)]")?;
+    write!(out, "{}struct {}", visibility.prefix(), name)?;
+
+    if has_lifetime_types(fields) && owned == Ownership::BorrowNamed {
+        write!(out, "<'a>")?;
+    }
+
+    writeln!(out, " {{")?;
+
+    for field in fields {
+        write!(out, "    {}{}: ", visibility.prefix(), field.ident)?;
+        write_simple_type(out, owned, &field.type_)?;
+        writeln!(out, ",")?;
+    }
+    writeln!(out, "}}")
+}
+
+/// Generate Rust code for an enum type, and its conversion from the integer code.
+fn write_enum_definition(
+    out: &mut dyn io::Write,
+    visibility: Visibility,
+    name: &str,
+    variants: &[EnumVariant<&str>],
+) -> io::Result<()> {
+    writeln!(out, "\n#[derive(Copy, Clone, Debug, Eq, PartialEq)]")?;
+    writeln!(out, "#[repr(i64)]")?;
+    writeln!(out, "{}enum {} {{", visibility.prefix(), name)?;
+    for variant in variants {
+        writeln!(out, "    {} = {},", variant.name, variant.code)?;
+    }
+    writeln!(out, "}}")?;
+
+    writeln!(out, "\nimpl TryFrom<i64> for {} {{", name)?;
+    writeln!(out, "    type Error = sqlite::Error;\n")?;
+    writeln!(out, "    fn try_from(code: i64) -> Result<{}> {{", name)?;
+    writeln!(out, "        match code {{")?;
+    for variant in variants {
+        writeln!(
+            out,
+            "            {} => Ok({}::{}),",
+            variant.code, name, variant.name
+        )?;
+    }
+    writeln!(out, "            _ => Err(sqlite::Error {{")?;
+    writeln!(out, "                code: None,")?;
+    writeln!(
+        out,
+        "                message: Some(format!(\"Invalid code for {}: {{}}.\", code)),",
+        name
+    )?;
+    writeln!(out, "            }}),")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")
+}
+
+/// Generate Rust code for a tagged enum, an enum where variants have fields.
+fn write_tagged_enum_definition(
+    out: &mut dyn io::Write,
+    visibility: Visibility,
+    name: &str,
+    variants: &[TaggedVariant<&str>],
+) -> io::Result<()> {
+    writeln!(out, "\n#[derive(Debug)]")?;
+    writeln!(out, "{}enum {} {{", visibility.prefix(), name)?;
+    for variant in variants {
+        if variant.fields.is_empty() {
+            writeln!(out, "    {},", variant.name)?;
+            continue;
+        }
+        writeln!(out, "    {} {{", variant.name)?;
+        for field in &variant.fields {
+            write!(out, "        {}: ", field.ident)?;
+            write_simple_type(out, Ownership::Owned, &field.type_)?;
+            writeln!(out, ",")?;
+        }
+        writeln!(out, "    }},")?;
+    }
+    writeln!(out, "}}")
+}
+
+/// Return the names of the columns that hold the fields of a tagged enum.
+///
+/// The first column holds the code of the variant, the fields follow it, in
+/// the order in which they first occur. Fields with the same name in different
+/// variants share a column.
+fn tagged_enum_columns<'a>(variants: &[TaggedVariant<&'a str>]) -> Vec<&'a str> {
+    let mut columns = Vec::new();
+    for field in variants.iter().flat_map(|v| v.fields.iter()) {
+        if !columns.contains(&field.ident) {
+            columns.push(field.ident);
+        }
+    }
+    columns
+}
+
+/// Define the enums that the query uses, that were not defined before.
+///
+/// Every enum is defined at its first use, so unlike structs, multiple queries
+/// can use the same enum.
+fn write_enum_definitions<'a>(
+    out: &mut dyn io::Write,
+    options: &Options,
+    defined: &mut HashSet<&'a str>,
+    annotation: &Annotation<&'a str>,
+) -> io::Result<()> {
+    let mut types: Vec<&SimpleType<&'a str>> = match &annotation.arguments {
+        ArgType::Args(args) => args.iter().map(|a| &a.type_).collect(),
+        ArgType::Struct { fields, .. } | ArgType::StructSlice { fields, .. } => {
+            fields.iter().map(|f| &f.type_).collect()
+        }
+    };
+    match annotation.result_type.get() {
+        None => {}
+        Some(ComplexType::Simple(t)) => types.push(t),
+        Some(ComplexType::Tuple(_, elems)) => types.extend(elems.iter().map(|e| &e.type_)),
+        Some(ComplexType::Struct(_, fields, _)) => types.extend(fields.iter().map(|f| &f.type_)),
+        Some(ComplexType::TaggedEnum(_, variants)) => types.extend(
+            variants
+                .iter()
+                .flat_map(|v| v.fields.iter().map(|f| &f.type_)),
+        ),
+    }
+    for type_ in types {
+        if let SimpleType::Enum { name, variants, .. } = type_ {
+            if defined.insert(name) {
+                write_enum_definition(out, options.visibility, name, variants)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Helper to convert integers, which the sqlite crate reads as `i64`, to `i16`.
+///
+/// This is only included in the output when a query uses `i16`.
+const NARROW_I16: &str = r#"
+/// Convert an `i64` read from SQLite to `i16`, or fail if it is out of range.
+fn narrow_i16(value: i64) -> Result<i16> {
+    use std::convert::TryFrom;
+    i16::try_from(value).map_err(|_| sqlite::Error {
+        code: None,
+        message: Some(format!("Value {} does not fit in an i16.", value)),
+    })
+}
+"#;
+
+const NARROW_U32: &str = r#"
+/// Convert an `i64` read from SQLite to `u32`, or fail if it is out of range.
+///
+/// The range of `u32` is 0 through 4294967295, so negative values are an error.
+fn narrow_u32(value: i64) -> Result<u32> {
+    use std::convert::TryFrom;
+    u32::try_from(value).map_err(|_| sqlite::Error {
+        code: None,
+        message: Some(format!("Value {} does not fit in a u32.", value)),
+    })
+}
+"#;
+
+const PARSE_JSON: &str = r#"
+/// Parse a JSON value that was stored as text, or fail if it is not valid JSON.
+fn parse_json(value: String) -> Result<serde_json::Value> {
+    serde_json::from_str(&value).map_err(|err| sqlite::Error {
+        code: None,
+        message: Some(format!("Value is not valid JSON: {}", err)),
+    })
+}
+"#;
+
+const COLUMN_INDEX: &str = r#"
+/// Return the index of the result column with the given name.
+fn column_index(statement: &Statement, name: &str) -> Result<usize> {
+    (0..statement.column_count())
+        .find(|&i| statement.column_name(i) == name)
+        .ok_or_else(|| sqlite::Error {
+            code: None,
+            message: Some(format!("The result has no column '{}'.", name)),
+        })
+}
+"#;
+
+/// Helper to read a column, for `--strict-null` and `--lenient-null`.
+const READ_COLUMN: &str = r#"
+/// Read a column, and include the column name in the error if that fails.
+fn read_column<T: sqlite::Readable>(statement: &Statement, index: usize) -> Result<T> {
+    statement.read(index).map_err(|err| sqlite::Error {
+        code: err.code,
+        message: Some(format!(
+            "Failed to read column '{}': {}",
+            statement.column_name(index),
+            err,
+        )),
+    })
+}
+"#;
+
+/// Helper to read a column that is not nullable, for `--lenient-null`.
+const READ_NOT_NULL: &str = r#"
+/// Read a column that is not nullable, or fail with the column name if it is null.
+fn read_not_null<T: sqlite::Readable>(statement: &Statement, index: usize) -> Result<T> {
+    match read_column::<Option<T>>(statement, index)? {
+        Some(value) => Ok(value),
+        None => Err(sqlite::Error {
+            code: None,
+            message: Some(format!(
+                "Column '{}' is null, but its type is not nullable.",
+                statement.column_name(index),
+            )),
+        }),
+    }
+}
+"#;
+
+/// Return whether any query in the documents returns a struct.
+fn documents_return_struct(documents: &[NamedDocument]) -> bool {
+    documents.iter().any(|doc| {
+        doc.document.iter_queries().any(|q| {
+            matches!(
+                q.annotation.result_type.get(),
+                Some(ComplexType::Struct(..))
+            )
+        })
+    })
+}
+
+/// Return whether any query in the documents has a result type, so it reads columns.
+fn documents_return_rows(documents: &[NamedDocument]) -> bool {
+    documents.iter().any(|doc| {
+        doc.document
+            .iter_queries()
+            .any(|q| q.annotation.result_type.get().is_some())
+    })
+}
+
+/// Return the expression that reads a column as the given Rust type.
+///
+/// When `rust_type` is empty, the type is inferred. With `--strict-null` the
+/// read goes through `read_column`, which names the column on error. With
+/// `--lenient-null`, reads of types that are not nullable go through
+/// `read_not_null`, which also fails with the column name when it is null.
+fn read_expr(
+    null_policy: Option<NullPolicy>,
+    statement: &str,
+    column: &str,
+    rust_type: &str,
+    is_nullable: bool,
+) -> String {
+    let turbofish = match rust_type {
+        "" => String::new(),
+        t => format!("::<{}>", t),
+    };
+    match null_policy {
+        None => format!("{}.read{}({})", statement, turbofish, column),
+        Some(NullPolicy::Lenient) if !is_nullable => {
+            format!("read_not_null{}({}, {})", turbofish, statement, column)
+        }
+        Some(_) => format!("read_column{}({}, {})", turbofish, statement, column),
+    }
+}
+
+/// Generate an expression that reads `column` as a `Result` of the type.
+///
+/// The column is an expression that evaluates to the column index. The sqlite
+/// crate reads all integers as `i64`, so for `i16` and `u32`, we read an `i64`
+/// and then narrow it. JSON values are stored as text, we parse them after
+/// reading a `String`.
+fn write_read(
+    out: &mut dyn io::Write,
+    null_policy: Option<NullPolicy>,
+    statement: &str,
+    column: &str,
+    type_: &SimpleType<&str>,
+) -> io::Result<()> {
+    let read =
+        |rust_type, is_nullable| read_expr(null_policy, statement, column, rust_type, is_nullable);
+    match type_ {
+        SimpleType::Primitive {
+            type_: PrimitiveType::I16,
+            ..
+        } => write!(out, "{}.and_then(narrow_i16)", read("i64", false)),
+        SimpleType::Option {
+            type_: PrimitiveType::I16,
+            ..
+        } => write!(
+            out,
+            "{}.and_then(|v| v.map(narrow_i16).transpose())",
+            read("Option<i64>", true)
+        ),
+        SimpleType::Primitive {
+            type_: PrimitiveType::U32,
+            ..
+        } => write!(out, "{}.and_then(narrow_u32)", read("i64", false)),
+        SimpleType::Option {
+            type_: PrimitiveType::U32,
+            ..
+        } => write!(
+            out,
+            "{}.and_then(|v| v.map(narrow_u32).transpose())",
+            read("Option<i64>", true)
+        ),
+        SimpleType::Primitive {
+            type_: PrimitiveType::Json,
+            ..
+        } => write!(out, "{}.and_then(parse_json)", read("String", false)),
+        SimpleType::Option {
+            type_: PrimitiveType::Json,
+            ..
+        } => write!(
+            out,
+            "{}.and_then(|v| v.map(parse_json).transpose())",
+            read("Option<String>", true)
+        ),
+        SimpleType::Enum { name, .. } => {
+            write!(out, "{}.and_then({}::try_from)", read("i64", false), name)
+        }
+        SimpleType::Option { .. } => write!(out, "{}", read("", true)),
+        SimpleType::Primitive { .. } => write!(out, "{}", read("", false)),
+    }
+}
+
+/// Generate a view into the current row of a statement, with one accessor per field.
+///
+/// This is used instead of an owned struct with `--borrow-rows`.
+fn write_row_view_definition(
+    out: &mut dyn io::Write,
+    options: &Options,
+    name: &str,
+    fields: &[TypedIdent<&str>],
+) -> io::Result<()> {
+    let visibility = options.visibility;
+    writeln!(out, "\n{}struct {}<'s, 'a> {{", visibility.prefix(), name)?;
+    writeln!(out, "    statement: &'s Statement<'a>,")?;
+    if options.read_by_name {
+        writeln!(out, "    columns: &'s [usize],")?;
+    }
+    writeln!(out, "}}")?;
+    writeln!(out, "\nimpl<'s, 'a> {}<'s, 'a> {{", name)?;
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        write!(
+            out,
+            "    {}fn {}(&self) -> Result<",
+            visibility.prefix(),
+            field.ident
+        )?;
+        write_simple_type(out, Ownership::Owned, &field.type_)?;
+        writeln!(out, "> {{")?;
+        write!(out, "        ")?;
+        let column = field_column("self.columns", options.read_by_name, i);
+        write_read(
+            out,
+            options.null_policy,
+            "self.statement",
+            &column,
+            &field.type_,
+        )?;
+        writeln!(out, "\n    }}")?;
+    }
+    writeln!(out, "}}")
+}
+
+/// Generate code for all structs that occur in the query's type.
+///
+/// With `--dedup-structs`, `defined` holds the names of the structs that we
+/// defined already, and we skip those.
+fn write_struct_definitions<'a>(
+    out: &mut dyn io::Write,
+    options: &Options,
+    defined: &mut HashSet<&'a str>,
+    annotation: Annotation<&'a str>,
+) -> io::Result<()> {
+    let visibility = options.visibility;
+    let mut is_new = |name| !options.dedup_structs || defined.insert(name);
+    match &annotation.arguments {
+        ArgType::Struct {
+            type_name, fields, ..
+        }
+        | ArgType::StructSlice {
+            type_name, fields, ..
+        } => {
+            if is_new(*type_name) {
+                write_struct_definition(
+                    out,
+                    visibility,
+                    Ownership::BorrowNamed,
+                    type_name,
+                    fields,
+                )?;
+            }
+        }
+        ArgType::Args(..) => {}
+    }
+
+    match &annotation.result_type {
+        ResultType::Iterator(ComplexType::Struct(name, fields, _)) if options.borrow_rows => {
+            if is_new(*name) {
+                write_row_view_definition(out, options, name, fields)?;
+            }
+        }
+        // Extern structs are defined by the user, we only construct them.
+        ResultType::Option(ComplexType::Struct(_, _, true))
+        | ResultType::Single(ComplexType::Struct(_, _, true))
+        | ResultType::Iterator(ComplexType::Struct(_, _, true)) => {}
+        ResultType::Option(ComplexType::Struct(name, fields, false))
+        | ResultType::Single(ComplexType::Struct(name, fields, false))
+        | ResultType::Iterator(ComplexType::Struct(name, fields, false)) => {
+            if is_new(*name) {
+                write_struct_definition(out, visibility, Ownership::Owned, name, fields)?;
+            }
+        }
+        ResultType::Option(ComplexType::TaggedEnum(name, variants))
+        | ResultType::Single(ComplexType::TaggedEnum(name, variants))
+        | ResultType::Iterator(ComplexType::TaggedEnum(name, variants)) => {
+            if is_new(*name) {
+                write_tagged_enum_definition(out, visibility, name, variants)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// If the result is a tuple with named elements, document what each position holds.
+fn write_tuple_docs(
+    out: &mut dyn io::Write,
+    has_docs: bool,
+    result_type: &ResultType<&str>,
+) -> io::Result<()> {
+    let fields = match result_type.get() {
+        Some(ComplexType::Tuple(_, fields)) => fields,
+        _ => return Ok(()),
+    };
+    if fields.iter().all(|field| field.name.is_none()) {
+        return Ok(());
+    }
+    if has_docs {
+        writeln!(out, "///")?;
+    }
+    writeln!(out, "/// Returns a tuple of:")?;
+    writeln!(out, "///")?;
+    for (i, field) in fields.iter().enumerate() {
+        match field.name {
+            Some(name) => writeln!(out, "///  * `.{}`: {}", i, name)?,
+            None => writeln!(out, "///  * `.{}`", i)?,
+        }
+    }
+    Ok(())
+}
+
+/// Document the arguments that have `@param` docs, if there are any.
+///
+/// Returns whether anything was written.
+fn write_param_docs(
+    out: &mut dyn io::Write,
+    has_docs: bool,
+    arguments: &ArgType<&str>,
+) -> io::Result<bool> {
+    let args = match arguments {
+        ArgType::Args(args) if args.iter().any(|arg| arg.doc.is_some()) => args,
+        _ => return Ok(false),
+    };
+    if has_docs {
+        writeln!(out, "///")?;
+    }
+    writeln!(out, "/// Arguments:")?;
+    writeln!(out, "///")?;
+    for arg in args {
+        match arg.doc {
+            Some(doc) => writeln!(out, "///  * `{}`: {}", arg.ident, doc)?,
+            None => writeln!(out, "///  * `{}`", arg.ident)?,
+        }
+    }
+    Ok(true)
+}
+
+/// Return the expression for the column index of a struct field.
+///
+/// By default fields are read by position, with `--read-by-name` the query
+/// looks up the column with the same name as the field once, in `columns`.
+fn field_column(columns: &str, read_by_name: bool, index: usize) -> String {
+    match read_by_name {
+        true => format!("{}[{}]", columns, index),
+        false => index.to_string(),
+    }
+}
+
+/// Generate code that calls `.read` on the statement, and constructs a return value.
+fn write_return_value(
+    out: &mut dyn io::Write,
+    options: &Options,
+    index: usize,
+    type_: ComplexType<&str>,
+) -> io::Result<()> {
+    let null_policy = options.null_policy;
+    match type_ {
+        ComplexType::Simple(t) => {
+            write_read(out, null_policy, "statement", &index.to_string(), &t)?;
+            write!(out, "?")?;
+        }
+        ComplexType::Tuple(_, fields) => {
+            writeln!(out, "(")?;
+            for (i, field) in (index..).zip(fields) {
+                write!(out, "        ")?;
+                write_read(out, null_policy, "statement", &i.to_string(), &field.type_)?;
+                writeln!(out, "?,")?;
+            }
+            write!(out, ")")?;
+        }
+        ComplexType::Struct(name, fields, _) => {
+            writeln!(out, "{} {{", name)?;
+            // TODO: Once we unify types across multiple queries, the index of
+            // the fields may not be the order in which they occur.
+            for (i, field) in (index..).zip(fields) {
+                write!(out, "        {}: ", field.ident)?;
+                let column = field_column("columns", options.read_by_name, i - index);
+                write_read(out, null_policy, "statement", &column, &field.type_)?;
+                writeln!(out, "?,")?;
+            }
+            write!(out, "    }}")?;
+        }
+        ComplexType::TaggedEnum(name, variants) => {
+            // The fields are always read by index, they are not annotated in
+            // the query body, so we cannot look up their column by name.
+            let columns = tagged_enum_columns(&variants);
+            writeln!(out, "match statement.read::<i64>({})? {{", index)?;
+            for variant in &variants {
+                if variant.fields.is_empty() {
+                    writeln!(
+                        out,
+                        "        {} => {}::{},",
+                        variant.code, name, variant.name
+                    )?;
+                    continue;
+                }
+                writeln!(
+                    out,
+                    "        {} => {}::{} {{",
+                    variant.code, name, variant.name
+                )?;
+                for field in &variant.fields {
+                    let i = index + 1 + columns.iter().position(|c| *c == field.ident).unwrap();
+                    write!(out, "            {}: ", field.ident)?;
+                    write_read(out, null_policy, "statement", &i.to_string(), &field.type_)?;
+                    writeln!(out, "?,")?;
+                }
+                writeln!(out, "        }},")?;
+            }
+            writeln!(out, "        code => {{")?;
+            writeln!(out, "            return Err(sqlite::Error {{")?;
+            writeln!(out, "                code: None,")?;
+            writeln!(
+                out,
+                "                message: Some(format!(\"Invalid code for {}: {{}}.\", code)),",
+                name
+            )?;
+            writeln!(out, "            }})")?;
+            writeln!(out, "        }}")?;
+            write!(out, "    }}")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a query is generated as a free function, or as a method on `Transaction`.
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum Receiver {
+    Free,
+    Method,
+    /// A private function named `run` inside the free function, for `--tracing`.
+    Nested,
+}
+
+/// Return the name of the method for a query, the path and name joined by `_`.
+fn method_name(ann: &Annotation<&str>) -> String {
+    let mut name = String::new();
+    for segment in &ann.path {
+        name.push_str(segment);
+        name.push('_');
+    }
+    name.push_str(ann.name);
+    name
+}
+
+/// Generate the signature of a query function, including the opening brace.
+///
+/// When the signature does not fit in `--max-line-width` columns, put every
+/// parameter on a line of its own, like rustfmt does.
+fn write_function_signature(
+    out: &mut dyn io::Write,
+    options: &Options,
+    receiver: Receiver,
+    borrow_rows: bool,
+    ann: &Annotation<&str>,
+) -> io::Result<()> {
+    // Methods go inside an impl block, so they need to be indented. The nested
+    // function, and functions in modules, get indented afterwards, one level
+    // per module, but that counts towards the width too.
+    let module_indent = 4 * ann.path.len();
+    let (indent, width_indent) = match receiver {
+        Receiver::Free => ("", module_indent),
+        Receiver::Nested => ("", module_indent + 4),
+        Receiver::Method => ("    ", 4),
+    };
+    let visibility = match receiver {
+        Receiver::Nested => String::new(),
+        Receiver::Free => item_visibility(options.visibility, ann.path.len()),
+        Receiver::Method => options.visibility.prefix().to_string(),
+    };
+    // Methods of all modules share one impl block, so their name includes the
+    // module path, to keep `users::count` and `teams::count` apart.
+    let name = match receiver {
+        Receiver::Nested => "run".to_string(),
+        Receiver::Free => ann.name.to_string(),
+        Receiver::Method => method_name(ann),
+    };
+
+    // We buffer the parts of the signature, so we can measure it before we
+    // decide how to lay it out.
+    let mut head = Vec::new();
+    let mut params = Vec::new();
+    write!(head, "{}{}fn {}", indent, visibility, name)?;
+    match (&ann.result_type, receiver) {
+        (ResultType::Iterator(..), Receiver::Free | Receiver::Nested) if borrow_rows => {
+            write!(head, "<F>(")?;
+            params.push(format!("tx: &mut {}Transaction", options.type_prefix));
+        }
+        (ResultType::Iterator(..), Receiver::Free | Receiver::Nested) => {
+            write!(head, "<'i, 't, 'a>(")?;
+            params.push(format!(
+                "tx: &'i mut {}Transaction<'t, 'a>",
+                options.type_prefix
+            ));
+        }
+        (_, Receiver::Free | Receiver::Nested) => {
+            write!(head, "(")?;
+            params.push(format!("tx: &mut {}Transaction", options.type_prefix));
+        }
+        (ResultType::Iterator(..), Receiver::Method) if borrow_rows => {
+            write!(head, "<F>(")?;
+            params.push("&mut self".to_string());
+        }
+        (ResultType::Iterator(..), Receiver::Method) => {
+            write!(head, "<'i>(")?;
+            params.push("&'i mut self".to_string());
+        }
+        (_, Receiver::Method) => {
+            write!(head, "(")?;
+            params.push("&mut self".to_string());
+        }
+    }
+
+    match &ann.arguments {
+        ArgType::Args(args) => {
+            for arg in args {
+                let mut param = Vec::new();
+                write!(param, "{}: ", arg.ident)?;
+                write_simple_type(&mut param, Ownership::Borrow, &arg.type_)?;
+                params.push(String::from_utf8(param).expect("Generated code is UTF-8."));
+            }
+        }
+        ArgType::Struct {
+            var_name,
+            type_name,
+            fields,
+        } => match has_lifetime_types(fields) {
+            true => params.push(format!("{}: {}<'_>", var_name, type_name)),
+            false => params.push(format!("{}: {}", var_name, type_name)),
+        },
+        ArgType::StructSlice {
+            var_name,
+            type_name,
+            fields,
+        } => match has_lifetime_types(fields) {
+            true => params.push(format!("{}: &[{}<'_>]", var_name, type_name)),
+            false => params.push(format!("{}: &[{}]", var_name, type_name)),
+        },
+    }
+
+    // The method only passes the callback on, so it does not need to be
+    // mutable. With `--tracing`, the same holds for the free function.
+    match receiver {
+        Receiver::Free if borrow_rows && options.tracing => params.push("f: F".to_string()),
+        Receiver::Free | Receiver::Nested if borrow_rows => params.push("mut f: F".to_string()),
+        Receiver::Method if borrow_rows => params.push("f: F".to_string()),
+        _ => {}
+    }
+
+    let mut tail = Vec::new();
+    write!(tail, ") -> Result<")?;
+    match &ann.result_type {
+        ResultType::Unit => write!(tail, "()> {{")?,
+        ResultType::Iterator(t) if borrow_rows => {
+            write!(tail, "()>\n{}where\n{}    F: FnMut(", indent, indent)?;
+            write_complex_type(&mut tail, Ownership::Owned, t)?;
+            write!(tail, ") -> Result<()>,\n{}{{", indent)?;
+        }
+        ResultType::Option(t) => {
+            write!(tail, "Option<")?;
+            write_complex_type(&mut tail, Ownership::Owned, t)?;
+            write!(tail, ">> {{")?;
+        }
+        ResultType::Single(t) => {
+            write_complex_type(&mut tail, Ownership::Owned, t)?;
+            write!(tail, "> {{")?;
+        }
+        ResultType::Iterator(t) => {
+            write!(tail, "{}Iter<'i, 'a, ", options.type_prefix)?;
+            write_complex_type(&mut tail, Ownership::Owned, t)?;
+            write!(tail, ">> {{")?;
+        }
+    }
+
+    let head = String::from_utf8(head).expect("Generated code is UTF-8.");
+    let tail = String::from_utf8(tail).expect("Generated code is UTF-8.");
+    let params_line = params.join(", ");
+    let tail_len = tail.lines().next().unwrap_or("").len();
+    let line_len = width_indent + head.len() + params_line.len() + tail_len;
+
+    if line_len <= options.max_line_width {
+        writeln!(out, "{}{}{}", head, params_line, tail)
+    } else {
+        writeln!(out, "{}", head)?;
+        for param in &params {
+            writeln!(out, "{}    {},", indent, param)?;
+        }
+        writeln!(out, "{}{}", indent, tail)
+    }
+}
+
+/// Generate a method on `Transaction` that calls the free function for the query.
+fn write_method(
+    out: &mut dyn io::Write,
+    options: &Options,
+    borrow_rows: bool,
+    docs: &[&str],
+    ann: &Annotation<&str>,
+) -> io::Result<()> {
+    for doc_line in docs {
+        writeln!(out, "    ///{}", doc_line)?;
+    }
+    write_function_signature(out, options, Receiver::Method, borrow_rows, ann)?;
+    write!(out, "        ")?;
+    for segment in &ann.path {
+        write!(out, "{}::", segment)?;
+    }
+    write!(out, "{}(self", ann.name)?;
+    write_call_arguments(out, borrow_rows, ann)?;
+    writeln!(out, ")")?;
+    writeln!(out, "    }}")
+}
+
+/// Pass on the arguments of the query to a call, after the transaction.
+fn write_call_arguments(
+    out: &mut dyn io::Write,
+    borrow_rows: bool,
+    ann: &Annotation<&str>,
+) -> io::Result<()> {
+    match &ann.arguments {
+        ArgType::Args(args) => {
+            for arg in args {
+                write!(out, ", {}", arg.ident)?;
+            }
+        }
+        ArgType::Struct { var_name, .. } | ArgType::StructSlice { var_name, .. } => {
+            write!(out, ", {}", var_name)?
+        }
+    }
+    if borrow_rows {
+        write!(out, ", f")?;
+    }
+    Ok(())
+}
+
+/// Generate an impl block with every query as a method on `Transaction`.
+fn write_methods(
+    out: &mut dyn io::Write,
+    options: &Options,
+    documents: &[NamedDocument],
+) -> io::Result<()> {
+    writeln!(
+        out,
+        "\nimpl<'tx, 'a> {}Transaction<'tx, 'a> {{",
+        options.type_prefix
+    )?;
+    let mut is_first = true;
+    for named_document in documents {
+        let input = named_document.input;
+        for query in named_document.document.iter_queries() {
+            if !is_first {
+                writeln!(out)?;
+            }
+            is_first = false;
+            let query = query.resolve(input);
+            let borrow_rows = options.borrow_rows
+                && matches!(query.annotation.result_type, ResultType::Iterator(..));
+            write_method(out, options, borrow_rows, &query.docs, &query.annotation)?;
+        }
+    }
+    writeln!(out, "}}")
+}
+
+/// Generate the function for one query, from its doc comment to the closing brace.
+fn write_query_function(
+    out: &mut dyn io::Write,
+    options: &Options,
+    input: &str,
+    query: &Query<Span>,
+) -> io::Result<()> {
+    let ann = &query.annotation;
+
+    // With `--borrow-rows`, iterator queries call a callback for every
+    // row instead of returning an iterator.
+    let borrow_rows = options.borrow_rows && matches!(ann.result_type, ResultType::Iterator(..));
+
+    writeln!(out)?;
+
+    for doc_line in &query.docs {
+        writeln!(out, "///{}", doc_line.resolve(input))?;
+    }
+    let has_param_docs =
+        write_param_docs(out, !query.docs.is_empty(), &ann.arguments.resolve(input))?;
+    let has_docs = !query.docs.is_empty() || has_param_docs;
+    write_tuple_docs(out, has_docs, &ann.result_type.resolve(input))?;
+
+    write_function_signature(
+        out,
+        options,
+        Receiver::Free,
+        borrow_rows,
+        &ann.resolve(input),
+    )?;
+
+    if !options.tracing {
+        return write_query_body(out, options, input, query, borrow_rows);
+    }
+
+    // With `--tracing`, the body goes in a nested function, so we can log its
+    // result in one place, regardless of where it returns.
+    let mut nested = Vec::new();
+    write_function_signature(
+        &mut nested,
+        options,
+        Receiver::Nested,
+        borrow_rows,
+        &ann.resolve(input),
+    )?;
+    write_query_body(&mut nested, options, input, query, borrow_rows)?;
+    write_indented(out, &nested)?;
+
+    let name = ann.name.resolve(input);
+    writeln!(out)?;
+    writeln!(out, "    let span = tracing::debug_span!(\"{}\");", name)?;
+    writeln!(out, "    let _guard = span.enter();")?;
+    writeln!(out, "    let start = std::time::Instant::now();")?;
+    write!(out, "    let result = run(tx")?;
+    write_call_arguments(out, borrow_rows, &ann.resolve(input))?;
+    writeln!(out, ");")?;
+    writeln!(out, "    match &result {{")?;
+    writeln!(
+        out,
+        "        Ok(..) => tracing::debug!(elapsed = ?start.elapsed(), \"Query '{}' completed.\"),",
+        name
+    )?;
+    writeln!(
+        out,
+        "        Err(err) => tracing::debug!(elapsed = ?start.elapsed(), error = %err, \"Query '{}' failed.\"),",
+        name
+    )?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "    result")?;
+    writeln!(out, "}}")
+}
+
+/// Return the raw string literal with the SQL of the statement, as we send it.
+fn sql_literal(options: &Options, input: &str, statement: &Statement<Span>) -> String {
+    let mut sql = String::new();
+    // TODO: Include the source file name and line number as a comment.
+    for fragment in &statement.fragments {
+        let span = match fragment {
+            Fragment::Verbatim(span) => span,
+            Fragment::Comment(span) => span,
+            Fragment::Param(span) => span,
+            // When we put the SQL in the source code, omit the type
+            // annotations, it's only a distraction. An alias in the
+            // annotation does matter, it becomes the column name.
+            Fragment::TypedIdent(_full_span, ti) => {
+                sql.push_str(ti.ident.resolve(input));
+                if let Some(alias) = ti.alias {
+                    sql.push_str(" as ");
+                    sql.push_str(alias.resolve(input));
+                }
+                continue;
+            }
+            Fragment::TypedParam(_full_span, ti) => &ti.ident,
+        };
+        sql.push_str(span.resolve(input));
+    }
+    if options.strip_comments {
+        sql = strip_comments(&sql);
+    }
+    match options.minify_sql {
+        true => format!("r#\"{}\"#", minify_sql(&sql)),
+        false => format!(
+            "r#\"\n        {}\n        \"#",
+            sql.replace('\n', "\n        ")
+        ),
+    }
+}
+
+/// Generate a function that returns the SQL of the query, for `--expose-sql`.
+///
+/// A query with multiple statements executes them one by one, so for those
+/// the function returns a slice with one element per statement.
+fn write_sql_accessor(
+    out: &mut dyn io::Write,
+    options: &Options,
+    input: &str,
+    query: &Query<Span>,
+) -> io::Result<()> {
+    let ann = &query.annotation;
+    let visibility = item_visibility(options.visibility, ann.path.len());
+    let name = ann.name.resolve(input);
+    writeln!(out, "\n/// Return the SQL that `{}` executes.", name)?;
+    match &query.statements[..] {
+        [statement] => {
+            writeln!(out, "{}fn {}_sql() -> &'static str {{", visibility, name)?;
+            writeln!(out, "    {}", sql_literal(options, input, statement))?;
+        }
+        statements => {
+            writeln!(
+                out,
+                "{}fn {}_sql() -> &'static [&'static str] {{",
+                visibility, name
+            )?;
+            writeln!(out, "    &[")?;
+            for statement in statements {
+                writeln!(out, "        {},", sql_literal(options, input, statement))?;
+            }
+            writeln!(out, "    ]")?;
+        }
+    }
+    writeln!(out, "}}")
+}
+
+/// Generate the body of a query function, after the signature.
+fn write_query_body(
+    out: &mut dyn io::Write,
+    options: &Options,
+    input: &str,
+    query: &Query<Span>,
+    borrow_rows: bool,
+) -> io::Result<()> {
+    let ann = &query.annotation;
+
+    // With the statement cache, `statement` is a reference into the cache,
+    // without it, `statement` is owned by the function.
+    let statement_ref = match options.cache_statements {
+        true => "statement",
+        false => "&statement",
+    };
+
+    // With a slice argument, we execute every statement once per element.
+    let is_slice = matches!(ann.arguments, ArgType::StructSlice { .. });
+    let unexpected_row = format!(
+        "Query '{}' unexpectedly returned a row.",
+        ann.name.resolve(input)
+    );
+
+    for (i, statement) in query.statements.iter().enumerate() {
+        let is_last = i + 1 == query.statements.len();
+        let parameters = statement_parameters(statement, input);
+
+        writeln!(
+            out,
+            "    let sql = {};",
+            sql_literal(options, input, statement)
+        )?;
+
+        // The literal starts with a newline that we don't want here.
+        // TODO: For now we use the address of the literal as the cache key.
+        // But we should instead use a precomputed hash of the query, so that
+        // LLVM can constant-fold the hash function.
+        if options.cache_statements {
+            out.write_all(&GET_STATEMENT.as_bytes()[1..])?;
+            if !is_slice {
+                writeln!(out, "    statement.reset()?;")?;
+            }
+        } else {
+            // Without the statement cache, we prepare the statement every
+            // time, and it is finalized when it goes out of scope. Binding
+            // and stepping need it to be mutable, but when there is nothing
+            // to bind, an iterator takes ownership before it is stepped.
+            let moves_into_iter = is_last
+                && !is_slice
+                && !borrow_rows
+                && matches!(ann.result_type, ResultType::Iterator(..));
+            let mutability = match moves_into_iter && parameters.is_empty() {
+                true => "",
+                false => "mut ",
+            };
+            writeln!(
+                out,
+                "    let {}statement = tx.connection.prepare(sql)?;",
+                mutability
+            )?;
+        }
+
+        // For a slice, we loop over the elements, and reset the
+        // statement before executing it again.
+        let indent = match query.annotation.arguments {
+            ArgType::StructSlice { var_name, .. } => {
+                writeln!(out, "    for item in {} {{", var_name.resolve(input))?;
+                writeln!(out, "        statement.reset()?;")?;
+                "        "
+            }
+            _ => "    ",
+        };
+
+        // Next we bind all query parameters.
+        let prefix = &match query.annotation.arguments {
+            ArgType::Struct { var_name, .. } => {
+                let mut prefix = var_name.resolve(input).to_string();
+                prefix.push('.');
+                prefix
+            }
+            ArgType::StructSlice { .. } => "item.".to_string(),
+            ArgType::Args(..) => String::new(),
+        };
+        let param_types: HashMap<&str, SimpleType<&str>> =
+            match query.annotation.arguments.resolve(input) {
+                ArgType::Args(args) => args,
+                ArgType::Struct { fields, .. } => fields,
+                ArgType::StructSlice { fields, .. } => fields,
+            }
+            .into_iter()
+            .map(|ti| (ti.ident.trim_start_matches(':'), ti.type_))
+            .collect();
+        for (j, variable_name) in parameters.iter().enumerate() {
+            // The sqlite crate binds integers as `i64`, so we widen
+            // narrower types. Every `u32` fits in an `i64`, so the
+            // conversion cannot fail, and the value is never negative.
+            let value = format!("{}{}", prefix, variable_name);
+            let value = match param_types.get(variable_name) {
+                Some(SimpleType::Primitive {
+                    type_: PrimitiveType::I16,
+                    ..
+                })
+                | Some(SimpleType::Primitive {
+                    type_: PrimitiveType::U32,
+                    ..
+                }) => format!("i64::from({})", value),
+                Some(SimpleType::Option {
+                    type_: PrimitiveType::I16,
+                    ..
+                })
+                | Some(SimpleType::Option {
+                    type_: PrimitiveType::U32,
+                    ..
+                }) => format!("{}.map(i64::from)", value),
+                // JSON values are stored as text.
+                Some(SimpleType::Primitive {
+                    type_: PrimitiveType::Json,
+                    ..
+                }) => format!("{}.to_string().as_str()", value),
+                Some(SimpleType::Option {
+                    type_: PrimitiveType::Json,
+                    ..
+                }) => format!("{}.map(|v| v.to_string()).as_deref()", value),
+                // Enums are stored as their integer code.
+                Some(SimpleType::Enum { .. }) => format!("{} as i64", value),
+                _ => value,
+            };
+            // SQLite parameters are 1-based.
+            writeln!(out, "{}statement.bind({}, {})?;", indent, j + 1, value)?;
+        }
+
+        // For all but the last statement, we execute it, and expect it
+        // to return zero rows. For a slice, we do that for every element,
+        // also for the last statement, as there is no result to return.
+        if is_slice {
+            writeln!(out, "        match statement.next()? {{")?;
+            writeln!(
+                out,
+                "            Row => {},",
+                cardinality_violation(options, &unexpected_row)
+            )?;
+            writeln!(out, "            Done => {{}}")?;
+            writeln!(out, "        }}")?;
+            writeln!(out, "    }}")?;
+            if !is_last {
+                writeln!(out)?;
+            }
+        } else if !is_last {
+            writeln!(out, "    match statement.next()? {{")?;
+            writeln!(
+                out,
+                "        Row => {},",
+                cardinality_violation(options, &unexpected_row)
+            )?;
+            writeln!(out, "        Done => {{}}")?;
+            writeln!(out, "    }}\n")?;
+        }
+    }
+
+    // With `--read-by-name`, we look up the columns of struct fields once,
+    // before stepping the statement, rather than for every row. The iterator
+    // decodes rows with a function pointer, so it gets the indices passed in.
+    let is_iter = matches!(query.annotation.result_type, ResultType::Iterator(..)) && !borrow_rows;
+    let reads_by_name = match query.annotation.result_type.get() {
+        Some(ComplexType::Struct(_, fields, _)) if options.read_by_name => {
+            writeln!(
+                out,
+                "    let columns = {}",
+                if is_iter { "vec![" } else { "[" }
+            )?;
+            for field in fields {
+                writeln!(
+                    out,
+                    "        column_index({}, \"{}\")?,",
+                    statement_ref,
+                    field.ident.resolve(input)
+                )?;
+            }
+            writeln!(out, "    ];")?;
+            true
+        }
+        _ => false,
+    };
+    let decode_params = match (options.read_by_name && is_iter, reads_by_name) {
+        (true, true) => "statement: &Statement, columns: &[usize]",
+        (true, false) => "statement: &Statement, _columns: &[usize]",
+        (false, _) => "statement: &Statement",
+    };
+
+    match query.annotation.result_type.get() {
+        // A view into the row does not need to be decoded.
+        Some(ComplexType::Struct(..)) if borrow_rows => {}
+        Some(type_) => {
+            write!(out, "    let decode_row = |{}| Ok(", decode_params)?;
+            write_return_value(out, options, 0, type_.resolve(input))?;
+            writeln!(out, ");")?;
+        }
+        None => {}
+    }
+
+    match &query.annotation.result_type {
+        // The loop over the slice executed the statements already.
+        ResultType::Unit if is_slice => {
+            writeln!(out, "    Ok(())")?;
+            writeln!(out, "}}")?;
+            return Ok(());
+        }
+        ResultType::Unit => {
+            writeln!(out, "    let result = match statement.next()? {{")?;
+            writeln!(
+                out,
+                "        Row => {},",
+                cardinality_violation(options, &unexpected_row)
+            )?;
+            writeln!(out, "        Done => (),")?;
+            writeln!(out, "    }};")?;
+        }
+        ResultType::Option(..) => {
+            writeln!(out, "    let result = match statement.next()? {{")?;
+            writeln!(out, "        Row => Some(decode_row({})?),", statement_ref)?;
+            writeln!(out, "        Done => None,")?;
+            writeln!(out, "    }};")?;
+            // Call next() until Done, even though we know we should be
+            // done at this point. Without it, we cannot commit, SQLite
+            // complains: "SQL statements in progress".
+            // Should we join the two conditions with &&? It saves two
+            // lines of code and rightward drift, but having a
+            // side-effect not be executed due to short circuiting && is
+            // quite subtle, I would not call that readable code.
+            writeln!(out, "    if result.is_some() {{")?;
+            writeln!(out, "        if statement.next()? != Done {{")?;
+            let message = format!(
+                "Query '{}' should return at most one row.",
+                query.annotation.name.resolve(input)
+            );
+            writeln!(
+                out,
+                "            {};",
+                cardinality_violation(options, &message)
+            )?;
+            writeln!(out, "        }}")?;
+            writeln!(out, "    }}")?;
+        }
+        ResultType::Single(..) => {
+            let message = format!(
+                "Query '{}' should return exactly one row.",
+                query.annotation.name.resolve(input)
+            );
+            writeln!(out, "    let result = match statement.next()? {{")?;
+            writeln!(out, "        Row => decode_row({})?,", statement_ref)?;
+            writeln!(
+                out,
+                "        Done => {},",
+                cardinality_violation(options, &message)
+            )?;
+            writeln!(out, "    }};")?;
+            // Call next() until Done, see also the note further above.
+            writeln!(out, "    if statement.next()? != Done {{")?;
+            writeln!(out, "        {};", cardinality_violation(options, &message))?;
+            writeln!(out, "    }}")?;
+        }
+        ResultType::Iterator(t) if borrow_rows => {
+            writeln!(out, "    while statement.next()? == Row {{")?;
+            match t {
+                ComplexType::Struct(name, ..) => {
+                    let columns = match options.read_by_name {
+                        true => ", columns: &columns",
+                        false => "",
+                    };
+                    match options.cache_statements {
+                        true => writeln!(
+                            out,
+                            "        f({} {{ statement{} }})?;",
+                            name.resolve(input),
+                            columns
+                        )?,
+                        false => writeln!(
+                            out,
+                            "        f({} {{ statement: &statement{} }})?;",
+                            name.resolve(input),
+                            columns
+                        )?,
+                    }
+                }
+                _ => writeln!(out, "        f(decode_row({})?)?;", statement_ref)?,
+            }
+            writeln!(out, "    }}")?;
+            writeln!(out, "    Ok(())")?;
+            writeln!(out, "}}")?;
+            return Ok(());
+        }
+        ResultType::Iterator(..) => {
+            let columns = match (options.read_by_name, reads_by_name) {
+                (true, true) => ", columns",
+                (true, false) => ", columns: Vec::new()",
+                (false, _) => "",
+            };
+            match options.cache_statements {
+                true => writeln!(
+                    out,
+                    "    let result = {}Iter {{ statement, decode_row{} }};",
+                    options.type_prefix, columns
+                )?,
+                false => writeln!(
+                    out,
+                    "    let result = {}Iter {{ statement, decode_row{}, _tx: PhantomData }};",
+                    options.type_prefix, columns
+                )?,
+            }
+        }
+    }
+
+    writeln!(out, "    Ok(result)")?;
+    writeln!(out, "}}")?;
+
+    Ok(())
+}
+
+/// Generated functions for queries with a module path, grouped by module.
+#[derive(Default)]
+struct Module<'a> {
+    name: &'a str,
+    code: Vec<u8>,
+    children: Vec<Module<'a>>,
+}
+
+impl<'a> Module<'a> {
+    /// Return the submodule with the given name, add it if it does not exist.
+    fn child(&mut self, name: &'a str) -> &mut Module<'a> {
+        match self.children.iter().position(|m| m.name == name) {
+            Some(i) => &mut self.children[i],
+            None => {
+                self.children.push(Module {
+                    name,
+                    ..Module::default()
+                });
+                self.children.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+/// Return the visibility prefix for a function in a module `depth` levels deep.
+///
+/// The function must be usable wherever the outermost module is, and it must
+/// not be more visible than the types in its signature, or the generated code
+/// does not compile. For private items, that is the module that contains the
+/// generated code, so we name it with `super`.
+fn item_visibility(visibility: Visibility, depth: usize) -> String {
+    match (visibility, depth) {
+        (Visibility::Private, 0) => String::new(),
+        (Visibility::Private, 1) => "pub(super) ".to_string(),
+        (Visibility::Private, n) => format!("pub(in {}) ", vec!["super"; n].join("::")),
+        (visibility, _) => visibility.prefix().to_string(),
+    }
+}
+
+/// Write a module with its functions and submodules, indented one level.
+///
+/// Only the outermost module gets the configured visibility, the submodules
+/// inside it are `pub`. The functions restrict their own visibility, see
+/// [`item_visibility`].
+fn write_module(
+    out: &mut dyn io::Write,
+    visibility: Visibility,
+    module: &Module,
+) -> io::Result<()> {
+    let mut inner = b"use super::*;\n".to_vec();
+    inner.extend_from_slice(&module.code);
+    for child in &module.children {
+        write_module(&mut inner, Visibility::Public, child)?;
+    }
+
+    writeln!(out, "\n{}mod {} {{", visibility.prefix(), module.name)?;
+    write_indented(out, &inner)?;
+    writeln!(out, "}}")
+}
+
+/// Write generated code, indented one level.
+fn write_indented(out: &mut dyn io::Write, code: &[u8]) -> io::Result<()> {
+    let code = std::str::from_utf8(code).expect("Generated code is valid UTF-8.");
+    for line in code.lines() {
+        match line.is_empty() {
+            true => writeln!(out)?,
+            false => writeln!(out, "    {}", line)?,
+        }
+    }
+    Ok(())
+}
+
+/// Generate Rust code that uses the `sqlite` crate.
+pub fn process_documents(
+    out: &mut dyn io::Write,
+    options: &Options,
+    documents: &[NamedDocument],
+) -> io::Result<()> {
+    use crate::version::{REV, VERSION};
+    if options.version_header {
+        write!(out, "// This file was generated by Squiller {}", VERSION,)?;
+        match REV {
+            Some(rev) => writeln!(out, " (commit {}).", &rev[..10])?,
+            None => writeln!(out, " (unspecified checkout).")?,
+        }
+        writeln!(out, "// Input files:")?;
+        // Files without queries contribute nothing to the output, omit them.
+        for doc in documents.iter().filter(|doc| doc.document.has_queries()) {
+            writeln!(out, "// - {}", doc.fname.to_string_lossy())?;
+        }
+    }
+
+    let query_count = documents.iter().map(|d| d.document.query_count()).sum();
+    let uses_enums = documents_use(documents, |t| matches!(t, SimpleType::Enum { .. }));
+    write_preamble(out, options, query_count, uses_enums)?;
+    if documents_use(documents, |t| t.inner_type() == PrimitiveType::I16) {
+        out.write_all(NARROW_I16.as_bytes())?;
+    }
+    if documents_use(documents, |t| t.inner_type() == PrimitiveType::U32) {
+        out.write_all(NARROW_U32.as_bytes())?;
+    }
+    if documents_use(documents, |t| t.inner_type() == PrimitiveType::Json) {
+        out.write_all(PARSE_JSON.as_bytes())?;
+    }
+    if options.read_by_name && documents_return_struct(documents) {
+        out.write_all(COLUMN_INDEX.as_bytes())?;
+    }
+    if options.null_policy.is_some() && documents_return_rows(documents) {
+        out.write_all(READ_COLUMN.as_bytes())?;
+    }
+    if options.null_policy == Some(NullPolicy::Lenient) && documents_return_rows(documents) {
+        out.write_all(READ_NOT_NULL.as_bytes())?;
+    }
+
+    let mut defined_structs = HashSet::new();
+    let mut defined_enums = HashSet::new();
+    let mut modules = Module::default();
+
+    for named_document in documents {
+        let input = named_document.input;
+
+        for query in named_document.document.iter_queries() {
+            let ann = &query.annotation;
+
+            write_enum_definitions(out, options, &mut defined_enums, &ann.resolve(input))?;
+
+            // Before the query itself, define any types that it may reference.
+            // For now, we put these interspersed with the queries. If we share
+            // struct types in the future, we might group all types before the
+            // queries.
+            write_struct_definitions(
+                out,
+                options,
+                &mut defined_structs,
+                query.annotation.resolve(input),
+            )?;
+
+            // Functions for queries in a module are collected first, and
+            // written inside their module after all other functions.
+            let query_out: &mut dyn io::Write = match ann.path.split_first() {
+                None => out,
+                Some((first, rest)) => {
+                    let mut module = modules.child(first.resolve(input));
+                    for segment in rest {
+                        module = module.child(segment.resolve(input));
+                    }
+                    &mut module.code
+                }
+            };
+            write_query_function(query_out, options, input, query)?;
+            if options.expose_sql {
+                write_sql_accessor(query_out, options, input, query)?;
+            }
+        }
+    }
+
+    for module in &modules.children {
+        write_module(out, options.visibility, module)?;
+    }
+
+    if options.methods {
+        write_methods(out, options, documents)?;
+    }
+
+    // TODO: Make this configurable.
+    write_main(out, options)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::process_documents;
+    use crate::target::{BeginMode, NullPolicy, Options, Visibility};
+    use crate::NamedDocument;
+    use std::path::Path;
+
+    fn generate(options: &Options, input: &str) -> String {
+        let fname = Path::new("test.sql");
+        let document = NamedDocument::process_input(fname, input.as_bytes())
+            .expect("Test input should be valid.");
+        let mut out = Vec::new();
+        process_documents(&mut out, options, &[document]).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// Generate one module from multiple input files.
+    fn generate_files(options: &Options, inputs: &[(&str, &str)]) -> String {
+        let documents: Vec<_> = inputs
+            .iter()
+            .map(|(fname, input)| {
+                NamedDocument::process_input(Path::new(fname), input.as_bytes())
+                    .expect("Test input should be valid.")
+            })
+            .collect();
+        let mut out = Vec::new();
+        process_documents(&mut out, options, &documents).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn imports_only_what_is_used() {
+        let options = Options::default();
+
+        let output = generate(&options, "-- @query setup()\ncreate table t (x integer);");
+        assert!(output.contains("\n#![allow(dead_code)]\n"));
+        assert!(output.contains("\nuse std::collections::hash_map::Entry::{Occupied, Vacant};\n"));
+        assert!(output.contains("\nuse sqlite::{State::{Row, Done}, Statement};\n"));
+
+        let output = generate(&options, "-- Not a query.\nselect 1;\n");
+        assert!(!output.contains("Occupied"));
+        assert!(output.contains("\nuse std::collections::hash_map::HashMap;\n"));
+    }
+
+    #[test]
+    fn binds_parameters_per_statement() {
+        let input = "\
+-- @begin move_user(id: i64, from_team: i64, to_team: i64)
+delete from memberships where user_id = :id and team_id = :from_team;
+insert into memberships (user_id, team_id) values (:id, :to_team);
+-- @end move_user";
+        let output = generate(&Options::default(), input);
+        let binds: Vec<&str> = output
+            .lines()
+            .filter(|line| line.contains("statement.bind("))
+            .collect();
+        assert_eq!(
+            binds,
+            [
+                "    statement.bind(1, id)?;",
+                "    statement.bind(2, from_team)?;",
+                "    statement.bind(1, id)?;",
+                "    statement.bind(2, to_team)?;",
+            ]
+        );
+    }
+
+    #[test]
+    fn struct_slice_executes_statement_per_element() {
+        let input = "\
+-- @query insert_users(users: [User])
+insert into users (name, karma) values (:name /* :str */, :karma /* :i16 */);";
+        let output = generate(&Options::default(), input);
+        assert!(output.contains(
+            "pub fn insert_users(tx: &mut Transaction, users: &[User<'_>]) -> Result<()> {"
+        ));
+        assert!(output.contains(
+            "    for item in users {
+        statement.reset()?;
+        statement.bind(1, item.name)?;
+        statement.bind(2, i64::from(item.karma))?;
+        match statement.next()? {"
+        ));
+        assert!(output.contains("    }\n    Ok(())\n}\n"));
+    }
+
+    #[test]
+    fn struct_with_only_bytes_field_borrows_with_lifetime() {
+        let input = "\
+-- @query insert_blob(blob: Blob)
+insert into blobs (data) values (:data /* :bytes */);";
+        let output = generate(&Options::default(), input);
+        assert!(output.contains("pub struct Blob<'a> {\n    pub data: &'a [u8],\n}"));
+        assert!(output
+            .contains("pub fn insert_blob(tx: &mut Transaction, blob: Blob<'_>) -> Result<()> {"));
+    }
+
+    #[test]
+    fn strict_null_names_the_column_in_read_errors() {
+        let input = "\
+-- @query get_user(id: i64) ->1 User
+select name /* :str */, karma /* :i16 */ from users where id = :id;";
+        let options = Options {
+            null_policy: Some(NullPolicy::Strict),
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+        assert!(output.contains(
+            "fn read_column<T: sqlite::Readable>(statement: &Statement, index: usize) -> Result<T> {"
+        ));
+        assert!(output.contains(
+            "\"Failed to read column '{}': {}\",\n            statement.column_name(index),"
+        ));
+        assert!(output.contains("        name: read_column(statement, 0)?,\n"));
+        assert!(output
+            .contains("        karma: read_column::<i64>(statement, 1).and_then(narrow_i16)?,\n"));
+        assert!(!output.contains("fn read_not_null"));
+    }
+
+    #[test]
+    fn lenient_null_checks_columns_that_are_not_nullable() {
+        let input = "\
+-- @query get_user(id: i64) ->1 User
+select name /* :str */, email /* :str? */ from users where id = :id;";
+        let options = Options {
+            null_policy: Some(NullPolicy::Lenient),
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+        assert!(output.contains("fn read_column<T: sqlite::Readable>"));
+        assert!(output.contains("    match read_column::<Option<T>>(statement, index)? {"));
+        assert!(output.contains(
+            "\"Column '{}' is null, but its type is not nullable.\",\n                statement.column_name(index),"
+        ));
+        assert!(output.contains("        name: read_not_null(statement, 0)?,\n"));
+        assert!(output.contains("        email: read_column(statement, 1)?,\n"));
+    }
+
+    #[test]
+    fn expose_sql_generates_sql_accessor() {
+        let input = "\
+-- @query get_name(id: i64) ->1 str
+select name from users where id = :id;";
+        let options = Options {
+            expose_sql: true,
+            minify_sql: true,
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+        assert!(output.contains("    let sql = r#\"select name from users where id = :id;\"#;\n"));
+        assert!(output.contains(
+            "\n/// Return the SQL that `get_name` executes.\n\
+            pub fn get_name_sql() -> &'static str {\n    \
+                r#\"select name from users where id = :id;\"#\n\
+            }\n"
+        ));
+    }
+
+    #[test]
+    fn optional_argument_is_bound_as_option() {
+        let input = "\
+-- @query set_karma(id: i64, karma: i64?)
+update users set karma = :karma where id = :id;";
+        let output = generate(&Options::default(), input);
+        assert!(output.contains(
+            "pub fn set_karma(tx: &mut Transaction, id: i64, karma: Option<i64>) -> Result<()> {\n"
+        ));
+        // The `sqlite` crate binds `None` as NULL, so we pass the option as-is.
+        assert!(output.contains("    statement.bind(1, karma)?;\n"));
+        assert!(output.contains("    statement.bind(2, id)?;\n"));
+    }
+
+    #[test]
+    fn long_signature_puts_arguments_on_separate_lines() {
+        let input = "\
+-- @query insert_user(name: str, email: str, karma: i64, score: f64, bio: str?, avatar: bytes) ->1 i64
+insert into users (name, email, karma, score, bio, avatar)
+values (:name, :email, :karma, :score, :bio, :avatar)
+returning id;";
+        let output = generate(&Options::default(), input);
+        assert!(output.contains(
+            "pub fn insert_user(\n    \
+                tx: &mut Transaction,\n    \
+                name: &str,\n    \
+                email: &str,\n    \
+                karma: i64,\n    \
+                score: f64,\n    \
+                bio: Option<&str>,\n    \
+                avatar: &[u8],\n\
+            ) -> Result<i64> {\n"
+        ));
+
+        // With more room, the signature fits on a single line.
+        let options = Options {
+            max_line_width: 200,
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+        assert!(output.contains(
+            "pub fn insert_user(tx: &mut Transaction, name: &str, email: &str, karma: i64, \
+            score: f64, bio: Option<&str>, avatar: &[u8]) -> Result<i64> {\n"
+        ));
+
+        // Functions in modules get indented, which counts towards the width.
+        let input = "\
+-- @query users::admin::iter_names() ->* str
+select name from users;";
+        let output = generate(&Options::default(), input);
+        assert!(output.contains(
+            "        pub fn iter_names<'i, 't, 'a>(\n            \
+                tx: &'i mut Transaction<'t, 'a>,\n        \
+            ) -> Result<Iter<'i, 'a, String>> {\n"
+        ));
+    }
+
+    #[test]
+    fn tracing_wraps_query_in_span() {
+        let input = "\
+-- @query get_name(id: i64) ->1 str
+select name from users where id = :id;";
+        let options = Options {
+            tracing: true,
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+        assert!(output.contains(
+            "pub fn get_name(tx: &mut Transaction, id: i64) -> Result<String> {\n    \
+            fn run(tx: &mut Transaction, id: i64) -> Result<String> {\n"
+        ));
+        assert!(output.contains("        statement.bind(1, id)?;\n"));
+        assert!(output.contains("    let span = tracing::debug_span!(\"get_name\");\n"));
+        assert!(output.contains("    let _guard = span.enter();\n"));
+        assert!(output.contains("    let result = run(tx, id);\n"));
+        assert!(output.contains("error = %err, \"Query 'get_name' failed.\"),\n"));
+        assert!(output.contains("    result\n}\n"));
+
+        let output = generate(&Options::default(), input);
+        assert!(!output.contains("tracing"));
+    }
+
+    #[test]
+    fn cardinality_errors_returns_err_instead_of_panic() {
+        let input = "\
+-- @query get_answer() ->1 i64
+select 42;";
+        let output = generate(&Options::default(), input);
+        assert!(output.contains("panic!(\"Query 'get_answer' should return exactly one row.\")"));
+        assert!(!output.contains("fn cardinality_error"));
+
+        let options = Options {
+            cardinality_errors: true,
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+        assert!(!output.contains("panic!"));
+        assert!(output.contains("fn cardinality_error(message: &str) -> sqlite::Error {"));
+        assert!(output.contains(
+            "        Done => return Err(cardinality_error(\"Query 'get_answer' should return exactly one row.\")),"
+        ));
+        assert!(output.contains(
+            "        return Err(cardinality_error(\"Query 'get_answer' should return exactly one row.\"));"
+        ));
+    }
+
+    #[test]
+    fn tagged_enum_decodes_variant_from_first_column() {
+        let input = "\
+-- @query iter_events() ->* Event(Created = 0 (id: i64, name: str), Deleted = 1 (id: i64), Cleared = 2)
+select kind, id, name from events;";
+        let output = generate(&Options::default(), input);
+        assert!(output.contains(
+            "pub enum Event {
+    Created {
+        id: i64,
+        name: String,
+    },
+    Deleted {
+        id: i64,
+    },
+    Cleared,
+}"
+        ));
+        // Fields with the same name share a column, even in different variants.
+        assert!(output.contains(
+            "let decode_row = |statement: &Statement| Ok(match statement.read::<i64>(0)? {
+        0 => Event::Created {
+            id: statement.read(1)?,
+            name: statement.read(2)?,
+        },
+        1 => Event::Deleted {
+            id: statement.read(1)?,
+        },
+        2 => Event::Cleared,
+        code => {"
+        ));
+        assert!(output.contains(r#"Some(format!("Invalid code for Event: {}.", code))"#));
+    }
+
+    #[test]
+    fn dedup_structs_defines_identical_structs_once() {
+        let users = "\
+-- @query get_user(id: i64) ->1 User
+select name /* :str */, email /* :str */ from users where id = :id;
+-- @query iter_users() ->* User
+select name /* :str */, email /* :str */ from users;";
+        let orders = "\
+-- @query get_order_user(order_id: i64) ->? User
+select u.name /* :str */, u.email /* :str */
+from orders o join users u on o.user_id = u.id
+where o.id = :order_id;";
+        let inputs = [("users.sql", users), ("orders.sql", orders)];
+
+        let output = generate_files(&Options::default(), &inputs);
+        assert_eq!(output.matches("pub struct User {").count(), 3);
+
+        let options = Options {
+            dedup_structs: true,
+            ..Options::default()
+        };
+        let output = generate_files(&options, &inputs);
+        assert_eq!(output.matches("pub struct User {").count(), 1);
+        assert!(output.contains("-> Result<Option<User>>"));
+    }
+
+    #[test]
+    fn multiple_files_share_one_module() {
+        let users = "\
+-- @query get_user(id: i64) ->1 User
+select name /* :str */, email /* :str */ from users where id = :id;";
+        // The second file refers to the struct that the first one defines.
+        let orders = "\
+-- @query get_order_user(order_id: i64) ->1 @extern User
+select u.name /* :str */, u.email /* :str */
+from orders o join users u on o.user_id = u.id
+where o.id = :order_id;";
+        let output = generate_files(
+            &Options::default(),
+            &[("users.sql", users), ("orders.sql", orders)],
+        );
+        assert!(output.contains("// Input files:\n// - users.sql\n// - orders.sql\n"));
+        assert_eq!(output.matches("pub struct Connection<'a>").count(), 1);
+        assert_eq!(output.matches("pub struct User {").count(), 1);
+        assert!(output.contains("            statements: HashMap::with_capacity(2),\n"));
+        assert!(output.contains("pub fn get_user(tx: &mut Transaction, id: i64) -> Result<User>"));
+        assert!(output.contains(
+            "pub fn get_order_user(tx: &mut Transaction, order_id: i64) -> Result<User>"
+        ));
+    }
+
+    #[test]
+    fn connection_reserves_capacity_for_all_queries() {
+        let input = "\
+-- @query get_one() ->1 i64
+select 1;
+-- @query get_two() ->1 i64
+select 2;";
+        let output = generate(&Options::default(), input);
+        assert!(output.contains("            statements: HashMap::with_capacity(2),\n"));
+    }
+
+    #[test]
+    fn visibility_applies_to_all_items() {
+        let input = "-- @query get_user(id: i64) ->1 User\nselect name /* :str */ from users where id = :id;";
+        let options = Options {
+            visibility: Visibility::Crate,
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+        assert!(output.contains("\npub(crate) type Result<T>"));
+        assert!(output.contains("\npub(crate) struct Connection<'a>"));
+        assert!(output.contains("\npub(crate) struct Transaction<'tx, 'a>"));
+        assert!(output.contains("\npub(crate) struct Iter<'i, 'a, T>"));
+        assert!(output.contains("\npub(crate) struct User {"));
+        assert!(output.contains("\n    pub(crate) name: String,"));
+        assert!(output.contains("\npub(crate) fn get_user(tx: &mut Transaction, id: i64)"));
+        assert!(!output.contains("\npub struct"));
+        assert!(!output.contains("\npub fn"));
+    }
+
+    #[test]
+    fn nullable_borrowed_struct_fields_get_a_lifetime() {
+        let input = "-- @query set_note(note: Note)\nupdate notes set body = :body /* :str? */, data = :data /* :bytes? */;";
+        let output = generate(&Options::default(), input);
+        assert!(output.contains(
+            "pub struct Note<'a> {\n    pub body: Option<&'a str>,\n    pub data: Option<&'a [u8]>,\n}\n"
+        ));
+        assert!(
+            output.contains("pub fn set_note(tx: &mut Transaction, note: Note<'_>) -> Result<()>")
+        );
+    }
+
+    #[test]
+    fn extern_structs_are_not_defined() {
+        let input = "-- @query get_user(id: i64) ->1 @extern User\nselect name /* :str */ from users where id = :id;";
+        let output = generate(&Options::default(), input);
+        assert!(!output.contains("struct User"));
+        assert!(output.contains("pub fn get_user(tx: &mut Transaction, id: i64) -> Result<User>"));
+        assert!(output.contains("Ok(User {\n        name: statement.read(0)?,\n    })"));
+    }
+
+    #[test]
+    fn read_by_name_reads_struct_fields_by_column_name() {
+        let input = "-- @query get_user(id: i64) ->1 User\nselect name /* :str */, age /* :i16 */ from users where id = :id;";
+        let options = Options {
+            read_by_name: true,
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+        assert!(output
+            .contains("\nfn column_index(statement: &Statement, name: &str) -> Result<usize> {"));
+        // The columns are looked up once per query, not for every row.
+        assert!(output.contains(
+            "    let columns = [\n        \
+            column_index(statement, \"name\")?,\n        \
+            column_index(statement, \"age\")?,\n    \
+            ];\n"
+        ));
+        assert!(output.contains(
+            "        name: statement.read(columns[0])?,\n        \
+            age: statement.read::<i64>(columns[1]).and_then(narrow_i16)?,\n"
+        ));
+
+        // An iterator stores the columns, it decodes rows through a function pointer.
+        let input = "-- @query iter_users() ->* User\nselect name /* :str */ from users;";
+        let output = generate(&options, input);
+        assert!(output.contains("    decode_row: fn(&Statement<'a>, &[usize]) -> Result<T>,\n"));
+        assert!(output.contains("    let columns = vec![\n"));
+        assert!(output.contains("    let result = Iter { statement, decode_row, columns };\n"));
+
+        // Without struct results, there is nothing to read by name.
+        let input = "-- @query get_name(id: i64) ->1 str\nselect name from users where id = :id;";
+        let output = generate(&options, input);
+        assert!(output.contains("Ok(statement.read(0)?)"));
+        assert!(!output.contains("column_index"));
+    }
+
+    #[test]
+    fn minify_sql_collapses_whitespace_outside_literals() {
+        let input = "-- @query get_names(id: i64) ->* str\nselect\n  name  -- The full name.\n  from users\n  where id = :id and note = 'a  b'  and \"c  d\" = 'it''s  x';";
+        let output = generate(&Options::default(), input);
+        assert!(output.contains(
+            "    let sql = r#\"\n        select\n          name  -- The full name.\n          from users\n          \
+            where id = :id and note = 'a  b'  and \"c  d\" = 'it''s  x';\n        \"#;\n"
+        ));
+
+        let options = Options {
+            minify_sql: true,
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+        assert!(output.contains(
+            "    let sql = r#\"select name -- The full name.\nfrom users \
+            where id = :id and note = 'a  b' and \"c  d\" = 'it''s  x';\"#;\n"
+        ));
+    }
+
+    #[test]
+    fn strip_comments_removes_comments_outside_literals() {
+        let input = "-- @query get_names(id: i64) ->* str\n-- Only active users.\nselect\n  name, -- The full name.\n  /* Not email. */ age\n  /* Only adults. */\n  from users\n  where id = :id/* x */and note = '-- a /* b */';";
+        let output = generate(&Options::default(), input);
+        assert!(output.contains(
+            "    let sql = r#\"\n        select\n          name, -- The full name.\n          \
+            /* Not email. */ age\n          /* Only adults. */\n          from users\n          \
+            where id = :id/* x */and note = '-- a /* b */';\n        \"#;\n"
+        ));
+
+        let options = Options {
+            strip_comments: true,
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+        assert!(output.contains(
+            "    let sql = r#\"\n        select\n          name,\n          \
+            age\n          from users\n          \
+            where id = :id and note = '-- a /* b */';\n        \"#;\n"
+        ));
+    }
+
+    #[test]
+    fn json_is_bound_and_read_as_text() {
+        let input = "\
+-- @query get_settings(id: i64) ->1 json
+select settings from users where id = :id;
+-- @query set_settings(id: i64, settings: json?)
+update users set settings = :settings where id = :id;";
+        let output = generate(&Options::default(), input);
+        assert!(output.contains("fn parse_json(value: String) -> Result<serde_json::Value> {"));
+        assert!(output.contains("statement.read::<String>(0).and_then(parse_json)"));
+        assert!(output.contains("settings: Option<&serde_json::Value>,"));
+        assert!(output.contains("statement.bind(1, settings.map(|v| v.to_string()).as_deref())?;"));
+
+        let input = "-- @query get_name(id: i64) ->1 str\nselect name from users where id = :id;";
+        assert!(!generate(&Options::default(), input).contains("parse_json"));
+    }
+
+    #[test]
+    fn nullable_copy_struct_fields_do_not_get_a_lifetime() {
+        let input = "-- @query set_age(user: User)\nupdate users set age = :age /* :i64? */;";
+        let output = generate(&Options::default(), input);
+        assert!(output.contains("pub struct User {\n    pub age: Option<i64>,\n}\n"));
+    }
+
+    #[test]
+    fn named_tuple_elements_are_documented() {
+        let input = "-- Count users.\n-- @query count_by_name() ->* (count: i64, str)\nselect count(*), name from users group by name;";
+        let output = generate(&Options::default(), input);
+        assert!(output.contains(
+            "/// Count users.\n///\n/// Returns a tuple of:\n///\n///  * `.0`: count\n///  * `.1`\npub fn count_by_name"
+        ));
+    }
+
+    #[test]
+    fn borrow_rows_passes_row_views_to_callback() {
+        let input =
+            "-- @query get_users() ->* User\nselect id /* :i64 */, name /* :str */ from users;";
+        let options = Options {
+            borrow_rows: true,
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+        assert!(
+            output.contains("pub struct User<'s, 'a> {\n    statement: &'s Statement<'a>,\n}\n")
+        );
+        assert!(output.contains(
+            "    pub fn name(&self) -> Result<String> {\n        self.statement.read(1)\n"
+        ));
+        assert!(output.contains(
+            "pub fn get_users<F>(tx: &mut Transaction, mut f: F) -> Result<()>\nwhere\n    F: FnMut(User) -> Result<()>,\n{\n"
+        ));
+        assert!(output.contains("        f(User { statement })?;\n"));
+        assert!(!output.contains("let decode_row"));
+    }
+
+    #[test]
+    fn methods_call_free_functions() {
+        let input = "-- Get a user.\n-- @query get_user(id: i64) ->1 str\nselect name from users where id = :id;";
+        let options = Options {
+            methods: true,
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+        assert!(
+            output.contains("pub fn get_user(tx: &mut Transaction, id: i64) -> Result<String> {")
+        );
+        assert!(output.contains(
+            "\nimpl<'tx, 'a> Transaction<'tx, 'a> {\n    /// Get a user.\n    pub fn get_user(&mut self, id: i64) -> Result<String> {\n        get_user(self, id)\n    }\n}\n"
+        ));
+    }
+
+    #[test]
+    fn empty_files_are_omitted_from_header() {
+        let empty = NamedDocument::process_input(Path::new("empty.sql"), b"").unwrap();
+        let input = "-- @query f()\nselect 1;";
+        let nonempty =
+            NamedDocument::process_input(Path::new("test.sql"), input.as_bytes()).unwrap();
+        let mut out = Vec::new();
+        process_documents(&mut out, &Options::default(), &[empty, nonempty]).unwrap();
+        let output = String::from_utf8(out).unwrap();
+
+        assert!(!output.contains("empty.sql"));
+        assert!(output.contains("// Input files:\n// - test.sql\n\n#![allow"));
+        // The empty file adds nothing, the output is the same as without it.
+        assert_eq!(output, generate(&Options::default(), input));
+    }
+
+    #[test]
+    fn cfg_feature_gates_module() {
+        let input = "-- @query f()\nselect 1;";
+        let output = generate(&Options::default(), input);
+        assert!(!output.contains("#![cfg("));
+
+        let options = Options {
+            cfg_feature: Some("sqlite".into()),
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+        assert!(output.contains("\n#![cfg(feature = \"sqlite\")]\n#![allow(unknown_lints)]\n"));
+    }
+
+    #[test]
+    fn begin_mode_changes_begin_statement() {
+        let input = "-- @query q()\nselect 1;";
+        let output = generate(&Options::default(), input);
+        assert!(output.contains("        self.connection.execute(\"BEGIN;\")?;\n"));
+
+        let options = Options {
+            begin_mode: Some(BeginMode::Immediate),
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+        assert!(output.contains("        self.connection.execute(\"BEGIN IMMEDIATE;\")?;\n"));
+        assert!(!output.contains("\"BEGIN;\""));
+    }
+
+    #[test]
+    fn no_cache_prepares_statements_in_every_call() {
+        let input = "-- @query get_name(id: i64) ->1 str\nselect name from users where id = :id;";
+        let options = Options {
+            cache_statements: false,
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+        assert!(!output.contains("HashMap"));
+        assert!(!output.contains("statement.reset()"));
+        assert!(output.contains("    let mut statement = tx.connection.prepare(sql)?;\n"));
+        assert!(output.contains("        Row => decode_row(&statement)?,\n"));
+        assert!(!output.contains("unused_mut"));
+
+        // An iterator without parameters takes ownership of the statement
+        // before anything mutates it.
+        let input = "-- @query iter_names() ->* str\nselect name from users;";
+        let output = generate(&options, input);
+        assert!(output.contains("    let statement = tx.connection.prepare(sql)?;\n"));
+        let input = "-- @query iter_names(id: i64) ->* str\nselect name from users where id > :id;";
+        let output = generate(&options, input);
+        assert!(output.contains("    let mut statement = tx.connection.prepare(sql)?;\n"));
+    }
+
+    #[test]
+    fn i16_is_widened_and_narrowed_through_i64() {
+        let input = "-- @query f()\nselect 1;";
+        let output = generate(&Options::default(), input);
+        assert!(!output.contains("narrow_i16"));
+
+        let input = "-- @query get_age(id: i16, max: i16?) ->1 (i16, i16?)\nselect age, min_age from users where id = :id and age < :max;";
+        let output = generate(&Options::default(), input);
+        assert!(output.contains("fn narrow_i16(value: i64) -> Result<i16> {"));
+        assert!(output.contains("    statement.bind(1, i64::from(id))?;\n"));
+        assert!(output.contains("    statement.bind(2, max.map(i64::from))?;\n"));
+        assert!(output.contains("        statement.read::<i64>(0).and_then(narrow_i16)?,\n"));
+        assert!(output.contains(
+            "        statement.read::<Option<i64>>(1).and_then(|v| v.map(narrow_i16).transpose())?,\n"
+        ));
+    }
+
+    #[test]
+    fn u32_is_widened_and_narrowed_through_i64() {
+        let input = "\
+-- @query iter_names(page: Page) ->* (str, u32?)
+select name, karma from users limit :limit /* :u32 */ offset :offset /* :u32 */;";
+        let output = generate(&Options::default(), input);
+        assert!(output.contains("    pub limit: u32,\n"));
+        assert!(output.contains("fn narrow_u32(value: i64) -> Result<u32> {"));
+        assert!(output.contains("/// The range of `u32` is 0 through 4294967295"));
+        assert!(output.contains("    statement.bind(1, i64::from(page.limit))?;\n"));
+        assert!(output.contains("    statement.bind(2, i64::from(page.offset))?;\n"));
+        assert!(output.contains(
+            "        statement.read::<Option<i64>>(1).and_then(|v| v.map(narrow_u32).transpose())?,\n"
+        ));
+        assert!(!output.contains("narrow_i16"));
+    }
+
+    #[test]
+    fn module_paths_produce_nested_modules() {
+        let input = "\
+-- @query users::get_name(id: i64) ->1 str
+select name from users where id = :id;
+
+-- @query count_users() ->1 i64
+select count(*) from users;
+
+-- @query users::admin::count_admins() ->1 i64
+select count(*) from users where is_admin;
+
+-- @query users::delete_all()
+delete from users;";
+        let options = Options {
+            visibility: Visibility::Crate,
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+
+        // Queries without a path come first, then the modules, in the order
+        // in which they first occur.
+        let top_level = output.find("pub(crate) fn count_users(").unwrap();
+        let users = output
+            .find("\npub(crate) mod users {\n    use super::*;\n")
+            .unwrap();
+        let get_name = output.find("\n    pub(crate) fn get_name(").unwrap();
+        let delete_all = output.find("\n    pub(crate) fn delete_all(").unwrap();
+        let admin = output
+            .find("\n    pub mod admin {\n        use super::*;\n")
+            .unwrap();
+        let count_admins = output
+            .find("\n        pub(crate) fn count_admins(")
+            .unwrap();
+        assert!(top_level < users);
+        assert!(users < get_name);
+        assert!(get_name < delete_all);
+        assert!(delete_all < admin);
+        assert!(admin < count_admins);
+
+        // The SQL is indented along with the function.
+        assert!(output.contains(
+            "            let sql = r#\"\n                select count(*) from users where is_admin;\n"
+        ));
+
+        // Private functions must still be visible outside of their module.
+        let options = Options {
+            visibility: Visibility::Private,
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+        assert!(output.contains("\nmod users {\n"));
+        assert!(output.contains("\n    pub(super) fn get_name("));
+        assert!(output.contains("\n        pub(in super::super) fn count_admins("));
+
+        let options = Options {
+            methods: true,
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+        assert!(
+            output.contains("    pub fn users_admin_count_admins(&mut self) -> Result<i64> {\n")
+        );
+        assert!(output.contains("        users::admin::count_admins(self)\n"));
+    }
+
+    #[test]
+    fn type_prefix_applies_to_all_references() {
+        let input =
+            "-- @query get_users() ->* str\nselect name from users;\n-- @query f()\nselect 1;";
+        let options = Options {
+            type_prefix: "Db".into(),
+            methods: true,
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+        assert!(output
+            .contains("\npub struct DbConnection<'a> {\n    connection: &'a sqlite::Connection,"));
+        assert!(output.contains("\npub struct DbTransaction<'tx, 'a> {"));
+        assert!(output.contains("\npub struct DbIter<'i, 'a, T> {"));
+        assert!(output.contains("\nimpl<'a> DbConnection<'a> {"));
+        assert!(output.contains("-> Result<DbTransaction<'tx, 'a>> {"));
+        assert!(output.contains("\nimpl<'i, 'a, T> Iterator for DbIter<'i, 'a, T> {"));
+        assert!(output.contains(
+            "pub fn get_users<'i, 't, 'a>(tx: &'i mut DbTransaction<'t, 'a>) -> Result<DbIter<'i, 'a, String>> {"
+        ));
+        assert!(output.contains("    let result = DbIter { statement, decode_row };"));
+        assert!(output.contains("pub fn f(tx: &mut DbTransaction) -> Result<()> {"));
+        assert!(output.contains("\nimpl<'tx, 'a> DbTransaction<'tx, 'a> {\n"));
+        assert!(output.contains("let mut connection = DbConnection::new(&raw_connection);"));
+
+        // Every mention of the types should be prefixed.
+        let unprefixed = output
+            .replace("DbConnection", "")
+            .replace("DbTransaction", "")
+            .replace("DbIter", "")
+            .replace("sqlite::Connection", "");
+        assert!(!unprefixed.contains("Connection<"));
+        assert!(!unprefixed.contains("Transaction<"));
+        assert!(!unprefixed.contains("Iter<"));
+    }
+
+    #[test]
+    fn param_docs_are_documented() {
+        let input = "-- Get a user.\n-- @query get_user(id: i64, name: str) ->1 str\n-- @param id The user's id.\nselect name from users where id = :id and name = :name;";
+        let output = generate(&Options::default(), input);
+        assert!(output.contains(
+            "/// Get a user.\n///\n/// Arguments:\n///\n///  * `id`: The user's id.\n///  * `name`\npub fn get_user("
+        ));
+    }
+}
@@ -0,0 +1,245 @@
+// Squiller -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2023 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! A target that generates smoke tests for the code of the `rust-sqlite` target.
+//!
+//! For every query, we generate a test that calls it with placeholder
+//! arguments, in a transaction on a new in-memory database. The tests go in a
+//! `generated_tests` module that refers to the queries through `super`, so the
+//! output should be included in the module that contains the generated queries.
+
+use std::io;
+
+use crate::ast::{Annotation, ArgType, PrimitiveType, ResultType, SimpleType};
+use crate::target::Options;
+use crate::NamedDocument;
+
+/// Write a placeholder value of the given type, to pass as argument.
+fn write_placeholder(out: &mut dyn io::Write, type_: &SimpleType<&str>) -> io::Result<()> {
+    let value = match type_ {
+        SimpleType::Option { .. } => "None",
+        SimpleType::Enum { name, variants, .. } => {
+            return write!(out, "{}::{}", name, variants[0].name);
+        }
+        SimpleType::Primitive { type_, .. } => match type_ {
+            PrimitiveType::Str => "\"\"",
+            PrimitiveType::I16 | PrimitiveType::I32 | PrimitiveType::I64 | PrimitiveType::U32 => {
+                "0"
+            }
+            PrimitiveType::F32 | PrimitiveType::F64 => "0.0",
+            PrimitiveType::Bytes => "&[]",
+            PrimitiveType::Json => "&serde_json::Value::Null",
+        },
+    };
+    write!(out, "{}", value)
+}
+
+fn write_test(
+    out: &mut dyn io::Write,
+    options: &Options,
+    ann: &Annotation<&str>,
+) -> io::Result<()> {
+    writeln!(out, "\n    #[test]")?;
+    // For a query in a module, the test name includes the module path, so
+    // queries with the same name in different modules get distinct tests.
+    let mut test_name = String::new();
+    let mut query_path = "super::".to_string();
+    for segment in &ann.path {
+        test_name.push_str(segment);
+        test_name.push('_');
+        query_path.push_str(segment);
+        query_path.push_str("::");
+    }
+    test_name.push_str(ann.name);
+    query_path.push_str(ann.name);
+
+    writeln!(out, "    fn {}() {{", test_name)?;
+    writeln!(out, "        with_transaction(|tx| {{")?;
+
+    // The test function has the same name as the query, so we need to call the
+    // query through `super`.
+    let is_iterator = matches!(ann.result_type, ResultType::Iterator(..));
+    match is_iterator && !options.borrow_rows {
+        true => write!(out, "            for row in {}(tx", query_path)?,
+        false => write!(out, "            {}(tx", query_path)?,
+    }
+    match &ann.arguments {
+        ArgType::Args(args) => {
+            for arg in args {
+                write!(out, ", ")?;
+                write_placeholder(out, &arg.type_)?;
+            }
+        }
+        ArgType::Struct {
+            type_name, fields, ..
+        } => {
+            write!(out, ", {} {{", type_name)?;
+            for (i, field) in fields.iter().enumerate() {
+                let sep = if i > 0 { ", " } else { " " };
+                write!(out, "{}{}: ", sep, field.ident)?;
+                write_placeholder(out, &field.type_)?;
+            }
+            write!(out, " }}")?;
+        }
+        ArgType::StructSlice {
+            type_name, fields, ..
+        } => {
+            write!(out, ", &[{} {{", type_name)?;
+            for (i, field) in fields.iter().enumerate() {
+                let sep = if i > 0 { ", " } else { " " };
+                write!(out, "{}{}: ", sep, field.ident)?;
+                write_placeholder(out, &field.type_)?;
+            }
+            write!(out, " }}]")?;
+        }
+    }
+    match is_iterator {
+        true if options.borrow_rows => writeln!(out, ", |_row| Ok(()))?;")?,
+        true => {
+            writeln!(out, ")? {{")?;
+            writeln!(out, "                row?;")?;
+            writeln!(out, "            }}")?;
+        }
+        false => writeln!(out, ")?;")?,
+    }
+
+    writeln!(out, "            Ok(())")?;
+    writeln!(out, "        }});")?;
+    writeln!(out, "    }}")
+}
+
+/// Write the schema as a raw string literal that cannot be terminated early.
+fn write_schema(out: &mut dyn io::Write, schema: &str) -> io::Result<()> {
+    let mut hashes = "#".to_string();
+    while schema.contains(&format!("\"{}", hashes)) {
+        hashes.push('#');
+    }
+    writeln!(
+        out,
+        "\n    const SCHEMA: &str = r{}\"\n{}\n\"{};",
+        hashes,
+        schema.trim(),
+        hashes
+    )
+}
+
+pub fn process_documents(
+    out: &mut dyn io::Write,
+    options: &Options,
+    documents: &[NamedDocument],
+) -> io::Result<()> {
+    use crate::version::{REV, VERSION};
+    if options.version_header {
+        write!(out, "// This file was generated by Squiller {}", VERSION,)?;
+        match REV {
+            Some(rev) => writeln!(out, " (commit {}).", &rev[..10])?,
+            None => writeln!(out, " (unspecified checkout).")?,
+        }
+        writeln!(out, "// Input files:")?;
+        for doc in documents.iter().filter(|doc| doc.document.has_queries()) {
+            writeln!(out, "// - {}", doc.fname.to_string_lossy())?;
+        }
+    }
+
+    writeln!(out, "\n#[cfg(test)]")?;
+    writeln!(out, "mod generated_tests {{")?;
+    writeln!(out, "    use super::*;")?;
+
+    if let Some(schema) = &options.schema_sql {
+        write_schema(out, schema)?;
+    }
+
+    let p = &options.type_prefix;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "    /// Run `f` in a transaction, on a new in-memory database."
+    )?;
+    writeln!(out, "    fn with_transaction<F>(f: F)")?;
+    writeln!(out, "    where")?;
+    writeln!(
+        out,
+        "        F: FnOnce(&mut {}Transaction) -> Result<()>,",
+        p
+    )?;
+    writeln!(out, "    {{")?;
+    writeln!(
+        out,
+        "        let raw_connection = sqlite::open(\":memory:\").unwrap();"
+    )?;
+    if options.schema_sql.is_some() {
+        writeln!(out, "        raw_connection.execute(SCHEMA).unwrap();")?;
+    }
+    writeln!(
+        out,
+        "        let mut connection = {}Connection::new(&raw_connection);",
+        p
+    )?;
+    writeln!(out, "        connection.transaction(f).unwrap();")?;
+    writeln!(out, "    }}")?;
+
+    for named_document in documents {
+        let input = named_document.input;
+        for query in named_document.document.iter_queries() {
+            write_test(out, options, &query.annotation.resolve(input))?;
+        }
+    }
+
+    writeln!(out, "}}")
+}
+
+#[cfg(test)]
+mod test {
+    use super::process_documents;
+    use crate::target::Options;
+    use crate::NamedDocument;
+    use std::path::Path;
+
+    fn generate(options: &Options, input: &str) -> String {
+        let doc = NamedDocument::process_input(Path::new("x.sql"), input.as_bytes()).unwrap();
+        let mut out = Vec::new();
+        process_documents(&mut out, options, &[doc]).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn queries_are_called_with_placeholder_arguments() {
+        let input = "\
+-- @query insert_user(name: str, age: i64?, photo: bytes) ->1 i64
+insert into users (name, age, photo) values (:name, :age, :photo) returning id;
+-- @query insert_score(score: Score)
+insert into scores (value) values (:value /* :f64 */);
+-- @query select_names() ->* str
+select name from users;
+";
+        let output = generate(&Options::default(), input);
+        assert!(output.contains("\n#[cfg(test)]\nmod generated_tests {\n    use super::*;\n"));
+        assert!(output.contains(
+            "    #[test]\n    fn insert_user() {\n        with_transaction(|tx| {\n            \
+            super::insert_user(tx, \"\", None, &[])?;\n            Ok(())\n        });\n    }\n"
+        ));
+        assert!(output.contains("            super::insert_score(tx, Score { value: 0.0 })?;\n"));
+        assert!(output.contains(
+            "            for row in super::select_names(tx)? {\n                row?;\n            }\n"
+        ));
+        assert!(!output.contains("SCHEMA"));
+
+        let options = Options {
+            borrow_rows: true,
+            type_prefix: "Db".into(),
+            schema_sql: Some("create table users (name text);\n".into()),
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+        assert!(output.contains("            super::select_names(tx, |_row| Ok(()))?;\n"));
+        assert!(output.contains("        F: FnOnce(&mut DbTransaction) -> Result<()>,\n"));
+        assert!(output.contains("DbConnection::new(&raw_connection);"));
+        assert!(output
+            .contains("    const SCHEMA: &str = r#\"\ncreate table users (name text);\n\"#;\n"));
+        assert!(output.contains("        raw_connection.execute(SCHEMA).unwrap();\n"));
+    }
+}
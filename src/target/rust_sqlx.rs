@@ -0,0 +1,499 @@
+// Squiller -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2023 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Target Rust and the `sqlx` crate, with plain async functions.
+//!
+//! Unlike the `query!` macros of sqlx, the generated code does not need a
+//! database at compile time. Every query becomes a function that takes a pool,
+//! and uses `sqlx::query`, `sqlx::query_scalar`, or `sqlx::query_as` to execute
+//! it. The dialect determines the pool type and the placeholder syntax.
+
+use std::collections::HashSet;
+use std::io;
+
+use crate::ast::{
+    ArgType, ComplexType, Dialect, Fragment, PrimitiveType, Query, ResultType, SimpleType,
+    Statement, TypedIdent,
+};
+use crate::target::Options;
+use crate::NamedDocument;
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum Ownership {
+    Borrow,
+    BorrowNamed,
+    Owned,
+}
+
+fn write_primitive_type(
+    out: &mut dyn io::Write,
+    owned: Ownership,
+    type_: PrimitiveType,
+) -> io::Result<()> {
+    use Ownership::{Borrow, BorrowNamed, Owned};
+    let name = match (type_, owned) {
+        (PrimitiveType::Str, Borrow) => "&str",
+        (PrimitiveType::Str, BorrowNamed) => "&'a str",
+        (PrimitiveType::Str, Owned) => "String",
+        (PrimitiveType::Bytes, Borrow) => "&[u8]",
+        (PrimitiveType::Bytes, BorrowNamed) => "&'a [u8]",
+        (PrimitiveType::Bytes, Owned) => "Vec<u8>",
+        (PrimitiveType::Json, Borrow) => "&serde_json::Value",
+        (PrimitiveType::Json, BorrowNamed) => "&'a serde_json::Value",
+        (PrimitiveType::Json, Owned) => "serde_json::Value",
+        (PrimitiveType::I16, _) => "i16",
+        (PrimitiveType::I32, _) => "i32",
+        (PrimitiveType::I64, _) => "i64",
+        (PrimitiveType::U32, _) => "u32",
+        (PrimitiveType::F32, _) => "f32",
+        (PrimitiveType::F64, _) => "f64",
+    };
+    out.write_all(name.as_bytes())
+}
+
+fn write_simple_type(
+    out: &mut dyn io::Write,
+    owned: Ownership,
+    type_: &SimpleType<&str>,
+) -> io::Result<()> {
+    match type_ {
+        SimpleType::Primitive { type_: t, .. } => write_primitive_type(out, owned, *t),
+        SimpleType::Option { type_: t, .. } => {
+            write!(out, "Option<")?;
+            write_primitive_type(out, owned, *t)?;
+            write!(out, ">")
+        }
+        // This target does not generate enums, it passes the integer code.
+        SimpleType::Enum { .. } => write_primitive_type(out, owned, PrimitiveType::I64),
+    }
+}
+
+/// Write the type that a row decodes into, for the type parameter of `query_as`.
+fn write_row_type(out: &mut dyn io::Write, type_: &ComplexType<&str>) -> io::Result<()> {
+    match type_ {
+        ComplexType::Simple(t) => write_simple_type(out, Ownership::Owned, t),
+        ComplexType::Tuple(_, fields) => {
+            write!(out, "(")?;
+            for field in fields {
+                write_simple_type(out, Ownership::Owned, &field.type_)?;
+                // A trailing comma is needed for 1-tuples, and allowed otherwise.
+                write!(out, ",")?;
+            }
+            write!(out, ")")
+        }
+        ComplexType::Struct(name, ..) => write!(out, "{}", name),
+        ComplexType::TaggedEnum(..) => {
+            unreachable!("Tagged enums are rejected by `check_target_support`.")
+        }
+    }
+}
+
+/// Whether the struct needs a lifetime parameter, when it borrows its fields.
+fn has_borrowed_fields(fields: &[TypedIdent<&str>]) -> bool {
+    fields.iter().any(|field| {
+        matches!(
+            field.type_.inner_type(),
+            PrimitiveType::Str | PrimitiveType::Bytes | PrimitiveType::Json
+        )
+    })
+}
+
+fn write_struct_definition(
+    out: &mut dyn io::Write,
+    options: &Options,
+    owned: Ownership,
+    name: &str,
+    fields: &[TypedIdent<&str>],
+) -> io::Result<()> {
+    let vis = options.visibility.prefix();
+    writeln!(out)?;
+    match owned {
+        // Result structs are decoded by column name, the derive takes care of that.
+        Ownership::Owned => writeln!(out, "#[derive(Debug, sqlx::FromRow)]")?,
+        _ => writeln!(out, "#[derive(Debug)]")?,
+    }
+    match owned != Ownership::Owned && has_borrowed_fields(fields) {
+        true => writeln!(out, "{}struct {}<'a> {{", vis, name)?,
+        false => writeln!(out, "{}struct {} {{", vis, name)?,
+    }
+    for field in fields {
+        write!(out, "    {}{}: ", vis, field.ident)?;
+        write_simple_type(out, owned, &field.type_)?;
+        writeln!(out, ",")?;
+    }
+    writeln!(out, "}}")
+}
+
+/// Generate definitions for the structs that the query uses, unless they are extern.
+///
+/// With `--dedup-structs`, `defined` holds the names of the structs that we
+/// defined already, and we skip those.
+fn write_struct_definitions<'a>(
+    out: &mut dyn io::Write,
+    options: &Options,
+    defined: &mut HashSet<&'a str>,
+    query: &Query<&'a str>,
+) -> io::Result<()> {
+    let mut is_new = |name| !options.dedup_structs || defined.insert(name);
+    if let ArgType::Struct {
+        type_name, fields, ..
+    }
+    | ArgType::StructSlice {
+        type_name, fields, ..
+    } = &query.annotation.arguments
+    {
+        if is_new(*type_name) {
+            write_struct_definition(out, options, Ownership::BorrowNamed, type_name, fields)?;
+        }
+    }
+    match query.annotation.result_type.get() {
+        Some(ComplexType::Struct(name, fields, false)) if is_new(*name) => {
+            write_struct_definition(out, options, Ownership::Owned, name, fields)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn write_function_signature(
+    out: &mut dyn io::Write,
+    options: &Options,
+    query: &Query<&str>,
+) -> io::Result<()> {
+    let pool = match options.dialect {
+        Dialect::Postgres => "sqlx::PgPool",
+        Dialect::Generic | Dialect::Sqlite => "sqlx::SqlitePool",
+    };
+    let ann = &query.annotation;
+    write!(
+        out,
+        "{}async fn {}(pool: &{}",
+        options.visibility.prefix(),
+        ann.name,
+        pool
+    )?;
+    match &ann.arguments {
+        ArgType::Args(args) => {
+            for arg in args {
+                write!(out, ", {}: ", arg.ident)?;
+                write_simple_type(out, Ownership::Borrow, &arg.type_)?;
+            }
+        }
+        ArgType::Struct {
+            var_name,
+            type_name,
+            fields,
+        } => match has_borrowed_fields(fields) {
+            true => write!(out, ", {}: {}<'_>", var_name, type_name)?,
+            false => write!(out, ", {}: {}", var_name, type_name)?,
+        },
+        ArgType::StructSlice {
+            var_name,
+            type_name,
+            fields,
+        } => match has_borrowed_fields(fields) {
+            true => write!(out, ", {}: &[{}<'_>]", var_name, type_name)?,
+            false => write!(out, ", {}: &[{}]", var_name, type_name)?,
+        },
+    }
+    write!(out, ") -> Result<")?;
+    match &ann.result_type {
+        ResultType::Unit => write!(out, "()")?,
+        ResultType::Option(t) => {
+            write!(out, "Option<")?;
+            write_row_type(out, t)?;
+            write!(out, ">")?;
+        }
+        ResultType::Single(t) => write_row_type(out, t)?,
+        ResultType::Iterator(t) => {
+            write!(out, "Vec<")?;
+            write_row_type(out, t)?;
+            write!(out, ">")?;
+        }
+    }
+    writeln!(out, ", sqlx::Error> {{")
+}
+
+/// Write the SQL of the statement as a string literal, with placeholders.
+///
+/// Returns the parameters to bind, in order. SQLite uses `?` placeholders, so
+/// a parameter that occurs multiple times is bound multiple times. PostgreSQL
+/// uses numbered `$N` placeholders, so every parameter is bound only once.
+fn write_sql<'a>(
+    out: &mut dyn io::Write,
+    dialect: Dialect,
+    statement: &Statement<&'a str>,
+) -> io::Result<Vec<&'a str>> {
+    let mut sql = String::new();
+    let mut params: Vec<&str> = Vec::new();
+    for fragment in &statement.fragments {
+        let param = match fragment {
+            Fragment::Verbatim(s) | Fragment::Comment(s) => {
+                sql.push_str(s);
+                continue;
+            }
+            // When we put the SQL in the source code, omit the type
+            // annotations, it's only a distraction. An alias in the annotation
+            // does matter, it becomes the column name.
+            Fragment::TypedIdent(_full, ti) => {
+                sql.push_str(ti.ident);
+                if let Some(alias) = ti.alias {
+                    sql.push_str(" as ");
+                    sql.push_str(alias);
+                }
+                continue;
+            }
+            Fragment::Param(s) => &s[1..],
+            Fragment::TypedParam(_full, ti) => &ti.ident[1..],
+        };
+        match dialect {
+            Dialect::Postgres => {
+                let n = match params.iter().position(|p| *p == param) {
+                    Some(i) => i + 1,
+                    None => {
+                        params.push(param);
+                        params.len()
+                    }
+                };
+                sql.push_str(&format!("${}", n));
+            }
+            Dialect::Generic | Dialect::Sqlite => {
+                params.push(param);
+                sql.push('?');
+            }
+        }
+    }
+    writeln!(
+        out,
+        "    let sql = r#\"\n        {}\n        \"#;",
+        sql.replace('\n', "\n        ")
+    )?;
+    Ok(params)
+}
+
+/// Write the `sqlx` call that executes the statement, up to the executor.
+///
+/// The caller writes the indent before, and the executor and `.await` after
+/// this. The indent applies to the continuation lines.
+fn write_execute(
+    out: &mut dyn io::Write,
+    indent: &str,
+    result_type: &ResultType<&str>,
+    prefix: &str,
+    params: &[&str],
+) -> io::Result<()> {
+    match result_type.get() {
+        None => write!(out, "sqlx::query(sql)")?,
+        Some(ComplexType::Simple(t)) => {
+            write!(out, "sqlx::query_scalar::<_, ")?;
+            write_simple_type(out, Ownership::Owned, t)?;
+            write!(out, ">(sql)")?;
+        }
+        Some(t) => {
+            write!(out, "sqlx::query_as::<_, ")?;
+            write_row_type(out, t)?;
+            write!(out, ">(sql)")?;
+        }
+    }
+    for param in params {
+        write!(out, "\n{}    .bind({}{})", indent, prefix, param)?;
+    }
+    let method = match result_type {
+        ResultType::Unit => "execute",
+        ResultType::Option(..) => "fetch_optional",
+        ResultType::Single(..) => "fetch_one",
+        ResultType::Iterator(..) => "fetch_all",
+    };
+    write!(out, "\n{}    .{}(", indent, method)
+}
+
+fn write_query<'a>(
+    out: &mut dyn io::Write,
+    options: &Options,
+    defined_structs: &mut HashSet<&'a str>,
+    query: &Query<&'a str>,
+) -> io::Result<()> {
+    write_struct_definitions(out, options, defined_structs, query)?;
+
+    writeln!(out)?;
+    for doc_line in &query.docs {
+        writeln!(out, "///{}", doc_line)?;
+    }
+    write_function_signature(out, options, query)?;
+
+    // An `@dialect` line overrides the placeholder syntax for this query. The
+    // pool type is shared by all queries, so it follows `--dialect`.
+    let dialect = query.dialect.unwrap_or(options.dialect);
+
+    // For struct arguments, the parameters are fields of the struct.
+    let prefix = match &query.annotation.arguments {
+        ArgType::Struct { var_name, .. } => format!("{}.", var_name),
+        ArgType::StructSlice { .. } => "item.".to_string(),
+        ArgType::Args(..) => String::new(),
+    };
+
+    // For a slice argument, we execute every statement once per element, in
+    // a transaction, so a failure does not leave the batch half inserted.
+    if let ArgType::StructSlice { var_name, .. } = &query.annotation.arguments {
+        writeln!(out, "    let mut tx = pool.begin().await?;")?;
+        for statement in &query.statements {
+            let params = write_sql(out, dialect, statement)?;
+            writeln!(out, "    for item in {} {{", var_name)?;
+            write!(out, "        ")?;
+            write_execute(out, "        ", &ResultType::Unit, &prefix, &params)?;
+            writeln!(out, "&mut *tx)\n            .await?;")?;
+            writeln!(out, "    }}")?;
+        }
+        writeln!(out, "    tx.commit().await?;")?;
+        writeln!(out, "    Ok(())")?;
+        return writeln!(out, "}}");
+    }
+
+    // A query with a single statement runs directly on the pool. For multiple
+    // statements we start a transaction, so they run on the same connection.
+    if let [statement] = &query.statements[..] {
+        let params = write_sql(out, dialect, statement)?;
+        write!(out, "    ")?;
+        write_execute(out, "    ", &query.annotation.result_type, &prefix, &params)?;
+        match query.annotation.result_type {
+            ResultType::Unit => {
+                writeln!(out, "pool)\n        .await?;")?;
+                writeln!(out, "    Ok(())")?;
+            }
+            _ => writeln!(out, "pool)\n        .await")?,
+        }
+        return writeln!(out, "}}");
+    }
+
+    writeln!(out, "    let mut tx = pool.begin().await?;")?;
+    for (i, statement) in query.statements.iter().enumerate() {
+        let is_last = i + 1 == query.statements.len();
+        let params = write_sql(out, dialect, statement)?;
+        // All but the last statement return nothing, only the final statement
+        // has the result type of the query.
+        let unit = ResultType::Unit;
+        let result_type = match is_last {
+            true => &query.annotation.result_type,
+            false => &unit,
+        };
+        match result_type {
+            ResultType::Unit => write!(out, "    ")?,
+            _ => write!(out, "    let result = ")?,
+        }
+        write_execute(out, "    ", result_type, &prefix, &params)?;
+        writeln!(out, "&mut *tx)\n        .await?;")?;
+    }
+    writeln!(out, "    tx.commit().await?;")?;
+    match query.annotation.result_type {
+        ResultType::Unit => writeln!(out, "    Ok(())")?,
+        _ => writeln!(out, "    Ok(result)")?,
+    }
+    writeln!(out, "}}")
+}
+
+pub fn process_documents(
+    out: &mut dyn io::Write,
+    options: &Options,
+    documents: &[NamedDocument],
+) -> io::Result<()> {
+    use crate::version::{REV, VERSION};
+    if options.version_header {
+        write!(out, "// This file was generated by Squiller {}", VERSION)?;
+        match REV {
+            Some(rev) => writeln!(out, " (commit {}).", &rev[..10])?,
+            None => writeln!(out, " (unspecified checkout).")?,
+        }
+        writeln!(out, "// Input files:")?;
+        for doc in documents.iter().filter(|doc| doc.document.has_queries()) {
+            writeln!(out, "// - {}", doc.fname.to_string_lossy())?;
+        }
+    }
+
+    let mut defined_structs = HashSet::new();
+    for named_document in documents {
+        let input = named_document.input;
+        for query in named_document.document.iter_queries() {
+            write_query(out, options, &mut defined_structs, &query.resolve(input))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::process_documents;
+    use crate::ast::Dialect;
+    use crate::target::Options;
+    use crate::NamedDocument;
+    use std::path::Path;
+
+    fn generate(options: &Options, input: &str) -> String {
+        let doc = NamedDocument::process_input(Path::new("x.sql"), input.as_bytes()).unwrap();
+        let mut out = Vec::new();
+        process_documents(&mut out, options, &[doc]).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn dedup_structs_defines_identical_structs_once() {
+        let input = "\
+-- @query get_user(id: i64) ->1 User
+select name /* :str */, email /* :str */ from users where id = :id;
+-- @query iter_users() ->* User
+select name /* :str */, email /* :str */ from users;";
+
+        let output = generate(&Options::default(), input);
+        assert_eq!(output.matches("pub struct User {").count(), 2);
+
+        let options = Options {
+            dedup_structs: true,
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+        assert_eq!(output.matches("pub struct User {").count(), 1);
+        assert!(output.contains("-> Result<Vec<User>, sqlx::Error>"));
+    }
+
+    #[test]
+    fn postgres_binds_repeated_parameters_once() {
+        let input = "-- @query find(name: str) ->* i64\nselect id from users where name = :name or email = :name;";
+        let output = generate(&Options::default(), input);
+        assert!(output.contains("pub async fn find(pool: &sqlx::SqlitePool, name: &str)"));
+        assert!(output.contains("where name = ? or email = ?;"));
+        assert!(
+            output.contains("        .bind(name)\n        .bind(name)\n        .fetch_all(pool)")
+        );
+
+        let options = Options {
+            dialect: Dialect::Postgres,
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+        assert!(output.contains("pub async fn find(pool: &sqlx::PgPool, name: &str)"));
+        assert!(output.contains("where name = $1 or email = $1;"));
+        assert!(output.contains("        .bind(name)\n        .fetch_all(pool)"));
+    }
+
+    #[test]
+    fn dialect_marker_overrides_placeholders_for_query() {
+        let input = "\
+-- @query find_sqlite(name: str) ->* i64
+select id from users where name = :name;
+
+-- @dialect postgres
+-- @query find_postgres(name: str, email: str) ->* i64
+select id from users where name = :name or email = :email;";
+        let options = Options {
+            dialect: Dialect::Sqlite,
+            ..Options::default()
+        };
+        let output = generate(&options, input);
+        assert!(output.contains("pub async fn find_sqlite(pool: &sqlx::SqlitePool, name: &str)"));
+        assert!(output.contains("select id from users where name = ?;"));
+        assert!(output.contains("where name = $1 or email = $2;"));
+    }
+}
@@ -0,0 +1,268 @@
+// Squiller -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2023 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! A target that prints the plain SQL of every query.
+//!
+//! This strips the type annotations, so the output can be fed to tools that
+//! do not understand them. Every query is preceded by a `-- name` comment.
+//! With `--strip-comments` and `--minify-sql`, the SQL is cleaned up in the
+//! same way as in the `rust-sqlite` target, which uses the helpers below.
+
+use std::io;
+
+use crate::ast::{Fragment, Query, Statement};
+use crate::target::Options;
+use crate::{NamedDocument, Span};
+
+/// Collapse every run of whitespace in the SQL into a single space.
+///
+/// Whitespace inside string literals and quoted identifiers is preserved. A
+/// run of whitespace that ends a line comment becomes a newline, so the
+/// comment does not swallow the rest of the query.
+pub fn minify_sql(sql: &str) -> String {
+    let mut result = String::with_capacity(sql.len());
+    let mut chars = sql.trim().chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\'' | '"' => {
+                // Quotes inside the literal are escaped by doubling them, so
+                // the literal ends at the next quote, and a doubled quote
+                // starts a new literal that we copy in the same way.
+                result.push(ch);
+                for inner in chars.by_ref() {
+                    result.push(inner);
+                    if inner == ch {
+                        break;
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                result.push(ch);
+                while let Some(inner) = chars.peek() {
+                    if *inner == '\n' {
+                        break;
+                    }
+                    result.push(*inner);
+                    chars.next();
+                }
+                while chars.peek().map_or(false, |c| c.is_whitespace()) {
+                    chars.next();
+                }
+                result.push('\n');
+            }
+            _ if ch.is_whitespace() => {
+                while chars.peek().map_or(false, |c| c.is_whitespace()) {
+                    chars.next();
+                }
+                result.push(' ');
+            }
+            _ => result.push(ch),
+        }
+    }
+    result
+}
+
+/// Remove `--` and `/* */` comments from the SQL.
+///
+/// Comment markers inside string literals and quoted identifiers are not
+/// comments. When a line contains nothing but a comment, we remove the line.
+pub fn strip_comments(sql: &str) -> String {
+    let mut result = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\'' | '"' => {
+                result.push(ch);
+                for inner in chars.by_ref() {
+                    result.push(inner);
+                    if inner == ch {
+                        break;
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                while chars.peek().map_or(false, |c| *c != '\n') {
+                    chars.next();
+                }
+                let line_len = result.trim_end_matches(|c| c == ' ' || c == '\t').len();
+                result.truncate(line_len);
+                // If the comment was the only thing on the line, also drop
+                // the newline, so we don't leave an empty line behind.
+                if result.is_empty() || result.ends_with('\n') {
+                    chars.next();
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = ' ';
+                for inner in chars.by_ref() {
+                    if prev == '*' && inner == '/' {
+                        break;
+                    }
+                    prev = inner;
+                }
+                let is_blank = |c: &char| *c == ' ' || *c == '\t';
+                if result.is_empty() || result.ends_with(char::is_whitespace) {
+                    while chars.peek().map_or(false, is_blank) {
+                        chars.next();
+                    }
+                }
+                match chars.peek() {
+                    None | Some('\n') => {
+                        let line_len = result.trim_end_matches(|c| is_blank(&c)).len();
+                        result.truncate(line_len);
+                        if result.is_empty() || result.ends_with('\n') {
+                            chars.next();
+                        }
+                    }
+                    // Do not glue together the tokens around the comment.
+                    Some(c) if !c.is_whitespace() && !result.ends_with(char::is_whitespace) => {
+                        result.push(' ')
+                    }
+                    _ => {}
+                }
+            }
+            _ => result.push(ch),
+        }
+    }
+    result
+}
+
+fn write_statement(
+    out: &mut dyn io::Write,
+    options: &Options,
+    input: &str,
+    statement: &Statement<Span>,
+) -> io::Result<()> {
+    let mut sql = String::new();
+    for fragment in &statement.fragments {
+        let span = match fragment {
+            Fragment::Verbatim(span) => span,
+            Fragment::Comment(span) => span,
+            Fragment::Param(span) => span,
+            // Like in the code generating targets, drop the type annotation,
+            // but keep the alias, it determines the column name.
+            Fragment::TypedIdent(_full_span, ti) => {
+                sql.push_str(ti.ident.resolve(input));
+                if let Some(alias) = ti.alias {
+                    sql.push_str(" as ");
+                    sql.push_str(alias.resolve(input));
+                }
+                continue;
+            }
+            Fragment::TypedParam(_full_span, ti) => &ti.ident,
+        };
+        sql.push_str(span.resolve(input));
+    }
+    if options.strip_comments {
+        sql = strip_comments(&sql);
+    }
+    if options.minify_sql {
+        sql = minify_sql(&sql);
+    }
+    writeln!(out, "{}", sql)
+}
+
+fn write_query(
+    out: &mut dyn io::Write,
+    options: &Options,
+    input: &str,
+    query: &Query<Span>,
+) -> io::Result<()> {
+    write!(out, "-- ")?;
+    for segment in &query.annotation.path {
+        write!(out, "{}::", segment.resolve(input))?;
+    }
+    writeln!(out, "{}", query.annotation.name.resolve(input))?;
+    for statement in &query.statements {
+        write_statement(out, options, input, statement)?;
+    }
+    Ok(())
+}
+
+pub fn process_documents(
+    out: &mut dyn io::Write,
+    options: &Options,
+    documents: &[NamedDocument],
+) -> io::Result<()> {
+    let mut is_first = true;
+    for named_document in documents {
+        let input = named_document.input;
+        for query in named_document.document.iter_queries() {
+            if !is_first {
+                writeln!(out)?;
+            }
+            write_query(out, options, input, query)?;
+            is_first = false;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::process_documents;
+    use crate::target::Options;
+    use crate::NamedDocument;
+    use std::path::Path;
+
+    fn generate(options: &Options, input: &str) -> String {
+        let document = NamedDocument::process_input(Path::new("test.sql"), input.as_bytes())
+            .expect("Test input should be valid.");
+        let mut out = Vec::new();
+        process_documents(&mut out, options, &[document]).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn type_annotations_are_stripped() {
+        let input = "\
+-- Look up a user by id.
+-- @query get_user(id: i64) ->1 User
+select name /* :str */, email /* :mail: str? */
+from users where id = :id;
+
+-- @begin rename_user(user: Rename)
+update users set name = :name /* :str */ where id = :id /* :i64 */;
+-- Also record the change.
+insert into renames (user_id) values (:id /* :i64 */);
+-- @end rename_user
+";
+        let expected = "\
+-- get_user
+select name, email as mail
+from users where id = :id;
+
+-- rename_user
+update users set name = :name where id = :id;
+-- Also record the change.
+insert into renames (user_id) values (:id);
+";
+        assert_eq!(generate(&Options::default(), input), expected);
+    }
+
+    #[test]
+    fn comments_and_whitespace_can_be_removed() {
+        let input = "\
+-- @query get_user(id: i64) ->1 User
+select
+  name /* :str */, -- The full name.
+  email /* :str? */
+from users where id = :id and note = 'a  -- b';
+";
+        let options = Options {
+            strip_comments: true,
+            minify_sql: true,
+            ..Options::default()
+        };
+        let expected = "\
+-- get_user
+select name, email from users where id = :id and note = 'a  -- b';
+";
+        assert_eq!(generate(&options, input), expected);
+    }
+}
@@ -17,16 +17,21 @@ use std::collections::hash_map::{Entry, HashMap};
 use std::collections::hash_set::HashSet;
 
 use crate::ast::{
-    Annotation, ArgType, ComplexType, Document, Fragment, Query, Section, Statement, TypedIdent,
+    Annotation, ArgType, ComplexType, Document, Fragment, PrimitiveType, Query, ResultType,
+    Section, SimpleType, Statement, TupleElement, TypedIdent,
 };
-use crate::error::{TResult, TypeError};
-use crate::Span;
+use crate::error::{TResult, TypeError, Warning};
+use crate::schema::Schema;
+use crate::{is_ascii_identifier, Span};
 
 /// Holds the state across various stages of checking a query.
 struct QueryChecker<'a> {
     /// Input file that the spans reference.
     input: &'a str,
 
+    /// Column types to resolve untyped parameters with.
+    schema: &'a Schema,
+
     /// All the parameters specified in the annotation.
     query_args: HashMap<&'a str, TypedIdent<Span>>,
 
@@ -51,9 +56,10 @@ struct QueryChecker<'a> {
 }
 
 impl<'a> QueryChecker<'a> {
-    fn new(input: &'a str) -> Self {
+    fn new(input: &'a str, schema: &'a Schema) -> Self {
         Self {
             input,
+            schema,
             query_args: HashMap::new(),
             query_args_used: HashSet::new(),
             input_fields: HashMap::new(),
@@ -68,18 +74,34 @@ impl<'a> QueryChecker<'a> {
     /// We ensure that every query parameter that occurs in the query is known
     /// (either because the query argument is a struct, or because the parameter
     /// was listed explicitly). We also fill the fields of structs.
-    pub fn check_and_resolve<'b: 'a>(input: &'b str, query: Query<Span>) -> TResult<Query<Span>> {
+    pub fn check_and_resolve<'b: 'a>(
+        input: &'b str,
+        schema: &'b Schema,
+        query: Query<Span>,
+    ) -> TResult<Query<Span>> {
         let mut annotation = query.annotation;
+        let mut statements = query.statements;
 
-        let mut checker = Self::new(input);
+        let mut checker = Self::new(input, schema);
         checker.populate_query_args(&annotation)?;
-        checker.populate_inputs_outputs(&query.statements)?;
+
+        // Bare parameters of a query with a struct argument become fields of
+        // the struct, if we can learn their type from the schema.
+        if let ArgType::Struct { .. } | ArgType::StructSlice { .. } = annotation.arguments {
+            for statement in statements.iter_mut() {
+                checker.resolve_schema_types(statement)?;
+            }
+        }
+
+        checker.populate_inputs_outputs(&statements)?;
+        checker.check_multi_statement_result(&annotation, &statements)?;
 
         checker.fill_input_struct(&mut annotation)?;
-        checker.fill_output_struct(&mut annotation)?;
+        checker.fill_output_struct(&mut annotation, &statements)?;
 
         let query = Query {
             annotation: annotation,
+            statements: statements,
             ..query
         };
 
@@ -91,7 +113,7 @@ impl<'a> QueryChecker<'a> {
         // Populate the query args map with the args those provided in the
         // annotation, and at the same time ensure there are no duplicates.
         let args = match &annotation.arguments {
-            ArgType::Struct { .. } => return Ok(()),
+            ArgType::Struct { .. } | ArgType::StructSlice { .. } => return Ok(()),
             ArgType::Args(args) => args,
         };
 
@@ -114,6 +136,71 @@ impl<'a> QueryChecker<'a> {
         Ok(())
     }
 
+    /// Give bare parameters the type of the column that they are compared to.
+    ///
+    /// A parameter `:col` gets the type of column `col` in table `t` from the
+    /// schema, if the statement references `t.col`. If the statement references
+    /// columns of that name with different types, the type is ambiguous.
+    fn resolve_schema_types(&self, statement: &mut Statement<Span>) -> TResult<()> {
+        let columns = qualified_columns(self.input, statement);
+
+        for fragment in statement.fragments.iter_mut() {
+            let span = match fragment {
+                Fragment::Param(span) => *span,
+                _ => continue,
+            };
+            let name = span.trim_start(1).resolve(self.input);
+
+            let mut resolved: Option<(Span, PrimitiveType)> = None;
+            for &(table, column) in &columns {
+                if column.resolve(self.input) != name {
+                    continue;
+                }
+                let type_ = match self.schema.get(table, name) {
+                    Some(t) => t,
+                    None => continue,
+                };
+                match resolved {
+                    None => resolved = Some((column, type_)),
+                    Some((prev_column, prev_type)) if prev_type != type_ => {
+                        let error = TypeError {
+                            span,
+                            message: "Cannot infer parameter type, \
+                            the schema defines columns of different types.",
+                            note: Some((
+                                "This column has a different type than an earlier one.".to_string(),
+                                column,
+                            )),
+                            hint: Some(format!(
+                                "The first column is '{}'. Add a type annotation to the parameter.",
+                                prev_column.resolve(self.input),
+                            )),
+                        };
+                        return Err(error);
+                    }
+                    Some(..) => {}
+                }
+            }
+
+            if let Some((column, type_)) = resolved {
+                // There is no type annotation in the source, so the closest
+                // place for the type to point at is the column it came from.
+                let ti = TypedIdent {
+                    ident: span,
+                    type_: SimpleType::Primitive {
+                        inner: column,
+                        type_,
+                    },
+                    doc: None,
+                    alias: None,
+                };
+                *fragment = Fragment::TypedParam(span, ti);
+            }
+        }
+
+        Ok(())
+    }
+
     /// Handle fragments of the query body, populate inputs and outputs.
     fn populate_inputs_outputs(&mut self, statements: &[Statement<Span>]) -> TResult<()> {
         for (i, statement) in statements.iter().enumerate() {
@@ -141,25 +228,68 @@ impl<'a> QueryChecker<'a> {
         Ok(())
     }
 
+    /// Ensure that a query with multiple statements does not return anything.
+    ///
+    /// The targets execute every statement in turn, but they do not yet
+    /// support decoding the result from the final one.
+    fn check_multi_statement_result(
+        &self,
+        annotation: &Annotation<Span>,
+        statements: &[Statement<Span>],
+    ) -> TResult<()> {
+        match annotation.result_type.get() {
+            Some(type_) if statements.len() > 1 => {
+                let error = TypeError {
+                    span: type_.span(),
+                    message: "A query with multiple statements cannot return a result.",
+                    note: Some((
+                        "The query has more than one statement, the second one starts here."
+                            .to_string(),
+                        statements[1].span(),
+                    )),
+                    hint: Some(
+                        "Move the statement that returns the result into a separate query, \
+                        and call both in the same transaction."
+                            .to_string(),
+                    ),
+                };
+                Err(error)
+            }
+            _ => Ok(()),
+        }
+    }
+
     /// Handle a single fragment of the query body, populate inputs and outputs.
     fn populate_input_output(&mut self, fragment: &Fragment<Span>) -> TResult<()> {
         match fragment {
-            Fragment::Verbatim(..) => return Ok(()),
+            Fragment::Verbatim(..) | Fragment::Comment(..) => return Ok(()),
             Fragment::TypedIdent(_span, ti) => {
-                // A typed identifier is an output that the query selects.
-                let name = ti.ident.resolve(self.input);
+                // A typed identifier is an output that the query selects. When
+                // the annotation has an alias, the output is named after that.
+                let output = TypedIdent {
+                    ident: ti.alias.unwrap_or(ti.ident),
+                    type_: ti.type_.clone(),
+                    doc: None,
+                    alias: None,
+                };
+                let name = output.ident.resolve(self.input);
                 match self.output_fields.entry(name) {
                     Entry::Vacant(vacancy) => {
-                        vacancy.insert(ti.clone());
-                        self.output_fields_vec.push(ti.clone());
+                        vacancy.insert(output.clone());
+                        self.output_fields_vec.push(output);
                     }
                     Entry::Occupied(previous) => {
-                        let error = TypeError::with_note(
-                            ti.ident,
-                            "Redefinition of query output.",
-                            previous.get().ident,
-                            "First defined here.",
-                        );
+                        let error = TypeError {
+                            span: output.ident,
+                            message: "Redefinition of query output.",
+                            note: Some(("First defined here.".to_string(), previous.get().ident)),
+                            hint: Some(format!(
+                                "Name the output differently with an alias in the annotation, \
+                                as in '/* :other_{}: {} */'.",
+                                name,
+                                ti.type_.span().resolve(self.input),
+                            )),
+                        };
                         return Err(error);
                     }
                 }
@@ -244,7 +374,7 @@ impl<'a> QueryChecker<'a> {
         // struct.
         if self.input_fields_vec.len() == 0 {
             match &annotation.arguments {
-                ArgType::Struct { type_name, .. } => {
+                ArgType::Struct { type_name, .. } | ArgType::StructSlice { type_name, .. } => {
                     let error = TypeError::with_hint(
                         *type_name,
                         "Annotation contains a struct argument, \
@@ -273,7 +403,7 @@ impl<'a> QueryChecker<'a> {
                 );
                 return Err(error);
             }
-            ArgType::Struct { fields, .. } => fields,
+            ArgType::Struct { fields, .. } | ArgType::StructSlice { fields, .. } => fields,
         };
 
         // Originally, all the typed idents for the parameter include the colon,
@@ -290,20 +420,34 @@ impl<'a> QueryChecker<'a> {
     ///
     /// This moves the fields out of `self.output_fields_vec`, which becomes
     /// empty.
-    fn fill_output_struct(&mut self, annotation: &mut Annotation<Span>) -> TResult<()> {
+    fn fill_output_struct(
+        &mut self,
+        annotation: &mut Annotation<Span>,
+        statements: &[Statement<Span>],
+    ) -> TResult<()> {
         // Before we put the fields in, check if we have any. If not, but there
         // is a struct result type, that's an error, because we would make an
         // empty struct.
         if self.output_fields_vec.len() == 0 {
             match annotation.result_type.get() {
-                Some(ComplexType::Struct(name_span, _fields)) => {
-                    let error = TypeError::with_hint(
-                        *name_span,
-                        "The annotation specifies a struct as result type, \
+                Some(ComplexType::Struct(name_span, ..)) => {
+                    // The outputs would come from the final statement, so we
+                    // point there for the place where they are missing.
+                    let statement = statements.last().expect("Queries have a statement.");
+                    let error = TypeError {
+                        span: *name_span,
+                        message: "The annotation specifies a struct as result type, \
                         but the query body contains no annotated outputs.",
-                        "Add a SELECT or RETURNING clause with type annotations \
-                        to the query, to turn them into fields of the struct.",
-                    );
+                        note: Some((
+                            "Expected annotated outputs in this statement.".to_string(),
+                            statement.span(),
+                        )),
+                        hint: Some(
+                            "Add a SELECT or RETURNING clause with type annotations \
+                            to the query, to turn them into fields of the struct."
+                                .to_string(),
+                        ),
+                    };
                     return Err(error);
                 }
                 _ => return Ok(()),
@@ -313,7 +457,10 @@ impl<'a> QueryChecker<'a> {
         // Conversely, if there are outputs, but no struct, then we have nowhere
         // to put them.
         let fields = match annotation.result_type.get_mut() {
-            Some(ComplexType::Struct(_name_span, fields)) => fields,
+            Some(ComplexType::Struct(_name_span, fields, _)) => fields,
+            Some(ComplexType::Tuple(tuple_span, elements)) => {
+                return self.check_tuple_outputs(*tuple_span, elements);
+            }
             _not_struct => {
                 // Does not go out of bounds, if it was empty we returned already.
                 let ti = &self.output_fields_vec[0];
@@ -333,17 +480,261 @@ impl<'a> QueryChecker<'a> {
 
         Ok(())
     }
+
+    /// Check that the annotated outputs match the elements of a tuple result.
+    ///
+    /// Annotated outputs are optional for tuples, when a query has none, as in
+    /// a bare `select a, b`, we have nothing to check. When it does, there must
+    /// be one per tuple element, with the same type.
+    fn check_tuple_outputs(
+        &mut self,
+        tuple_span: Span,
+        elements: &[TupleElement<Span>],
+    ) -> TResult<()> {
+        let outputs = &self.output_fields_vec;
+
+        if outputs.len() != elements.len() {
+            let (note_span, note) = match outputs.get(elements.len()) {
+                Some(extra) => (
+                    extra.ident,
+                    "This output has no corresponding tuple element.".to_string(),
+                ),
+                None => (
+                    // Does not go out of bounds, the caller only calls this
+                    // when there are outputs.
+                    outputs[outputs.len() - 1].ident,
+                    format!(
+                        "The query has only {} annotated outputs, the last one is here.",
+                        outputs.len()
+                    ),
+                ),
+            };
+            let error = TypeError {
+                span: tuple_span,
+                message: "The number of annotated outputs does not match the tuple result type.",
+                note: Some((note, note_span)),
+                hint: Some(format!(
+                    "The tuple has {} elements, but the query has {} annotated outputs. \
+                    Annotate one output per element, or remove the annotations.",
+                    elements.len(),
+                    outputs.len(),
+                )),
+            };
+            return Err(error);
+        }
+
+        for (output, element) in outputs.iter().zip(elements) {
+            let output_type = output.type_.resolve(self.input);
+            let element_type = element.type_.resolve(self.input);
+            if !output_type.is_equal_to(&element_type) {
+                let error = TypeError::with_note(
+                    output.type_.span(),
+                    "Output type differs from the tuple element type.",
+                    element.type_.span(),
+                    "The tuple element is defined here.",
+                );
+                return Err(error);
+            }
+        }
+
+        self.output_fields_vec.clear();
+        Ok(())
+    }
+}
+
+/// Find all `table.column` references in the statement.
+///
+/// Returns the table name and the span of the column name.
+fn qualified_columns<'a>(input: &'a str, statement: &Statement<Span>) -> Vec<(&'a str, Span)> {
+    let bytes = input.as_bytes();
+    let mut result = Vec::new();
+
+    for fragment in &statement.fragments {
+        let span = match fragment {
+            Fragment::Verbatim(span) => *span,
+            _ => continue,
+        };
+        for i in span.start..span.end {
+            if bytes[i] != b'.' {
+                continue;
+            }
+            let mut start = i;
+            while start > span.start && is_ascii_identifier(bytes[start - 1]) {
+                start -= 1;
+            }
+            let mut end = i + 1;
+            while end < span.end && is_ascii_identifier(bytes[end]) {
+                end += 1;
+            }
+            if start < i && end > i + 1 {
+                result.push((&input[start..i], Span { start: i + 1, end }));
+            }
+        }
+    }
+
+    result
+}
+
+/// Find a top-level `returning` or `select` that makes the statement return rows.
+///
+/// This is a heuristic on the verbatim parts of the statement. It skips string
+/// literals, quoted identifiers, and everything inside parentheses, so
+/// subqueries do not count. A `select` after `insert` or `create`, as in
+/// `insert into t select ...`, produces the rows to insert, so it does not
+/// count either.
+fn find_returned_rows(input: &str, statement: &Statement<Span>) -> Option<Span> {
+    let bytes = input.as_bytes();
+    let mut depth = 0_u32;
+    let mut quote = None;
+    let mut is_write = false;
+
+    for fragment in &statement.fragments {
+        let span = match fragment {
+            Fragment::Verbatim(span) => *span,
+            _ => continue,
+        };
+        let mut i = span.start;
+        while i < span.end {
+            let ch = bytes[i];
+            if let Some(q) = quote {
+                if ch == q {
+                    quote = None;
+                }
+                i += 1;
+                continue;
+            }
+            match ch {
+                b'\'' | b'"' | b'`' => quote = Some(ch),
+                b'(' => depth += 1,
+                b')' => depth = depth.saturating_sub(1),
+                _ if is_ascii_identifier(ch) => {
+                    let start = i;
+                    while i < span.end && is_ascii_identifier(bytes[i]) {
+                        i += 1;
+                    }
+                    let word = &input[start..i];
+                    let word_span = Span { start, end: i };
+                    if depth > 0 {
+                        continue;
+                    }
+                    if word.eq_ignore_ascii_case("returning") {
+                        return Some(word_span);
+                    }
+                    if word.eq_ignore_ascii_case("select") && !is_write {
+                        return Some(word_span);
+                    }
+                    if word.eq_ignore_ascii_case("insert") || word.eq_ignore_ascii_case("create") {
+                        is_write = true;
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    None
+}
+
+/// Warn about statements that return rows, in a query without result type.
+///
+/// The generated code expects such statements to return no rows, so it would
+/// fail at runtime. Because we only look at the SQL loosely, this is a warning
+/// rather than an error.
+fn check_unit_result(input: &str, query: &Query<Span>, warnings: &mut Vec<Warning>) {
+    if !matches!(query.annotation.result_type, ResultType::Unit) {
+        return;
+    }
+    for statement in &query.statements {
+        if let Some(span) = find_returned_rows(input, statement) {
+            warnings.push(Warning {
+                span,
+                message: "This statement returns rows, but the query has no result type.",
+                hint: Some(
+                    "Add a result type, for example '->1 i64' for a single row, \
+                    '->? i64' for an optional row, or '->* i64' for any number of rows.",
+                ),
+            });
+        }
+    }
+}
+
+/// Names that are keywords in SQL, or in one of the target languages.
+///
+/// The keywords differ per target, so this is a conservative set of common
+/// ones, rather than the union of all of them. Sorted, for binary search.
+const KEYWORDS: &[&str] = &[
+    "and", "as", "async", "await", "by", "case", "class", "def", "default", "delete", "else",
+    "enum", "false", "fn", "for", "from", "group", "if", "impl", "import", "in", "insert", "into",
+    "is", "join", "lambda", "let", "match", "mod", "none", "not", "null", "or", "order", "pass",
+    "ref", "return", "select", "self", "struct", "table", "true", "type", "union", "update", "use",
+    "values", "where", "while", "with", "yield",
+];
+
+fn is_keyword(name: &str) -> bool {
+    KEYWORDS
+        .binary_search(&&name.to_ascii_lowercase()[..])
+        .is_ok()
+}
+
+/// Warn about parameters and output fields that are named after a keyword.
+///
+/// These names end up as function arguments and struct fields in the generated
+/// code, where a keyword is at best confusing, and at worst does not compile.
+fn check_keyword_names(input: &str, query: &Query<Span>, warnings: &mut Vec<Warning>) {
+    let params: &[TypedIdent<Span>] = match &query.annotation.arguments {
+        ArgType::Args(args) => args,
+        ArgType::Struct { fields, .. } | ArgType::StructSlice { fields, .. } => fields,
+    };
+    for param in params {
+        if is_keyword(param.ident.resolve(input)) {
+            warnings.push(Warning {
+                span: param.ident,
+                message: "This parameter is named after a keyword.",
+                hint: Some(
+                    "Use a different name for the parameter, \
+                    to avoid confusing names in the generated code.",
+                ),
+            });
+        }
+    }
+
+    if let Some(ComplexType::Struct(_, fields, _)) = query.annotation.result_type.get() {
+        for field in fields {
+            if is_keyword(field.ident.resolve(input)) {
+                warnings.push(Warning {
+                    span: field.ident,
+                    message: "This output is named after a keyword.",
+                    hint: Some(
+                        "Name the output differently with an alias in the annotation, \
+                        e.g. 'order /* :sort_order: i64 */'.",
+                    ),
+                });
+            }
+        }
+    }
 }
 
 /// Apply `check_and_resolve` to every query in the document.
-pub fn check_document(input: &str, doc: Document<Span>) -> TResult<Document<Span>> {
+///
+/// Problems that do not prevent generating code are added to `warnings`.
+pub fn check_document(
+    input: &str,
+    schema: &Schema,
+    doc: Document<Span>,
+    warnings: &mut Vec<Warning>,
+) -> TResult<Document<Span>> {
     let mut sections = Vec::with_capacity(doc.sections.len());
 
     for section in doc.sections {
         match section {
             Section::Verbatim(s) => sections.push(Section::Verbatim(s)),
             Section::Query(q) => {
-                sections.push(Section::Query(QueryChecker::check_and_resolve(input, q)?))
+                let query = QueryChecker::check_and_resolve(input, schema, q)?;
+                check_unit_result(input, &query, warnings);
+                check_keyword_names(input, &query, warnings);
+                sections.push(Section::Query(query));
             }
         }
     }
@@ -355,20 +746,25 @@ pub fn check_document(input: &str, doc: Document<Span>) -> TResult<Document<Span
 
 #[cfg(test)]
 mod test {
-    use super::QueryChecker;
+    use super::{check_keyword_names, check_unit_result, QueryChecker, KEYWORDS};
     use crate::ast::{
         ArgType, ComplexType, PrimitiveType, Query, ResultType, Section, SimpleType, TypedIdent,
     };
     use crate::error::Result;
+    use crate::schema::Schema;
     use crate::Span;
 
     fn check_and_resolve_query(input: &str) -> Result<Query<Span>> {
+        check_and_resolve_query_with_schema(input, &Schema::default())
+    }
+
+    fn check_and_resolve_query_with_schema(input: &str, schema: &Schema) -> Result<Query<Span>> {
         use crate::lexer::document::Lexer;
         use crate::parser::document::Parser;
 
-        let lexer = Lexer::new(&input);
+        let lexer = Lexer::new(input);
         let tokens = lexer.run()?;
-        let mut parser = Parser::new(&input, &tokens);
+        let mut parser = Parser::new(input, &tokens);
         let mut doc = parser.parse_document()?;
 
         assert_eq!(
@@ -381,7 +777,7 @@ mod test {
             Section::Query(q) => q,
         };
 
-        Ok(QueryChecker::check_and_resolve(&input, query)?)
+        Ok(QueryChecker::check_and_resolve(input, schema, query)?)
     }
 
     #[test]
@@ -407,6 +803,8 @@ mod test {
                         inner: "i64",
                         type_: PrimitiveType::I64,
                     },
+                    doc: None,
+                    alias: None,
                 },
                 TypedIdent {
                     ident: "name",
@@ -414,12 +812,14 @@ mod test {
                         inner: "str",
                         type_: PrimitiveType::Str,
                     },
+                    doc: None,
+                    alias: None,
                 },
             ],
         };
 
         let query = check_and_resolve_query(input).unwrap();
-        assert_eq!(query.annotation.arguments.resolve(&input), expected);
+        assert_eq!(query.annotation.arguments.resolve(input), expected);
     }
 
     #[test]
@@ -436,8 +836,8 @@ mod test {
           ;";
 
         let query = check_and_resolve_query(input).unwrap();
-        match query.annotation.result_type.resolve(&input) {
-            ResultType::Single(ComplexType::Struct("User", fields)) => {
+        match query.annotation.result_type.resolve(input) {
+            ResultType::Single(ComplexType::Struct("User", fields, _)) => {
                 let expected = [
                     TypedIdent {
                         ident: "id",
@@ -445,6 +845,8 @@ mod test {
                             inner: "i64",
                             type_: PrimitiveType::I64,
                         },
+                        doc: None,
+                        alias: None,
                     },
                     TypedIdent {
                         ident: "name",
@@ -452,6 +854,8 @@ mod test {
                             inner: "str",
                             type_: PrimitiveType::Str,
                         },
+                        doc: None,
+                        alias: None,
                     },
                 ];
                 assert_eq!(&fields, &expected);
@@ -460,6 +864,114 @@ mod test {
         }
     }
 
+    #[test]
+    fn fill_output_struct_notes_statement_without_outputs() {
+        let input = "\
+          -- @query get_admin() ->1 User
+          select 1;";
+
+        let err = check_and_resolve_query(input).err().unwrap();
+        assert_eq!(err.span().resolve(input), "User");
+        let (_note, note_span) = err.note().unwrap();
+        assert_eq!(note_span.resolve(input), "select 1;");
+    }
+
+    #[test]
+    fn check_tuple_outputs_rejects_arity_mismatch() {
+        let input = "\
+          -- @query get_user(id: i64) ->1 (i64, str)
+          select id /* :i64 */, name /* :str */, email /* :str */
+          from users where id = :id;";
+
+        let err = check_and_resolve_query(input).err().unwrap();
+        assert_eq!(err.span().resolve(input), "(i64, str)");
+        assert_eq!(
+            err.message(),
+            "The number of annotated outputs does not match the tuple result type."
+        );
+        let (_note, note_span) = err.note().unwrap();
+        assert_eq!(note_span.resolve(input), "email");
+    }
+
+    #[test]
+    fn check_tuple_outputs_accepts_matching_or_absent_outputs() {
+        let input = "\
+          -- @query get_user(id: i64) ->1 (i64, str)
+          select id /* :i64 */, name /* :str */ from users where id = :id;";
+        assert!(check_and_resolve_query(input).is_ok());
+
+        // Without annotations, we cannot count the columns, so we skip the check.
+        let input = "\
+          -- @query get_user(id: i64) ->1 (i64, str)
+          select id, name, email from users where id = :id;";
+        assert!(check_and_resolve_query(input).is_ok());
+
+        let input = "\
+          -- @query get_user(id: i64) ->1 (i64, str)
+          select id /* :i64 */, karma /* :f64 */ from users where id = :id;";
+        let err = check_and_resolve_query(input).err().unwrap();
+        assert_eq!(err.span().resolve(input), "f64");
+        let (_note, note_span) = err.note().unwrap();
+        assert_eq!(note_span.resolve(input), "str");
+    }
+
+    #[test]
+    fn check_multi_statement_result_rejects_non_unit_result() {
+        let input = "\
+          -- @begin insert_user(name: str) ->1 i64
+          insert into audit_events (type) values ('create_user');
+          insert into users (name) values (:name) returning id;
+          -- @end insert_user";
+
+        let err = check_and_resolve_query(input).err().unwrap();
+        assert_eq!(err.span().resolve(input), "i64");
+        assert_eq!(
+            err.message(),
+            "A query with multiple statements cannot return a result."
+        );
+        let (_note, note_span) = err.note().unwrap();
+        assert_eq!(
+            note_span.resolve(input),
+            "insert into users (name) values (:name) returning id;"
+        );
+
+        let input = "\
+          -- @begin insert_user(name: str)
+          insert into audit_events (type) values ('create_user');
+          insert into users (name) values (:name);
+          -- @end insert_user";
+        assert!(check_and_resolve_query(input).is_ok());
+    }
+
+    #[test]
+    fn fill_output_struct_names_outputs_after_alias() {
+        let input = "\
+          -- @query iterate_edges() ->* Edge
+          select
+            a.id /* :src_id: i64 */,
+            b.id /* :dst_id: i64 */
+          from
+            nodes a join nodes b on a.id = b.parent_id
+          ;";
+
+        let query = check_and_resolve_query(input).unwrap();
+        match query.annotation.result_type.resolve(input) {
+            ResultType::Iterator(ComplexType::Struct("Edge", fields, _)) => {
+                let names: Vec<&str> = fields.iter().map(|f| f.ident).collect();
+                assert_eq!(names, ["src_id", "dst_id"]);
+                assert_eq!(fields[0].alias, None);
+            }
+            _ => panic!("Incorrect result type."),
+        }
+
+        // Without aliases, the same names are a redefinition.
+        let input = "\
+          -- @query iterate_edges() ->* Edge
+          select a.id /* :i64 */, b.id /* :i64 */ from nodes a, nodes b;";
+        let err = check_and_resolve_query(input).err().unwrap();
+        assert_eq!(err.message(), "Redefinition of query output.");
+    }
+
     #[test]
     fn fill_output_struct_populates_inner_types() {
         let input = "\
@@ -472,31 +984,195 @@ mod test {
           ;";
 
         let query = check_and_resolve_query(input).unwrap();
-        match query.annotation.result_type.resolve(&input) {
-            ResultType::Iterator(inner) => match inner {
-                ComplexType::Struct("Node", fields) => {
-                    let expected = [
-                        TypedIdent {
-                            ident: "id",
-                            type_: SimpleType::Primitive {
-                                inner: "i64",
-                                type_: PrimitiveType::I64,
-                            },
+        match query.annotation.result_type.resolve(input) {
+            ResultType::Iterator(ComplexType::Struct("Node", fields, _)) => {
+                let expected = [
+                    TypedIdent {
+                        ident: "id",
+                        type_: SimpleType::Primitive {
+                            inner: "i64",
+                            type_: PrimitiveType::I64,
                         },
-                        TypedIdent {
-                            ident: "parent_id",
-                            type_: SimpleType::Option {
-                                outer: "i64?",
-                                inner: "i64",
-                                type_: PrimitiveType::I64,
-                            },
+                        doc: None,
+                        alias: None,
+                    },
+                    TypedIdent {
+                        ident: "parent_id",
+                        type_: SimpleType::Option {
+                            outer: "i64?",
+                            inner: "i64",
+                            type_: PrimitiveType::I64,
                         },
-                    ];
-                    assert_eq!(&fields, &expected);
-                }
-                _ => panic!("Incorrect result type."),
-            },
+                        doc: None,
+                        alias: None,
+                    },
+                ];
+                assert_eq!(&fields, &expected);
+            }
             _ => panic!("Incorrect result type."),
         }
     }
+
+    #[test]
+    fn resolve_schema_types_infers_param_type_from_column() {
+        let schema = Schema::parse(
+            "create table users (id integer primary key, name text not null, karma integer);",
+        )
+        .unwrap();
+        let input = "\
+          -- @query f(user: User) ->1 i64
+          select
+            max(karma)
+          from
+            users
+          where
+            users.name = :name
+            and karma > :karma /* :i64 */
+          ;";
+
+        let expected = ArgType::Struct {
+            var_name: "user",
+            type_name: "User",
+            fields: vec![
+                TypedIdent {
+                    ident: "name",
+                    type_: SimpleType::Primitive {
+                        // The type points at the column that it was inferred from.
+                        inner: "name",
+                        type_: PrimitiveType::Str,
+                    },
+                    doc: None,
+                    alias: None,
+                },
+                TypedIdent {
+                    ident: "karma",
+                    type_: SimpleType::Primitive {
+                        inner: "i64",
+                        type_: PrimitiveType::I64,
+                    },
+                    doc: None,
+                    alias: None,
+                },
+            ],
+        };
+
+        let query = check_and_resolve_query_with_schema(input, &schema).unwrap();
+        assert_eq!(query.annotation.arguments.resolve(input), expected);
+
+        // Without the schema, the parameter is undefined.
+        assert!(check_and_resolve_query(input).is_err());
+    }
+
+    #[test]
+    fn resolve_schema_types_reports_ambiguous_type() {
+        let schema = Schema::parse(
+            "create table users (id integer, name text);\n\
+             create table pets (id integer, name blob);",
+        )
+        .unwrap();
+        let input = "\
+          -- @query f(q: Q) ->1 i64
+          select users.id from users, pets
+          where users.name = :name or pets.name = :name;";
+
+        let err = check_and_resolve_query_with_schema(input, &schema).unwrap_err();
+        assert_eq!(
+            err.message(),
+            "Cannot infer parameter type, the schema defines columns of different types."
+        );
+    }
+
+    #[test]
+    fn check_unit_result_warns_about_returned_rows() {
+        let warned_span = |input: &str| {
+            let query = check_and_resolve_query(input).unwrap();
+            let mut warnings = Vec::new();
+            check_unit_result(input, &query, &mut warnings);
+            warnings.first().map(|w| w.span.resolve(input).to_string())
+        };
+
+        let warns = [
+            ("-- @query f()\nselect 1;", "select"),
+            (
+                "-- @query f(x: i64)\nSELECT * FROM t WHERE x = :x;",
+                "SELECT",
+            ),
+            (
+                "-- @query f(x: i64)\ninsert into t (x) values (:x) Returning id;",
+                "Returning",
+            ),
+            (
+                "-- @query f()\nwith q as (select 1) select * from q;",
+                "select",
+            ),
+        ];
+        for (input, span) in &warns {
+            assert_eq!(warned_span(input).as_deref(), Some(*span), "{}", input);
+        }
+
+        let silent = [
+            "-- @query f()\ndelete from t where id in (select id from u);",
+            "-- @query f()\ninsert into t (x) select x from u;",
+            "-- @query f()\ncreate table t as select 1 as x;",
+            "-- @query f()\nupdate t set x = 'returning select';",
+            "-- @query f()\nupdate t /* returning */ set selected = 1;",
+        ];
+        for input in &silent {
+            assert_eq!(warned_span(input), None, "{}", input);
+        }
+    }
+
+    #[test]
+    fn keywords_are_sorted() {
+        let mut sorted = KEYWORDS.to_vec();
+        sorted.sort_unstable();
+        assert_eq!(KEYWORDS, &sorted[..]);
+    }
+
+    #[test]
+    fn check_keyword_names_warns_about_keywords() {
+        let warned_spans = |input: &str| {
+            let query = check_and_resolve_query(input).unwrap();
+            let mut warnings = Vec::new();
+            check_keyword_names(input, &query, &mut warnings);
+            warnings
+                .iter()
+                .map(|w| (w.message, w.span.resolve(input).to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        let param_message = "This parameter is named after a keyword.";
+        let output_message = "This output is named after a keyword.";
+
+        assert_eq!(
+            warned_spans(
+                "-- @query f(select: str)
+update t set x = :select;"
+            ),
+            [(param_message, "select".to_string())],
+        );
+        assert_eq!(
+            warned_spans(
+                "-- @query f(r: Row)
+update t set x = :Select /* :str */;"
+            ),
+            [(param_message, "Select".to_string())],
+        );
+        assert_eq!(
+            warned_spans(
+                "-- @query f() ->* Row
+select id /* :i64 */, type /* :str */ from t;"
+            ),
+            [(output_message, "type".to_string())],
+        );
+
+        // An alias renames the output, so it no longer collides.
+        assert_eq!(
+            warned_spans(
+                "-- @query f() ->* Row
+select type /* :kind: str */ from t;"
+            ),
+            [],
+        );
+    }
 }
@@ -0,0 +1,92 @@
+// Squiller -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2023 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Tests for `--cache-dir`, which need to run the binary.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+
+fn run_squiller(cache_dir: &Path, args: &[&str]) -> Vec<u8> {
+    let output = Command::new(env!("CARGO_BIN_EXE_squiller"))
+        .arg("--cache-dir")
+        .arg(cache_dir)
+        .args(args)
+        .output()
+        .expect("Failed to run squiller.");
+    assert!(output.status.success(), "{:?}", output);
+    output.stdout
+}
+
+/// Return the files in the cache directory with their modification time.
+fn cache_entries(cache_dir: &Path) -> Vec<(PathBuf, SystemTime)> {
+    let mut entries: Vec<_> = std::fs::read_dir(cache_dir)
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            (entry.path(), entry.metadata().unwrap().modified().unwrap())
+        })
+        .collect();
+    entries.sort();
+    entries
+}
+
+#[test]
+fn second_run_with_unchanged_input_reuses_cached_output() {
+    let cache_dir = std::env::temp_dir().join(format!("squiller-cache-{}", std::process::id()));
+    let args = ["--target=rust-sqlite", "examples/users.sql"];
+
+    let first = run_squiller(&cache_dir, &args);
+    let entries = cache_entries(&cache_dir);
+    assert_eq!(entries.len(), 1);
+    assert_eq!(std::fs::read(&entries[0].0).unwrap(), first);
+
+    // Wait a bit, so a rewrite of the cache file would change its mtime.
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    let second = run_squiller(&cache_dir, &args);
+    assert_eq!(second, first);
+    assert_eq!(cache_entries(&cache_dir), entries);
+
+    // Different options produce different output, so they get a new entry.
+    let methods = run_squiller(&cache_dir, &["--methods", args[0], args[1]]);
+    assert_ne!(methods, first);
+    assert_eq!(cache_entries(&cache_dir).len(), 2);
+
+    std::fs::remove_dir_all(&cache_dir).unwrap();
+}
+
+#[test]
+fn output_with_warnings_is_not_cached() {
+    let dir = std::env::temp_dir().join(format!("squiller-warnings-{}", std::process::id()));
+    let cache_dir = dir.join("cache");
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.sql");
+    // A statement that returns rows in a query without result type is a warning.
+    std::fs::write(&input, "-- @query get_answer()\nselect 42;\n").unwrap();
+    let args = ["--target=rust-sqlite", input.to_str().unwrap()];
+
+    let first = run_squiller(&cache_dir, &args);
+    assert!(!first.is_empty());
+    assert!(!cache_dir.exists() || cache_entries(&cache_dir).is_empty());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn failure_to_write_cache_does_not_fail_the_run() {
+    let dir = std::env::temp_dir().join(format!("squiller-readonly-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // A file where the cache directory should be, so it cannot be created.
+    let cache_dir = dir.join("cache");
+    std::fs::write(&cache_dir, b"").unwrap();
+    let args = ["--target=rust-sqlite", "examples/users.sql"];
+
+    let output = run_squiller(&cache_dir, &args);
+    assert!(!output.is_empty());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
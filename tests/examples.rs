@@ -0,0 +1,107 @@
+// Squiller -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2023 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Tests that the committed examples are what the targets currently generate.
+//!
+//! The examples in the `examples` directory are formatted with rustfmt and
+//! Black, so they are not byte-for-byte what Squiller outputs. The unformatted
+//! output is committed in `examples/unformatted`, and that is what we compare
+//! against here. Run `tools/update_examples.py` to regenerate both.
+
+use squiller::target::{Options, Target};
+use squiller::NamedDocument;
+use std::path::Path;
+
+/// Replace the Squiller version in the generated header with a placeholder.
+///
+/// The version and revision depend on how the binary was built, we don't want
+/// the test to fail only because it runs on a different checkout.
+fn normalize_version(output: &str) -> String {
+    let marker = "This file was generated by Squiller ";
+    let mut result = String::with_capacity(output.len());
+    for line in output.split_inclusive('\n') {
+        match line.find(marker) {
+            Some(i) => {
+                result.push_str(&line[..i + marker.len()]);
+                result.push_str("<version>\n");
+            }
+            None => result.push_str(line),
+        }
+    }
+    result
+}
+
+fn assert_generates(input_fname: &str, target_name: &str, expected_fname: &str) {
+    let input = std::fs::read(input_fname).unwrap();
+    let document = NamedDocument::process_input(Path::new(input_fname), &input)
+        .expect("Example input should be valid.");
+    let target = Target::from_name(target_name).unwrap();
+    let mut out = Vec::new();
+    target
+        .process_files(&mut out, &Options::default(), &[document])
+        .unwrap();
+
+    let actual = String::from_utf8(out).unwrap();
+    let expected = std::fs::read_to_string(expected_fname).unwrap();
+
+    // Compare line by line first, so a failure points at the first difference
+    // instead of dumping two entire files.
+    let actual = normalize_version(&actual);
+    let expected = normalize_version(&expected);
+    for (i, (a, e)) in actual.lines().zip(expected.lines()).enumerate() {
+        assert_eq!(
+            a,
+            e,
+            "Output of {} differs from {} on line {}, run tools/update_examples.py.",
+            target_name,
+            expected_fname,
+            i + 1,
+        );
+    }
+    assert!(
+        actual == expected,
+        "Output of {} differs from {}, run tools/update_examples.py.",
+        target_name,
+        expected_fname,
+    );
+}
+
+#[test]
+fn rust_sqlite_reproduces_users_example() {
+    assert_generates(
+        "examples/users.sql",
+        "rust-sqlite",
+        "examples/unformatted/users_rust_sqlite.rs",
+    );
+}
+
+#[test]
+fn python_sqlite_reproduces_users_example() {
+    assert_generates(
+        "examples/users.sql",
+        "python-sqlite",
+        "examples/unformatted/users_python_sqlite.py",
+    );
+}
+
+#[test]
+fn python_psycopg2_reproduces_users_example() {
+    assert_generates(
+        "examples/users.sql",
+        "python-psycopg2",
+        "examples/unformatted/users_python_psycopg2.py",
+    );
+}
+
+#[test]
+fn debug_reproduces_users_example() {
+    assert_generates(
+        "examples/users.sql",
+        "debug",
+        "examples/unformatted/users_debug.sql",
+    );
+}
@@ -0,0 +1,354 @@
+// Squiller -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2023 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Tests that run the code generated by the `rust-sqlite` target.
+//!
+//! The generated code under test is the examples in the `examples` directory,
+//! see `tools/update_examples.py` for how to regenerate them.
+
+// Not every generated query is used in the tests.
+#[allow(dead_code)]
+#[path = "../examples/users_rust_sqlite.rs"]
+mod users;
+
+#[allow(dead_code)]
+#[path = "../examples/users_rust_sqlite_borrow_rows.rs"]
+mod users_borrow_rows;
+
+#[allow(dead_code)]
+#[path = "../examples/users_rust_sqlite_methods.rs"]
+mod users_methods;
+
+#[allow(dead_code)]
+#[path = "../examples/tests/struct_args_rust_sqlite.rs"]
+mod struct_args;
+
+#[path = "../examples/tests/enums_rust_sqlite.rs"]
+mod enums;
+
+#[path = "../examples/tests/tagged_enums_rust_sqlite.rs"]
+mod tagged_enums;
+
+#[path = "../examples/tests/cardinality_rust_sqlite_cardinality_errors.rs"]
+mod cardinality;
+
+#[path = "../examples/tests/module_paths_rust_sqlite_visibility_crate.rs"]
+mod module_paths_crate;
+
+// With private visibility, the generated items are only visible inside this
+// module, so we cannot call them here, but it does check that they compile.
+#[allow(dead_code)]
+#[path = "../examples/tests/module_paths_rust_sqlite_visibility_private.rs"]
+mod module_paths_private;
+
+// The generated smoke tests call the queries through `super`, so we include
+// them in a module that has the queries in scope.
+mod users_generated_tests {
+    use super::users::*;
+    include!("../examples/tests/users_rust_sqlite_tests.rs");
+}
+
+use users::{Connection, Result};
+
+fn count_users(connection: &mut Connection) -> Result<usize> {
+    connection.transaction(|tx| Ok(users::select_all_users(tx)?.count()))
+}
+
+#[test]
+fn transaction_commits_when_closure_returns_ok() {
+    let raw_connection = sqlite::open(":memory:").unwrap();
+    let mut connection = Connection::new(&raw_connection);
+    connection.transaction(users::setup_schema).unwrap();
+
+    let id = connection
+        .transaction(|tx| users::insert_user(tx, "henk", "henk@example.com"))
+        .unwrap();
+
+    let user = connection
+        .transaction(|tx| users::select_user_by_id(tx, id))
+        .unwrap();
+    assert_eq!(user.name, "henk");
+    assert_eq!(count_users(&mut connection).unwrap(), 1);
+}
+
+#[test]
+fn transaction_rolls_back_when_closure_returns_err() {
+    let raw_connection = sqlite::open(":memory:").unwrap();
+    let mut connection = Connection::new(&raw_connection);
+    connection.transaction(users::setup_schema).unwrap();
+
+    let result: Result<()> = connection.transaction(|tx| {
+        users::insert_user(tx, "piet", "piet@example.com")?;
+        Err(sqlite::Error {
+            code: None,
+            message: Some("Abort the transaction.".into()),
+        })
+    });
+    assert!(result.is_err());
+    assert_eq!(count_users(&mut connection).unwrap(), 0);
+}
+
+#[test]
+fn borrow_rows_calls_callback_for_every_row() {
+    let raw_connection = sqlite::open(":memory:").unwrap();
+    let mut connection = users_borrow_rows::Connection::new(&raw_connection);
+    connection
+        .transaction(users_borrow_rows::setup_schema)
+        .unwrap();
+
+    let mut names = Vec::new();
+    connection
+        .transaction(|tx| {
+            users_borrow_rows::select_all_users(tx, |user| {
+                names.push(user.name()?);
+                Ok(())
+            })
+        })
+        .unwrap();
+    assert_eq!(names, ["henk", "piet"]);
+}
+
+#[test]
+fn methods_can_be_called_on_transaction() {
+    let raw_connection = sqlite::open(":memory:").unwrap();
+    let mut connection = users_methods::Connection::new(&raw_connection);
+
+    let user = connection
+        .transaction(|tx| {
+            tx.setup_schema()?;
+            let id = tx.insert_user("klaas", "klaas@example.com")?;
+            tx.select_user_by_id(id)
+        })
+        .unwrap();
+    assert_eq!(user.name, "klaas");
+}
+
+#[test]
+fn struct_argument_can_be_dropped_before_iterator_is_consumed() {
+    let raw_connection = sqlite::open(":memory:").unwrap();
+    let mut connection = struct_args::Connection::new(&raw_connection);
+    let emails = connection
+        .transaction(|tx| {
+            struct_args::setup_schema(tx)?;
+            for email in &[Some("henk@example.com"), None] {
+                let user = struct_args::NewUser {
+                    name: "henk",
+                    email: *email,
+                };
+                struct_args::insert_user(tx, user)?;
+            }
+
+            // The name that the filter borrows goes out of scope before we
+            // consume the iterator. This compiles because the statement copies
+            // the bound values.
+            let iter = {
+                let name = String::from("henk");
+                let filter = struct_args::UserFilter { name: &name };
+                struct_args::iter_users_by_name(tx, filter)?
+            };
+            iter.map(|row| row.map(|(_id, email)| email))
+                .collect::<struct_args::Result<Vec<_>>>()
+        })
+        .unwrap();
+    assert_eq!(emails, [Some("henk@example.com".to_string()), None]);
+}
+
+#[test]
+fn slice_argument_inserts_every_element() {
+    let raw_connection = sqlite::open(":memory:").unwrap();
+    let mut connection = struct_args::Connection::new(&raw_connection);
+    let emails = connection
+        .transaction(|tx| {
+            struct_args::setup_schema(tx)?;
+            let users = [
+                struct_args::BatchUser {
+                    name: "henk",
+                    email: Some("henk@example.com"),
+                },
+                struct_args::BatchUser {
+                    name: "henk",
+                    email: None,
+                },
+            ];
+            struct_args::insert_users(tx, &users)?;
+            let filter = struct_args::UserFilter { name: "henk" };
+            struct_args::iter_users_by_name(tx, filter)?
+                .map(|row| row.map(|(_id, email)| email))
+                .collect::<struct_args::Result<Vec<_>>>()
+        })
+        .unwrap();
+    assert_eq!(emails, [Some("henk@example.com".to_string()), None]);
+}
+
+#[test]
+fn optional_argument_binds_null_for_none() {
+    let raw_connection = sqlite::open(":memory:").unwrap();
+    let mut connection = struct_args::Connection::new(&raw_connection);
+    let counts = connection
+        .transaction(|tx| {
+            struct_args::setup_schema(tx)?;
+            for email in &[Some("henk@example.com"), None, None] {
+                let user = struct_args::NewUser {
+                    name: "henk",
+                    email: *email,
+                };
+                struct_args::insert_user(tx, user)?;
+            }
+            Ok([
+                struct_args::count_users_by_email(tx, Some("henk@example.com"))?,
+                struct_args::count_users_by_email(tx, None)?,
+            ])
+        })
+        .unwrap();
+    assert_eq!(counts, [1, 2]);
+}
+
+#[test]
+fn struct_argument_with_only_bytes_borrows_its_field() {
+    let raw_connection = sqlite::open(":memory:").unwrap();
+    let mut connection = struct_args::Connection::new(&raw_connection);
+    let data = connection
+        .transaction(|tx| {
+            struct_args::setup_blobs(tx)?;
+            let id = {
+                let data = vec![0x00, 0xff, 0x42];
+                let blob = struct_args::Blob { data: &data };
+                struct_args::insert_blob(tx, blob)?
+            };
+            struct_args::select_blob_by_id(tx, id)
+        })
+        .unwrap();
+    assert_eq!(data, [0x00, 0xff, 0x42]);
+}
+
+#[test]
+fn enum_round_trips_through_its_code() {
+    use enums::Status;
+
+    let raw_connection = sqlite::open(":memory:").unwrap();
+    let mut connection = enums::Connection::new(&raw_connection);
+    let statuses = connection
+        .transaction(|tx| {
+            enums::setup_schema(tx)?;
+            let mut statuses = Vec::new();
+            for status in &[Status::Active, Status::Inactive, Status::Banned] {
+                let id = enums::insert_user(tx, "henk", *status)?;
+                statuses.push(enums::select_user_status(tx, id)?.status);
+            }
+            Ok(statuses)
+        })
+        .unwrap();
+    assert_eq!(statuses, [Status::Active, Status::Inactive, Status::Banned]);
+}
+
+#[test]
+fn enum_read_fails_for_unknown_code() {
+    let raw_connection = sqlite::open(":memory:").unwrap();
+    let mut connection = enums::Connection::new(&raw_connection);
+    let result = connection.transaction(|tx| {
+        enums::setup_schema(tx)?;
+        let id = enums::insert_user(tx, "henk", enums::Status::Active)?;
+        enums::set_raw_status(tx, id, 7)?;
+        enums::select_user_status(tx, id)
+    });
+    let err = result.err().unwrap();
+    assert_eq!(err.message.as_deref(), Some("Invalid code for Status: 7."));
+}
+
+#[test]
+fn tagged_enum_reads_variant_fields() {
+    use tagged_enums::Event;
+
+    let raw_connection = sqlite::open(":memory:").unwrap();
+    let mut connection = tagged_enums::Connection::new(&raw_connection);
+    let events = connection
+        .transaction(|tx| {
+            tagged_enums::setup_schema(tx)?;
+            tagged_enums::insert_event(tx, 0, Some(1), Some("henk"))?;
+            tagged_enums::insert_event(tx, 1, Some(1), None)?;
+            tagged_enums::insert_event(tx, 2, None, None)?;
+            tagged_enums::iter_events(tx)?.collect::<Result<Vec<_>>>()
+        })
+        .unwrap();
+    assert_eq!(events.len(), 3);
+    assert!(matches!(
+        &events[0],
+        Event::Created { user_id: 1, name } if name == "henk"
+    ));
+    assert!(matches!(events[1], Event::Deleted { user_id: 1 }));
+    assert!(matches!(events[2], Event::Cleared));
+}
+
+#[test]
+fn tagged_enum_read_fails_for_unknown_code() {
+    let raw_connection = sqlite::open(":memory:").unwrap();
+    let mut connection = tagged_enums::Connection::new(&raw_connection);
+    let result = connection.transaction(|tx| {
+        tagged_enums::setup_schema(tx)?;
+        tagged_enums::insert_event(tx, 7, None, None)?;
+        tagged_enums::iter_events(tx)?.collect::<Result<Vec<_>>>()
+    });
+    let err = result.err().unwrap();
+    assert_eq!(err.message.as_deref(), Some("Invalid code for Event: 7."));
+}
+
+#[test]
+fn cardinality_errors_return_err_for_unexpected_rows() {
+    let raw_connection = sqlite::open(":memory:").unwrap();
+    let mut connection = cardinality::Connection::new(&raw_connection);
+    connection.transaction(cardinality::setup_schema).unwrap();
+
+    // Zero rows is an error for `->1`, but fine for `->?`.
+    let result = connection.transaction(cardinality::select_single_number);
+    let err = result.err().unwrap();
+    assert_eq!(
+        err.message.as_deref(),
+        Some("Query 'select_single_number' should return exactly one row.")
+    );
+    let result = connection.transaction(cardinality::select_optional_number);
+    assert_eq!(result.unwrap(), None);
+
+    // Two rows is an error for both.
+    connection
+        .transaction(|tx| {
+            cardinality::insert_number(tx, 1)?;
+            cardinality::insert_number(tx, 2)
+        })
+        .unwrap();
+    let result = connection.transaction(cardinality::select_single_number);
+    let err = result.err().unwrap();
+    assert_eq!(
+        err.message.as_deref(),
+        Some("Query 'select_single_number' should return exactly one row.")
+    );
+    let result = connection.transaction(cardinality::select_optional_number);
+    let err = result.err().unwrap();
+    assert_eq!(
+        err.message.as_deref(),
+        Some("Query 'select_optional_number' should return at most one row.")
+    );
+}
+
+#[test]
+fn functions_in_modules_can_be_called_with_crate_visibility() {
+    use module_paths_crate::users;
+
+    let raw_connection = sqlite::open(":memory:").unwrap();
+    let mut connection = module_paths_crate::Connection::new(&raw_connection);
+    connection
+        .transaction(|tx| {
+            module_paths_crate::setup_schema(tx)?;
+            users::insert(tx, "henk", 1)?;
+            users::insert(tx, "piet", 0)
+        })
+        .unwrap();
+
+    let counts = connection.transaction(|tx| Ok((users::count(tx)?, users::admin::count(tx)?)));
+    assert_eq!(counts.unwrap(), (2, 1));
+    let user = connection.transaction(|tx| users::select_by_name(tx, "piet"));
+    assert_eq!(user.unwrap().map(|u| u.is_admin), Some(0));
+}
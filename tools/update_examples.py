@@ -13,11 +13,14 @@ import os.path
 import subprocess
 
 
-def generate_example(in_fname: str, target: str, extension: str) -> str:
+def generate_example(
+    in_fname: str, target: str, extension: str, *options: str
+) -> str:
     base = os.path.splitext(in_fname)[0]
-    out_fname = base + "_" + target.replace("-", "_") + extension
+    suffix = "".join("_" + opt.lstrip("-").replace("-", "_") for opt in options)
+    out_fname = base + "_" + target.replace("-", "_") + suffix + extension
 
-    cmd = ["target/debug/squiller", f"--target={target}", in_fname]
+    cmd = ["target/debug/squiller", f"--target={target}", *options, in_fname]
     result = subprocess.run(cmd, stdout=subprocess.PIPE, check=True)
 
     with open(out_fname, "wb") as f:
@@ -35,9 +38,76 @@ def main() -> None:
             continue
         in_fname = os.path.join("examples", fname)
         files_rs.append(generate_example(in_fname, "rust-sqlite", ".rs"))
+        files_rs.append(generate_example(in_fname, "rust-sqlite", ".rs", "--no-cache"))
+        files_rs.append(
+            generate_example(in_fname, "rust-sqlite", ".rs", "--borrow-rows")
+        )
+        files_rs.append(generate_example(in_fname, "rust-sqlite", ".rs", "--methods"))
         files_py.append(generate_example(in_fname, "python-psycopg2", ".py"))
         files_py.append(generate_example(in_fname, "python-sqlite", ".py"))
 
+    # The generated tests are not an example by themselves, they are included
+    # in tests/rust_sqlite.rs, so they go in a subdirectory.
+    cmd = [
+        "target/debug/squiller",
+        "--target=rust-sqlite-tests",
+        "--schema=examples/tests/users_schema.sql",
+        "examples/users.sql",
+    ]
+    result = subprocess.run(cmd, stdout=subprocess.PIPE, check=True)
+    with open("examples/tests/users_rust_sqlite_tests.rs", "wb") as f:
+        f.write(result.stdout)
+    files_rs.append("examples/tests/users_rust_sqlite_tests.rs")
+
+    # Queries that exist only to be exercised by tests/rust_sqlite.rs.
+    files_rs.append(
+        generate_example("examples/tests/struct_args.sql", "rust-sqlite", ".rs")
+    )
+    files_rs.append(
+        generate_example("examples/tests/enums.sql", "rust-sqlite", ".rs")
+    )
+    files_rs.append(
+        generate_example("examples/tests/tagged_enums.sql", "rust-sqlite", ".rs")
+    )
+    files_rs.append(
+        generate_example(
+            "examples/tests/cardinality.sql",
+            "rust-sqlite",
+            ".rs",
+            "--cardinality-errors",
+        )
+    )
+
+    # Module paths with restricted visibility, the functions inside the modules
+    # must be visible outside of them, but not more visible than their types.
+    for visibility, suffix in [("pub(crate)", "crate"), ("", "private")]:
+        cmd = [
+            "target/debug/squiller",
+            "--target=rust-sqlite",
+            f"--visibility={visibility}",
+            "examples/tests/module_paths.sql",
+        ]
+        result = subprocess.run(cmd, stdout=subprocess.PIPE, check=True)
+        out_fname = f"examples/tests/module_paths_rust_sqlite_visibility_{suffix}.rs"
+        with open(out_fname, "wb") as f:
+            f.write(result.stdout)
+        files_rs.append(out_fname)
+
+    # Unformatted output, to check in tests/examples.rs that the targets still
+    # reproduce the examples. These are not passed to the formatters.
+    os.makedirs("examples/unformatted", exist_ok=True)
+    for target, extension in [
+        ("rust-sqlite", ".rs"),
+        ("python-sqlite", ".py"),
+        ("python-psycopg2", ".py"),
+        ("debug", ".sql"),
+    ]:
+        cmd = ["target/debug/squiller", f"--target={target}", "examples/users.sql"]
+        result = subprocess.run(cmd, stdout=subprocess.PIPE, check=True)
+        out_fname = "examples/unformatted/users_" + target.replace("-", "_") + extension
+        with open(out_fname, "wb") as f:
+            f.write(result.stdout)
+
     subprocess.run(["black", *files_py])
     subprocess.run(["rustfmt", *files_rs])
 